@@ -4,6 +4,17 @@ mod m20240401_215840_create_tables;
 mod m20240404_213650_update_tables;
 mod m20240418_145628_add_devices_table;
 mod m20240519_114859_update_files_table;
+mod m20261018_100000_add_history_table;
+mod m20261018_110000_add_register_scaling;
+mod m20261018_120000_add_register_value_labels;
+mod m20261018_130000_add_device_templates;
+mod m20261018_140000_add_device_versions;
+mod m20261018_150000_add_device_catalogue;
+mod m20261018_160000_add_register_search;
+mod m20261018_170000_add_soft_delete_state;
+mod m20261018_180000_add_idempotency_keys;
+mod m20261018_190000_convert_timestamps;
+mod m20261019_100000_add_device_search;
 
 pub struct Migrator;
 
@@ -15,6 +26,17 @@ impl MigratorTrait for Migrator {
             Box::new(m20240404_213650_update_tables::Migration),
             Box::new(m20240418_145628_add_devices_table::Migration),
             Box::new(m20240519_114859_update_files_table::Migration),
+            Box::new(m20261018_100000_add_history_table::Migration),
+            Box::new(m20261018_110000_add_register_scaling::Migration),
+            Box::new(m20261018_120000_add_register_value_labels::Migration),
+            Box::new(m20261018_130000_add_device_templates::Migration),
+            Box::new(m20261018_140000_add_device_versions::Migration),
+            Box::new(m20261018_150000_add_device_catalogue::Migration),
+            Box::new(m20261018_160000_add_register_search::Migration),
+            Box::new(m20261018_170000_add_soft_delete_state::Migration),
+            Box::new(m20261018_180000_add_idempotency_keys::Migration),
+            Box::new(m20261018_190000_convert_timestamps::Migration),
+            Box::new(m20261019_100000_add_device_search::Migration),
         ]
     }
 }
@@ -0,0 +1,99 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum ModbusRegisterHistory {
+    Table,
+    Id,
+    TableName,
+    RecordId,
+    Revision,
+    Action,
+    Before,
+    After,
+    ChangedBy,
+    CreatedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create modbus_register_history table
+        manager
+            .create_table(
+                Table::create()
+                    .table(ModbusRegisterHistory::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ModbusRegisterHistory::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ModbusRegisterHistory::TableName)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ModbusRegisterHistory::RecordId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ModbusRegisterHistory::Revision)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ModbusRegisterHistory::Action)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ModbusRegisterHistory::Before).json())
+                    .col(ColumnDef::new(ModbusRegisterHistory::After).json())
+                    .col(ColumnDef::new(ModbusRegisterHistory::ChangedBy).string())
+                    .col(
+                        ColumnDef::new(ModbusRegisterHistory::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp)),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Revisions are always looked up per record
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_history_table_record_revision")
+                    .table(ModbusRegisterHistory::Table)
+                    .col(ModbusRegisterHistory::TableName)
+                    .col(ModbusRegisterHistory::RecordId)
+                    .col(ModbusRegisterHistory::Revision)
+                    .unique()
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ModbusRegisterHistory::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
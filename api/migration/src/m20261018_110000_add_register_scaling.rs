@@ -0,0 +1,68 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden, Clone, Copy)]
+enum ModbusRegister {
+    Table,
+    Scale,
+    Offset,
+    MinValue,
+    MaxValue,
+    Precision,
+    DefaultValue,
+}
+
+// The new columns and their names, all nullable so existing registers stay unscaled.
+const COLUMNS: [(ModbusRegister, &str); 6] = [
+    (ModbusRegister::Scale, "scale"),
+    (ModbusRegister::Offset, "offset"),
+    (ModbusRegister::MinValue, "min_value"),
+    (ModbusRegister::MaxValue, "max_value"),
+    (ModbusRegister::Precision, "precision"),
+    (ModbusRegister::DefaultValue, "default_value"),
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only adds one column per ALTER TABLE statement
+        for (column, name) in COLUMNS {
+            if manager.has_column("modbus_register", name).await? {
+                continue;
+            }
+            let mut definition = ColumnDef::new(column);
+            if let ModbusRegister::Precision = column {
+                definition.integer();
+            } else {
+                definition.double();
+            }
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegister::Table)
+                        .add_column(&mut definition)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for (column, _) in COLUMNS {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegister::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
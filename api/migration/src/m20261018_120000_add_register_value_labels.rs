@@ -0,0 +1,110 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum ModbusRegisterValueLabels {
+    Table,
+    Id,
+    RegisterId,
+    Kind,
+    Value,
+    BitLength,
+    Label,
+    Description,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ModbusRegister {
+    Table,
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create modbus_register_value_labels table, holding the enumerations and bit fields
+        // of a register
+        manager
+            .create_table(
+                Table::create()
+                    .table(ModbusRegisterValueLabels::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ModbusRegisterValueLabels::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ModbusRegisterValueLabels::RegisterId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ModbusRegisterValueLabels::Kind)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ModbusRegisterValueLabels::Value)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ModbusRegisterValueLabels::BitLength).integer())
+                    .col(
+                        ColumnDef::new(ModbusRegisterValueLabels::Label)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ModbusRegisterValueLabels::Description).string())
+                    .col(
+                        ColumnDef::new(ModbusRegisterValueLabels::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp)),
+                    )
+                    .foreign_key(
+                        ForeignKeyCreateStatement::new()
+                            .name("value_label_register_id_fk")
+                            .from_tbl(ModbusRegisterValueLabels::Table)
+                            .from_col(ModbusRegisterValueLabels::RegisterId)
+                            .to_tbl(ModbusRegister::Table)
+                            .to_col(ModbusRegister::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Labels are always loaded per register
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_value_labels_register_id")
+                    .table(ModbusRegisterValueLabels::Table)
+                    .col(ModbusRegisterValueLabels::RegisterId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ModbusRegisterValueLabels::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
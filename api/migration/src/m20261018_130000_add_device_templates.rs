@@ -0,0 +1,201 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum ModbusRegisterDevices {
+    Table,
+    Id,
+    IsTemplate,
+    ParentId,
+    AddressOffset,
+}
+
+#[derive(DeriveIden)]
+enum ModbusRegister {
+    Table,
+    ParentRegisterId,
+}
+
+#[derive(DeriveIden, Clone)]
+enum ModbusRegisterProductDeviceMapping {
+    Table,
+    ProductId,
+    DeviceId,
+}
+
+// The mapping table is rebuilt under this name, since SQLite can't change a primary key.
+const PREVIOUS_MAPPING_TABLE: &str = "modbus_register_product_device_mapping_previous";
+
+// Create the product-device mapping table with the given primary key columns.
+fn mapping_table(primary_key: &[ModbusRegisterProductDeviceMapping]) -> TableCreateStatement {
+    let mut key = Index::create();
+    for column in primary_key {
+        key.col(column.clone());
+    }
+    Table::create()
+        .table(ModbusRegisterProductDeviceMapping::Table)
+        .col(
+            ColumnDef::new(ModbusRegisterProductDeviceMapping::ProductId)
+                .integer()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(ModbusRegisterProductDeviceMapping::DeviceId)
+                .integer()
+                .not_null(),
+        )
+        .primary_key(&mut key)
+        .foreign_key(
+            ForeignKeyCreateStatement::new()
+                .name("device_id_fk")
+                .from_tbl(ModbusRegisterProductDeviceMapping::Table)
+                .from_col(ModbusRegisterProductDeviceMapping::DeviceId)
+                .to_tbl(ModbusRegisterDevices::Table)
+                .to_col(ModbusRegisterDevices::Id)
+                .on_delete(ForeignKeyAction::Cascade),
+        )
+        .to_owned()
+}
+
+// Move the existing mappings into a table with the given primary key.
+async fn rebuild_mapping_table(
+    manager: &SchemaManager<'_>,
+    primary_key: &[ModbusRegisterProductDeviceMapping],
+) -> Result<(), DbErr> {
+    manager
+        .rename_table(
+            Table::rename()
+                .table(
+                    ModbusRegisterProductDeviceMapping::Table,
+                    Alias::new(PREVIOUS_MAPPING_TABLE),
+                )
+                .to_owned(),
+        )
+        .await?;
+    manager.create_table(mapping_table(primary_key)).await?;
+    manager
+        .get_connection()
+        .execute_unprepared(&format!(
+            "INSERT OR IGNORE INTO modbus_register_product_device_mapping (product_id, device_id) \
+             SELECT product_id, device_id FROM {}",
+            PREVIOUS_MAPPING_TABLE
+        ))
+        .await?;
+    manager
+        .drop_table(
+            Table::drop()
+                .table(Alias::new(PREVIOUS_MAPPING_TABLE))
+                .to_owned(),
+        )
+        .await
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Devices can be marked as templates and remember the device they were cloned from
+        if !manager
+            .has_column("modbus_register_devices", "is_template")
+            .await?
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegisterDevices::Table)
+                        .add_column(
+                            ColumnDef::new(ModbusRegisterDevices::IsTemplate)
+                                .boolean()
+                                .not_null()
+                                .default(false),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+        if !manager
+            .has_column("modbus_register_devices", "parent_id")
+            .await?
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegisterDevices::Table)
+                        .add_column(ColumnDef::new(ModbusRegisterDevices::ParentId).integer())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        if !manager
+            .has_column("modbus_register_devices", "address_offset")
+            .await?
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegisterDevices::Table)
+                        .add_column(ColumnDef::new(ModbusRegisterDevices::AddressOffset).integer())
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // Cloned registers remember the register they were copied from
+        if !manager
+            .has_column("modbus_register", "parent_register_id")
+            .await?
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegister::Table)
+                        .add_column(ColumnDef::new(ModbusRegister::ParentRegisterId).integer())
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // A product may now be mapped to several devices, e.g. a template and its clones
+        rebuild_mapping_table(
+            manager,
+            &[
+                ModbusRegisterProductDeviceMapping::ProductId,
+                ModbusRegisterProductDeviceMapping::DeviceId,
+            ],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Only the first device of each product is kept
+        rebuild_mapping_table(manager, &[ModbusRegisterProductDeviceMapping::ProductId]).await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ModbusRegister::Table)
+                    .drop_column(ModbusRegister::ParentRegisterId)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [
+            ModbusRegisterDevices::AddressOffset,
+            ModbusRegisterDevices::ParentId,
+            ModbusRegisterDevices::IsTemplate,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegisterDevices::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum ModbusRegisterDeviceVersions {
+    Table,
+    Id,
+    DeviceId,
+    Version,
+    FirmwareMin,
+    FirmwareMax,
+    ReleaseNotes,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ModbusRegisterDevices {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum ModbusRegister {
+    Table,
+    VersionId,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create modbus_register_device_versions table, holding the firmware releases of a
+        // device that its register map differs between
+        manager
+            .create_table(
+                Table::create()
+                    .table(ModbusRegisterDeviceVersions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ModbusRegisterDeviceVersions::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ModbusRegisterDeviceVersions::DeviceId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ModbusRegisterDeviceVersions::Version)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ModbusRegisterDeviceVersions::FirmwareMin).string())
+                    .col(ColumnDef::new(ModbusRegisterDeviceVersions::FirmwareMax).string())
+                    .col(ColumnDef::new(ModbusRegisterDeviceVersions::ReleaseNotes).text())
+                    .col(
+                        ColumnDef::new(ModbusRegisterDeviceVersions::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp)),
+                    )
+                    .foreign_key(
+                        ForeignKeyCreateStatement::new()
+                            .name("device_version_device_id_fk")
+                            .from_tbl(ModbusRegisterDeviceVersions::Table)
+                            .from_col(ModbusRegisterDeviceVersions::DeviceId)
+                            .to_tbl(ModbusRegisterDevices::Table)
+                            .to_col(ModbusRegisterDevices::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_device_versions_device_version")
+                    .table(ModbusRegisterDeviceVersions::Table)
+                    .col(ModbusRegisterDeviceVersions::DeviceId)
+                    .col(ModbusRegisterDeviceVersions::Version)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // Registers without a version are shared by every version of their device
+        if !manager.has_column("modbus_register", "version_id").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegister::Table)
+                        .add_column(ColumnDef::new(ModbusRegister::VersionId).integer())
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ModbusRegister::Table)
+                    .drop_column(ModbusRegister::VersionId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ModbusRegisterDeviceVersions::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
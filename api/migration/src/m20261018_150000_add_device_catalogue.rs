@@ -0,0 +1,157 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum ModbusRegisterManufacturers {
+    Table,
+    Id,
+    Name,
+    Description,
+    Website,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ModbusRegisterDeviceCategories {
+    Table,
+    Id,
+    Name,
+    Description,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ModbusRegisterDevices {
+    Table,
+    ManufacturerId,
+    CategoryId,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create modbus_register_manufacturers table, the vendors devices are made by
+        manager
+            .create_table(
+                Table::create()
+                    .table(ModbusRegisterManufacturers::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ModbusRegisterManufacturers::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ModbusRegisterManufacturers::Name)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(ModbusRegisterManufacturers::Description).text())
+                    .col(ColumnDef::new(ModbusRegisterManufacturers::Website).string())
+                    .col(
+                        ColumnDef::new(ModbusRegisterManufacturers::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp)),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Create modbus_register_device_categories table, the kinds of equipment devices
+        // are, such as VAV, AHU, chiller or meter
+        manager
+            .create_table(
+                Table::create()
+                    .table(ModbusRegisterDeviceCategories::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ModbusRegisterDeviceCategories::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ModbusRegisterDeviceCategories::Name)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(ModbusRegisterDeviceCategories::Description).text())
+                    .col(
+                        ColumnDef::new(ModbusRegisterDeviceCategories::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp)),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Devices link to their manufacturer and category
+        if !manager
+            .has_column("modbus_register_devices", "manufacturer_id")
+            .await?
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegisterDevices::Table)
+                        .add_column(ColumnDef::new(ModbusRegisterDevices::ManufacturerId).integer())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        if !manager
+            .has_column("modbus_register_devices", "category_id")
+            .await?
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegisterDevices::Table)
+                        .add_column(ColumnDef::new(ModbusRegisterDevices::CategoryId).integer())
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            ModbusRegisterDevices::CategoryId,
+            ModbusRegisterDevices::ManufacturerId,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegisterDevices::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ModbusRegisterDeviceCategories::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ModbusRegisterManufacturers::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,98 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // Full-text index with a row per register, holding the register's name and description
+        // and the name and description of its device, so registers can be found by device
+        db.execute_unprepared(
+            r#"
+            CREATE VIRTUAL TABLE IF NOT EXISTS modbus_register_search USING fts5(
+                register_name,
+                description,
+                device_name,
+                device_description,
+                tokenize = 'unicode61 remove_diacritics 2',
+                prefix = '2 3'
+            );
+        "#,
+        )
+        .await?;
+
+        // Keep the index in sync with the registers and devices on every write path
+        db.execute_unprepared(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS modbus_register_search_insert
+            AFTER INSERT ON modbus_register
+            FOR EACH ROW
+            BEGIN
+                INSERT INTO modbus_register_search (rowid, register_name, description, device_name, device_description)
+                SELECT NEW.id, NEW.register_name, NEW.description, d.name, d.description
+                FROM (SELECT 1) LEFT JOIN modbus_register_devices d ON d.id = NEW.device_id;
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS modbus_register_search_update
+            AFTER UPDATE OF register_name, description, device_id ON modbus_register
+            FOR EACH ROW
+            BEGIN
+                DELETE FROM modbus_register_search WHERE rowid = OLD.id;
+                INSERT INTO modbus_register_search (rowid, register_name, description, device_name, device_description)
+                SELECT NEW.id, NEW.register_name, NEW.description, d.name, d.description
+                FROM (SELECT 1) LEFT JOIN modbus_register_devices d ON d.id = NEW.device_id;
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS modbus_register_search_delete
+            AFTER DELETE ON modbus_register
+            FOR EACH ROW
+            BEGIN
+                DELETE FROM modbus_register_search WHERE rowid = OLD.id;
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS modbus_register_search_device_update
+            AFTER UPDATE OF name, description ON modbus_register_devices
+            FOR EACH ROW
+            BEGIN
+                UPDATE modbus_register_search
+                SET device_name = NEW.name, device_description = NEW.description
+                WHERE rowid IN (SELECT id FROM modbus_register WHERE device_id = NEW.id);
+            END;
+        "#,
+        )
+        .await?;
+
+        // Index the existing registers
+        db.execute_unprepared(
+            r#"
+            DELETE FROM modbus_register_search;
+            INSERT INTO modbus_register_search (rowid, register_name, description, device_name, device_description)
+            SELECT r.id, r.register_name, r.description, d.name, d.description
+            FROM modbus_register r LEFT JOIN modbus_register_devices d ON d.id = r.device_id;
+        "#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                DROP TRIGGER IF EXISTS modbus_register_search_device_update;
+                DROP TRIGGER IF EXISTS modbus_register_search_delete;
+                DROP TRIGGER IF EXISTS modbus_register_search_update;
+                DROP TRIGGER IF EXISTS modbus_register_search_insert;
+                DROP TABLE IF EXISTS modbus_register_search;
+            "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden, Clone, Copy)]
+enum ModbusRegister {
+    Table,
+    PreviousStatus,
+    DeletedAt,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum ModbusRegisterDevices {
+    Table,
+    PreviousStatus,
+    DeletedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Soft-deleted registers and devices keep the status they had and when they were
+        // deleted, so they can be restored
+        let columns = [
+            (
+                "modbus_register",
+                "previous_status",
+                Table::alter()
+                    .table(ModbusRegister::Table)
+                    .add_column(ColumnDef::new(ModbusRegister::PreviousStatus).string())
+                    .to_owned(),
+            ),
+            (
+                "modbus_register",
+                "deleted_at",
+                Table::alter()
+                    .table(ModbusRegister::Table)
+                    .add_column(ColumnDef::new(ModbusRegister::DeletedAt).timestamp())
+                    .to_owned(),
+            ),
+            (
+                "modbus_register_devices",
+                "previous_status",
+                Table::alter()
+                    .table(ModbusRegisterDevices::Table)
+                    .add_column(ColumnDef::new(ModbusRegisterDevices::PreviousStatus).string())
+                    .to_owned(),
+            ),
+            (
+                "modbus_register_devices",
+                "deleted_at",
+                Table::alter()
+                    .table(ModbusRegisterDevices::Table)
+                    .add_column(ColumnDef::new(ModbusRegisterDevices::DeletedAt).timestamp())
+                    .to_owned(),
+            ),
+        ];
+        // SQLite only adds one column per ALTER TABLE statement
+        for (table, column, statement) in columns {
+            if !manager.has_column(table, column).await? {
+                manager.alter_table(statement).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [ModbusRegister::DeletedAt, ModbusRegister::PreviousStatus] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegister::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        for column in [
+            ModbusRegisterDevices::DeletedAt,
+            ModbusRegisterDevices::PreviousStatus,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ModbusRegisterDevices::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
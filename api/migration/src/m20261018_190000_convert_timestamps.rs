@@ -0,0 +1,120 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// The timestamp columns of every table.
+const TIMESTAMP_COLUMNS: &[(&str, &[&str])] = &[
+    (
+        "modbus_register",
+        &["created_at", "updated_at", "deleted_at"],
+    ),
+    (
+        "modbus_register_devices",
+        &["created_at", "updated_at", "deleted_at"],
+    ),
+    ("files", &["created_at", "updated_at"]),
+    ("modbus_register_history", &["created_at"]),
+    ("modbus_register_value_labels", &["created_at"]),
+    ("modbus_register_device_versions", &["created_at"]),
+    ("modbus_register_manufacturers", &["created_at"]),
+    ("modbus_register_device_categories", &["created_at"]),
+];
+
+// SQL converting a stored timestamp of any format SQLite understands to the RFC 3339 UTC
+// format timestamps are written in, such as "2024-05-01T12:00:00+00:00". Timestamps with a
+// time zone are converted to UTC, ones without are taken as UTC, and unreadable ones are
+// replaced with the current time.
+fn converted(value: &str) -> String {
+    let format = |value: &str| {
+        format!(
+            "REPLACE(strftime('%Y-%m-%dT%H:%M:%f', {}), '.000', '') || '+00:00'",
+            value
+        )
+    };
+    format!(
+        "CASE WHEN {value} IS NULL THEN NULL ELSE COALESCE({}, {}) END",
+        format(value),
+        format("'now'"),
+    )
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // updated_at is kept up to date by the application from now on
+        db.execute_unprepared(
+            r#"
+            DROP TRIGGER IF EXISTS update_timestamp;
+            DROP TRIGGER IF EXISTS update_device_timestamp;
+            "#,
+        )
+        .await?;
+
+        // SQLite can't change the type of a column, but the values can be rewritten
+        for (table, columns) in TIMESTAMP_COLUMNS {
+            if !manager.has_table(*table).await? {
+                continue;
+            }
+            let mut assignments = vec![];
+            for column in columns.iter() {
+                if manager.has_column(*table, *column).await? {
+                    assignments.push(format!("{} = {}", column, converted(column)));
+                }
+            }
+            if !assignments.is_empty() {
+                db.execute_unprepared(&format!("UPDATE {} SET {};", table, assignments.join(", ")))
+                    .await?;
+            }
+        }
+
+        // Register snapshots in the history have to be read back as registers
+        for snapshot in ["before", "after"] {
+            let field = |name: &str| {
+                let path = format!("'$.{}'", name);
+                format!(
+                    "{}, {}",
+                    path,
+                    converted(&format!("json_extract({}, {})", snapshot, path))
+                )
+            };
+            db.execute_unprepared(&format!(
+                "UPDATE modbus_register_history SET {snapshot} = json_set({snapshot}, {}, {}, {})
+                WHERE table_name = 'modbus_register' AND {snapshot} IS NOT NULL;",
+                field("created_at"),
+                field("updated_at"),
+                field("deleted_at"),
+            ))
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The converted timestamps stay, they still read as timestamps
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                CREATE TRIGGER IF NOT EXISTS update_timestamp
+                AFTER UPDATE ON modbus_register
+                FOR EACH ROW
+                BEGIN
+                    UPDATE modbus_register SET updated_at = CURRENT_TIMESTAMP WHERE id = OLD.id;
+                END;
+                CREATE TRIGGER IF NOT EXISTS update_device_timestamp
+                AFTER UPDATE ON modbus_register_devices
+                FOR EACH ROW
+                BEGIN
+                    UPDATE modbus_register_devices SET updated_at = CURRENT_TIMESTAMP WHERE name = OLD.name;
+                END;
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // Full-text index with a row per device, so devices without registers can be found too
+        db.execute_unprepared(
+            r#"
+            CREATE VIRTUAL TABLE IF NOT EXISTS modbus_register_device_search USING fts5(
+                name,
+                description,
+                tokenize = 'unicode61 remove_diacritics 2',
+                prefix = '2 3'
+            );
+        "#,
+        )
+        .await?;
+
+        // Keep the index in sync with the devices on every write path
+        db.execute_unprepared(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS modbus_register_device_search_insert
+            AFTER INSERT ON modbus_register_devices
+            FOR EACH ROW
+            BEGIN
+                INSERT INTO modbus_register_device_search (rowid, name, description)
+                VALUES (NEW.id, NEW.name, NEW.description);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS modbus_register_device_search_update
+            AFTER UPDATE OF name, description ON modbus_register_devices
+            FOR EACH ROW
+            BEGIN
+                DELETE FROM modbus_register_device_search WHERE rowid = OLD.id;
+                INSERT INTO modbus_register_device_search (rowid, name, description)
+                VALUES (NEW.id, NEW.name, NEW.description);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS modbus_register_device_search_delete
+            AFTER DELETE ON modbus_register_devices
+            FOR EACH ROW
+            BEGIN
+                DELETE FROM modbus_register_device_search WHERE rowid = OLD.id;
+            END;
+        "#,
+        )
+        .await?;
+
+        // Index the existing devices
+        db.execute_unprepared(
+            r#"
+            DELETE FROM modbus_register_device_search;
+            INSERT INTO modbus_register_device_search (rowid, name, description)
+            SELECT id, name, description FROM modbus_register_devices;
+        "#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                DROP TRIGGER IF EXISTS modbus_register_device_search_delete;
+                DROP TRIGGER IF EXISTS modbus_register_device_search_update;
+                DROP TRIGGER IF EXISTS modbus_register_device_search_insert;
+                DROP TABLE IF EXISTS modbus_register_device_search;
+            "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,8 +1,11 @@
 use std::time::Duration;
 
-use crate::utils::DATABASE_URL;
+use crate::{
+    error::{self, Error},
+    utils::DATABASE_URL,
+};
 
-use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+use sea_orm::{ConnectOptions, Database, DatabaseConnection, DatabaseTransaction};
 
 pub async fn establish_connection() -> Result<DatabaseConnection, Box<dyn std::error::Error>> {
     let mut opt = ConnectOptions::new(DATABASE_URL.as_str());
@@ -16,3 +19,26 @@ pub async fn establish_connection() -> Result<DatabaseConnection, Box<dyn std::e
 
     Database::connect(opt).await.map_err(|error| error.into())
 }
+
+/// Commits a transaction when the work done in it succeeded, and rolls it back otherwise.
+/// Failed transactions have to be rolled back explicitly, a dropped transaction keeps the
+/// database locked for a while.
+pub async fn commit_or_rollback<T>(
+    txn: DatabaseTransaction,
+    result: error::Result<T>,
+) -> error::Result<T> {
+    match result {
+        Ok(value) => {
+            txn.commit()
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?;
+            Ok(value)
+        }
+        Err(error) => {
+            txn.rollback()
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?;
+            Err(error)
+        }
+    }
+}
@@ -1,6 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 1.0.0-rc.3
 
-use sea_orm::entity::prelude::*;
+use sea_orm::{entity::prelude::*, Set};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
@@ -28,4 +28,20 @@ impl Related<super::modbus_register_devices::Entity> for Entity {
     }
 }
 
-impl ActiveModelBehavior for ActiveModel {}
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    // Stamp new files, and update the timestamp of changed ones.
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let now = chrono::Utc::now();
+        if insert && !self.created_at.is_set() {
+            self.created_at = Set(now);
+        }
+        if !self.updated_at.is_set() {
+            self.updated_at = Set(now);
+        }
+        Ok(self)
+    }
+}
@@ -3,8 +3,14 @@
 pub mod prelude;
 
 pub mod files;
+pub mod idempotency_keys;
 pub mod modbus_register;
+pub mod modbus_register_device_categories;
+pub mod modbus_register_device_versions;
 pub mod modbus_register_devices;
+pub mod modbus_register_history;
+pub mod modbus_register_manufacturers;
 pub mod modbus_register_product_device_mapping;
 pub mod modbus_register_settings;
+pub mod modbus_register_value_labels;
 pub mod user;
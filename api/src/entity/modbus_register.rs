@@ -1,9 +1,9 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.14
 
-use sea_orm::entity::prelude::*;
+use sea_orm::{entity::prelude::*, Set};
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "modbus_register")]
 pub struct Model {
     #[sea_orm(primary_key)]
@@ -18,10 +18,23 @@ pub struct Model {
     pub status: String,
     pub unit: Option<String>,
     pub private: Option<bool>,
-    #[sea_orm(column_type = "Text")]
-    pub created_at: String,
-    #[sea_orm(column_type = "Text")]
-    pub updated_at: String,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub scale: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub offset: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub min_value: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub max_value: Option<f64>,
+    pub precision: Option<i32>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub default_value: Option<f64>,
+    pub parent_register_id: Option<i32>,
+    pub version_id: Option<i32>,
+    pub previous_status: Option<String>,
+    pub deleted_at: Option<DateTimeUtc>,
+    pub created_at: DateTimeUtc,
+    pub updated_at: DateTimeUtc,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -34,6 +47,8 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     ModbusRegisterDevices,
+    #[sea_orm(has_many = "super::modbus_register_value_labels::Entity")]
+    ModbusRegisterValueLabels,
 }
 
 impl Related<super::modbus_register_devices::Entity> for Entity {
@@ -42,4 +57,26 @@ impl Related<super::modbus_register_devices::Entity> for Entity {
     }
 }
 
-impl ActiveModelBehavior for ActiveModel {}
+impl Related<super::modbus_register_value_labels::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ModbusRegisterValueLabels.def()
+    }
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    // Stamp new and changed rows. Timestamps set on the model, as by sync imports, are kept.
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let now = chrono::Utc::now();
+        if insert && !self.created_at.is_set() {
+            self.created_at = Set(now);
+        }
+        if !self.updated_at.is_set() {
+            self.updated_at = Set(now);
+        }
+        Ok(self)
+    }
+}
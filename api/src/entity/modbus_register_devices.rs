@@ -1,6 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 1.0.0-rc.3
 
-use sea_orm::entity::prelude::*;
+use sea_orm::{entity::prelude::*, Set};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
@@ -15,6 +15,14 @@ pub struct Model {
     pub status: String,
     pub private: bool,
     pub image_id: Option<i32>,
+    #[serde(default)]
+    pub is_template: bool,
+    pub parent_id: Option<i32>,
+    pub address_offset: Option<i32>,
+    pub manufacturer_id: Option<i32>,
+    pub category_id: Option<i32>,
+    pub previous_status: Option<String>,
+    pub deleted_at: Option<DateTimeUtc>,
     pub created_at: DateTimeUtc,
     pub updated_at: DateTimeUtc,
 }
@@ -29,8 +37,26 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     Files,
+    #[sea_orm(
+        belongs_to = "super::modbus_register_manufacturers::Entity",
+        from = "Column::ManufacturerId",
+        to = "super::modbus_register_manufacturers::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    ModbusRegisterManufacturers,
+    #[sea_orm(
+        belongs_to = "super::modbus_register_device_categories::Entity",
+        from = "Column::CategoryId",
+        to = "super::modbus_register_device_categories::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    ModbusRegisterDeviceCategories,
     #[sea_orm(has_many = "super::modbus_register::Entity")]
     ModbusRegister,
+    #[sea_orm(has_many = "super::modbus_register_device_versions::Entity")]
+    ModbusRegisterDeviceVersions,
     #[sea_orm(has_many = "super::modbus_register_product_device_mapping::Entity")]
     ModbusRegisterProductDeviceMapping,
 }
@@ -47,10 +73,44 @@ impl Related<super::modbus_register::Entity> for Entity {
     }
 }
 
+impl Related<super::modbus_register_manufacturers::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ModbusRegisterManufacturers.def()
+    }
+}
+
+impl Related<super::modbus_register_device_categories::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ModbusRegisterDeviceCategories.def()
+    }
+}
+
+impl Related<super::modbus_register_device_versions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ModbusRegisterDeviceVersions.def()
+    }
+}
+
 impl Related<super::modbus_register_product_device_mapping::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::ModbusRegisterProductDeviceMapping.def()
     }
 }
 
-impl ActiveModelBehavior for ActiveModel {}
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    // Stamp new and changed devices, unless the timestamps were set on purpose.
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let now = chrono::Utc::now();
+        if insert && !self.created_at.is_set() {
+            self.created_at = Set(now);
+        }
+        if !self.updated_at.is_set() {
+            self.updated_at = Set(now);
+        }
+        Ok(self)
+    }
+}
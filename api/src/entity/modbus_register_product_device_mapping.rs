@@ -8,6 +8,7 @@ use serde::{Deserialize, Serialize};
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub product_id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
     pub device_id: i32,
 }
 
@@ -1,8 +1,14 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 1.0.0-rc.3
 
 pub use super::files::Entity as Files;
+pub use super::idempotency_keys::Entity as IdempotencyKeys;
 pub use super::modbus_register::Entity as ModbusRegister;
+pub use super::modbus_register_device_categories::Entity as ModbusRegisterDeviceCategories;
+pub use super::modbus_register_device_versions::Entity as ModbusRegisterDeviceVersions;
 pub use super::modbus_register_devices::Entity as ModbusRegisterDevices;
+pub use super::modbus_register_history::Entity as ModbusRegisterHistory;
+pub use super::modbus_register_manufacturers::Entity as ModbusRegisterManufacturers;
 pub use super::modbus_register_product_device_mapping::Entity as ModbusRegisterProductDeviceMapping;
 pub use super::modbus_register_settings::Entity as ModbusRegisterSettings;
+pub use super::modbus_register_value_labels::Entity as ModbusRegisterValueLabels;
 pub use super::user::Entity as User;
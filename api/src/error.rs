@@ -1,5 +1,6 @@
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
+use axum::Json;
 use serde::Serialize;
 
 // Define a custom result type that uses the custom Error type.
@@ -14,7 +15,33 @@ pub enum Error {
     Unauthorized,
     PermissionDenied,
     BadRequest(String),
+    Conflict(String),
+    PreconditionFailed(String),
     ServerError(String),
+    Gateway(String),
+    Validation(Vec<FieldError>),
+}
+
+// Describes why the value of a single input field was rejected.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl core::fmt::Display for FieldError {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        write!(fmt, "{}: {}", self.field, self.message)
+    }
 }
 
 // Implement the Display trait for the Error enum to enable formatted output.
@@ -43,10 +70,24 @@ impl IntoResponse for Error {
             Self::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
             Self::PermissionDenied => (StatusCode::FORBIDDEN, "Permission Denied".to_string()),
             Self::BadRequest(err) => (StatusCode::BAD_REQUEST, format!("Bad Request: {err}")),
+            Self::Conflict(err) => (StatusCode::CONFLICT, format!("Conflict: {err}")),
+            Self::PreconditionFailed(err) => (
+                StatusCode::PRECONDITION_FAILED,
+                format!("Precondition Failed: {err}"),
+            ),
             Self::ServerError(err) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Server Error: {}", err),
             ),
+            Self::Gateway(err) => (StatusCode::BAD_GATEWAY, format!("Gateway Error: {}", err)),
+            // Validation errors carry field-level details, so they are returned as JSON.
+            Self::Validation(errors) => {
+                return (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    Json(serde_json::json!({ "message": "Validation failed", "errors": errors })),
+                )
+                    .into_response()
+            }
         };
 
         // Convert the tuple (StatusCode, String) into an HTTP response.
@@ -0,0 +1,118 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{
+        header::{CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH},
+        HeaderMap, HeaderValue, Method, Request, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+/// Computes the strong entity tag of a value, from a FNV-1a hash of its JSON. Rows are tagged
+/// as stored, so the tag changes whenever the row does.
+pub fn etag<T: Serialize>(value: &T) -> String {
+    tag(&serde_json::to_vec(value).unwrap_or_default())
+}
+
+fn tag(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("\"{:016x}\"", hash)
+}
+
+/// Headers carrying the entity tag of a row, for handlers to return with it.
+pub fn etag_header<T: Serialize>(value: &T) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(&etag(value)) {
+        headers.insert(ETAG, value);
+    }
+    headers
+}
+
+// The tags of an `If-Match` or `If-None-Match` header, None standing for `*`.
+fn header_tags(value: &HeaderValue) -> Option<Vec<&str>> {
+    let value = value.to_str().unwrap_or_default().trim();
+    if value == "*" {
+        return None;
+    }
+    Some(value.split(',').map(str::trim).collect())
+}
+
+/// Checks the `If-Match` header of a PATCH or DELETE request against the row it changes.
+/// Returns a `PreconditionFailed` error when someone else changed the row since the client
+/// read it. Requests without the header always pass.
+pub fn check_if_match<T: Serialize>(headers: &HeaderMap, current: &T) -> Result<()> {
+    let Some(tags) = headers.get(IF_MATCH).and_then(header_tags) else {
+        return Ok(());
+    };
+    // Weak tags never match here, they don't promise the same bytes.
+    let current = etag(current);
+    if tags.iter().any(|tag| *tag == current) {
+        return Ok(());
+    }
+    Err(Error::PreconditionFailed(format!(
+        "The resource was changed, its current ETag is {}",
+        current
+    )))
+}
+
+/// Middleware function answering conditional GET requests.
+///
+/// JSON responses without an `ETag` header get one computed from their body, and responses
+/// whose tag matches the `If-None-Match` header of the request are replaced with an empty
+/// "304 Not Modified".
+pub async fn conditional_get(req: Request<Body>, next: Next) -> Result<Response> {
+    if req.method() != Method::GET {
+        return Ok(next.run(req).await);
+    }
+    let if_none_match = req.headers().get(IF_NONE_MATCH).cloned();
+    let response = next.run(req).await;
+    if response.status() != StatusCode::OK {
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let is_json = parts
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let body = match parts.headers.get(ETAG) {
+        Some(_) => body,
+        None if is_json => {
+            let bytes = to_bytes(body, usize::MAX)
+                .await
+                .map_err(|error| Error::ServerError(error.to_string()))?;
+            if let Ok(value) = HeaderValue::from_str(&tag(&bytes)) {
+                parts.headers.insert(ETAG, value);
+            }
+            Body::from(bytes)
+        }
+        None => return Ok(Response::from_parts(parts, body)),
+    };
+
+    let not_modified = match (&if_none_match, parts.headers.get(ETAG)) {
+        (Some(if_none_match), Some(current)) => {
+            let current = current.to_str().unwrap_or_default();
+            header_tags(if_none_match).is_none_or(|tags| {
+                // Compare weakly, a `W/` prefix doesn't matter.
+                tags.iter()
+                    .any(|tag| tag.trim_start_matches("W/") == current.trim_start_matches("W/"))
+            })
+        }
+        _ => false,
+    };
+    if not_modified {
+        let mut headers = HeaderMap::new();
+        if let Some(current) = parts.headers.get(ETAG) {
+            headers.insert(ETAG, current.clone());
+        }
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    Ok(Response::from_parts(parts, body))
+}
@@ -1,17 +1,23 @@
 use axum::{
     extract::{DefaultBodyLimit, Multipart, Query, State},
+    http::HeaderMap,
     middleware,
     routing::{get, post},
     Json, Router,
 };
 
-use sea_orm::{entity::prelude::*, Set, TryIntoModel};
+use sea_orm::{entity::prelude::*, Set};
 use serde::{Deserialize, Serialize};
 
 use std::{fs, io::Write};
 use std::{fs::File, path::Path};
 
-use crate::{auth::require_auth, entity::prelude::*};
+use crate::{
+    auth::require_auth,
+    entity::prelude::*,
+    etag::{check_if_match, etag_header},
+    idempotency::idempotent_upload,
+};
 use crate::{entity::files, utils::SPA_DIR};
 
 use crate::{
@@ -25,15 +31,23 @@ pub struct QueryParams {
     pub path: Option<String>,
 }
 
+/// The largest file that can be uploaded, 300 MB.
+pub const MAX_UPLOAD_SIZE: usize = 1024 * 1000 * 300;
+
 /// Function to define file routes
-pub fn file_routes() -> Router<AppState> {
-    Router::new()
-        .route("/files", get(get_files))
-        .route("/files/:id", get(get_file_by_id).delete(delete_file))
+pub fn file_routes(app_state: AppState) -> Router<AppState> {
+    // Uploads may be retried with an Idempotency-Key without storing the file twice
+    let upload_routes = Router::new()
         .route(
             "/file",
-            post(upload_file).layer(DefaultBodyLimit::max(1024 * 1000 * 300) /* 300 MB */),
+            post(upload_file).layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE)),
         )
+        .route_layer(middleware::from_fn_with_state(app_state, idempotent_upload));
+
+    Router::new()
+        .route("/files", get(get_files))
+        .route("/files/:id", get(get_file_by_id).delete(delete_file))
+        .merge(upload_routes)
         .route_layer(middleware::from_fn(require_auth))
 }
 
@@ -98,20 +112,20 @@ async fn upload_file(
 
         file_path.push_str(&new_filename);
 
-        let model = Files::insert(files::ActiveModel {
+        let model = files::ActiveModel {
             id: Default::default(),
             name: Set(filename.clone()),
             path: Set(file_path.clone()),
             mime_type: Set(mime_type.to_string()),
             ..Default::default()
-        });
+        };
 
         let results = model
-            .exec_with_returning(&*conn)
+            .insert(&*conn)
             .await
             .map_err(|error| Error::DbError(error.to_string()))?;
 
-        return Ok(Json(results.try_into_model().unwrap()));
+        return Ok(Json(results));
     }
     Err(Error::BadRequest("No file field found".to_string()))
 }
@@ -133,7 +147,7 @@ pub async fn get_files(State(state): State<AppState>) -> Result<Json<Vec<files::
 pub async fn get_file_by_id(
     State(state): State<AppState>,
     axum::extract::Path(id): axum::extract::Path<i32>,
-) -> Result<Json<files::Model>> {
+) -> Result<(HeaderMap, Json<files::Model>)> {
     let conn = state.conn.lock().await;
     // Perform a query to find the file by its ID.
     let the_file = Files::find_by_id(id)
@@ -142,23 +156,28 @@ pub async fn get_file_by_id(
         .map_err(|error| Error::DbError(error.to_string()))? // Handle any database errors.
         .ok_or(Error::NotFound)?; // Return a NotFound error if the file does not exist.
 
-    // Return the found file as JSON.
-    Ok(Json(the_file))
+    // Return the found file as JSON, tagged for `If-Match` on deletion.
+    Ok((etag_header(&the_file), Json(the_file)))
 }
 
 // Asynchronously deletes a file from the database and the filesystem.
-pub async fn delete_file(State(state): State<AppState>) -> Result<Json<files::Model>> {
+pub async fn delete_file(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<i32>,
+    headers: HeaderMap,
+) -> Result<Json<files::Model>> {
     let conn = state.conn.lock().await;
-    // Perform a query to find the first file in the database.
-    let the_file = Files::find()
+    // Perform a query to find the file by its ID.
+    let the_file = Files::find_by_id(id)
         .one(&*conn) // Use the database connection from the application state.
         .await
         .map_err(|error| Error::DbError(error.to_string()))? // Handle any database errors.
         .ok_or(Error::NotFound)?; // Return a NotFound error if the file does not exist.
+    check_if_match(&headers, &the_file)?; // Return a PreconditionFailed error if the file was changed.
 
-    // Construct the file path to delete from the filesystem.
-    let path_str: String = SPA_DIR.clone(); // Get the directory path as a string.
-    let path = Path::new(&path_str).join(&the_file.path); // Join the directory path with the file path.
+    // Construct the file path to delete from the filesystem. The stored path starts with a
+    // slash, so it's appended to the directory the same way `upload_file` built it.
+    let path = format!("{}{}", SPA_DIR.as_str(), the_file.path);
     fs::remove_file(&path).map_err(|error| Error::ServerError(error.to_string()))?; // Delete the file and handle any filesystem errors.
 
     // Delete the file entry from the database by its ID.
@@ -0,0 +1,251 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::State,
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        HeaderName, HeaderValue, Method, Request, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{entity::prelude::*, Condition, DatabaseConnection, Set};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use std::sync::Arc;
+
+use crate::{
+    app_state::AppState,
+    entity::{idempotency_keys, prelude::*},
+    error::{Error, FieldError, Result},
+    file::routes::MAX_UPLOAD_SIZE,
+    utils::IDEMPOTENCY_RETENTION_HOURS,
+};
+
+/// Header of the key a client picks for a POST request, and sends again when retrying it.
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+/// Header marking a response as the stored response to an earlier request.
+pub const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+// The largest request body kept in memory to be hashed, axum's default limit that the create
+// routes keep. File uploads have their own limit.
+const MAX_BODY_SIZE: usize = 1024 * 1024 * 2;
+// How long a claimed key waits for its response before another request may take it over.
+const PENDING_TIMEOUT_MINUTES: i64 = 5;
+
+// A key claimed by a request that is still running. The claim is given up when the request
+// never finishes, because the client disconnected or the handler panicked.
+struct PendingClaim {
+    conn: Arc<Mutex<DatabaseConnection>>,
+    key: Option<String>,
+}
+
+impl PendingClaim {
+    // Keep the claim, its response got stored or it was given up already.
+    fn keep(mut self) {
+        self.key = None;
+    }
+}
+
+impl Drop for PendingClaim {
+    fn drop(&mut self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        let conn = self.conn.clone();
+        tokio::spawn(async move {
+            let conn = conn.lock().await;
+            let _ = IdempotencyKeys::delete_many()
+                .filter(idempotency_keys::Column::Key.eq(key))
+                .filter(idempotency_keys::Column::StatusCode.is_null())
+                .exec(&*conn)
+                .await;
+        });
+    }
+}
+
+/// Middleware function making POST requests with an `Idempotency-Key` header safe to retry,
+/// layered on the create routes.
+///
+/// The first request with a key runs as usual, and a successful response is stored for
+/// `IDEMPOTENCY_RETENTION_HOURS`. Retries get the stored response back instead of creating
+/// the same item again, while reusing the key for a different request is rejected.
+/// Responses that failed aren't stored, so those requests can be retried for real, and neither
+/// are requests that never finished.
+pub async fn idempotent(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response> {
+    run_idempotent(state, req, next, MAX_BODY_SIZE).await
+}
+
+/// Middleware function making file uploads with an `Idempotency-Key` header safe to retry, like
+/// `idempotent` but hashing bodies up to the upload limit.
+pub async fn idempotent_upload(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response> {
+    run_idempotent(state, req, next, MAX_UPLOAD_SIZE).await
+}
+
+async fn run_idempotent(
+    state: AppState,
+    req: Request<Body>,
+    next: Next,
+    max_body_size: usize,
+) -> Result<Response> {
+    if req.method() != Method::POST {
+        return Ok(next.run(req).await);
+    }
+    let Some(key) = req.headers().get(&IDEMPOTENCY_KEY) else {
+        return Ok(next.run(req).await);
+    };
+    let key = key.to_str().unwrap_or_default().trim().to_string();
+    if key.is_empty() || key.len() > 255 {
+        return Err(Error::BadRequest(
+            "The Idempotency-Key header must have 1 to 255 characters".to_string(),
+        ));
+    }
+
+    let (parts, body) = req.into_parts();
+    let body = to_bytes(body, max_body_size)
+        .await
+        .map_err(|error| Error::BadRequest(error.to_string()))?;
+    let path = parts
+        .uri
+        .path_and_query()
+        .map_or_else(|| parts.uri.path().to_string(), ToString::to_string);
+    // Requests of other clients never match, so they can't get someone else's response.
+    let credentials = parts
+        .headers
+        .get(AUTHORIZATION)
+        .or(parts.headers.get("auth"))
+        .map(HeaderValue::as_bytes)
+        .unwrap_or_default();
+    let request_hash = format!(
+        "{:x}",
+        Sha256::new()
+            .chain_update(credentials)
+            .chain_update([0])
+            .chain_update(&body)
+            .finalize()
+    );
+
+    // Claim the key, or answer with what the request that claimed it first got. Expired keys
+    // and claims left behind by a stopped server are cleared first. Retention periods too long
+    // to subtract from the current time never expire.
+    let expired_before = Duration::try_hours(*IDEMPOTENCY_RETENTION_HOURS)
+        .and_then(|retention| Utc::now().checked_sub_signed(retention))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let claim = {
+        let conn = state.conn.lock().await;
+        IdempotencyKeys::delete_many()
+            .filter(
+                Condition::any()
+                    .add(idempotency_keys::Column::CreatedAt.lt(expired_before))
+                    .add(
+                        Condition::all()
+                            .add(idempotency_keys::Column::StatusCode.is_null())
+                            .add(
+                                idempotency_keys::Column::CreatedAt
+                                    .lt(Utc::now() - Duration::minutes(PENDING_TIMEOUT_MINUTES)),
+                            ),
+                    ),
+            )
+            .exec(&*conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        let claimed = IdempotencyKeys::insert(idempotency_keys::ActiveModel {
+            key: Set(key.clone()),
+            method: Set(parts.method.to_string()),
+            path: Set(path.clone()),
+            request_hash: Set(request_hash.clone()),
+            status_code: Set(None),
+            content_type: Set(None),
+            response_body: Set(None),
+            created_at: Set(Utc::now()),
+        })
+        .exec(&*conn)
+        .await;
+        if let Err(error) = claimed {
+            let existing = IdempotencyKeys::find_by_id(&key)
+                .one(&*conn)
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?
+                .ok_or(Error::DbError(error.to_string()))?;
+            return replay(existing, &path, &request_hash);
+        }
+        PendingClaim {
+            conn: state.conn.clone(),
+            key: Some(key.clone()),
+        }
+    };
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    let conn = state.conn.lock().await;
+    if !response.status().is_success() {
+        IdempotencyKeys::delete_by_id(&key)
+            .exec(&*conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        claim.keep();
+        return Ok(response);
+    }
+    let (parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX)
+        .await
+        .map_err(|error| Error::ServerError(error.to_string()))?;
+    IdempotencyKeys::update_many()
+        .filter(idempotency_keys::Column::Key.eq(&key))
+        .set(idempotency_keys::ActiveModel {
+            status_code: Set(Some(i32::from(parts.status.as_u16()))),
+            content_type: Set(parts
+                .headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(ToString::to_string)),
+            response_body: Set(Some(body.to_vec())),
+            ..Default::default()
+        })
+        .exec(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    claim.keep();
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+// Answer a retried request with the stored response.
+fn replay(stored: idempotency_keys::Model, path: &str, request_hash: &str) -> Result<Response> {
+    if stored.path != path || stored.request_hash != request_hash {
+        return Err(Error::Validation(vec![FieldError::new(
+            "Idempotency-Key",
+            "was already used for a different request",
+        )]));
+    }
+    let Some(status_code) = stored.status_code else {
+        return Err(Error::Conflict(
+            "The request with this Idempotency-Key is still being processed".to_string(),
+        ));
+    };
+
+    let status = u16::try_from(status_code)
+        .ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .unwrap_or(StatusCode::OK);
+    let mut response = (status, stored.response_body.unwrap_or_default()).into_response();
+    if let Some(content_type) = stored
+        .content_type
+        .and_then(|content_type| HeaderValue::from_str(&content_type).ok())
+    {
+        response.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+    response
+        .headers_mut()
+        .insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+    Ok(response)
+}
@@ -1,4 +1,10 @@
+use std::ffi::{c_char, CStr, CString};
 use std::panic;
+use std::ptr;
+
+use db_connection::establish_connection;
+use error::Error;
+use modbus_register::inputs::ProductResolutionQueryParams;
 use utils::{copy_database_if_not_exists, SHUTDOWN_CHANNEL};
 
 pub mod app_state;
@@ -6,8 +12,11 @@ pub mod auth;
 pub mod db_connection;
 pub mod entity;
 pub mod error;
+pub mod etag;
 pub mod file;
+pub mod idempotency;
 pub mod modbus_register;
+pub mod modbus_tcp;
 pub mod server;
 pub mod user;
 pub mod utils;
@@ -65,3 +74,55 @@ pub extern "C" fn run_server() -> RustError {
         Err(_) => RustError::Error, // A panic occurred, return RustError::Error.
     }
 }
+
+/// Externally callable function to resolve a T3 product ID to its device and register map
+/// for using from C++, the same way as `GET /api/modbus-register/products/:product_id`.
+/// Returns the resolution as a JSON string, or a JSON object with an "error" field when the
+/// product can't be resolved. The string must be released with `free_string`.
+///
+/// # Safety
+///
+/// `firmware` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn resolve_product(product_id: i32, firmware: *const c_char) -> *mut c_char {
+    let firmware =
+        (!firmware.is_null()).then(|| CStr::from_ptr(firmware).to_string_lossy().into_owned());
+
+    // Use panic::catch_unwind to catch any panic and prevent it from unwinding across FFI boundaries.
+    let result = panic::catch_unwind(|| {
+        let runtime = tokio::runtime::Runtime::new()
+            .map_err(|error| Error::ServerError(error.to_string()))?;
+        runtime.block_on(async {
+            dotenvy::dotenv().ok(); // Load environment variables from a .env file, if it exists.
+            copy_database_if_not_exists().ok(); // Copy the database if it doesn't already exist.
+            let conn = establish_connection()
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?;
+            let params = ProductResolutionQueryParams {
+                firmware,
+                ..Default::default()
+            };
+            modbus_register::resolve::resolve_product(&conn, product_id, &params).await
+        })
+    });
+
+    let json = match result {
+        Ok(Ok(resolution)) => serde_json::to_value(resolution),
+        Ok(Err(error)) => Ok(serde_json::json!({ "error": error })),
+        Err(_) => Ok(serde_json::json!({ "error": Error::ServerError("Panicked".to_string()) })),
+    }
+    .unwrap_or_else(|error| serde_json::json!({ "error": Error::ServerError(error.to_string()) }));
+    CString::new(json.to_string()).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Externally callable function to release a string returned by `resolve_product`.
+///
+/// # Safety
+///
+/// `string` must be null or a pointer returned by `resolve_product` that wasn't released yet.
+#[no_mangle]
+pub unsafe extern "C" fn free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
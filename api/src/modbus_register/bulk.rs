@@ -0,0 +1,171 @@
+use axum::{extract::State, Json};
+use sea_orm::{prelude::*, DatabaseTransaction, QueryOrder, TransactionTrait};
+
+use super::filter_expression::parse_filter;
+use super::inputs::{BulkAction, BulkChange, BulkDeleteInput, BulkReport, BulkUpdateInput};
+use super::queries::{delete_register, update_register};
+use crate::{
+    app_state::AppState,
+    db_connection::commit_or_rollback,
+    entity::modbus_register::{self, Entity as ModbusRegister},
+    error::{Error, FieldError, Result},
+};
+
+// Find the registers a bulk change applies to, given either by ID or by a filter expression.
+async fn target_registers(
+    txn: &DatabaseTransaction,
+    ids: Option<Vec<i32>>,
+    expression: Option<String>,
+) -> Result<Vec<modbus_register::Model>> {
+    let expression = expression.filter(|expression| !expression.trim().is_empty());
+    let condition = match (ids, expression) {
+        (Some(ids), None) => {
+            let registers = ModbusRegister::find()
+                .filter(modbus_register::Column::Id.is_in(ids.clone()))
+                .order_by_asc(modbus_register::Column::Id)
+                .all(txn)
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?;
+            let unknown: Vec<String> = ids
+                .iter()
+                .filter(|id| !registers.iter().any(|register| register.id == **id))
+                .map(|id| id.to_string())
+                .collect();
+            if !unknown.is_empty() {
+                return Err(Error::BadRequest(format!(
+                    "Unknown register IDs: {}",
+                    unknown.join(", ")
+                )));
+            }
+            return Ok(registers);
+        }
+        (None, Some(expression)) => parse_filter(&expression)?,
+        _ => {
+            return Err(Error::BadRequest(
+                "Give either the IDs of the registers or a filter expression".to_string(),
+            ))
+        }
+    };
+
+    ModbusRegister::find()
+        .filter(condition)
+        .order_by_asc(modbus_register::Column::Id)
+        .all(txn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))
+}
+
+// Name the register in the validation errors of a single register.
+fn register_error(id: i32, error: Error) -> Error {
+    match error {
+        Error::Validation(errors) => Error::Validation(
+            errors
+                .into_iter()
+                .map(|error| {
+                    FieldError::new(error.field, format!("Register {}: {}", id, error.message))
+                })
+                .collect(),
+        ),
+        error => error,
+    }
+}
+
+// Keep the changes of a bulk run, or roll them back when it's a dry run or a change failed.
+async fn finish(
+    txn: DatabaseTransaction,
+    dry_run: bool,
+    changes: Result<Vec<BulkChange>>,
+) -> Result<BulkReport> {
+    let changes = match changes {
+        Ok(changes) if dry_run => {
+            txn.rollback()
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?;
+            changes
+        }
+        changes => commit_or_rollback(txn, changes).await?,
+    };
+
+    Ok(BulkReport {
+        dry_run,
+        count: changes.len(),
+        changes,
+    })
+}
+
+/// Handler to apply the same partial update to many Modbus registers, given by ID or by a
+/// filter expression. Every register is updated like a single update, in one transaction
+/// that is rolled back when any register fails. With `dry_run` the changes are reported
+/// and rolled back.
+pub async fn bulk_update(
+    State(state): State<AppState>,
+    Json(payload): Json<BulkUpdateInput>,
+) -> Result<Json<BulkReport>> {
+    let conn = state.conn.lock().await;
+    let txn = conn
+        .begin()
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    let changes = async {
+        let mut changes = vec![];
+        for register in target_registers(&txn, payload.ids, payload.r#where).await? {
+            let id = register.id;
+            let (after, warnings) =
+                update_register(&txn, register.clone(), payload.changes.clone())
+                    .await
+                    .map_err(|error| register_error(id, error))?;
+            changes.push(BulkChange {
+                id,
+                action: BulkAction::Updated,
+                before: register,
+                after: Some(after),
+                warnings,
+            });
+        }
+        Ok(changes)
+    }
+    .await;
+
+    Ok(Json(
+        finish(txn, payload.dry_run.unwrap_or(false), changes).await?,
+    ))
+}
+
+/// Handler to delete many Modbus registers, given by ID or by a filter expression. Registers
+/// are deleted like a single delete: "NEW" and "DELETED" registers are removed, others are
+/// marked "DELETED". With `dry_run` the changes are reported and rolled back.
+pub async fn bulk_delete(
+    State(state): State<AppState>,
+    Json(payload): Json<BulkDeleteInput>,
+) -> Result<Json<BulkReport>> {
+    let conn = state.conn.lock().await;
+    let txn = conn
+        .begin()
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    let changes = async {
+        let mut changes = vec![];
+        for register in target_registers(&txn, payload.ids, payload.r#where).await? {
+            let id = register.id;
+            let after = delete_register(&txn, register.clone()).await?;
+            changes.push(BulkChange {
+                id,
+                action: match after {
+                    Some(_) => BulkAction::MarkedDeleted,
+                    None => BulkAction::Removed,
+                },
+                before: register,
+                after,
+                warnings: vec![],
+            });
+        }
+        Ok(changes)
+    }
+    .await;
+
+    Ok(Json(
+        finish(txn, payload.dry_run.unwrap_or(false), changes).await?,
+    ))
+}
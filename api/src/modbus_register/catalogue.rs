@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use sea_orm::{prelude::*, ConnectionTrait, QueryOrder, Set};
+
+use super::inputs::{
+    CreateDeviceCategoryInput, CreateManufacturerInput, UpdateDeviceCategoryInput,
+    UpdateManufacturerInput,
+};
+use crate::{
+    app_state::AppState,
+    entity::{
+        modbus_register_device_categories as categories, modbus_register_devices as devices,
+        modbus_register_manufacturers as manufacturers, prelude::*,
+    },
+    error::{Error, FieldError, Result},
+};
+
+/// The manufacturers and categories devices link to, by ID.
+#[derive(Debug, Default)]
+pub struct DeviceCatalogue {
+    pub manufacturers: HashMap<i32, manufacturers::Model>,
+    pub categories: HashMap<i32, categories::Model>,
+}
+
+impl DeviceCatalogue {
+    /// Loads every manufacturer and category.
+    pub async fn load<C: ConnectionTrait>(conn: &C) -> Result<Self> {
+        Ok(Self {
+            manufacturers: all_manufacturers(conn)
+                .await?
+                .into_iter()
+                .map(|manufacturer| (manufacturer.id, manufacturer))
+                .collect(),
+            categories: all_categories(conn)
+                .await?
+                .into_iter()
+                .map(|category| (category.id, category))
+                .collect(),
+        })
+    }
+
+    pub fn manufacturer(&self, device: &devices::Model) -> Option<&manufacturers::Model> {
+        device
+            .manufacturer_id
+            .and_then(|id| self.manufacturers.get(&id))
+    }
+
+    pub fn category(&self, device: &devices::Model) -> Option<&categories::Model> {
+        device.category_id.and_then(|id| self.categories.get(&id))
+    }
+}
+
+async fn all_manufacturers<C: ConnectionTrait>(conn: &C) -> Result<Vec<manufacturers::Model>> {
+    ModbusRegisterManufacturers::find()
+        .order_by_asc(manufacturers::Column::Name)
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))
+}
+
+async fn all_categories<C: ConnectionTrait>(conn: &C) -> Result<Vec<categories::Model>> {
+    ModbusRegisterDeviceCategories::find()
+        .order_by_asc(categories::Column::Name)
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))
+}
+
+// Check the name of a manufacturer or category: it must not be empty and, ignoring case,
+// must not be used by another one of the same kind.
+fn check_name<'a>(
+    kind: &str,
+    name: &str,
+    id: i32,
+    others: impl IntoIterator<Item = (i32, &'a str)>,
+) -> Result<()> {
+    if name.trim().is_empty() {
+        return Err(Error::Validation(vec![FieldError::new(
+            "name",
+            "must not be empty",
+        )]));
+    }
+    match others
+        .into_iter()
+        .find(|(other_id, other)| *other_id != id && other.eq_ignore_ascii_case(name.trim()))
+    {
+        Some((other_id, _)) => Err(Error::Validation(vec![FieldError::new(
+            "name",
+            format!("is already used by {} {}", kind, other_id),
+        )])),
+        None => Ok(()),
+    }
+}
+
+// Count the devices linked through the given column, deleted devices included.
+async fn linked_devices<C: ConnectionTrait>(
+    conn: &C,
+    column: devices::Column,
+    id: i32,
+) -> Result<u64> {
+    ModbusRegisterDevices::find()
+        .filter(column.eq(id))
+        .count(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))
+}
+
+/// Resolves the manufacturer of a device, given by ID or by name. Unknown names create a
+/// manufacturer, so a device synced from another library keeps its manufacturer even though
+/// the IDs differ there, and an empty name unlinks the device. Returns None when neither is
+/// given, so the link is left as it is.
+pub async fn resolve_manufacturer<C: ConnectionTrait>(
+    conn: &C,
+    id: Option<Option<i32>>,
+    name: Option<String>,
+) -> Result<Option<Option<i32>>> {
+    if let Some(name) = name {
+        let name = name.trim();
+        if name.is_empty() {
+            return Ok(Some(None));
+        }
+        let existing = all_manufacturers(conn)
+            .await?
+            .into_iter()
+            .find(|manufacturer| manufacturer.name.eq_ignore_ascii_case(name));
+        let manufacturer = match existing {
+            Some(manufacturer) => manufacturer,
+            None => manufacturers::ActiveModel {
+                name: Set(name.to_string()),
+                ..Default::default()
+            }
+            .insert(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?,
+        };
+        return Ok(Some(Some(manufacturer.id)));
+    }
+
+    if let Some(Some(id)) = id {
+        ModbusRegisterManufacturers::find_by_id(id)
+            .one(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?
+            .ok_or(Error::Validation(vec![FieldError::new(
+                "manufacturer_id",
+                format!("Manufacturer {} does not exist", id),
+            )]))?;
+    }
+    Ok(id)
+}
+
+/// Resolves the category of a device, given by ID or by name, the same way as
+/// `resolve_manufacturer`.
+pub async fn resolve_category<C: ConnectionTrait>(
+    conn: &C,
+    id: Option<Option<i32>>,
+    name: Option<String>,
+) -> Result<Option<Option<i32>>> {
+    if let Some(name) = name {
+        let name = name.trim();
+        if name.is_empty() {
+            return Ok(Some(None));
+        }
+        let existing = all_categories(conn)
+            .await?
+            .into_iter()
+            .find(|category| category.name.eq_ignore_ascii_case(name));
+        let category = match existing {
+            Some(category) => category,
+            None => categories::ActiveModel {
+                name: Set(name.to_string()),
+                ..Default::default()
+            }
+            .insert(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?,
+        };
+        return Ok(Some(Some(category.id)));
+    }
+
+    if let Some(Some(id)) = id {
+        ModbusRegisterDeviceCategories::find_by_id(id)
+            .one(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?
+            .ok_or(Error::Validation(vec![FieldError::new(
+                "category_id",
+                format!("Category {} does not exist", id),
+            )]))?;
+    }
+    Ok(id)
+}
+
+/// Handler to list the manufacturers, ordered by name.
+pub async fn list_manufacturers(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<manufacturers::Model>>> {
+    let conn = state.conn.lock().await;
+    Ok(Json(all_manufacturers(&*conn).await?))
+}
+
+/// Handler to get a manufacturer by its ID.
+pub async fn get_manufacturer(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<manufacturers::Model>> {
+    let conn = state.conn.lock().await;
+    ModbusRegisterManufacturers::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)
+        .map(Json)
+}
+
+/// Handler to create a manufacturer.
+pub async fn create_manufacturer(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateManufacturerInput>,
+) -> Result<Json<manufacturers::Model>> {
+    let conn = state.conn.lock().await;
+    let others = all_manufacturers(&*conn).await?;
+    check_name(
+        "manufacturer",
+        &payload.name,
+        0,
+        others.iter().map(|other| (other.id, other.name.as_str())),
+    )?;
+
+    let res = manufacturers::ActiveModel {
+        name: Set(payload.name.trim().to_string()),
+        description: Set(payload.description),
+        website: Set(payload.website),
+        ..Default::default()
+    }
+    .insert(&*conn)
+    .await
+    .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(res))
+}
+
+/// Handler to update a manufacturer.
+pub async fn update_manufacturer(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<UpdateManufacturerInput>,
+) -> Result<Json<manufacturers::Model>> {
+    let conn = state.conn.lock().await;
+    let existing = ModbusRegisterManufacturers::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+    let mut model = manufacturers::ActiveModel::from(existing);
+
+    if let Some(name) = payload.name {
+        let others = all_manufacturers(&*conn).await?;
+        check_name(
+            "manufacturer",
+            &name,
+            id,
+            others.iter().map(|other| (other.id, other.name.as_str())),
+        )?;
+        model.name = Set(name.trim().to_string());
+    }
+    if let Some(description) = payload.description {
+        model.description = Set(description);
+    }
+    if let Some(website) = payload.website {
+        model.website = Set(website);
+    }
+
+    let updated = model
+        .update(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(updated))
+}
+
+/// Handler to delete a manufacturer. Manufacturers that devices still link to can't be
+/// deleted.
+pub async fn delete_manufacturer(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<manufacturers::Model>> {
+    let conn = state.conn.lock().await;
+    let manufacturer = ModbusRegisterManufacturers::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+
+    let count = linked_devices(&*conn, devices::Column::ManufacturerId, id).await?;
+    if count > 0 {
+        return Err(Error::BadRequest(format!(
+            "Manufacturer \"{}\" still has {} devices",
+            manufacturer.name, count
+        )));
+    }
+
+    ModbusRegisterManufacturers::delete_by_id(id)
+        .exec(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(manufacturer))
+}
+
+/// Handler to list the device categories, ordered by name.
+pub async fn list_categories(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<categories::Model>>> {
+    let conn = state.conn.lock().await;
+    Ok(Json(all_categories(&*conn).await?))
+}
+
+/// Handler to get a device category by its ID.
+pub async fn get_category(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<categories::Model>> {
+    let conn = state.conn.lock().await;
+    ModbusRegisterDeviceCategories::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)
+        .map(Json)
+}
+
+/// Handler to create a device category.
+pub async fn create_category(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateDeviceCategoryInput>,
+) -> Result<Json<categories::Model>> {
+    let conn = state.conn.lock().await;
+    let others = all_categories(&*conn).await?;
+    check_name(
+        "category",
+        &payload.name,
+        0,
+        others.iter().map(|other| (other.id, other.name.as_str())),
+    )?;
+
+    let res = categories::ActiveModel {
+        name: Set(payload.name.trim().to_string()),
+        description: Set(payload.description),
+        ..Default::default()
+    }
+    .insert(&*conn)
+    .await
+    .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(res))
+}
+
+/// Handler to update a device category.
+pub async fn update_category(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<UpdateDeviceCategoryInput>,
+) -> Result<Json<categories::Model>> {
+    let conn = state.conn.lock().await;
+    let existing = ModbusRegisterDeviceCategories::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+    let mut model = categories::ActiveModel::from(existing);
+
+    if let Some(name) = payload.name {
+        let others = all_categories(&*conn).await?;
+        check_name(
+            "category",
+            &name,
+            id,
+            others.iter().map(|other| (other.id, other.name.as_str())),
+        )?;
+        model.name = Set(name.trim().to_string());
+    }
+    if let Some(description) = payload.description {
+        model.description = Set(description);
+    }
+
+    let updated = model
+        .update(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(updated))
+}
+
+/// Handler to delete a device category. Categories that devices still link to can't be
+/// deleted.
+pub async fn delete_category(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<categories::Model>> {
+    let conn = state.conn.lock().await;
+    let category = ModbusRegisterDeviceCategories::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+
+    let count = linked_devices(&*conn, devices::Column::CategoryId, id).await?;
+    if count > 0 {
+        return Err(Error::BadRequest(format!(
+            "Category \"{}\" still has {} devices",
+            category.name, count
+        )));
+    }
+
+    ModbusRegisterDeviceCategories::delete_by_id(id)
+        .exec(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(category))
+}
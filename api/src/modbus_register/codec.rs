@@ -0,0 +1,528 @@
+use axum::{extract::State, Json};
+use sea_orm::EntityTrait;
+use serde::{Deserialize, Serialize};
+
+use super::inputs::{DecodeInput, DecodeResponse, EncodeInput, EncodeResponse};
+use super::value_labels::{describe, labels_by_register, resolve_label};
+use crate::{
+    app_state::AppState,
+    entity::{modbus_register_value_labels as value_labels, prelude::*},
+    error::{Error, Result},
+};
+
+/// The value types a register (or a run of registers) can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataType {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Int64,
+    Float32,
+    Float64,
+    Ascii,
+    Bitfield,
+}
+
+impl DataType {
+    /// Number of 16-bit registers a single value occupies.
+    /// Text and bitfields span as many registers as the definition says, starting at one.
+    pub fn words(self) -> usize {
+        match self {
+            Self::Int8 | Self::Uint8 | Self::Int16 | Self::Uint16 => 1,
+            Self::Ascii | Self::Bitfield => 1,
+            Self::Int32 | Self::Uint32 | Self::Float32 => 2,
+            Self::Int64 | Self::Float64 => 4,
+        }
+    }
+
+    // Range of raw values an integer type can hold.
+    fn integer_range(self) -> (i64, i64) {
+        match self {
+            Self::Int8 => (i8::MIN as i64, i8::MAX as i64),
+            Self::Uint8 => (0, u8::MAX as i64),
+            Self::Int16 => (i16::MIN as i64, i16::MAX as i64),
+            Self::Uint16 => (0, u16::MAX as i64),
+            Self::Int32 => (i32::MIN as i64, i32::MAX as i64),
+            Self::Uint32 => (0, u32::MAX as i64),
+            _ => (i64::MIN, i64::MAX),
+        }
+    }
+}
+
+/// Order of the bytes of a multi-register value, named after the positions of the bytes
+/// of the big-endian value `ABCD` as they appear on the wire.
+/// `CDAB` swaps the words, `BADC` swaps the bytes within each word and `DCBA` does both.
+/// For 64-bit values the word swap reverses all four words.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ByteOrder {
+    #[default]
+    Abcd,
+    Cdab,
+    Badc,
+    Dcba,
+}
+
+impl ByteOrder {
+    fn swaps_words(self) -> bool {
+        matches!(self, Self::Cdab | Self::Dcba)
+    }
+
+    fn swaps_bytes(self) -> bool {
+        matches!(self, Self::Badc | Self::Dcba)
+    }
+}
+
+/// How the raw registers of a value are interpreted.
+/// Decoded numbers are `raw * scale + offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DataFormat {
+    pub data_type: DataType,
+    pub byte_order: ByteOrder,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+/// A decoded register value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RegisterValue {
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    Bits(Vec<bool>),
+}
+
+impl DataFormat {
+    pub fn new(data_type: DataType) -> Self {
+        Self {
+            data_type,
+            byte_order: ByteOrder::default(),
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+
+    /// Applies the engineering scaling of a register on top of the scaling of the format,
+    /// so values become `(raw * format scale + format offset) * scale + offset`.
+    pub fn scaled(self, scale: Option<f64>, offset: Option<f64>) -> Self {
+        let scale = scale.unwrap_or(1.0);
+        Self {
+            scale: self.scale * scale,
+            offset: self.offset * scale + offset.unwrap_or(0.0),
+            ..self
+        }
+    }
+
+    /// Parses a data format name.
+    ///
+    /// Accepts the names used by the register editor, such as "32 Bit Float_CDAB",
+    /// "16 Bit Signed Integer/10" or "Character String LO_HI", as well as the short form
+    /// `<type>[_<order>][/<divisor>]`, such as "uint16", "int32_cdab" or "float64_dcba/100".
+    /// `HI_LO` means the high part comes first and `LO_HI` the low part, which is the low
+    /// word for numbers and the low byte for character strings.
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase();
+
+        // A trailing divisor, e.g. "/10", scales the raw value down.
+        let (name, scale) = match name.rsplit_once('/') {
+            Some((name, divisor)) => {
+                let divisor: f64 = divisor.trim().parse().ok()?;
+                if divisor == 0.0 || !divisor.is_finite() {
+                    return None;
+                }
+                (name.trim(), 1.0 / divisor)
+            }
+            None => (name.as_str(), 1.0),
+        };
+
+        let mut byte_order = ByteOrder::default();
+        let mut low_first = false;
+        let mut name = name;
+        for (suffix, order) in [
+            ("abcd", Some(ByteOrder::Abcd)),
+            ("cdab", Some(ByteOrder::Cdab)),
+            ("badc", Some(ByteOrder::Badc)),
+            ("dcba", Some(ByteOrder::Dcba)),
+            ("hi_lo", Some(ByteOrder::Abcd)),
+            ("lo_hi", None),
+        ] {
+            if let Some(rest) = name.strip_suffix(suffix) {
+                match order {
+                    Some(order) => byte_order = order,
+                    None => low_first = true,
+                }
+                name = rest.trim_end_matches([' ', '_', '-']);
+                break;
+            }
+        }
+
+        let data_type = match name {
+            "8 bit unsigned integer" | "uint8" | "byte" => DataType::Uint8,
+            "8 bit signed integer" | "int8" => DataType::Int8,
+            "16 bit unsigned integer" | "uint16" | "word" => DataType::Uint16,
+            "16 bit signed integer" | "int16" => DataType::Int16,
+            "32 bit unsigned integer" | "uint32" => DataType::Uint32,
+            "32 bit signed integer" | "int32" => DataType::Int32,
+            "64 bit signed integer" | "int64" => DataType::Int64,
+            "32 bit float" | "floating" | "float32" | "float" | "real" => DataType::Float32,
+            "64 bit float" | "float64" | "double" => DataType::Float64,
+            "character string" | "ascii" | "string" => DataType::Ascii,
+            "bitfield" | "bits" => DataType::Bitfield,
+            _ => return None,
+        };
+
+        if low_first {
+            byte_order = if data_type == DataType::Ascii {
+                ByteOrder::Badc
+            } else {
+                ByteOrder::Cdab
+            };
+        }
+
+        Some(Self {
+            data_type,
+            byte_order,
+            scale,
+            offset: 0.0,
+        })
+    }
+
+    /// Number of registers a value of this format occupies for a register definition
+    /// of the given length.
+    pub fn words(&self, register_length: i32) -> usize {
+        match self.data_type {
+            DataType::Ascii | DataType::Bitfield => register_length.max(1) as usize,
+            data_type => data_type.words(),
+        }
+    }
+
+    fn is_scaled(&self) -> bool {
+        self.scale != 1.0 || self.offset != 0.0
+    }
+}
+
+// Lay the registers out as the bytes of a big-endian value.
+fn words_to_bytes(words: &[u16], byte_order: ByteOrder) -> Vec<u8> {
+    let mut words = words.to_vec();
+    if byte_order.swaps_words() {
+        words.reverse();
+    }
+    words
+        .iter()
+        .flat_map(|word| {
+            let [high, low] = word.to_be_bytes();
+            if byte_order.swaps_bytes() {
+                [low, high]
+            } else {
+                [high, low]
+            }
+        })
+        .collect()
+}
+
+// Inverse of `words_to_bytes`.
+fn bytes_to_words(bytes: &[u8], byte_order: ByteOrder) -> Vec<u16> {
+    let mut words: Vec<u16> = bytes
+        .chunks(2)
+        .map(|pair| {
+            let (high, low) = (pair[0], pair.get(1).copied().unwrap_or(0));
+            if byte_order.swaps_bytes() {
+                u16::from_be_bytes([low, high])
+            } else {
+                u16::from_be_bytes([high, low])
+            }
+        })
+        .collect();
+    if byte_order.swaps_words() {
+        words.reverse();
+    }
+    words
+}
+
+/// Decodes the raw registers of a value.
+/// Fixed-size types need exactly as many registers as they occupy, text and bitfields
+/// take every register given.
+pub fn decode(format: &DataFormat, words: &[u16]) -> Result<RegisterValue> {
+    let data_type = format.data_type;
+    let expected = match data_type {
+        DataType::Ascii | DataType::Bitfield => words.len().max(1),
+        data_type => data_type.words(),
+    };
+    if words.len() != expected {
+        return Err(Error::BadRequest(format!(
+            "{:?} needs {} registers, got {}",
+            data_type,
+            expected,
+            words.len()
+        )));
+    }
+
+    let bytes = words_to_bytes(words, format.byte_order);
+    let raw: i64 = match data_type {
+        DataType::Int8 => bytes[1] as i8 as i64,
+        DataType::Uint8 => bytes[1] as i64,
+        DataType::Int16 => i16::from_be_bytes([bytes[0], bytes[1]]) as i64,
+        DataType::Uint16 => u16::from_be_bytes([bytes[0], bytes[1]]) as i64,
+        DataType::Int32 => i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64,
+        DataType::Uint32 => u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64,
+        DataType::Int64 => i64::from_be_bytes(bytes[..8].try_into().unwrap_or_default()),
+        DataType::Float32 => {
+            let value = f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64;
+            return Ok(RegisterValue::Float(value * format.scale + format.offset));
+        }
+        DataType::Float64 => {
+            let value = f64::from_be_bytes(bytes[..8].try_into().unwrap_or_default());
+            return Ok(RegisterValue::Float(value * format.scale + format.offset));
+        }
+        DataType::Ascii => {
+            let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+            return Ok(RegisterValue::Text(
+                String::from_utf8_lossy(&bytes[..end]).to_string(),
+            ));
+        }
+        DataType::Bitfield => {
+            // Bit 0 is the least significant bit of the first register.
+            let bits = bytes_to_words(&bytes, ByteOrder::Abcd)
+                .iter()
+                .flat_map(|word| (0..16).map(move |bit| word & (1 << bit) != 0))
+                .collect();
+            return Ok(RegisterValue::Bits(bits));
+        }
+    };
+
+    Ok(if format.is_scaled() {
+        RegisterValue::Float(raw as f64 * format.scale + format.offset)
+    } else {
+        RegisterValue::Integer(raw)
+    })
+}
+
+/// Encodes a value into the registers to write, the inverse of `decode`.
+/// Text is padded with NUL bytes and bitfields with cleared bits up to the register length.
+pub fn encode(
+    format: &DataFormat,
+    value: &RegisterValue,
+    register_length: i32,
+) -> Result<Vec<u16>> {
+    let data_type = format.data_type;
+    let words = format.words(register_length);
+
+    match (data_type, value) {
+        (DataType::Ascii, RegisterValue::Text(text)) => {
+            if !text.is_ascii() {
+                return Err(Error::BadRequest(format!(
+                    "\"{}\" is not an ASCII string",
+                    text
+                )));
+            }
+            if text.len() > words * 2 {
+                return Err(Error::BadRequest(format!(
+                    "\"{}\" is longer than {} characters",
+                    text,
+                    words * 2
+                )));
+            }
+            let mut bytes = text.as_bytes().to_vec();
+            bytes.resize(words * 2, 0);
+            Ok(bytes_to_words(&bytes, format.byte_order))
+        }
+        (DataType::Bitfield, RegisterValue::Bits(bits)) => {
+            if bits.len() > words * 16 {
+                return Err(Error::BadRequest(format!(
+                    "{} bits don't fit in {} registers",
+                    bits.len(),
+                    words
+                )));
+            }
+            let mut raw = vec![0u16; words];
+            for (index, _) in bits.iter().enumerate().filter(|(_, bit)| **bit) {
+                raw[index / 16] |= 1 << (index % 16);
+            }
+            let bytes = words_to_bytes(&raw, ByteOrder::Abcd);
+            Ok(bytes_to_words(&bytes, format.byte_order))
+        }
+        (DataType::Bitfield, RegisterValue::Integer(mask)) => {
+            if words < 4 && (*mask < 0 || *mask >> (words * 16) != 0) {
+                return Err(Error::BadRequest(format!(
+                    "{} doesn't fit in {} registers",
+                    mask, words
+                )));
+            }
+            let bits: Vec<bool> = (0..(words * 16).min(64))
+                .map(|bit| mask >> bit & 1 == 1)
+                .collect();
+            encode(format, &RegisterValue::Bits(bits), register_length)
+        }
+        (DataType::Ascii | DataType::Bitfield, value) => Err(Error::BadRequest(format!(
+            "{:?} can't be encoded as {:?}",
+            value, data_type
+        ))),
+        (_, RegisterValue::Text(_) | RegisterValue::Bits(_)) => Err(Error::BadRequest(format!(
+            "{:?} can't be encoded as {:?}",
+            value, data_type
+        ))),
+        (DataType::Float32 | DataType::Float64, value) => {
+            let raw = (number(value) - format.offset) / format.scale;
+            let bytes = if data_type == DataType::Float32 {
+                (raw as f32).to_be_bytes().to_vec()
+            } else {
+                raw.to_be_bytes().to_vec()
+            };
+            Ok(bytes_to_words(&bytes, format.byte_order))
+        }
+        (_, value) => {
+            // Unscaled integers are taken as is so 64-bit values keep their precision.
+            let raw = match value {
+                RegisterValue::Integer(value) if !format.is_scaled() => *value,
+                value => round_raw(data_type, (number(value) - format.offset) / format.scale)?,
+            };
+            let (min, max) = data_type.integer_range();
+            if raw < min || raw > max {
+                return Err(Error::BadRequest(format!(
+                    "{} is out of range for {:?}",
+                    raw, data_type
+                )));
+            }
+
+            let bytes = match data_type {
+                DataType::Int8 | DataType::Uint8 => vec![0, raw as u8],
+                DataType::Int16 | DataType::Uint16 => (raw as u16).to_be_bytes().to_vec(),
+                DataType::Int32 | DataType::Uint32 => (raw as u32).to_be_bytes().to_vec(),
+                _ => raw.to_be_bytes().to_vec(),
+            };
+            Ok(bytes_to_words(&bytes, format.byte_order))
+        }
+    }
+}
+
+// Numeric value of an integer or float, text and bits are rejected before this is used.
+fn number(value: &RegisterValue) -> f64 {
+    match value {
+        RegisterValue::Integer(value) => *value as f64,
+        RegisterValue::Float(value) => *value,
+        _ => f64::NAN,
+    }
+}
+
+fn round_raw(data_type: DataType, raw: f64) -> Result<i64> {
+    if !raw.is_finite() || raw < i64::MIN as f64 || raw > i64::MAX as f64 {
+        return Err(Error::BadRequest(format!(
+            "{} is out of range for {:?}",
+            raw, data_type
+        )));
+    }
+    Ok(raw.round() as i64)
+}
+
+// Resolve the format and register length of a decode or encode request, starting from the
+// stored register when one is referenced and letting the request override every part of it.
+async fn resolve_format(
+    state: &AppState,
+    register_id: Option<i32>,
+    data_format: Option<String>,
+    register_length: Option<i32>,
+    scale: Option<f64>,
+    offset: Option<f64>,
+) -> Result<(DataFormat, i32, Vec<value_labels::Model>)> {
+    let (register, labels) = match register_id {
+        Some(id) => {
+            let conn = state.conn.lock().await;
+            let register = ModbusRegister::find_by_id(id)
+                .one(&*conn)
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?
+                .ok_or(Error::NotFound)?;
+            let labels = labels_by_register(&*conn, vec![id])
+                .await?
+                .remove(&id)
+                .unwrap_or_default();
+            (Some(register), labels)
+        }
+        None => (None, vec![]),
+    };
+
+    let name = data_format
+        .or_else(|| register.as_ref().and_then(|r| r.data_format.clone()))
+        .ok_or(Error::BadRequest("No data format given".to_string()))?;
+    let mut format = DataFormat::parse(&name).ok_or(Error::BadRequest(format!(
+        "\"{}\" is not a known data format",
+        name
+    )))?;
+    if let Some(register) = &register {
+        format = format.scaled(register.scale, register.offset);
+    }
+    if let Some(scale) = scale {
+        if scale == 0.0 {
+            return Err(Error::BadRequest("The scale can't be zero".to_string()));
+        }
+        format.scale = scale;
+    }
+    if let Some(offset) = offset {
+        format.offset = offset;
+    }
+    let register_length = register_length
+        .or(register.map(|r| r.register_length))
+        .unwrap_or(format.data_type.words() as i32);
+
+    Ok((format, register_length, labels))
+}
+
+/// Handler to decode raw register words into a value.
+/// With a `register_id` the value is also described with the register's enumeration
+/// values and bit fields.
+pub async fn decode_value(
+    State(state): State<AppState>,
+    Json(payload): Json<DecodeInput>,
+) -> Result<Json<DecodeResponse>> {
+    let (format, _, labels) = resolve_format(
+        &state,
+        payload.register_id,
+        payload.data_format,
+        payload.register_length,
+        payload.scale,
+        payload.offset,
+    )
+    .await?;
+    let value = decode(&format, &payload.words)?;
+    let (label, bit_fields) = describe(&value, &labels);
+
+    Ok(Json(DecodeResponse {
+        data_format: format,
+        value,
+        label,
+        bit_fields,
+    }))
+}
+
+/// Handler to encode a value into the raw register words to write.
+pub async fn encode_value(
+    State(state): State<AppState>,
+    Json(payload): Json<EncodeInput>,
+) -> Result<Json<EncodeResponse>> {
+    let (format, register_length, labels) = resolve_format(
+        &state,
+        payload.register_id,
+        payload.data_format,
+        payload.register_length,
+        payload.scale,
+        payload.offset,
+    )
+    .await?;
+    // Enumerations may be written by their label, e.g. "Cooling".
+    let value = if format.data_type == DataType::Ascii {
+        payload.value
+    } else {
+        resolve_label(payload.value, &labels)
+    };
+    let words = encode(&format, &value, register_length)?;
+
+    Ok(Json(EncodeResponse {
+        data_format: format,
+        words,
+    }))
+}
@@ -0,0 +1,384 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    Json,
+};
+use sea_orm::EntityTrait;
+
+use super::imports::import_registers;
+use super::inputs::{
+    CreateModbusRegisterItemInput, ImportMode, RegisterMapField, RegisterMapImportQueryParams,
+    RegisterMapImportResponse, RegisterMapRowError,
+};
+use crate::{
+    app_state::AppState,
+    entity::prelude::*,
+    error::{Error, Result},
+};
+
+// Header spellings recognised for each register field, compared after normalisation.
+const HEADER_SYNONYMS: &[(RegisterMapField, &[&str])] = &[
+    (
+        RegisterMapField::Address,
+        &[
+            "address",
+            "registeraddress",
+            "regaddress",
+            "addr",
+            "regaddr",
+            "modbusaddress",
+            "startaddress",
+            "register",
+        ],
+    ),
+    (
+        RegisterMapField::Length,
+        &[
+            "length",
+            "registerlength",
+            "reglength",
+            "len",
+            "size",
+            "count",
+            "quantity",
+            "words",
+            "registers",
+        ],
+    ),
+    (
+        RegisterMapField::Name,
+        &[
+            "name",
+            "registername",
+            "regname",
+            "tag",
+            "tagname",
+            "point",
+            "pointname",
+            "parameter",
+            "variable",
+        ],
+    ),
+    (
+        RegisterMapField::Format,
+        &["format", "dataformat", "datatype", "type", "valuetype"],
+    ),
+    (
+        RegisterMapField::Unit,
+        &[
+            "unit",
+            "units",
+            "uom",
+            "engineeringunit",
+            "engineeringunits",
+        ],
+    ),
+    (
+        RegisterMapField::Description,
+        &[
+            "description",
+            "desc",
+            "comment",
+            "comments",
+            "notes",
+            "remarks",
+        ],
+    ),
+    (
+        RegisterMapField::Operation,
+        &[
+            "operation",
+            "function",
+            "functioncode",
+            "fc",
+            "access",
+            "readwrite",
+            "rw",
+        ],
+    ),
+];
+
+/// Handler to import a vendor register map from a CSV or TSV upload into a device.
+///
+/// The multipart body needs a `file` field and may carry a `mapping` field with a JSON object
+/// overriding the guessed header for each register field, e.g. `{"address": "Reg", "unit": null}`.
+/// With `preview=true` the parsed rows are returned without touching the database, otherwise
+/// they go through the same pipeline as the bulk import endpoint.
+pub async fn import_register_map(
+    State(state): State<AppState>,
+    Path(device_id): Path<i32>,
+    Query(params): Query<RegisterMapImportQueryParams>,
+    mut multipart: Multipart,
+) -> Result<Json<RegisterMapImportResponse>> {
+    let mut file: Option<(String, Vec<u8>)> = None;
+    let mut overrides: BTreeMap<RegisterMapField, Option<String>> = BTreeMap::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|error| Error::BadRequest(error.to_string()))?
+    {
+        match field.name() {
+            Some("file") => {
+                let filename = field.file_name().unwrap_or_default().to_string();
+                let data = field
+                    .bytes()
+                    .await
+                    .map_err(|error| Error::BadRequest(error.to_string()))?;
+                file = Some((filename, data.to_vec()));
+            }
+            Some("mapping") => {
+                let text = field
+                    .text()
+                    .await
+                    .map_err(|error| Error::BadRequest(error.to_string()))?;
+                overrides = serde_json::from_str(&text)
+                    .map_err(|error| Error::BadRequest(format!("Invalid mapping: {}", error)))?;
+            }
+            _ => continue,
+        }
+    }
+
+    let (filename, data) = file.ok_or(Error::BadRequest("No file field found".to_string()))?;
+    let text = String::from_utf8(data)
+        .map_err(|_| Error::BadRequest("The register map must be UTF-8 encoded".to_string()))?;
+    let text = text.trim_start_matches('\u{feff}');
+
+    let delimiter = match params.delimiter.as_deref() {
+        Some("tab") | Some("\\t") | Some("\t") => b'\t',
+        Some(delimiter) if delimiter.len() == 1 => delimiter.as_bytes()[0],
+        Some(delimiter) => {
+            return Err(Error::BadRequest(format!(
+                "Unsupported delimiter: {}",
+                delimiter
+            )))
+        }
+        None => detect_delimiter(&filename, text),
+    };
+
+    let (headers, records) = read_records(text, delimiter)?;
+    let mut mapping = guess_mapping(&headers);
+    for (field, header) in overrides {
+        match header {
+            Some(header) if headers.contains(&header) => {
+                mapping.insert(field, header);
+            }
+            Some(header) => {
+                return Err(Error::BadRequest(format!(
+                    "Mapped column \"{}\" is not in the file",
+                    header
+                )))
+            }
+            None => {
+                mapping.remove(&field);
+            }
+        }
+    }
+
+    let (mut rows, errors) = parse_rows(&headers, &records, &mapping, device_id);
+    for row in rows.iter_mut() {
+        row.version_id = params.version_id;
+    }
+
+    let conn = state.conn.lock().await;
+    ModbusRegisterDevices::find_by_id(device_id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+
+    let report = if params.preview.unwrap_or(false) {
+        None
+    } else {
+        let mode = params.mode.unwrap_or_default();
+        // Rows that couldn't be parsed count as failures, so an all-or-nothing import
+        // only gets as far as a dry run.
+        let dry_run = params.dry_run.unwrap_or(false)
+            || (mode == ImportMode::AllOrNothing && !errors.is_empty());
+        Some(import_registers(&*conn, rows.clone(), mode, dry_run).await?)
+    };
+
+    Ok(Json(RegisterMapImportResponse {
+        delimiter: (delimiter as char).to_string(),
+        headers,
+        mapping,
+        rows,
+        errors,
+        report,
+    }))
+}
+
+// Pick the delimiter from the file extension, or the most frequent candidate in the header line.
+fn detect_delimiter(filename: &str, text: &str) -> u8 {
+    let filename = filename.to_lowercase();
+    if filename.ends_with(".tsv") || filename.ends_with(".tab") {
+        return b'\t';
+    }
+    let header_line = text.lines().next().unwrap_or_default();
+    // Reversed so ties, including a header without any delimiter, fall back to a comma.
+    [b',', b'\t', b';']
+        .into_iter()
+        .rev()
+        .max_by_key(|delimiter| header_line.bytes().filter(|b| b == delimiter).count())
+        .unwrap_or(b',')
+}
+
+// Read the header row and every record of the upload.
+fn read_records(text: &str, delimiter: u8) -> Result<(Vec<String>, Vec<csv::StringRecord>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+
+    let headers = reader
+        .headers()
+        .map_err(|error| Error::BadRequest(error.to_string()))?
+        .iter()
+        .map(str::to_string)
+        .collect();
+    let records = reader
+        .records()
+        .collect::<core::result::Result<Vec<_>, _>>()
+        .map_err(|error| Error::BadRequest(error.to_string()))?;
+
+    Ok((headers, records))
+}
+
+fn normalize_header(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Guesses which column holds each register field from the header names.
+/// Exact synonym matches win over headers that merely contain a synonym,
+/// and every column is used for at most one field.
+pub fn guess_mapping(headers: &[String]) -> BTreeMap<RegisterMapField, String> {
+    let normalized: Vec<String> = headers.iter().map(|h| normalize_header(h)).collect();
+    let mut mapping = BTreeMap::new();
+    let mut used = vec![false; headers.len()];
+
+    for exact in [true, false] {
+        for (field, synonyms) in HEADER_SYNONYMS {
+            if mapping.contains_key(field) {
+                continue;
+            }
+            let found = synonyms.iter().find_map(|synonym| {
+                normalized.iter().enumerate().position(|(index, header)| {
+                    !used[index]
+                        && !header.is_empty()
+                        && if exact {
+                            header == synonym
+                        } else {
+                            header.contains(synonym)
+                        }
+                })
+            });
+            if let Some(index) = found {
+                used[index] = true;
+                mapping.insert(*field, headers[index].clone());
+            }
+        }
+    }
+
+    mapping
+}
+
+// Turn the records into register inputs, collecting a reason for every row that can't be parsed.
+fn parse_rows(
+    headers: &[String],
+    records: &[csv::StringRecord],
+    mapping: &BTreeMap<RegisterMapField, String>,
+    device_id: i32,
+) -> (Vec<CreateModbusRegisterItemInput>, Vec<RegisterMapRowError>) {
+    let columns: BTreeMap<RegisterMapField, usize> = mapping
+        .iter()
+        .filter_map(|(field, header)| {
+            headers
+                .iter()
+                .position(|h| h == header)
+                .map(|index| (*field, index))
+        })
+        .collect();
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for record in records {
+        // Header is line 1.
+        let line = record.position().map(|p| p.line() as usize).unwrap_or(0);
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        let cell = |field: RegisterMapField| {
+            columns
+                .get(&field)
+                .and_then(|index| record.get(*index))
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        let register_address = match cell(RegisterMapField::Address).map(|v| parse_number(&v)) {
+            Some(Ok(address)) => Some(address),
+            Some(Err(reason)) => {
+                errors.push(RegisterMapRowError {
+                    line,
+                    reason: format!("Invalid address: {}", reason),
+                });
+                continue;
+            }
+            None => None,
+        };
+        let register_length = match cell(RegisterMapField::Length).map(|v| parse_number(&v)) {
+            Some(Ok(length)) => length,
+            Some(Err(reason)) => {
+                errors.push(RegisterMapRowError {
+                    line,
+                    reason: format!("Invalid length: {}", reason),
+                });
+                continue;
+            }
+            None => 1,
+        };
+
+        rows.push(CreateModbusRegisterItemInput {
+            id: None,
+            register_address,
+            operation: cell(RegisterMapField::Operation),
+            register_length,
+            register_name: cell(RegisterMapField::Name),
+            data_format: cell(RegisterMapField::Format),
+            description: cell(RegisterMapField::Description),
+            device_id: Some(device_id),
+            unit: cell(RegisterMapField::Unit),
+            status: None,
+            created_at: None,
+            updated_at: None,
+            private: None,
+            scale: None,
+            offset: None,
+            min_value: None,
+            max_value: None,
+            precision: None,
+            default_value: None,
+            version_id: None,
+        });
+    }
+
+    (rows, errors)
+}
+
+// Parse a decimal or 0x-prefixed hexadecimal number.
+fn parse_number(value: &str) -> core::result::Result<i32, String> {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => i32::from_str_radix(hex, 16),
+        None => value.parse::<i32>(),
+    };
+    parsed.map_err(|_| format!("\"{}\" is not a number", value))
+}
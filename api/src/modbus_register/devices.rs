@@ -1,58 +1,235 @@
-use super::inputs::{CreateDeviceInput, ModbusRegisterDevicesQueryParams, UpdateDeviceInput};
+use std::collections::HashMap;
+
+use super::catalogue::{resolve_category, resolve_manufacturer, DeviceCatalogue};
+use super::history::{self, HistoryAction, DEVICE_TABLE, REGISTER_TABLE};
+use super::inputs::{
+    CreateDeviceInput, DeleteDeviceParams, DeviceColumns, DeviceDependencies, DeviceFacets,
+    FacetCount, ModbusRegisterDevicesQueryParams, OrderByDirection, UpdateDeviceInput,
+    UpdateModbusRegisterItemInput,
+};
+use super::queries::update_register;
+use super::search::{device_search_condition, device_search_rank, match_expression};
 use crate::app_state::AppState;
 use crate::{
-    entity::{modbus_register_devices as devices, prelude::*},
-    error::{Error, Result},
+    db_connection::commit_or_rollback,
+    entity::{
+        files, modbus_register, modbus_register_device_categories as categories,
+        modbus_register_device_versions as versions, modbus_register_devices as devices,
+        modbus_register_manufacturers as manufacturers,
+        modbus_register_product_device_mapping as mappings, prelude::*,
+    },
+    error::{Error, FieldError, Result},
+    etag::{check_if_match, etag_header},
 };
 use axum::{
     extract::{Path, Query, State},
+    http::HeaderMap,
     Json,
 };
-use sea_orm::{entity::prelude::*, Set, TryIntoModel};
+use chrono::Utc;
+use sea_orm::{
+    entity::prelude::*, Condition, ConnectionTrait, JoinType, Order, QueryOrder, QuerySelect, Set,
+    TransactionTrait, TryIntoModel,
+};
+
+// Serialize a device with its image, manufacturer and category.
+fn device_json(
+    device: &devices::Model,
+    image: &Option<files::Model>,
+    catalogue: &DeviceCatalogue,
+) -> serde_json::Value {
+    let mut json: serde_json::Value = serde_json::to_value(device).unwrap_or_default();
+    json["image"] = serde_json::to_value(image).unwrap_or_default();
+    json["manufacturer"] = serde_json::to_value(catalogue.manufacturer(device)).unwrap_or_default();
+    json["category"] = serde_json::to_value(catalogue.category(device)).unwrap_or_default();
+    json
+}
+
+// Serialize a stored device the way `get_by_id` returns it, the representation whose entity tag
+// `If-Match` headers are checked against.
+async fn stored_device_json<C: ConnectionTrait>(
+    conn: &C,
+    device: &devices::Model,
+) -> Result<serde_json::Value> {
+    let image = match device.image_id {
+        Some(image_id) => Files::find_by_id(image_id)
+            .one(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?,
+        None => None,
+    };
+    let catalogue = DeviceCatalogue::load(conn).await?;
+    Ok(device_json(device, &image, &catalogue))
+}
 
-// Fetch all modbus register devices, optionally filtering based on their status.
+// Filter devices by their status and search query, and by manufacturer and category unless
+// they are being faceted on.
+fn device_filters(
+    params: &ModbusRegisterDevicesQueryParams,
+    manufacturer: bool,
+    category: bool,
+) -> Condition {
+    let mut condition = Condition::all();
+    if Some(true) == params.local_only {
+        // Filter devices with specific statuses if local_only is true.
+        condition = condition.add(devices::Column::Status.is_in(vec!["NEW", "UPDATED", "DELETED"]));
+    } else {
+        // Exclude devices with status "DELETED" if local_only is not true.
+        condition = condition.add(devices::Column::Status.not_like("DELETED"));
+    }
+    if let (true, Some(manufacturer_id)) = (manufacturer, params.manufacturer_id) {
+        condition = condition.add(link_filter(
+            devices::Column::ManufacturerId,
+            manufacturer_id,
+        ));
+    }
+    if let (true, Some(category_id)) = (category, params.category_id) {
+        condition = condition.add(link_filter(devices::Column::CategoryId, category_id));
+    }
+    if let Some(search) = params.q.as_deref().and_then(match_expression) {
+        condition = condition.add(device_search_condition(&search));
+    }
+    condition
+}
+
+// Match the devices linked to an id, or the devices without a link for `None`.
+fn link_filter(column: devices::Column, id: Option<i32>) -> Condition {
+    let condition = Condition::all();
+    match id {
+        Some(id) => condition.add(column.eq(id)),
+        None => condition.add(column.is_null()),
+    }
+}
+
+// Fetch all modbus register devices, optionally filtering based on their status, manufacturer,
+// category and a search of their names and descriptions, and sorting them.
 pub async fn get_all(
     State(state): State<AppState>,
     Query(params): Query<ModbusRegisterDevicesQueryParams>,
 ) -> Result<Json<Vec<serde_json::Value>>> {
     let conn = state.conn.lock().await;
     // Start building the query to fetch devices.
-    let mut query = ModbusRegisterDevices::find();
+    let mut query = ModbusRegisterDevices::find().filter(device_filters(&params, true, true));
 
-    // Apply filters based on query parameters.
-    if Some(true) == params.local_only {
-        // Filter devices with specific statuses if local_only is true.
-        query = query.filter(devices::Column::Status.is_in(vec!["NEW", "UPDATED", "DELETED"]));
-    } else {
-        // Exclude devices with status "DELETED" if local_only is not true.
-        query = query.filter(devices::Column::Status.not_like("DELETED"));
+    // Sort by a device column, or by the name of the manufacturer or category.
+    if let Some(order_by) = params.order_by {
+        let order: Order = params.order_dir.unwrap_or(OrderByDirection::Asc).into();
+        query = match order_by {
+            DeviceColumns::Manufacturer => query
+                .join(
+                    JoinType::LeftJoin,
+                    devices::Relation::ModbusRegisterManufacturers.def(),
+                )
+                .order_by(manufacturers::Column::Name, order.clone()),
+            DeviceColumns::Category => query
+                .join(
+                    JoinType::LeftJoin,
+                    devices::Relation::ModbusRegisterDeviceCategories.def(),
+                )
+                .order_by(categories::Column::Name, order.clone()),
+            DeviceColumns::Id => query.order_by(devices::Column::Id, order.clone()),
+            DeviceColumns::Name => query.order_by(devices::Column::Name, order.clone()),
+            DeviceColumns::Status => query.order_by(devices::Column::Status, order.clone()),
+            DeviceColumns::CreatedAt => query.order_by(devices::Column::CreatedAt, order.clone()),
+            DeviceColumns::UpdatedAt => query.order_by(devices::Column::UpdatedAt, order.clone()),
+        }
+        .order_by(devices::Column::Id, order);
+    } else if let Some(search) = params.q.as_deref().and_then(match_expression) {
+        // Without an explicit order, the most relevant matches come first.
+        query = query
+            .order_by(device_search_rank(&search), Order::Asc)
+            .order_by_asc(devices::Column::Id);
     }
 
     // Execute the query and fetch related files.
-    let results = query.find_also_related(Files).all(&*conn).await;
+    let items = query
+        .find_also_related(Files)
+        .all(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let catalogue = DeviceCatalogue::load(&*conn).await?;
 
     // Process the results and return JSON response.
-    match results {
-        Ok(items) => Ok(Json(
-            items
-                .iter()
-                .map(|item| {
-                    let mut device: serde_json::Value =
-                        serde_json::to_value(&item.0).unwrap_or_default();
-                    device["image"] = serde_json::to_value(&item.1).unwrap_or_default();
-                    device
-                })
-                .collect(),
-        )),
-        Err(error) => Err(Error::DbError(error.to_string())),
-    }
+    Ok(Json(
+        items
+            .iter()
+            .map(|item| device_json(&item.0, &item.1, &catalogue))
+            .collect(),
+    ))
+}
+
+// Count the devices per manufacturer or category in name order, with the devices without one
+// last.
+async fn facet_counts<C: ConnectionTrait, T>(
+    conn: &C,
+    condition: Condition,
+    column: devices::Column,
+    entries: &HashMap<i32, T>,
+    name: impl Fn(&T) -> String,
+) -> Result<Vec<FacetCount>> {
+    let mut facets: Vec<FacetCount> = ModbusRegisterDevices::find()
+        .select_only()
+        .column(column)
+        .column_as(devices::Column::Id.count(), "count")
+        .filter(condition)
+        .group_by(column)
+        .into_tuple::<(Option<i32>, i64)>()
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .into_iter()
+        .map(|(id, count)| FacetCount {
+            id,
+            name: id.and_then(|id| entries.get(&id)).map(&name),
+            count: count as u64,
+        })
+        .collect();
+    facets.sort_by_key(|facet| (facet.id.is_none(), facet.name.clone()));
+    Ok(facets)
+}
+
+// Count the devices per manufacturer and per category. Each count applies the other filters,
+// so it tells how many devices selecting that manufacturer or category would list. The
+// devices without a manufacturer or category are counted under a null id, which the `none`
+// filter selects.
+pub async fn facets(
+    State(state): State<AppState>,
+    Query(params): Query<ModbusRegisterDevicesQueryParams>,
+) -> Result<Json<DeviceFacets>> {
+    let conn = state.conn.lock().await;
+    let catalogue = DeviceCatalogue::load(&*conn).await?;
+    let total = ModbusRegisterDevices::find()
+        .filter(device_filters(&params, true, true))
+        .count(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(DeviceFacets {
+        total,
+        manufacturers: facet_counts(
+            &*conn,
+            device_filters(&params, false, true),
+            devices::Column::ManufacturerId,
+            &catalogue.manufacturers,
+            |manufacturer| manufacturer.name.clone(),
+        )
+        .await?,
+        categories: facet_counts(
+            &*conn,
+            device_filters(&params, true, false),
+            devices::Column::CategoryId,
+            &catalogue.categories,
+            |category| category.name.clone(),
+        )
+        .await?,
+    }))
 }
 
 // Fetch a single modbus register device by its ID, including related file data.
 pub async fn get_by_id(
     State(state): State<AppState>,
     Path(id): Path<i32>,
-) -> Result<Json<serde_json::Value>> {
+) -> Result<(HeaderMap, Json<serde_json::Value>)> {
     let conn = state.conn.lock().await;
     let result = ModbusRegisterDevices::find_by_id(id)
         .find_also_related(Files)
@@ -61,12 +238,12 @@ pub async fn get_by_id(
         .map_err(|error| Error::DbError(error.to_string()))
         .unwrap();
 
-    // Process and return the result, or handle not found error.
+    // Process and return the result tagged with its body, or handle not found error.
     match result {
         Some(item) => {
-            let mut device: serde_json::Value = serde_json::to_value(&item.0).unwrap_or_default();
-            device["image"] = serde_json::to_value(&item.1).unwrap_or_default();
-            Ok(Json(device))
+            let catalogue = DeviceCatalogue::load(&*conn).await?;
+            let json = device_json(&item.0, &item.1, &catalogue);
+            Ok((etag_header(&json), Json(json)))
         }
         None => Err(Error::NotFound),
     }
@@ -89,9 +266,8 @@ pub async fn get_by_remote_id(
     // Process and return the result, or handle not found error.
     match result {
         Some(item) => {
-            let mut device: serde_json::Value = serde_json::to_value(&item.0).unwrap_or_default();
-            device["image"] = serde_json::to_value(&item.1).unwrap_or_default();
-            Ok(Json(device))
+            let catalogue = DeviceCatalogue::load(&*conn).await?;
+            Ok(Json(device_json(&item.0, &item.1, &catalogue)))
         }
         None => Err(Error::NotFound),
     }
@@ -125,11 +301,48 @@ pub async fn create(
         model.private = Set(payload.private.unwrap());
     }
 
-    // Insert the new device into the database and return the result.
-    let res = ModbusRegisterDevices::insert(model.clone())
-        .exec_with_returning(&*conn)
+    if let Some(is_template) = payload.is_template {
+        model.is_template = Set(is_template);
+    }
+
+    // Insert the new device into the database and record it in the history.
+    let txn = conn
+        .begin()
         .await
         .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = async {
+        // Link the manufacturer and category, creating them when given by an unknown name.
+        if let Some(manufacturer_id) = resolve_manufacturer(
+            &txn,
+            payload.manufacturer_id.map(Some),
+            payload.manufacturer_name,
+        )
+        .await?
+        {
+            model.manufacturer_id = Set(manufacturer_id);
+        }
+        if let Some(category_id) =
+            resolve_category(&txn, payload.category_id.map(Some), payload.category_name).await?
+        {
+            model.category_id = Set(category_id);
+        }
+        let res = model
+            .insert(&txn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        history::record(
+            &txn,
+            DEVICE_TABLE,
+            res.id,
+            HistoryAction::Create,
+            None,
+            Some(history::snapshot(&res)),
+        )
+        .await?;
+        Ok(res)
+    }
+    .await;
+    let res = commit_or_rollback(txn, result).await?;
 
     Ok(Json(res.try_into_model().unwrap()))
 }
@@ -138,18 +351,20 @@ pub async fn create(
 pub async fn update(
     State(state): State<AppState>,
     Path(id): Path<i32>,
+    headers: HeaderMap,
     Json(payload): Json<UpdateDeviceInput>,
-) -> Result<Json<devices::Model>> {
+) -> Result<(HeaderMap, Json<devices::Model>)> {
     let conn = state.conn.lock().await;
     // Fetch the existing device and convert it to an active model.
-    let mut model = Into::<devices::ActiveModel>::into(
-        ModbusRegisterDevices::find_by_id(id)
-            .one(&*conn)
-            .await
-            .map_err(|error| Error::DbError(error.to_string()))
-            .unwrap()
-            .ok_or(Error::NotFound)?,
-    );
+    let existing = ModbusRegisterDevices::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))
+        .unwrap()
+        .ok_or(Error::NotFound)?;
+    check_if_match(&headers, &stored_device_json(&*conn, &existing).await?)?;
+    let before = history::snapshot(&existing);
+    let mut model = Into::<devices::ActiveModel>::into(existing);
 
     // Update the status if necessary.
     if None == payload.status
@@ -184,18 +399,196 @@ pub async fn update(
     if let Some(remote_id) = payload.remote_id {
         model.remote_id = Set(remote_id);
     }
+    if let Some(is_template) = payload.is_template {
+        model.is_template = Set(is_template);
+    }
+    // Clones can be detached from their template, but only cloning links a device to one.
+    if let Some(parent_id) = payload.parent_id {
+        if parent_id.is_some() {
+            return Err(Error::Validation(vec![FieldError::new(
+                "parent_id",
+                "can only be cleared, clone a template to link a device to it",
+            )]));
+        }
+        model.parent_id = Set(None);
+        model.address_offset = Set(None);
+    }
 
-    // Save the updated model to the database and return the result.
-    let updated_item = model
-        .save(&*conn)
+    // Save the updated model to the database and record the change in the history.
+    let txn = conn
+        .begin()
         .await
         .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = async {
+        if let Some(manufacturer_id) =
+            resolve_manufacturer(&txn, payload.manufacturer_id, payload.manufacturer_name).await?
+        {
+            model.manufacturer_id = Set(manufacturer_id);
+        }
+        if let Some(category_id) =
+            resolve_category(&txn, payload.category_id, payload.category_name).await?
+        {
+            model.category_id = Set(category_id);
+        }
+        let updated_item = model
+            .save(&txn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?
+            .try_into_model()
+            .unwrap();
+        history::record(
+            &txn,
+            DEVICE_TABLE,
+            id,
+            HistoryAction::Update,
+            Some(before),
+            Some(history::snapshot(&updated_item)),
+        )
+        .await?;
+        Ok(updated_item)
+    }
+    .await;
+    let updated_item = commit_or_rollback(txn, result).await?;
+    let headers = etag_header(&stored_device_json(&*conn, &updated_item).await?);
 
-    Ok(Json(updated_item.try_into_model().unwrap()))
+    Ok((headers, Json(updated_item)))
 }
 
-// Delete a modbus register device by its ID.
-pub async fn delete(State(state): State<AppState>, Path(id): Path<i32>) -> Result<Json<String>> {
+// Find what depends on a device: its registers, product mappings, firmware versions and clones.
+async fn device_dependencies<C: ConnectionTrait>(
+    conn: &C,
+    device: &devices::Model,
+) -> Result<DeviceDependencies> {
+    let registers = ModbusRegister::find()
+        .filter(modbus_register::Column::DeviceId.eq(device.id))
+        .filter(modbus_register::Column::Status.ne("DELETED"))
+        .count(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let product_ids = ModbusRegisterProductDeviceMapping::find()
+        .filter(mappings::Column::DeviceId.eq(device.id))
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .into_iter()
+        .map(|mapping| mapping.product_id)
+        .collect();
+    let versions = ModbusRegisterDeviceVersions::find()
+        .filter(versions::Column::DeviceId.eq(device.id))
+        .count(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let clone_ids = ModbusRegisterDevices::find()
+        .filter(devices::Column::ParentId.eq(device.id))
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .into_iter()
+        .map(|clone| clone.id)
+        .collect();
+
+    Ok(DeviceDependencies {
+        device_id: device.id,
+        registers,
+        product_ids,
+        versions,
+        clone_ids,
+        image_id: device.image_id,
+    })
+}
+
+// Describe the dependencies deleting a device would take along, if there are any.
+// The image stays, so it doesn't count.
+fn blocking_dependencies(dependencies: &DeviceDependencies) -> Option<String> {
+    let mut parts = vec![];
+    if dependencies.registers > 0 {
+        parts.push(format!("{} registers", dependencies.registers));
+    }
+    if !dependencies.product_ids.is_empty() {
+        parts.push(format!(
+            "{} product mappings",
+            dependencies.product_ids.len()
+        ));
+    }
+    if dependencies.versions > 0 {
+        parts.push(format!("{} firmware versions", dependencies.versions));
+    }
+    if !dependencies.clone_ids.is_empty() {
+        parts.push(format!("{} cloned devices", dependencies.clone_ids.len()));
+    }
+    (!parts.is_empty()).then(|| {
+        format!(
+            "Device {} has {}. Delete it with cascade to delete them too, or reassign its registers first",
+            dependencies.device_id,
+            parts.join(", ")
+        )
+    })
+}
+
+// Get what deleting a device would affect.
+pub async fn dependencies(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<DeviceDependencies>> {
+    let conn = state.conn.lock().await;
+    let device = ModbusRegisterDevices::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+
+    Ok(Json(device_dependencies(&*conn, &device).await?))
+}
+
+// Move the non-deleted registers of a device to another device, like updating each of them.
+// Firmware versions belong to a device, so the moved registers apply to every version.
+async fn reassign_registers<C: ConnectionTrait>(conn: &C, id: i32, target_id: i32) -> Result<()> {
+    if target_id == id {
+        return Err(Error::BadRequest(
+            "Registers can't be reassigned to the device being deleted".to_string(),
+        ));
+    }
+    let target = ModbusRegisterDevices::find_by_id(target_id)
+        .one(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    if target.is_none_or(|target| target.status == "DELETED") {
+        return Err(Error::BadRequest(format!(
+            "Device {} to reassign the registers to doesn't exist",
+            target_id
+        )));
+    }
+
+    let registers = ModbusRegister::find()
+        .filter(modbus_register::Column::DeviceId.eq(id))
+        .filter(modbus_register::Column::Status.ne("DELETED"))
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    for register in registers {
+        update_register(
+            conn,
+            register,
+            UpdateModbusRegisterItemInput {
+                device_id: Some(Some(target_id)),
+                version_id: Some(None),
+                ..Default::default()
+            },
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+// Delete a modbus register device by its ID. Devices that others depend on are only deleted
+// with `cascade` (or `force`), after `reassign_to` moved their registers to another device.
+pub async fn delete(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Query(params): Query<DeleteDeviceParams>,
+    headers: HeaderMap,
+) -> Result<Json<String>> {
     let conn = state.conn.lock().await;
     // Fetch the device to be deleted.
     let item: devices::Model = ModbusRegisterDevices::find_by_id(id)
@@ -204,22 +597,137 @@ pub async fn delete(State(state): State<AppState>, Path(id): Path<i32>) -> Resul
         .map_err(|error| Error::DbError(error.to_string()))?
         .ok_or(Error::NotFound)
         .map(Into::into)?;
+    check_if_match(&headers, &stored_device_json(&*conn, &item).await?)?;
+
+    // Check the dependencies before changing anything. Reassigned registers don't count.
+    if !params.cascade.unwrap_or(false) {
+        let mut dependencies = device_dependencies(&*conn, &item).await?;
+        if params.reassign_to.is_some() {
+            dependencies.registers = 0;
+        }
+        if let Some(message) = blocking_dependencies(&dependencies) {
+            return Err(Error::Conflict(message));
+        }
+    }
+
+    let txn = conn
+        .begin()
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = async {
+        if let Some(target_id) = params.reassign_to {
+            reassign_registers(&txn, id, target_id).await?;
+        }
+        delete_device(&txn, item).await
+    }
+    .await;
+    commit_or_rollback(txn, result).await?;
+
+    Ok(Json("Deleted successfully".to_string()))
+}
+
+/// Deletes a device and records the deletion in the history. Devices that are "NEW" or
+/// already "DELETED" are removed with their registers, others are marked "DELETED" along
+/// with their synced registers, keeping the statuses to restore. Returns the marked device,
+/// or None when it was removed.
+pub async fn delete_device<C: ConnectionTrait>(
+    conn: &C,
+    item: devices::Model,
+) -> Result<Option<devices::Model>> {
+    let id = item.id;
+    let before = history::snapshot(&item);
 
-    // If the device is "NEW" or "DELETED", remove it from the database.
+    // If the device is "NEW" or "DELETED", remove it from the database. Its registers,
+    // product mappings and versions go with it, its clones become ordinary devices.
     if item.status == "NEW" || item.status == "DELETED" {
+        // The registers are removed by the database, record them first so they can be
+        // reverted like registers deleted one by one.
+        let registers = ModbusRegister::find()
+            .filter(modbus_register::Column::DeviceId.eq(id))
+            .all(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        for register in registers {
+            history::record(
+                conn,
+                REGISTER_TABLE,
+                register.id,
+                HistoryAction::Delete,
+                Some(history::snapshot(&register)),
+                None,
+            )
+            .await?;
+        }
+        ModbusRegisterDevices::update_many()
+            .col_expr(devices::Column::ParentId, Expr::value(Option::<i32>::None))
+            .col_expr(devices::Column::UpdatedAt, Expr::value(Utc::now()))
+            .filter(devices::Column::ParentId.eq(id))
+            .exec(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
         ModbusRegisterDevices::delete_by_id(id)
-            .exec(&*conn)
+            .exec(conn)
             .await
             .map_err(|error| Error::DbError(error.to_string()))?;
-        Ok(Json("Deleted successfully".to_string()))
-    } else {
-        // Otherwise, update its status to "DELETED".
-        let mut updated_item = devices::ActiveModel::from(item.clone());
-        updated_item.status = Set("DELETED".to_string());
-        updated_item
-            .save(&*conn)
+        history::record(
+            conn,
+            DEVICE_TABLE,
+            id,
+            HistoryAction::Delete,
+            Some(before),
+            None,
+        )
+        .await?;
+        return Ok(None);
+    }
+
+    // Otherwise, update its status to "DELETED". Registers that were never synced are left
+    // as they are, they go away with the device once the deletion is synced.
+    let deleted_at = Utc::now();
+    let registers = ModbusRegister::find()
+        .filter(modbus_register::Column::DeviceId.eq(id))
+        .filter(modbus_register::Column::Status.is_not_in(["NEW", "DELETED"]))
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    for register in registers {
+        let register_before = history::snapshot(&register);
+        let mut model = modbus_register::ActiveModel::from(register.clone());
+        model.status = Set("DELETED".to_string());
+        model.previous_status = Set(Some(register.status));
+        model.deleted_at = Set(Some(deleted_at));
+        let updated = model
+            .update(conn)
             .await
             .map_err(|error| Error::DbError(error.to_string()))?;
-        Ok(Json("Deleted successfully".to_string()))
+        history::record(
+            conn,
+            REGISTER_TABLE,
+            updated.id,
+            HistoryAction::Delete,
+            Some(register_before),
+            Some(history::snapshot(&updated)),
+        )
+        .await?;
     }
+
+    let mut updated_item = devices::ActiveModel::from(item.clone());
+    updated_item.status = Set("DELETED".to_string());
+    updated_item.previous_status = Set(Some(item.status));
+    updated_item.deleted_at = Set(Some(deleted_at));
+    let updated_item = updated_item
+        .update(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    history::record(
+        conn,
+        DEVICE_TABLE,
+        id,
+        HistoryAction::Delete,
+        Some(before),
+        Some(history::snapshot(&updated_item)),
+    )
+    .await?;
+
+    Ok(Some(updated_item))
 }
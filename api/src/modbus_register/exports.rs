@@ -0,0 +1,564 @@
+use std::collections::HashMap;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::StreamExt;
+use sea_orm::{prelude::*, DatabaseConnection, QueryOrder, QuerySelect, SelectTwo};
+
+use super::catalogue::DeviceCatalogue;
+use super::filter_expression::parse_filter;
+use super::inputs::{
+    ExportDevice, ExportDocument, ExportFormat, ExportImage, ExportQueryParams, ExportRegister,
+    ExportValueLabel,
+};
+use super::queries::generate_filter_query;
+use super::search::{match_expression, search_condition};
+use super::value_labels::labels_by_register;
+use super::versions::requested_versions;
+use crate::{
+    app_state::AppState,
+    entity::{
+        files, modbus_register, modbus_register_devices as devices,
+        modbus_register_value_labels as value_labels, prelude::*,
+    },
+    error::{Error, Result},
+    utils::SPA_DIR,
+};
+
+/// Identifies documents produced by the JSON export.
+pub const EXPORT_SCHEMA: &str = "t3-register-map";
+/// Bumped whenever a field of the JSON export is renamed or removed.
+pub const EXPORT_SCHEMA_VERSION: u32 = 1;
+
+// Column headers of the CSV export, spelled so the CSV import maps them back automatically.
+const CSV_HEADERS: [&str; 17] = [
+    "Id",
+    "Device",
+    "Register Address",
+    "Register Length",
+    "Register Name",
+    "Data Format",
+    "Unit",
+    "Scale",
+    "Offset",
+    "Min",
+    "Max",
+    "Precision",
+    "Default",
+    "Operation",
+    "Description",
+    "Status",
+    "Labels",
+];
+
+// Registers read per query while streaming the CSV export, so the database is only locked
+// for short moments and large register maps never sit in memory at once.
+const CSV_PAGE_SIZE: u64 = 500;
+
+type RegisterQuery = SelectTwo<ModbusRegister, ModbusRegisterDevices>;
+
+/// Handler to export the register map of a single device.
+pub async fn export_device(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Query(params): Query<ExportQueryParams>,
+) -> Result<Response> {
+    if params.device_id.is_some_and(|device_id| device_id != id) {
+        return Err(Error::BadRequest(
+            "device_id doesn't match the exported device".to_string(),
+        ));
+    }
+    let (device, query) = {
+        let conn = state.conn.lock().await;
+        let device = ModbusRegisterDevices::find_by_id(id)
+            .one(&*conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?
+            .ok_or(Error::NotFound)?;
+        let version_ids = requested_versions(
+            &*conn,
+            Some(id),
+            params.firmware.as_deref(),
+            params.version_id,
+        )
+        .await?;
+        (device, export_query(&params, Some(id), version_ids)?)
+    };
+
+    let name = device.name.clone();
+    export(
+        &state,
+        params.format.unwrap_or_default(),
+        &name,
+        query,
+        Some(device),
+    )
+    .await
+}
+
+/// Handler to export every register matching the same filters as the register list.
+pub async fn export_registers(
+    State(state): State<AppState>,
+    Query(params): Query<ExportQueryParams>,
+) -> Result<Response> {
+    let query = {
+        let conn = state.conn.lock().await;
+        let version_ids = requested_versions(
+            &*conn,
+            params.device_id,
+            params.firmware.as_deref(),
+            params.version_id,
+        )
+        .await?;
+        export_query(&params, params.device_id, version_ids)?
+    };
+
+    export(
+        &state,
+        params.format.unwrap_or_default(),
+        "modbus-registers",
+        query,
+        None,
+    )
+    .await
+}
+
+// Build the query of the exported registers from the same filters as the register list, in
+// device and address order. Searches and filter expressions only restrict the registers,
+// they don't change the order.
+fn export_query(
+    params: &ExportQueryParams,
+    device_id: Option<i32>,
+    version_ids: Option<Vec<i32>>,
+) -> Result<RegisterQuery> {
+    let mut query = generate_filter_query(
+        &params.filter,
+        &device_id,
+        params.local_only.unwrap_or(false),
+        version_ids,
+    );
+    if let Some(search) = params.q.as_deref().and_then(match_expression) {
+        query = query.filter(search_condition(&search));
+    }
+    if let Some(condition) = params.r#where.as_deref().map(parse_filter).transpose()? {
+        query = query.filter(condition);
+    }
+    Ok(query
+        .order_by_asc(modbus_register::Column::DeviceId)
+        .order_by_asc(modbus_register::Column::RegisterAddress)
+        .order_by_asc(modbus_register::Column::Id))
+}
+
+// Build the download response for the requested format. The given device gets a JSON export
+// and a datasheet even when none of its registers match.
+async fn export(
+    state: &AppState,
+    format: ExportFormat,
+    name: &str,
+    query: RegisterQuery,
+    device: Option<devices::Model>,
+) -> Result<Response> {
+    let filename: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+
+    let (content_type, disposition, extension, body) = match format {
+        ExportFormat::Csv => (
+            "text/csv; charset=utf-8",
+            "attachment",
+            "csv",
+            Body::from_stream(csv_stream(state.clone(), query)),
+        ),
+        ExportFormat::Json => (
+            "application/json",
+            "attachment",
+            "json",
+            Body::from(
+                serde_json::to_string_pretty(&ExportDocument {
+                    schema: EXPORT_SCHEMA.to_string(),
+                    version: EXPORT_SCHEMA_VERSION,
+                    exported_at: chrono::Utc::now(),
+                    devices: load_export_devices(state, query, device).await?,
+                })
+                .unwrap_or_default(),
+            ),
+        ),
+        ExportFormat::Html => (
+            "text/html; charset=utf-8",
+            "inline",
+            "html",
+            Body::from(datasheet(&load_export_devices(state, query, device).await?)),
+        ),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("{}; filename=\"{}.{}\"", disposition, filename, extension),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+// Fetch the exported registers grouped by device.
+async fn load_export_devices(
+    state: &AppState,
+    query: RegisterQuery,
+    device: Option<devices::Model>,
+) -> Result<Vec<ExportDevice>> {
+    let conn = state.conn.lock().await;
+    let items = query
+        .all(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    let mut labels = labels_by_register(
+        &*conn,
+        items.iter().map(|(register, _)| register.id).collect(),
+    )
+    .await?;
+
+    let catalogue = DeviceCatalogue::load(&*conn).await?;
+
+    let mut devices: Vec<ExportDevice> = vec![];
+    for (register, device) in items {
+        let device_id = device.as_ref().map(|device| device.id);
+        if devices.last().map(|last| last.id) != Some(device_id) {
+            devices.push(export_device_header(device.as_ref(), &catalogue));
+        }
+        if let Some(last) = devices.last_mut() {
+            let register_labels = labels.remove(&register.id).unwrap_or_default();
+            last.registers
+                .push(export_register(register, register_labels));
+        }
+    }
+    // Devices without registers still get a datasheet.
+    if devices.is_empty() {
+        if let Some(device) = device {
+            devices.push(export_device_header(Some(&device), &catalogue));
+        }
+    }
+
+    attach_images(&conn, &mut devices).await?;
+    Ok(devices)
+}
+
+fn export_device_header(
+    device: Option<&devices::Model>,
+    catalogue: &DeviceCatalogue,
+) -> ExportDevice {
+    ExportDevice {
+        id: device.map(|device| device.id),
+        name: device.map(|device| device.name.clone()),
+        description: device.and_then(|device| device.description.clone()),
+        manufacturer: device
+            .and_then(|device| catalogue.manufacturer(device))
+            .map(|manufacturer| manufacturer.name.clone()),
+        category: device
+            .and_then(|device| catalogue.category(device))
+            .map(|category| category.name.clone()),
+        image: None,
+        registers: vec![],
+    }
+}
+
+fn export_register(
+    register: modbus_register::Model,
+    labels: Vec<value_labels::Model>,
+) -> ExportRegister {
+    ExportRegister {
+        id: register.id,
+        register_address: register.register_address,
+        register_length: register.register_length,
+        register_name: register.register_name,
+        data_format: register.data_format,
+        unit: register.unit,
+        scale: register.scale,
+        offset: register.offset,
+        min_value: register.min_value,
+        max_value: register.max_value,
+        precision: register.precision,
+        default_value: register.default_value,
+        operation: register.operation,
+        description: register.description,
+        status: register.status,
+        labels: labels
+            .into_iter()
+            .map(|label| ExportValueLabel {
+                kind: label.kind,
+                value: label.value,
+                bit_length: label.bit_length,
+                label: label.label,
+                description: label.description,
+            })
+            .collect(),
+    }
+}
+
+// Summarize the labels of a register, e.g. "0=Off; 2=Cooling; bit 0=Alarm; bits 4-7=Mode".
+fn labels_summary(labels: &[ExportValueLabel]) -> String {
+    labels
+        .iter()
+        .map(
+            |label| match (label.kind.as_str(), label.bit_length.unwrap_or(1)) {
+                ("bits", 1) => format!("bit {}={}", label.value, label.label),
+                ("bits", length) => format!(
+                    "bits {}-{}={}",
+                    label.value,
+                    label.value + length as i64 - 1,
+                    label.label
+                ),
+                _ => format!("{}={}", label.value, label.label),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// Look up the image of every exported device.
+async fn attach_images(conn: &DatabaseConnection, export: &mut [ExportDevice]) -> Result<()> {
+    let ids: Vec<i32> = export.iter().filter_map(|device| device.id).collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let images: HashMap<i32, files::Model> = ModbusRegisterDevices::find()
+        .filter(devices::Column::Id.is_in(ids))
+        .find_also_related(Files)
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .into_iter()
+        .filter_map(|(device, image)| image.map(|image| (device.id, image)))
+        .collect();
+
+    for device in export.iter_mut() {
+        if let Some(image) = device.id.and_then(|id| images.get(&id)) {
+            device.image = Some(ExportImage {
+                name: image.name.clone(),
+                mime_type: image.mime_type.clone(),
+                path: image.path.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Stream the CSV export a page of registers at a time. The database isn't locked between
+// pages, so registers changed during a long download may be missed or repeated.
+fn csv_stream(state: AppState, query: RegisterQuery) -> impl futures::Stream<Item = Result<Bytes>> {
+    let header = csv_line(CSV_HEADERS.iter().map(|h| h.to_string()));
+    let pages = futures::stream::try_unfold(Some(0), move |offset| {
+        let state = state.clone();
+        let query = query.clone();
+        async move {
+            let Some(offset) = offset else {
+                return Ok(None);
+            };
+            let conn = state.conn.lock().await;
+            let items = query
+                .offset(offset)
+                .limit(CSV_PAGE_SIZE)
+                .all(&*conn)
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?;
+            let mut labels = labels_by_register(
+                &*conn,
+                items.iter().map(|(register, _)| register.id).collect(),
+            )
+            .await?;
+
+            let next = (items.len() as u64 == CSV_PAGE_SIZE).then_some(offset + CSV_PAGE_SIZE);
+            let mut page = vec![];
+            for (register, device) in items {
+                let register_labels = labels.remove(&register.id).unwrap_or_default();
+                let name = device.map(|device| device.name).unwrap_or_default();
+                page.extend_from_slice(&csv_row(&name, export_register(register, register_labels)));
+            }
+            Ok(Some((Bytes::from(page), next)))
+        }
+    });
+
+    futures::stream::once(async { Ok(header) }).chain(pages)
+}
+
+fn csv_row(device: &str, register: ExportRegister) -> Bytes {
+    csv_line([
+        register.id.to_string(),
+        text_cell(device.to_string()),
+        optional(register.register_address),
+        register.register_length.to_string(),
+        text_cell(register.register_name.unwrap_or_default()),
+        text_cell(register.data_format.unwrap_or_default()),
+        text_cell(register.unit.unwrap_or_default()),
+        optional(register.scale),
+        optional(register.offset),
+        optional(register.min_value),
+        optional(register.max_value),
+        optional(register.precision),
+        optional(register.default_value),
+        text_cell(register.operation.unwrap_or_default()),
+        text_cell(register.description.unwrap_or_default()),
+        text_cell(register.status),
+        text_cell(labels_summary(&register.labels)),
+    ])
+}
+
+// Keep spreadsheets from running text as a formula, by quoting text that starts like one.
+// Numeric cells are left alone, negative numbers have to stay numbers.
+fn text_cell(value: String) -> String {
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value
+    }
+}
+
+fn csv_line(fields: impl IntoIterator<Item = String>) -> Bytes {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(fields).ok();
+    Bytes::from(writer.into_inner().unwrap_or_default())
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+pub fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// Read an uploaded image and inline it so the datasheet doesn't depend on the server.
+fn image_data_uri(image: &ExportImage) -> Option<String> {
+    let data = std::fs::read(format!("{}{}", SPA_DIR.as_str(), image.path)).ok()?;
+    Some(format!(
+        "data:{};base64,{}",
+        image.mime_type,
+        STANDARD.encode(data)
+    ))
+}
+
+// The description of a register, followed by its enumeration values and bit fields.
+fn description_cell(register: &ExportRegister) -> String {
+    let mut cell = escape_html(register.description.as_deref().unwrap_or_default());
+    if !register.labels.is_empty() {
+        cell.push_str("<ul class=\"labels\">");
+        for label in &register.labels {
+            cell.push_str(&format!(
+                "<li>{}</li>",
+                escape_html(&labels_summary(std::slice::from_ref(label)))
+            ));
+        }
+        cell.push_str("</ul>");
+    }
+    cell
+}
+
+/// Renders a self-contained, printable HTML datasheet for the given devices.
+pub fn datasheet(devices: &[ExportDevice]) -> String {
+    let mut html = String::from(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Modbus register datasheet</title>
+<style>
+  body { font-family: Arial, Helvetica, sans-serif; margin: 24px; color: #222; }
+  section { page-break-after: always; }
+  section:last-child { page-break-after: auto; }
+  header { display: flex; gap: 24px; align-items: flex-start; margin-bottom: 16px; }
+  header img { max-width: 180px; max-height: 180px; object-fit: contain; }
+  h1 { margin: 0 0 8px 0; font-size: 22px; }
+  p.description { margin: 0; white-space: pre-line; }
+  p.catalogue { margin: 0 0 8px 0; color: #555; }
+  table { border-collapse: collapse; width: 100%; font-size: 12px; }
+  th, td { border: 1px solid #999; padding: 4px 6px; text-align: left; vertical-align: top; }
+  th { background: #eee; }
+  tr { page-break-inside: avoid; }
+  td.number { text-align: right; }
+  ul.labels { margin: 4px 0 0 0; padding-left: 16px; }
+  footer { margin-top: 8px; font-size: 10px; color: #666; }
+</style>
+</head>
+<body>
+"#,
+    );
+
+    for device in devices {
+        let name = device.name.as_deref().unwrap_or("Unassigned registers");
+        html.push_str("<section>\n<header>\n");
+        if let Some(uri) = device.image.as_ref().and_then(image_data_uri) {
+            html.push_str(&format!(
+                "<img src=\"{}\" alt=\"{}\">\n",
+                uri,
+                escape_html(name)
+            ));
+        }
+        html.push_str(&format!("<div>\n<h1>{}</h1>\n", escape_html(name)));
+        let catalogue: Vec<&str> = [&device.manufacturer, &device.category]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        if !catalogue.is_empty() {
+            html.push_str(&format!(
+                "<p class=\"catalogue\">{}</p>\n",
+                escape_html(&catalogue.join(" · "))
+            ));
+        }
+        if let Some(description) = &device.description {
+            html.push_str(&format!(
+                "<p class=\"description\">{}</p>\n",
+                escape_html(description)
+            ));
+        }
+        html.push_str("</div>\n</header>\n<table>\n<thead>\n<tr>");
+        for heading in [
+            "Address",
+            "Length",
+            "Name",
+            "Format",
+            "Unit",
+            "Operation",
+            "Description",
+        ] {
+            html.push_str(&format!("<th>{}</th>", heading));
+        }
+        html.push_str("</tr>\n</thead>\n<tbody>\n");
+        for register in &device.registers {
+            html.push_str(&format!(
+                "<tr><td class=\"number\">{}</td><td class=\"number\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                optional(register.register_address),
+                register.register_length,
+                escape_html(register.register_name.as_deref().unwrap_or_default()),
+                escape_html(register.data_format.as_deref().unwrap_or_default()),
+                escape_html(register.unit.as_deref().unwrap_or_default()),
+                escape_html(register.operation.as_deref().unwrap_or_default()),
+                description_cell(register),
+            ));
+        }
+        html.push_str(&format!(
+            "</tbody>\n</table>\n<footer>{} registers, generated {}</footer>\n</section>\n",
+            device.registers.len(),
+            chrono::Utc::now().format("%Y-%m-%d %H:%M UTC")
+        ));
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use sea_orm::{prelude::*, Condition, QuerySelect};
+
+use super::inputs::{FacetCount, ModbusRegisterQueryParams, RegisterFacets, ValueCount};
+use super::queries::list_condition;
+use crate::{
+    app_state::AppState,
+    entity::modbus_register::{self, Entity as ModbusRegister},
+    entity::modbus_register_devices::{self, Entity as ModbusRegisterDevices},
+    error::{Error, Result},
+};
+
+// Count the matching registers per value of a column, most common values first.
+async fn value_counts<C, T>(
+    conn: &C,
+    condition: &Condition,
+    column: modbus_register::Column,
+) -> Result<Vec<(Option<T>, u64)>>
+where
+    C: ConnectionTrait,
+    T: sea_orm::TryGetable + Ord,
+{
+    let mut counts: Vec<(Option<T>, u64)> = ModbusRegister::find()
+        .select_only()
+        .column(column)
+        .column_as(modbus_register::Column::Id.count(), "count")
+        .filter(condition.clone())
+        .group_by(column)
+        .into_tuple::<(Option<T>, i64)>()
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .into_iter()
+        .map(|(value, count)| (value, count as u64))
+        .collect();
+    counts.sort_by(|(a, a_count), (b, b_count)| {
+        b_count
+            .cmp(a_count)
+            .then(a.is_none().cmp(&b.is_none()))
+            .then(a.cmp(b))
+    });
+    Ok(counts)
+}
+
+async fn text_counts<C: ConnectionTrait>(
+    conn: &C,
+    condition: &Condition,
+    column: modbus_register::Column,
+) -> Result<Vec<ValueCount>> {
+    Ok(value_counts::<C, String>(conn, condition, column)
+        .await?
+        .into_iter()
+        .map(|(value, count)| ValueCount { value, count })
+        .collect())
+}
+
+/// Handler to count the registers matching the register list's filters per data format,
+/// unit, operation, status and device, to show next to the filter options.
+pub async fn facets(
+    State(state): State<AppState>,
+    Query(params): Query<ModbusRegisterQueryParams>,
+) -> Result<Json<RegisterFacets>> {
+    let conn = state.conn.lock().await;
+    let (condition, _) = list_condition(&*conn, &params).await?;
+
+    let total = ModbusRegister::find()
+        .filter(condition.clone())
+        .count(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    // Name the devices of the counts.
+    let device_counts =
+        value_counts::<_, i32>(&*conn, &condition, modbus_register::Column::DeviceId).await?;
+    let names: HashMap<i32, String> = ModbusRegisterDevices::find()
+        .filter(
+            modbus_register_devices::Column::Id
+                .is_in(device_counts.iter().filter_map(|(id, _)| *id)),
+        )
+        .all(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .into_iter()
+        .map(|device| (device.id, device.name))
+        .collect();
+
+    Ok(Json(RegisterFacets {
+        total,
+        data_formats: text_counts(&*conn, &condition, modbus_register::Column::DataFormat).await?,
+        units: text_counts(&*conn, &condition, modbus_register::Column::Unit).await?,
+        operations: text_counts(&*conn, &condition, modbus_register::Column::Operation).await?,
+        statuses: text_counts(&*conn, &condition, modbus_register::Column::Status).await?,
+        devices: device_counts
+            .into_iter()
+            .map(|(id, count)| FacetCount {
+                id,
+                name: id.and_then(|id| names.get(&id).cloned()),
+                count,
+            })
+            .collect(),
+    }))
+}
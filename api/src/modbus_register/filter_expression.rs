@@ -0,0 +1,444 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use sea_orm::{prelude::*, Condition};
+
+use crate::{
+    entity::modbus_register,
+    error::{Error, Result},
+};
+
+// How the values a field is compared with are parsed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldType {
+    Integer,
+    Float,
+    Text,
+    Boolean,
+    Timestamp,
+}
+
+// The register fields filters can name.
+fn field(name: &str) -> Option<(modbus_register::Column, FieldType)> {
+    use modbus_register::Column;
+    Some(match name {
+        "id" => (Column::Id, FieldType::Integer),
+        "register_address" => (Column::RegisterAddress, FieldType::Integer),
+        "register_length" => (Column::RegisterLength, FieldType::Integer),
+        "register_name" => (Column::RegisterName, FieldType::Text),
+        "operation" => (Column::Operation, FieldType::Text),
+        "data_format" => (Column::DataFormat, FieldType::Text),
+        "description" => (Column::Description, FieldType::Text),
+        "device_id" => (Column::DeviceId, FieldType::Integer),
+        "status" => (Column::Status, FieldType::Text),
+        "unit" => (Column::Unit, FieldType::Text),
+        "private" => (Column::Private, FieldType::Boolean),
+        "scale" => (Column::Scale, FieldType::Float),
+        "offset" => (Column::Offset, FieldType::Float),
+        "min_value" => (Column::MinValue, FieldType::Float),
+        "max_value" => (Column::MaxValue, FieldType::Float),
+        "precision" => (Column::Precision, FieldType::Integer),
+        "default_value" => (Column::DefaultValue, FieldType::Float),
+        "parent_register_id" => (Column::ParentRegisterId, FieldType::Integer),
+        "version_id" => (Column::VersionId, FieldType::Integer),
+        "created_at" => (Column::CreatedAt, FieldType::Timestamp),
+        "updated_at" => (Column::UpdatedAt, FieldType::Timestamp),
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Operator(&'static str),
+    Open,
+    Close,
+    Comma,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => format!("\"{}\"", word),
+            Token::Quoted(text) => format!("'{}'", text),
+            Token::Operator(operator) => format!("\"{}\"", operator),
+            Token::Open => "\"(\"".to_string(),
+            Token::Close => "\")\"".to_string(),
+            Token::Comma => "\",\"".to_string(),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+}
+
+// Comparison operators, longest first so ">=" isn't read as ">".
+const OPERATORS: [&str; 8] = [">=", "<=", "!=", "<>", "=", ">", "<", "~"];
+
+// How deep parentheses and NOTs may nest, the parser recurses once per level.
+const MAX_DEPTH: usize = 64;
+
+fn invalid(position: usize, message: impl Into<String>) -> Error {
+    Error::BadRequest(format!(
+        "Invalid filter at position {}: {}",
+        position + 1,
+        message.into()
+    ))
+}
+
+// Split an expression into tokens and the character positions they start at.
+fn tokenize(expression: &str) -> Result<Vec<(usize, Token)>> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        match c {
+            '(' => {
+                tokens.push((start, Token::Open));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((start, Token::Close));
+                i += 1;
+            }
+            ',' => {
+                tokens.push((start, Token::Comma));
+                i += 1;
+            }
+            '\'' | '"' => {
+                // Quotes are escaped by doubling them.
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(invalid(start, "unterminated quoted value")),
+                        Some(&quote) if quote == c && chars.get(i + 1) == Some(&c) => {
+                            text.push(c);
+                            i += 2;
+                        }
+                        Some(&quote) if quote == c => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&other) => {
+                            text.push(other);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push((start, Token::Quoted(text)));
+            }
+            _ => {
+                let rest: String = chars[i..].iter().take(2).collect();
+                if let Some(operator) = OPERATORS
+                    .iter()
+                    .find(|operator| rest.starts_with(**operator))
+                {
+                    tokens.push((start, Token::Operator(operator)));
+                    i += operator.chars().count();
+                    continue;
+                }
+                if c == '!' {
+                    return Err(invalid(start, "unexpected \"!\""));
+                }
+
+                let mut word = String::new();
+                while let Some(&c) = chars.get(i) {
+                    if c.is_whitespace()
+                        || "(),'\"".contains(c)
+                        || OPERATORS.iter().any(|operator| operator.starts_with(c))
+                        || c == '!'
+                    {
+                        break;
+                    }
+                    word.push(c);
+                    i += 1;
+                }
+                tokens.push((start, Token::Word(word)));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// Read a timestamp as UTC, timestamps without a time zone are in UTC. Dates stand for midnight.
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Some(timestamp.with_timezone(&Utc));
+    }
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default())
+        })
+        .ok()
+        .map(|timestamp| timestamp.and_utc())
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    end: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.index)
+            .map_or(self.end, |(position, _)| *position)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<(usize, Token)> {
+        let position = self.position();
+        let token = self
+            .tokens
+            .get(self.index)
+            .cloned()
+            .ok_or(invalid(position, format!("expected {}", expected)))?;
+        self.index += 1;
+        Ok(token)
+    }
+
+    fn next_keyword(&mut self, keyword: &str) -> bool {
+        let matched = self.peek().is_some_and(|token| token.is_keyword(keyword));
+        if matched {
+            self.index += 1;
+        }
+        matched
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let (position, token) = self.next(&expected.describe())?;
+        if token != expected {
+            return Err(invalid(
+                position,
+                format!(
+                    "expected {}, found {}",
+                    expected.describe(),
+                    token.describe()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    // or := and (OR and)*
+    fn or(&mut self) -> Result<Condition> {
+        let mut condition = Condition::any().add(self.and()?);
+        while self.next_keyword("or") {
+            condition = condition.add(self.and()?);
+        }
+        Ok(condition)
+    }
+
+    // and := unary (AND unary)*
+    fn and(&mut self) -> Result<Condition> {
+        let mut condition = Condition::all().add(self.unary()?);
+        while self.next_keyword("and") {
+            condition = condition.add(self.unary()?);
+        }
+        Ok(condition)
+    }
+
+    // unary := NOT unary | "(" or ")" | comparison
+    fn unary(&mut self) -> Result<Condition> {
+        if self.peek().is_some_and(|token| token.is_keyword("not")) {
+            return self.nested(|parser| {
+                parser.index += 1;
+                Ok(parser.unary()?.not())
+            });
+        }
+        if self.peek() == Some(&Token::Open) {
+            return self.nested(|parser| {
+                parser.index += 1;
+                let condition = parser.or()?;
+                parser.expect(Token::Close)?;
+                Ok(condition)
+            });
+        }
+        self.comparison()
+    }
+
+    // Parse one more level of nesting, unless the expression is nested too deep already.
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<Condition>) -> Result<Condition> {
+        if self.depth >= MAX_DEPTH {
+            return Err(invalid(
+                self.position(),
+                format!("nested deeper than {} levels", MAX_DEPTH),
+            ));
+        }
+        self.depth += 1;
+        let condition = parse(self);
+        self.depth -= 1;
+        condition
+    }
+
+    fn value(&mut self, field_type: FieldType) -> Result<Value> {
+        let (position, token) = self.next("a value")?;
+        let text = match &token {
+            Token::Word(word) if word.eq_ignore_ascii_case("null") => {
+                return Err(invalid(
+                    position,
+                    "use IS NULL or IS NOT NULL to match null",
+                ));
+            }
+            Token::Word(text) | Token::Quoted(text) => text.clone(),
+            _ => {
+                return Err(invalid(
+                    position,
+                    format!("expected a value, found {}", token.describe()),
+                ))
+            }
+        };
+        let parsed = match field_type {
+            FieldType::Integer => text.parse::<i64>().ok().map(Value::from),
+            FieldType::Float => text.parse::<f64>().ok().map(Value::from),
+            FieldType::Text => Some(Value::from(text.clone())),
+            FieldType::Boolean => match text.to_lowercase().as_str() {
+                "true" | "1" => Some(Value::from(true)),
+                "false" | "0" => Some(Value::from(false)),
+                _ => None,
+            },
+            FieldType::Timestamp => parse_timestamp(&text).map(Value::from),
+        };
+        parsed.ok_or(invalid(
+            position,
+            format!(
+                "{} is not {}",
+                token.describe(),
+                match field_type {
+                    FieldType::Integer => "an integer",
+                    FieldType::Float => "a number",
+                    FieldType::Text => "text",
+                    FieldType::Boolean => "true or false",
+                    FieldType::Timestamp => "a date or timestamp",
+                }
+            ),
+        ))
+    }
+
+    // A parenthesized, comma separated list of values.
+    fn values(&mut self, field_type: FieldType) -> Result<Vec<Value>> {
+        self.expect(Token::Open)?;
+        let mut values = vec![self.value(field_type)?];
+        loop {
+            let (position, token) = self.next("\",\" or \")\"")?;
+            match token {
+                Token::Comma => values.push(self.value(field_type)?),
+                Token::Close => return Ok(values),
+                token => {
+                    return Err(invalid(
+                        position,
+                        format!("expected \",\" or \")\", found {}", token.describe()),
+                    ))
+                }
+            }
+        }
+    }
+
+    // comparison := field operator value | field [NOT] IN (values) | field IS [NOT] NULL
+    fn comparison(&mut self) -> Result<Condition> {
+        let (position, token) = self.next("a field")?;
+        let Token::Word(name) = token else {
+            return Err(invalid(
+                position,
+                format!("expected a field, found {}", token.describe()),
+            ));
+        };
+        let (column, field_type) = field(&name.to_lowercase())
+            .ok_or(invalid(position, format!("unknown field \"{}\"", name)))?;
+
+        if self.next_keyword("is") {
+            let negated = self.next_keyword("not");
+            if !self.next_keyword("null") {
+                return Err(invalid(self.position(), "expected NULL"));
+            }
+            return Ok(Condition::all().add(if negated {
+                column.is_not_null()
+            } else {
+                column.is_null()
+            }));
+        }
+        let negated = self.next_keyword("not");
+        if self.next_keyword("in") {
+            let values = self.values(field_type)?;
+            return Ok(Condition::all().add(if negated {
+                column.is_not_in(values)
+            } else {
+                column.is_in(values)
+            }));
+        }
+        if negated {
+            return Err(invalid(self.position(), "expected IN"));
+        }
+
+        let (position, token) = self.next("an operator")?;
+        let Token::Operator(operator) = token else {
+            return Err(invalid(
+                position,
+                format!("expected an operator, found {}", token.describe()),
+            ));
+        };
+        if operator == "~" && field_type != FieldType::Text {
+            return Err(invalid(
+                position,
+                format!("\"~\" only applies to text fields, not \"{}\"", name),
+            ));
+        }
+        let value = self.value(field_type)?;
+        Ok(Condition::all().add(match operator {
+            "=" => column.eq(value),
+            "!=" | "<>" => column.ne(value),
+            ">" => column.gt(value),
+            ">=" => column.gte(value),
+            "<" => column.lt(value),
+            "<=" => column.lte(value),
+            _ => match value {
+                Value::String(Some(text)) => column.contains(text.as_str()),
+                _ => unreachable!("\"~\" values are text"),
+            },
+        }))
+    }
+}
+
+/// Parses a filter expression over register fields into a condition.
+///
+/// Comparisons are `field op value` with the operators `=`, `!=`, `<>`, `>`, `>=`, `<`,
+/// `<=` and `~` (contains, for text fields), `field IN (a, b)`, `field NOT IN (a, b)`,
+/// `field IS NULL` and `field IS NOT NULL`. They combine with `AND`, `OR`, `NOT` and
+/// parentheses, `AND` binding tighter than `OR`. Values with spaces or symbols are quoted
+/// with single or double quotes. Timestamps are compared in UTC and dates stand for
+/// midnight, e.g. `status IN (NEW, UPDATED) AND created_at >= 2024-05-01`.
+pub fn parse_filter(expression: &str) -> Result<Condition> {
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Ok(Condition::all());
+    }
+
+    let mut parser = Parser {
+        tokens,
+        index: 0,
+        end: expression.chars().count(),
+        depth: 0,
+    };
+    let condition = parser.or()?;
+    if let Some((position, token)) = parser.tokens.get(parser.index) {
+        return Err(invalid(
+            *position,
+            format!("expected AND or OR, found {}", token.describe()),
+        ));
+    }
+    Ok(condition)
+}
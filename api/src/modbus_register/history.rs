@@ -0,0 +1,347 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::Request,
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use sea_orm::{
+    prelude::*, sea_query::Expr, ConnectionTrait, NotSet, QueryOrder, QuerySelect, Set,
+    TransactionTrait,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use super::inputs::{FieldChange, HistoryEntry};
+use crate::{
+    app_state::AppState,
+    db_connection::commit_or_rollback,
+    entity::{
+        modbus_register, modbus_register_devices as devices, modbus_register_history as history,
+        prelude::*, user,
+    },
+    error::{Error, Result},
+};
+
+/// Table name recorded for Modbus register revisions.
+pub const REGISTER_TABLE: &str = "modbus_register";
+/// Table name recorded for device revisions.
+pub const DEVICE_TABLE: &str = "modbus_register_devices";
+
+/// The kind of change a history revision describes.
+#[derive(Debug, Clone, Copy, strum_macros::AsRefStr)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum HistoryAction {
+    Create,
+    Update,
+    Delete,
+    Revert,
+    Restore,
+}
+
+tokio::task_local! {
+    // The name of the user making the current request, the author of the changes it makes.
+    static AUTHOR: Option<String>;
+}
+
+/// Middleware function naming the author of the changes a request makes.
+///
+/// The request is matched to the signed-in user by the user token in its `auth` header.
+/// Changes made by requests without a known token are recorded without an author.
+pub async fn track_author(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response> {
+    let token = req
+        .headers()
+        .get("auth")
+        .and_then(|value| value.to_str().ok())
+        .filter(|token| !token.is_empty())
+        .map(ToString::to_string);
+    let author = match token {
+        Some(token) => {
+            let conn = state.conn.lock().await;
+            User::find()
+                .filter(user::Column::Token.eq(token))
+                .one(&*conn)
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?
+                .map(|user| user.name)
+        }
+        None => None,
+    };
+    Ok(AUTHOR.scope(author, next.run(req)).await)
+}
+
+/// Serializes a model into the JSON snapshot stored in the history table.
+pub fn snapshot<T: Serialize>(model: &T) -> Value {
+    serde_json::to_value(model).unwrap_or_default()
+}
+
+/// Appends a new revision for a record, numbering it after the latest existing revision.
+/// The user making the request, as found by `track_author`, is stored as the author.
+pub async fn record<C: ConnectionTrait>(
+    conn: &C,
+    table_name: &str,
+    record_id: i32,
+    action: HistoryAction,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Result<()> {
+    let latest_revision: Option<Option<i32>> = ModbusRegisterHistory::find()
+        .select_only()
+        .column_as(Expr::col(history::Column::Revision).max(), "revision")
+        .filter(history::Column::TableName.eq(table_name))
+        .filter(history::Column::RecordId.eq(record_id))
+        .into_tuple()
+        .one(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    let changed_by = AUTHOR.try_with(Clone::clone).ok().flatten();
+
+    history::ActiveModel {
+        table_name: Set(table_name.to_string()),
+        record_id: Set(record_id),
+        revision: Set(latest_revision.flatten().unwrap_or(0) + 1),
+        action: Set(action.as_ref().to_string()),
+        before: Set(before),
+        after: Set(after),
+        changed_by: Set(changed_by),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await
+    .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(())
+}
+
+/// Lists the fields whose values differ between two snapshots.
+pub fn diff(before: &Option<Value>, after: &Option<Value>) -> Vec<FieldChange> {
+    let empty = serde_json::Map::new();
+    let before = before.as_ref().and_then(Value::as_object).unwrap_or(&empty);
+    let after = after.as_ref().and_then(Value::as_object).unwrap_or(&empty);
+
+    let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    fields
+        .into_iter()
+        .filter_map(|field| {
+            let old = before.get(field).cloned().unwrap_or(Value::Null);
+            let new = after.get(field).cloned().unwrap_or(Value::Null);
+            (old != new).then(|| FieldChange {
+                field: field.clone(),
+                before: old,
+                after: new,
+            })
+        })
+        .collect()
+}
+
+// Fetch every revision of a record, newest first, with its field diff.
+async fn list_history<C: ConnectionTrait>(
+    conn: &C,
+    table_name: &str,
+    record_id: i32,
+) -> Result<Vec<HistoryEntry>> {
+    let items = ModbusRegisterHistory::find()
+        .filter(history::Column::TableName.eq(table_name))
+        .filter(history::Column::RecordId.eq(record_id))
+        .order_by_desc(history::Column::Revision)
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(items
+        .into_iter()
+        .map(|item| HistoryEntry {
+            id: item.id,
+            revision: item.revision,
+            changes: diff(&item.before, &item.after),
+            action: item.action,
+            changed_by: item.changed_by,
+            created_at: item.created_at,
+            before: item.before,
+            after: item.after,
+        })
+        .collect())
+}
+
+// Fetch the state a record was left in by the given revision.
+async fn revision_snapshot<C: ConnectionTrait>(
+    conn: &C,
+    table_name: &str,
+    record_id: i32,
+    revision: i32,
+) -> Result<Value> {
+    let item = ModbusRegisterHistory::find()
+        .filter(history::Column::TableName.eq(table_name))
+        .filter(history::Column::RecordId.eq(record_id))
+        .filter(history::Column::Revision.eq(revision))
+        .one(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+
+    item.after.ok_or(Error::BadRequest(format!(
+        "Revision {} deleted the record, choose an earlier revision to revert to",
+        revision
+    )))
+}
+
+// Fetch the last state recorded for a record, the state it was deleted in for removed records.
+async fn last_recorded_state<C: ConnectionTrait>(
+    conn: &C,
+    table_name: &str,
+    record_id: i32,
+) -> Result<Value> {
+    let item = ModbusRegisterHistory::find()
+        .filter(history::Column::TableName.eq(table_name))
+        .filter(history::Column::RecordId.eq(record_id))
+        .order_by_desc(history::Column::Revision)
+        .one(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    Ok(item
+        .and_then(|item| item.after.or(item.before))
+        .unwrap_or_default())
+}
+
+// Lay a snapshot over a newer state of the record. Columns added since the snapshot was taken
+// keep their newer values, rather than being cleared or failing to load.
+fn merge_snapshot(base: Value, snapshot: Value) -> Value {
+    match (base, snapshot) {
+        (Value::Object(mut base), Value::Object(snapshot)) => {
+            base.extend(snapshot);
+            Value::Object(base)
+        }
+        (_, snapshot) => snapshot,
+    }
+}
+
+/// Handler to list the change history of a Modbus register.
+pub async fn register_history(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<HistoryEntry>>> {
+    let conn = state.conn.lock().await;
+    Ok(Json(list_history(&*conn, REGISTER_TABLE, id).await?))
+}
+
+/// Handler to restore a Modbus register to the state of a prior revision.
+/// Hard-deleted registers are re-inserted with their original ID. Fields that didn't exist yet
+/// when the revision was recorded keep their current values.
+pub async fn revert_register(
+    State(state): State<AppState>,
+    Path((id, revision)): Path<(i32, i32)>,
+) -> Result<Json<modbus_register::Model>> {
+    let conn = state.conn.lock().await;
+    let txn = conn
+        .begin()
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = async {
+        let current = ModbusRegister::find_by_id(id)
+            .one(&txn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        let base = match &current {
+            Some(current) => snapshot(current),
+            None => last_recorded_state(&txn, REGISTER_TABLE, id).await?,
+        };
+        let target = revision_snapshot(&txn, REGISTER_TABLE, id, revision).await?;
+        let restored: modbus_register::Model = serde_json::from_value(merge_snapshot(base, target))
+            .map_err(|error| Error::ServerError(error.to_string()))?;
+
+        // Mark every column as changed so the whole snapshot is written back, with a new
+        // updated_at.
+        let mut model = modbus_register::ActiveModel::from(restored).reset_all();
+        model.updated_at = NotSet;
+        let reverted = match current {
+            Some(_) => model.update(&txn).await,
+            None => model.insert(&txn).await,
+        }
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+        record(
+            &txn,
+            REGISTER_TABLE,
+            id,
+            HistoryAction::Revert,
+            current.as_ref().map(snapshot),
+            Some(snapshot(&reverted)),
+        )
+        .await?;
+        Ok(reverted)
+    }
+    .await;
+    let reverted = commit_or_rollback(txn, result).await?;
+
+    Ok(Json(reverted))
+}
+
+/// Handler to list the change history of a device.
+pub async fn device_history(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<HistoryEntry>>> {
+    let conn = state.conn.lock().await;
+    Ok(Json(list_history(&*conn, DEVICE_TABLE, id).await?))
+}
+
+/// Handler to restore a device to the state of a prior revision.
+/// Hard-deleted devices are re-inserted with their original ID. Fields missing from the revision
+/// keep the values the device has now.
+pub async fn revert_device(
+    State(state): State<AppState>,
+    Path((id, revision)): Path<(i32, i32)>,
+) -> Result<Json<devices::Model>> {
+    let conn = state.conn.lock().await;
+    let txn = conn
+        .begin()
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = async {
+        let current = ModbusRegisterDevices::find_by_id(id)
+            .one(&txn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        let base = match &current {
+            Some(current) => snapshot(current),
+            None => last_recorded_state(&txn, DEVICE_TABLE, id).await?,
+        };
+        let target = revision_snapshot(&txn, DEVICE_TABLE, id, revision).await?;
+        let restored: devices::Model = serde_json::from_value(merge_snapshot(base, target))
+            .map_err(|error| Error::ServerError(error.to_string()))?;
+
+        // Mark every column as changed so the whole snapshot is written back, with a new
+        // updated_at.
+        let mut model = devices::ActiveModel::from(restored).reset_all();
+        model.updated_at = NotSet;
+        let reverted = match current {
+            Some(_) => model.update(&txn).await,
+            None => model.insert(&txn).await,
+        }
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+        record(
+            &txn,
+            DEVICE_TABLE,
+            id,
+            HistoryAction::Revert,
+            current.as_ref().map(snapshot),
+            Some(snapshot(&reverted)),
+        )
+        .await?;
+        Ok(reverted)
+    }
+    .await;
+    let reverted = commit_or_rollback(txn, result).await?;
+
+    Ok(Json(reverted))
+}
@@ -0,0 +1,323 @@
+use axum::{extract::State, Json};
+use sea_orm::{
+    prelude::*, Condition, ConnectionTrait, DatabaseTransaction, NotSet, TransactionTrait,
+};
+
+use super::history::{self, HistoryAction, REGISTER_TABLE};
+use super::inputs::{
+    BulkImportInput, CreateModbusRegisterItemInput, ImportMode, ImportReport, ImportRowResult,
+    ImportRowStatus,
+};
+use super::map_lint::check_overlaps;
+use super::queries::{marks_as_updated, new_register_model};
+use super::validation::{validate_register, RegisterDefinition};
+use crate::{
+    app_state::AppState,
+    db_connection::commit_or_rollback,
+    entity::modbus_register::{self, Entity as ModbusRegister},
+    error::{Error, Result},
+};
+
+/// Handler to import many Modbus registers in one transaction.
+///
+/// `all_or_nothing` rolls back the whole import when any row fails, `best_effort` keeps the
+/// rows that succeed and `upsert` additionally updates registers that already exist with the
+/// same (device_id, register_address, operation) instead of skipping them.
+/// With `dry_run` every row is processed and reported, then the transaction is rolled back.
+pub async fn bulk_import(
+    State(state): State<AppState>,
+    Json(payload): Json<BulkImportInput>,
+) -> Result<Json<ImportReport>> {
+    let conn = state.conn.lock().await;
+    let report = import_registers(
+        &*conn,
+        payload.items,
+        payload.mode.unwrap_or_default(),
+        payload.dry_run.unwrap_or(false),
+    )
+    .await?;
+
+    Ok(Json(report))
+}
+
+/// Imports the given rows inside a transaction and reports the outcome of every row.
+/// Each row runs in its own savepoint so a failing row never leaves partial changes behind.
+pub async fn import_registers<C: ConnectionTrait + TransactionTrait>(
+    conn: &C,
+    items: Vec<CreateModbusRegisterItemInput>,
+    mode: ImportMode,
+    dry_run: bool,
+) -> Result<ImportReport> {
+    let txn = conn
+        .begin()
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    // Roll back when a savepoint can't be handled, rows failing on their own are reported.
+    let result = async {
+        let mut rows = Vec::with_capacity(items.len());
+        for (index, item) in items.into_iter().enumerate() {
+            let savepoint = txn
+                .begin()
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?;
+
+            match import_row(&savepoint, item, mode).await {
+                Ok((status, id, reason)) => {
+                    savepoint
+                        .commit()
+                        .await
+                        .map_err(|error| Error::DbError(error.to_string()))?;
+                    rows.push(ImportRowResult {
+                        index,
+                        status,
+                        // Created IDs are only tentative when nothing gets committed.
+                        id: if dry_run && status == ImportRowStatus::Created {
+                            None
+                        } else {
+                            id
+                        },
+                        reason,
+                    });
+                }
+                Err(error) => {
+                    savepoint
+                        .rollback()
+                        .await
+                        .map_err(|error| Error::DbError(error.to_string()))?;
+                    rows.push(ImportRowResult {
+                        index,
+                        status: ImportRowStatus::Failed,
+                        id: None,
+                        reason: Some(error_reason(error)),
+                    });
+                }
+            }
+        }
+        Ok(rows)
+    }
+    .await;
+    let rows = match result {
+        Ok(rows) => rows,
+        Err(error) => return commit_or_rollback(txn, Err(error)).await,
+    };
+
+    let count = |status: ImportRowStatus| rows.iter().filter(|row| row.status == status).count();
+    let failed = count(ImportRowStatus::Failed);
+
+    // Only keep the changes when this is a real run and the mode tolerates the failures.
+    let committed = !dry_run && (mode != ImportMode::AllOrNothing || failed == 0);
+    if committed {
+        txn.commit()
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+    } else {
+        txn.rollback()
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+    }
+
+    Ok(ImportReport {
+        mode,
+        dry_run,
+        committed,
+        created: count(ImportRowStatus::Created),
+        updated: count(ImportRowStatus::Updated),
+        skipped: count(ImportRowStatus::Skipped),
+        failed,
+        rows,
+    })
+}
+
+// Import a single row, returning its status, the affected register ID and an optional reason.
+async fn import_row(
+    txn: &DatabaseTransaction,
+    item: CreateModbusRegisterItemInput,
+    mode: ImportMode,
+) -> Result<(ImportRowStatus, Option<i32>, Option<String>)> {
+    validate_register(txn, &RegisterDefinition::from(&item)).await?;
+
+    // Rows without an address can't be matched against existing registers.
+    let existing = match item.register_address {
+        Some(register_address) => ModbusRegister::find()
+            .filter(register_key_condition(
+                item.device_id,
+                item.version_id,
+                register_address,
+                &item.operation,
+            ))
+            .one(txn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?,
+        None => None,
+    };
+
+    match existing {
+        Some(existing) if mode == ImportMode::Upsert => {
+            let id = existing.id;
+            if let Some(warnings) = upsert_existing(txn, existing, item).await? {
+                Ok((
+                    ImportRowStatus::Updated,
+                    Some(id),
+                    warnings_reason(warnings),
+                ))
+            } else {
+                Ok((
+                    ImportRowStatus::Skipped,
+                    Some(id),
+                    Some("Register is already up to date".to_string()),
+                ))
+            }
+        }
+        Some(existing) => Ok((
+            ImportRowStatus::Skipped,
+            Some(existing.id),
+            Some(format!("Duplicate of register {}", existing.id)),
+        )),
+        None => {
+            let res = new_register_model(item, false)
+                .insert(txn)
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?;
+            let warnings = check_overlaps(txn, &res).await?;
+            history::record(
+                txn,
+                REGISTER_TABLE,
+                res.id,
+                HistoryAction::Create,
+                None,
+                Some(history::snapshot(&res)),
+            )
+            .await?;
+            Ok((
+                ImportRowStatus::Created,
+                Some(res.id),
+                warnings_reason(warnings),
+            ))
+        }
+    }
+}
+
+/// Matches non-deleted registers sharing the import key
+/// (device_id, version_id, register_address, operation).
+pub fn register_key_condition(
+    device_id: Option<i32>,
+    version_id: Option<i32>,
+    register_address: i32,
+    operation: &Option<String>,
+) -> Condition {
+    Condition::all()
+        .add(match device_id {
+            Some(device_id) => modbus_register::Column::DeviceId.eq(device_id),
+            None => modbus_register::Column::DeviceId.is_null(),
+        })
+        .add(match version_id {
+            Some(version_id) => modbus_register::Column::VersionId.eq(version_id),
+            None => modbus_register::Column::VersionId.is_null(),
+        })
+        .add(modbus_register::Column::RegisterAddress.eq(register_address))
+        .add(match operation {
+            Some(operation) => modbus_register::Column::Operation.eq(operation.clone()),
+            None => modbus_register::Column::Operation.is_null(),
+        })
+        .add(modbus_register::Column::Status.ne("DELETED"))
+}
+
+// Apply the provided fields of an import row to an existing register.
+// Returns the overlap warnings of the updated register, or None when the row wouldn't change anything.
+async fn upsert_existing(
+    txn: &DatabaseTransaction,
+    existing: modbus_register::Model,
+    item: CreateModbusRegisterItemInput,
+) -> Result<Option<Vec<String>>> {
+    let mut updated = existing.clone();
+    updated.register_length = item.register_length;
+    if item.register_name.is_some() {
+        updated.register_name = item.register_name;
+    }
+    if item.data_format.is_some() {
+        updated.data_format = item.data_format;
+    }
+    if item.description.is_some() {
+        updated.description = item.description;
+    }
+    if item.unit.is_some() {
+        updated.unit = item.unit;
+    }
+    if item.private.is_some() {
+        updated.private = item.private;
+    }
+    if item.scale.is_some() {
+        updated.scale = item.scale;
+    }
+    if item.offset.is_some() {
+        updated.offset = item.offset;
+    }
+    if item.min_value.is_some() {
+        updated.min_value = item.min_value;
+    }
+    if item.max_value.is_some() {
+        updated.max_value = item.max_value;
+    }
+    if item.precision.is_some() {
+        updated.precision = item.precision;
+    }
+    if item.default_value.is_some() {
+        updated.default_value = item.default_value;
+    }
+    if let Some(status) = item.status {
+        updated.status = status;
+    }
+
+    if updated == existing {
+        return Ok(None);
+    }
+
+    // Mark published registers as updated, the same way a regular update does.
+    if updated.status == existing.status && marks_as_updated(&existing) {
+        updated.status = "UPDATED".to_string();
+    }
+
+    let mut model = modbus_register::ActiveModel::from(updated).reset_all();
+    model.updated_at = NotSet;
+    let result = model
+        .update(txn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let warnings = check_overlaps(txn, &result).await?;
+    history::record(
+        txn,
+        REGISTER_TABLE,
+        result.id,
+        HistoryAction::Update,
+        Some(history::snapshot(&existing)),
+        Some(history::snapshot(&result)),
+    )
+    .await?;
+
+    Ok(Some(warnings))
+}
+
+// Report overlap warnings of a written row as its reason.
+fn warnings_reason(warnings: Vec<String>) -> Option<String> {
+    if warnings.is_empty() {
+        None
+    } else {
+        Some(warnings.join("; "))
+    }
+}
+
+// Turn an error into a human readable reason for the row report.
+fn error_reason(error: Error) -> String {
+    match error {
+        Error::DbError(message) | Error::BadRequest(message) | Error::ServerError(message) => {
+            message
+        }
+        Error::Validation(errors) => errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; "),
+        error => error.to_string(),
+    }
+}
@@ -1,11 +1,18 @@
+use std::collections::BTreeMap;
 use std::fmt::Display;
 
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_with::skip_serializing_none;
 use strum_macros::Display;
 
+use super::codec::{DataFormat, RegisterValue};
+use super::units::UnitSystem;
+use crate::entity::files;
 use crate::entity::modbus_register;
+use crate::entity::modbus_register_device_versions;
 use crate::entity::modbus_register_devices;
+use crate::entity::modbus_register_value_labels;
+use crate::modbus_tcp::simulator::{RegisterTable, SimulatedValue, Waveform};
 
 fn deserialize_option_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
 where
@@ -16,6 +23,21 @@ where
     Ok(Some(opt))
 }
 
+// Read an id filter from the query string, where `none` selects the rows without one.
+fn deserialize_id_or_none<'de, D>(deserializer: D) -> Result<Option<Option<i32>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    match value.as_deref() {
+        None => Ok(None),
+        Some("none") => Ok(Some(None)),
+        Some(id) => id.parse().map(|id| Some(Some(id))).map_err(|_| {
+            serde::de::Error::custom(format!("expected an id or \"none\", got \"{}\"", id))
+        }),
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct ModbusRegister {
     pub id: i32,
@@ -28,8 +50,8 @@ pub struct ModbusRegister {
     pub device_id: i32,
     pub status: String,
     pub unit: Option<String>,
-    pub created_at: String,
-    pub updated_at: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -41,6 +63,19 @@ pub struct ModbusRegisterQueryParams {
     pub filter: Option<String>,
     pub device_id: Option<i32>,
     pub local_only: Option<bool>,
+    pub unit_system: Option<UnitSystem>,
+    pub firmware: Option<String>,
+    pub version_id: Option<i32>,
+    pub q: Option<String>,
+    #[serde(rename = "where")]
+    pub r#where: Option<String>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ModbusRegisterGetParams {
+    pub unit_system: Option<UnitSystem>,
 }
 
 #[derive(Deserialize, Display, Debug)]
@@ -59,7 +94,7 @@ impl Into<sea_orm::Order> for OrderByDirection {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum ModbusRegisterColumns {
     Id,
@@ -117,12 +152,19 @@ pub struct CreateModbusRegisterItemInput {
     pub device_id: Option<i32>,
     pub unit: Option<String>,
     pub status: Option<String>,
-    pub created_at: Option<String>,
-    pub updated_at: Option<String>,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
     pub private: Option<bool>,
+    pub scale: Option<f64>,
+    pub offset: Option<f64>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub precision: Option<i32>,
+    pub default_value: Option<f64>,
+    pub version_id: Option<i32>,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Deserialize, Clone, Debug, Default)]
 #[skip_serializing_none]
 pub struct UpdateModbusRegisterItemInput {
     pub register_address: Option<i32>,
@@ -142,6 +184,25 @@ pub struct UpdateModbusRegisterItemInput {
     pub status: Option<String>,
     #[serde(default, deserialize_with = "deserialize_option_option")]
     pub private: Option<Option<bool>>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub scale: Option<Option<f64>>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub offset: Option<Option<f64>>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub min_value: Option<Option<f64>>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub max_value: Option<Option<f64>>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub precision: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub default_value: Option<Option<f64>>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub version_id: Option<Option<i32>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct CreateModbusRegisterParams {
+    pub sync: Option<bool>,
 }
 
 #[derive(Serialize, Debug)]
@@ -158,14 +219,42 @@ pub struct ModbusRegisterModel {
     pub status: String,
     pub unit: Option<String>,
     pub private: Option<bool>,
-    pub created_at: String,
-    pub updated_at: String,
+    pub scale: Option<f64>,
+    pub offset: Option<f64>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub precision: Option<i32>,
+    pub default_value: Option<f64>,
+    pub version_id: Option<i32>,
+    pub labels: Vec<modbus_register_value_labels::Model>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search: Option<SearchMatch>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchMatch {
+    pub rank: f64,
+    pub highlights: BTreeMap<String, String>,
+}
+
+/// A created or updated register, with the overlaps writing it caused under the "warn"
+/// overlap policy.
+#[derive(Serialize, Debug)]
+pub struct SavedModbusRegister {
+    #[serde(flatten)]
+    pub register: modbus_register::Model,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
 
 #[derive(Serialize, Debug)]
 pub struct ModbusRegisterResponse {
     pub data: Vec<ModbusRegisterModel>,
     pub count: u64,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -186,6 +275,11 @@ pub struct CreateDeviceInput {
     pub status: Option<String>,
     pub private: Option<bool>,
     pub image_id: Option<i32>,
+    pub is_template: Option<bool>,
+    pub manufacturer_id: Option<i32>,
+    pub manufacturer_name: Option<String>,
+    pub category_id: Option<i32>,
+    pub category_name: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -200,6 +294,82 @@ pub struct UpdateDeviceInput {
     pub private: Option<bool>,
     #[serde(default, deserialize_with = "deserialize_option_option")]
     pub image_id: Option<Option<i32>>,
+    pub is_template: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub parent_id: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub manufacturer_id: Option<Option<i32>>,
+    pub manufacturer_name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub category_id: Option<Option<i32>>,
+    pub category_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateManufacturerInput {
+    pub name: String,
+    pub description: Option<String>,
+    pub website: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[skip_serializing_none]
+pub struct UpdateManufacturerInput {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub description: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub website: Option<Option<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDeviceCategoryInput {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[skip_serializing_none]
+pub struct UpdateDeviceCategoryInput {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub description: Option<Option<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CloneDeviceInput {
+    pub name: String,
+    pub description: Option<String>,
+    pub private: Option<bool>,
+    pub is_template: Option<bool>,
+    pub address_offset: Option<i32>,
+    pub register_ids: Option<Vec<i32>>,
+    pub include_mappings: Option<bool>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CloneDeviceResponse {
+    pub device: modbus_register_devices::Model,
+    pub registers: usize,
+    pub labels: usize,
+    pub mappings: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ResyncDeviceInput {
+    pub include_new: Option<bool>,
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ResyncDeviceResponse {
+    pub device_id: i32,
+    pub parent_id: i32,
+    pub dry_run: bool,
+    pub created: Vec<i32>,
+    pub updated: Vec<i32>,
+    pub deleted: Vec<i32>,
+    pub unchanged: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -208,7 +378,637 @@ pub struct CreateDeviceNameIdMappingInput {
     pub device_id: i32,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CreateDeviceVersionInput {
+    pub version: String,
+    pub firmware_min: Option<String>,
+    pub firmware_max: Option<String>,
+    pub release_notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[skip_serializing_none]
+pub struct UpdateDeviceVersionInput {
+    pub version: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub firmware_min: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub firmware_max: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub release_notes: Option<Option<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeviceMapQueryParams {
+    pub firmware: Option<String>,
+    pub version_id: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeviceVersionDiffParams {
+    pub from: i32,
+    pub to: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RegisterDiff {
+    pub from: modbus_register::Model,
+    pub to: modbus_register::Model,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeviceVersionDiff {
+    pub device_id: i32,
+    pub from: modbus_register_device_versions::Model,
+    pub to: modbus_register_device_versions::Model,
+    pub added: Vec<modbus_register::Model>,
+    pub removed: Vec<modbus_register::Model>,
+    pub changed: Vec<RegisterDiff>,
+    pub unchanged: usize,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ProductDeviceMappingQueryParams {
+    pub device_id: Option<i32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ProductResolutionQueryParams {
+    pub firmware: Option<String>,
+    pub unit_system: Option<UnitSystem>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProductResolutionSource {
+    Mapping,
+    Fallback,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ProductCandidate {
+    pub device: modbus_register_devices::Model,
+    pub version: Option<modbus_register_device_versions::Model>,
+    pub register_count: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResolvedRegister {
+    #[serde(flatten)]
+    pub register: ModbusRegisterModel,
+    pub table: RegisterTable,
+    pub function_codes: Vec<u8>,
+    pub format: Option<DataFormat>,
+    pub words: Option<usize>,
+    pub format_error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ProductResolution {
+    pub product_id: i32,
+    pub resolved_by: ProductResolutionSource,
+    pub device: modbus_register_devices::Model,
+    pub image: Option<files::Model>,
+    pub version: Option<modbus_register_device_versions::Model>,
+    pub registers: Vec<ResolvedRegister>,
+    pub candidates: Vec<ProductCandidate>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ModbusRegisterDevicesQueryParams {
     pub local_only: Option<bool>,
+    /// A manufacturer id, or `none` for the devices without a manufacturer.
+    #[serde(default, deserialize_with = "deserialize_id_or_none")]
+    pub manufacturer_id: Option<Option<i32>>,
+    /// A category id, or `none` for the devices without a category.
+    #[serde(default, deserialize_with = "deserialize_id_or_none")]
+    pub category_id: Option<Option<i32>>,
+    /// Full-text search of the device names and descriptions, ranked by relevance unless
+    /// `order_by` is given.
+    pub q: Option<String>,
+    pub order_by: Option<DeviceColumns>,
+    pub order_dir: Option<OrderByDirection>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceColumns {
+    Id,
+    Name,
+    Status,
+    Manufacturer,
+    Category,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FacetCount {
+    pub id: Option<i32>,
+    pub name: Option<String>,
+    pub count: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeviceFacets {
+    pub total: u64,
+    pub manufacturers: Vec<FacetCount>,
+    pub categories: Vec<FacetCount>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeleteDeviceParams {
+    #[serde(alias = "force")]
+    pub cascade: Option<bool>,
+    pub reassign_to: Option<i32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeviceDependencies {
+    pub device_id: i32,
+    pub registers: u64,
+    pub product_ids: Vec<i32>,
+    pub versions: u64,
+    pub clone_ids: Vec<i32>,
+    pub image_id: Option<i32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ValueCount {
+    pub value: Option<String>,
+    pub count: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RegisterFacets {
+    pub total: u64,
+    pub data_formats: Vec<ValueCount>,
+    pub units: Vec<ValueCount>,
+    pub operations: Vec<ValueCount>,
+    pub statuses: Vec<ValueCount>,
+    pub devices: Vec<FacetCount>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+#[derive(Serialize, Debug)]
+pub struct HistoryEntry {
+    pub id: i32,
+    pub revision: i32,
+    pub action: String,
+    pub changed_by: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub changes: Vec<FieldChange>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    #[default]
+    AllOrNothing,
+    BestEffort,
+    Upsert,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BulkImportInput {
+    pub mode: Option<ImportMode>,
+    pub dry_run: Option<bool>,
+    pub items: Vec<CreateModbusRegisterItemInput>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportRowStatus {
+    Created,
+    Updated,
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportRowResult {
+    pub index: usize,
+    pub status: ImportRowStatus,
+    pub id: Option<i32>,
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BulkUpdateInput {
+    pub ids: Option<Vec<i32>>,
+    #[serde(rename = "where")]
+    pub r#where: Option<String>,
+    pub changes: UpdateModbusRegisterItemInput,
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BulkDeleteInput {
+    pub ids: Option<Vec<i32>>,
+    #[serde(rename = "where")]
+    pub r#where: Option<String>,
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkAction {
+    Updated,
+    MarkedDeleted,
+    Removed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BulkChange {
+    pub id: i32,
+    pub action: BulkAction,
+    pub before: modbus_register::Model,
+    pub after: Option<modbus_register::Model>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BulkReport {
+    pub dry_run: bool,
+    pub count: usize,
+    pub changes: Vec<BulkChange>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeletedQueryParams {
+    pub limit: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeletedItems {
+    pub registers: Vec<modbus_register::Model>,
+    pub devices: Vec<modbus_register_devices::Model>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportReport {
+    pub mode: ImportMode,
+    pub dry_run: bool,
+    pub committed: bool,
+    pub created: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub rows: Vec<ImportRowResult>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum RegisterMapField {
+    Address,
+    Length,
+    Name,
+    Format,
+    Unit,
+    Description,
+    Operation,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RegisterMapImportQueryParams {
+    pub preview: Option<bool>,
+    pub mode: Option<ImportMode>,
+    pub dry_run: Option<bool>,
+    pub delimiter: Option<String>,
+    pub version_id: Option<i32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RegisterMapRowError {
+    pub line: usize,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RegisterMapImportResponse {
+    pub delimiter: String,
+    pub headers: Vec<String>,
+    pub mapping: std::collections::BTreeMap<RegisterMapField, String>,
+    pub rows: Vec<CreateModbusRegisterItemInput>,
+    pub errors: Vec<RegisterMapRowError>,
+    pub report: Option<ImportReport>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+    Html,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExportQueryParams {
+    pub format: Option<ExportFormat>,
+    pub filter: Option<String>,
+    pub device_id: Option<i32>,
+    pub local_only: Option<bool>,
+    pub firmware: Option<String>,
+    pub version_id: Option<i32>,
+    pub q: Option<String>,
+    #[serde(rename = "where")]
+    pub r#where: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportDocument {
+    pub schema: String,
+    pub version: u32,
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    pub devices: Vec<ExportDevice>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportDevice {
+    pub id: Option<i32>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub manufacturer: Option<String>,
+    pub category: Option<String>,
+    pub image: Option<ExportImage>,
+    pub registers: Vec<ExportRegister>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportImage {
+    pub name: String,
+    pub mime_type: String,
+    pub path: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportRegister {
+    pub id: i32,
+    pub register_address: Option<i32>,
+    pub register_length: i32,
+    pub register_name: Option<String>,
+    pub data_format: Option<String>,
+    pub unit: Option<String>,
+    pub scale: Option<f64>,
+    pub offset: Option<f64>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub precision: Option<i32>,
+    pub default_value: Option<f64>,
+    pub operation: Option<String>,
+    pub description: Option<String>,
+    pub status: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<ExportValueLabel>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportValueLabel {
+    pub kind: String,
+    pub value: i64,
+    pub bit_length: Option<i32>,
+    pub label: String,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlapPolicy {
+    Reject,
+    #[default]
+    Warn,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapKind {
+    Duplicate,
+    Overlap,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RegisterOverlap {
+    pub kind: OverlapKind,
+    pub operation: Option<String>,
+    pub register_ids: (i32, i32),
+    pub start: i32,
+    pub end: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RegisterGap {
+    pub operation: Option<String>,
+    pub start: i32,
+    pub end: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MapLintReport {
+    pub device_id: i32,
+    pub register_count: usize,
+    pub overlaps: Vec<RegisterOverlap>,
+    pub gaps: Vec<RegisterGap>,
+    pub unaddressed: Vec<i32>,
+    pub unnamed: Vec<i32>,
+    pub missing_units: Vec<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DecodeInput {
+    pub register_id: Option<i32>,
+    pub data_format: Option<String>,
+    pub register_length: Option<i32>,
+    pub scale: Option<f64>,
+    pub offset: Option<f64>,
+    pub words: Vec<u16>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DecodeResponse {
+    pub data_format: DataFormat,
+    pub value: RegisterValue,
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bit_fields: Vec<BitFieldValue>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EncodeInput {
+    pub register_id: Option<i32>,
+    pub data_format: Option<String>,
+    pub register_length: Option<i32>,
+    pub scale: Option<f64>,
+    pub offset: Option<f64>,
+    pub value: RegisterValue,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EncodeResponse {
+    pub data_format: DataFormat,
+    pub words: Vec<u16>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LiveConnectionInput {
+    pub host: String,
+    pub port: Option<u16>,
+    pub unit_id: Option<u8>,
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LiveReadInput {
+    #[serde(flatten)]
+    pub connection: LiveConnectionInput,
+    pub register_ids: Option<Vec<i32>>,
+    pub firmware: Option<String>,
+    pub version_id: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LiveWriteInput {
+    #[serde(flatten)]
+    pub connection: LiveConnectionInput,
+    pub register_id: i32,
+    pub value: RegisterValue,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LiveRegisterValue {
+    pub id: i32,
+    pub register_name: Option<String>,
+    pub register_address: Option<i32>,
+    pub register_length: i32,
+    pub operation: Option<String>,
+    pub data_format: Option<String>,
+    pub function_code: Option<u8>,
+    pub words: Option<Vec<u16>>,
+    pub value: Option<RegisterValue>,
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bit_fields: Vec<BitFieldValue>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LiveReadResponse {
+    pub device_id: i32,
+    pub address: String,
+    pub unit_id: u8,
+    pub registers: Vec<LiveRegisterValue>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LiveWriteResponse {
+    pub register_id: i32,
+    pub function_code: u8,
+    pub words: Option<Vec<u16>>,
+    pub bits: Option<Vec<bool>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SimulatorRegisterInput {
+    pub register_id: i32,
+    pub value: Option<RegisterValue>,
+    pub waveform: Option<Waveform>,
+    pub writable: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StartSimulatorInput {
+    pub device_id: i32,
+    pub bind_address: Option<String>,
+    pub port: Option<u16>,
+    pub tick_ms: Option<u64>,
+    pub registers: Option<Vec<SimulatorRegisterInput>>,
+    pub firmware: Option<String>,
+    pub version_id: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateSimulatorRegisterInput {
+    pub value: Option<RegisterValue>,
+    pub waveform: Option<Waveform>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SimulatorRegisterState {
+    pub register_id: i32,
+    pub register_name: Option<String>,
+    pub table: RegisterTable,
+    pub address: u16,
+    pub length: u16,
+    pub data_format: DataFormat,
+    pub writable: bool,
+    pub waveform: Waveform,
+    #[serde(flatten)]
+    pub value: SimulatedValue,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SimulatorSessionResponse {
+    pub id: u32,
+    pub device_id: i32,
+    pub device_name: String,
+    pub address: String,
+    pub port: u16,
+    pub tick_ms: u64,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub registers: Vec<SimulatorRegisterState>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueLabelKind {
+    Enum,
+    Bits,
+}
+
+impl ValueLabelKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Enum => "enum",
+            Self::Bits => "bits",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateValueLabelInput {
+    pub kind: ValueLabelKind,
+    pub value: i64,
+    pub bit_length: Option<i32>,
+    pub label: String,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[skip_serializing_none]
+pub struct UpdateValueLabelInput {
+    pub kind: Option<ValueLabelKind>,
+    pub value: Option<i64>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub bit_length: Option<Option<i32>>,
+    pub label: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_option_option")]
+    pub description: Option<Option<String>>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BitFieldValue {
+    pub label: String,
+    pub bit: i64,
+    pub bit_length: i32,
+    pub value: u64,
 }
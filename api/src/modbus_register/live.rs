@@ -0,0 +1,340 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use sea_orm::{prelude::*, QueryOrder};
+
+use super::codec::{decode, encode, DataFormat, DataType, RegisterValue};
+use super::inputs::{
+    LiveConnectionInput, LiveReadInput, LiveReadResponse, LiveRegisterValue, LiveWriteInput,
+    LiveWriteResponse,
+};
+use super::validation::operation_function_codes;
+use super::value_labels::{describe, labels_by_register, resolve_label};
+use super::versions::{requested_versions, versions_condition};
+use crate::{
+    app_state::AppState,
+    entity::{modbus_register, prelude::*},
+    error::{Error, Result},
+    modbus_tcp::{client::ModbusTcpClient, FunctionCode, MAX_WRITE_COILS, MAX_WRITE_REGISTERS},
+};
+
+/// Port used when a live request doesn't name one.
+pub const DEFAULT_MODBUS_PORT: u16 = 502;
+/// Unit identifier used when a live request doesn't name one.
+pub const DEFAULT_UNIT_ID: u8 = 1;
+const DEFAULT_TIMEOUT_MS: u64 = 3000;
+
+async fn connect(connection: &LiveConnectionInput) -> Result<(ModbusTcpClient, String, u8)> {
+    let address = format!(
+        "{}:{}",
+        connection.host.trim(),
+        connection.port.unwrap_or(DEFAULT_MODBUS_PORT)
+    );
+    let unit_id = connection.unit_id.unwrap_or(DEFAULT_UNIT_ID);
+    let timeout = Duration::from_millis(connection.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+
+    let client = ModbusTcpClient::connect(&address, unit_id, timeout)
+        .await
+        .map_err(|error| Error::Gateway(format!("Couldn't connect to {}: {}", address, error)))?;
+    Ok((client, address, unit_id))
+}
+
+/// Function codes named by a register's operation, empty when it has none.
+pub fn function_codes(register: &modbus_register::Model) -> Vec<u8> {
+    register
+        .operation
+        .as_deref()
+        .and_then(operation_function_codes)
+        .unwrap_or_default()
+}
+
+// Coils and discrete inputs address single bits rather than 16-bit registers.
+fn is_bit_addressed(codes: &[u8]) -> bool {
+    codes.iter().any(|code| [1, 2, 5, 15].contains(code))
+}
+
+// Pick the read function for an operation, holding registers unless it says otherwise.
+fn read_function(codes: &[u8]) -> FunctionCode {
+    [1, 2, 3, 4]
+        .into_iter()
+        .find(|code| codes.contains(code))
+        .and_then(FunctionCode::from_u8)
+        .unwrap_or(if codes.contains(&5) || codes.contains(&15) {
+            FunctionCode::ReadCoils
+        } else {
+            FunctionCode::ReadHoldingRegisters
+        })
+}
+
+/// Parses the data format of a register, including the register's scale and offset.
+/// Registers without a data format are read as plain unsigned 16-bit values.
+pub fn register_format(
+    register: &modbus_register::Model,
+) -> core::result::Result<DataFormat, String> {
+    let format = match register
+        .data_format
+        .as_deref()
+        .filter(|format| !format.trim().is_empty())
+    {
+        Some(name) => {
+            DataFormat::parse(name).ok_or(format!("\"{}\" is not a known data format", name))?
+        }
+        None => DataFormat::new(DataType::Uint16),
+    };
+    Ok(format.scaled(register.scale, register.offset))
+}
+
+fn register_start(register: &modbus_register::Model) -> core::result::Result<u16, String> {
+    let address = register
+        .register_address
+        .ok_or("The register has no address".to_string())?;
+    u16::try_from(address).map_err(|_| format!("Address {} is out of range", address))
+}
+
+// Read and decode a single register, reporting failures as a message.
+async fn read_register(
+    client: &mut ModbusTcpClient,
+    register: &modbus_register::Model,
+    result: &mut LiveRegisterValue,
+) -> core::result::Result<(), String> {
+    let address = register_start(register)?;
+    let count = register.register_length.max(1) as u16;
+    let codes = function_codes(register);
+    let function = read_function(&codes);
+    result.function_code = Some(function as u8);
+
+    match function {
+        FunctionCode::ReadCoils | FunctionCode::ReadDiscreteInputs => {
+            let bits = if function == FunctionCode::ReadCoils {
+                client.read_coils(address, count).await
+            } else {
+                client.read_discrete_inputs(address, count).await
+            }
+            .map_err(|error| error.to_string())?;
+            result.value = Some(if bits.len() == 1 {
+                RegisterValue::Integer(bits[0] as i64)
+            } else {
+                RegisterValue::Bits(bits)
+            });
+        }
+        _ => {
+            let words = if function == FunctionCode::ReadInputRegisters {
+                client.read_input_registers(address, count).await
+            } else {
+                client.read_holding_registers(address, count).await
+            }
+            .map_err(|error| error.to_string())?;
+            result.words = Some(words.clone());
+
+            let format = register_format(register)?;
+            // Registers spanning several values are decoded from their first value.
+            let length = format.words(register.register_length).min(words.len());
+            result.value = Some(decode(&format, &words[..length]).map_err(
+                |error| match error {
+                    Error::BadRequest(message) => message,
+                    error => error.to_string(),
+                },
+            )?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Handler to read the register map of a device from a live Modbus TCP server.
+///
+/// Every register that isn't deleted is read with the function its operation names and
+/// decoded with its data format, or only the registers in `register_ids` when given. With
+/// `firmware` or `version_id` only the register map of that version is read.
+/// Failures of single registers, such as Modbus exceptions, are reported per register.
+pub async fn read_device(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<LiveReadInput>,
+) -> Result<Json<LiveReadResponse>> {
+    let (registers, mut labels) = {
+        let conn = state.conn.lock().await;
+        ModbusRegisterDevices::find_by_id(id)
+            .one(&*conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?
+            .ok_or(Error::NotFound)?;
+
+        let version_ids = requested_versions(
+            &*conn,
+            Some(id),
+            payload.firmware.as_deref(),
+            payload.version_id,
+        )
+        .await?;
+
+        let mut query = ModbusRegister::find()
+            .filter(modbus_register::Column::DeviceId.eq(id))
+            .filter(modbus_register::Column::Status.ne("DELETED"))
+            .filter(versions_condition(version_ids));
+        if let Some(register_ids) = payload.register_ids {
+            query = query.filter(modbus_register::Column::Id.is_in(register_ids));
+        }
+        let registers = query
+            .order_by_asc(modbus_register::Column::RegisterAddress)
+            .order_by_asc(modbus_register::Column::Id)
+            .all(&*conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        let labels = labels_by_register(&*conn, registers.iter().map(|r| r.id).collect()).await?;
+        (registers, labels)
+    };
+
+    // The database lock is released before talking to the device, which may be slow.
+    let (mut client, address, unit_id) = connect(&payload.connection).await?;
+
+    let mut results = Vec::with_capacity(registers.len());
+    for register in registers {
+        let mut result = LiveRegisterValue {
+            id: register.id,
+            register_name: register.register_name.clone(),
+            register_address: register.register_address,
+            register_length: register.register_length,
+            operation: register.operation.clone(),
+            data_format: register.data_format.clone(),
+            function_code: None,
+            words: None,
+            value: None,
+            label: None,
+            bit_fields: vec![],
+            error: None,
+        };
+        if let Err(error) = read_register(&mut client, &register, &mut result).await {
+            result.error = Some(error);
+        }
+        if let Some(value) = &result.value {
+            let labels = labels.remove(&register.id).unwrap_or_default();
+            (result.label, result.bit_fields) = describe(value, &labels);
+        }
+        results.push(result);
+    }
+
+    Ok(Json(LiveReadResponse {
+        device_id: id,
+        address,
+        unit_id,
+        registers: results,
+    }))
+}
+
+/// Handler to write a value to a register of a device on a live Modbus TCP server.
+/// The value is encoded with the register's data format and written with the write
+/// function its operation names.
+pub async fn write_register(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<LiveWriteInput>,
+) -> Result<Json<LiveWriteResponse>> {
+    let (register, labels) = {
+        let conn = state.conn.lock().await;
+        let register = ModbusRegister::find_by_id(payload.register_id)
+            .filter(modbus_register::Column::DeviceId.eq(id))
+            .one(&*conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?
+            .ok_or(Error::NotFound)?;
+        let labels = labels_by_register(&*conn, vec![register.id])
+            .await?
+            .remove(&register.id)
+            .unwrap_or_default();
+        (register, labels)
+    };
+
+    let address = register_start(&register).map_err(Error::BadRequest)?;
+    let codes = function_codes(&register);
+    let writable = |code: u8| codes.contains(&code);
+
+    if is_bit_addressed(&codes) {
+        let bits = match &payload.value {
+            RegisterValue::Integer(value) => vec![*value != 0],
+            RegisterValue::Bits(bits) => bits.clone(),
+            value => {
+                return Err(Error::BadRequest(format!(
+                    "{:?} can't be written to a coil",
+                    value
+                )))
+            }
+        };
+        if bits.is_empty() || bits.len() > MAX_WRITE_COILS {
+            return Err(Error::BadRequest(format!(
+                "A write takes 1 to {} coils, not {}",
+                MAX_WRITE_COILS,
+                bits.len()
+            )));
+        }
+        let function = if bits.len() == 1 && writable(5) {
+            FunctionCode::WriteSingleCoil
+        } else if writable(15) {
+            FunctionCode::WriteMultipleCoils
+        } else {
+            return Err(Error::BadRequest(format!(
+                "Register {} can't be written with its operation",
+                register.id
+            )));
+        };
+
+        let (mut client, _, _) = connect(&payload.connection).await?;
+        if function == FunctionCode::WriteSingleCoil {
+            client.write_single_coil(address, bits[0]).await
+        } else {
+            client.write_multiple_coils(address, &bits).await
+        }
+        .map_err(|error| Error::Gateway(error.to_string()))?;
+
+        return Ok(Json(LiveWriteResponse {
+            register_id: register.id,
+            function_code: function as u8,
+            words: None,
+            bits: Some(bits),
+        }));
+    }
+
+    let format = register_format(&register).map_err(Error::BadRequest)?;
+    // Enumerations may be written by their label, e.g. "Cooling".
+    let value = if format.data_type == DataType::Ascii {
+        payload.value
+    } else {
+        resolve_label(payload.value, &labels)
+    };
+    let words = encode(&format, &value, register.register_length)?;
+    if words.len() > MAX_WRITE_REGISTERS {
+        return Err(Error::BadRequest(format!(
+            "A write takes 1 to {} registers, not {}",
+            MAX_WRITE_REGISTERS,
+            words.len()
+        )));
+    }
+    let function = if words.len() == 1 && writable(6) {
+        FunctionCode::WriteSingleRegister
+    } else if writable(16) {
+        FunctionCode::WriteMultipleRegisters
+    } else {
+        return Err(Error::BadRequest(format!(
+            "Register {} can't be written with its operation",
+            register.id
+        )));
+    };
+
+    let (mut client, _, _) = connect(&payload.connection).await?;
+    if function == FunctionCode::WriteSingleRegister {
+        client.write_single_register(address, words[0]).await
+    } else {
+        client.write_multiple_registers(address, &words).await
+    }
+    .map_err(|error| Error::Gateway(error.to_string()))?;
+
+    Ok(Json(LiveWriteResponse {
+        register_id: register.id,
+        function_code: function as u8,
+        words: Some(words),
+        bits: None,
+    }))
+}
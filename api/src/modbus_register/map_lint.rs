@@ -0,0 +1,233 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use sea_orm::{prelude::*, ConnectionTrait, QueryOrder};
+
+use super::inputs::{
+    DeviceMapQueryParams, MapLintReport, OverlapKind, OverlapPolicy, RegisterGap, RegisterOverlap,
+};
+use super::versions::{requested_versions, versions_condition};
+use crate::{
+    app_state::AppState,
+    entity::{modbus_register, prelude::*},
+    error::{Error, FieldError, Result},
+};
+
+/// Name of the setting that decides whether overlapping registers are rejected or only reported.
+/// Its value is either "reject" or "warn", and a missing setting means "warn".
+pub const OVERLAP_POLICY_SETTING: &str = "overlap_policy";
+
+/// Reads the configured overlap policy.
+pub async fn overlap_policy<C: ConnectionTrait>(conn: &C) -> Result<OverlapPolicy> {
+    let setting = ModbusRegisterSettings::find_by_id(OVERLAP_POLICY_SETTING)
+        .one(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(
+        match setting
+            .and_then(|setting| setting.value)
+            .map(|value| value.trim().to_lowercase())
+            .as_deref()
+        {
+            Some("reject") => OverlapPolicy::Reject,
+            _ => OverlapPolicy::Warn,
+        },
+    )
+}
+
+// Last address occupied by a register, counting a zero length as a single register.
+fn register_end(address: i32, length: i32) -> i32 {
+    address + length.max(1) - 1
+}
+
+/// Finds the other registers of the same device and operation whose address range
+/// intersects the given register's range. Deleted registers are ignored, and so are the
+/// registers of other versions of the device's map.
+pub async fn find_overlapping<C: ConnectionTrait>(
+    conn: &C,
+    register: &modbus_register::Model,
+) -> Result<Vec<modbus_register::Model>> {
+    let (Some(device_id), Some(address)) = (register.device_id, register.register_address) else {
+        return Ok(vec![]);
+    };
+    let end = register_end(address, register.register_length);
+
+    let candidates = ModbusRegister::find()
+        .filter(modbus_register::Column::DeviceId.eq(device_id))
+        .filter(match &register.operation {
+            Some(operation) => modbus_register::Column::Operation.eq(operation.clone()),
+            None => modbus_register::Column::Operation.is_null(),
+        })
+        .filter(modbus_register::Column::Id.ne(register.id))
+        .filter(modbus_register::Column::Status.ne("DELETED"))
+        .filter(versions_condition(register.version_id.map(|id| vec![id])))
+        .filter(modbus_register::Column::RegisterAddress.lte(end))
+        .order_by_asc(modbus_register::Column::RegisterAddress)
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(candidates
+        .into_iter()
+        .filter(|other| {
+            other.register_address.is_some_and(|other_address| {
+                register_end(other_address, other.register_length) >= address
+            })
+        })
+        .collect())
+}
+
+/// Checks a register that was just written against the rest of its device's map.
+/// With the "reject" policy overlaps fail validation, otherwise they are logged and
+/// returned as warnings. Callers are expected to run this inside their transaction.
+pub async fn check_overlaps<C: ConnectionTrait>(
+    conn: &C,
+    register: &modbus_register::Model,
+) -> Result<Vec<String>> {
+    let overlapping = find_overlapping(conn, register).await?;
+    if overlapping.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let messages: Vec<String> = overlapping
+        .iter()
+        .map(|other| {
+            let address = other.register_address.unwrap_or_default();
+            let duplicate = other.register_address == register.register_address
+                && other.register_length == register.register_length;
+            format!(
+                "{} register {} at {}-{}",
+                if duplicate { "duplicates" } else { "overlaps" },
+                other.id,
+                address,
+                register_end(address, other.register_length)
+            )
+        })
+        .collect();
+
+    match overlap_policy(conn).await? {
+        OverlapPolicy::Reject => Err(Error::Validation(
+            messages
+                .into_iter()
+                .map(|message| FieldError::new("register_address", message))
+                .collect(),
+        )),
+        OverlapPolicy::Warn => {
+            for message in &messages {
+                println!(
+                    "->> {:<12} - register {} {}",
+                    "OVERLAP", register.id, message
+                );
+            }
+            Ok(messages)
+        }
+    }
+}
+
+/// Handler to lint the register map of a device.
+/// Lists overlapping and duplicate registers, address gaps between registers of the same
+/// operation, and registers without an address, a name or a unit. With `firmware` or
+/// `version_id` only the map of that version is linted.
+pub async fn lint_device(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Query(params): Query<DeviceMapQueryParams>,
+) -> Result<Json<MapLintReport>> {
+    let conn = state.conn.lock().await;
+    ModbusRegisterDevices::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+
+    let version_ids = requested_versions(
+        &*conn,
+        Some(id),
+        params.firmware.as_deref(),
+        params.version_id,
+    )
+    .await?;
+
+    let registers = ModbusRegister::find()
+        .filter(modbus_register::Column::DeviceId.eq(id))
+        .filter(modbus_register::Column::Status.ne("DELETED"))
+        .filter(versions_condition(version_ids))
+        .order_by_asc(modbus_register::Column::Operation)
+        .order_by_asc(modbus_register::Column::RegisterAddress)
+        .order_by_asc(modbus_register::Column::Id)
+        .all(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(lint_registers(id, &registers)))
+}
+
+/// Lints a device's registers, which must be sorted by operation and then by address.
+pub fn lint_registers(device_id: i32, registers: &[modbus_register::Model]) -> MapLintReport {
+    let is_blank = |value: &Option<String>| value.as_deref().is_none_or(|v| v.trim().is_empty());
+
+    let mut report = MapLintReport {
+        device_id,
+        register_count: registers.len(),
+        overlaps: vec![],
+        gaps: vec![],
+        unaddressed: registers
+            .iter()
+            .filter(|r| r.register_address.is_none())
+            .map(|r| r.id)
+            .collect(),
+        unnamed: registers
+            .iter()
+            .filter(|r| is_blank(&r.register_name))
+            .map(|r| r.id)
+            .collect(),
+        missing_units: registers
+            .iter()
+            .filter(|r| is_blank(&r.unit))
+            .map(|r| r.id)
+            .collect(),
+    };
+
+    let addressed: Vec<(&modbus_register::Model, i32, i32)> = registers
+        .iter()
+        .filter_map(|r| {
+            r.register_address
+                .map(|address| (r, address, register_end(address, r.register_length)))
+        })
+        .collect();
+
+    for group in addressed.chunk_by(|(a, _, _), (b, _, _)| a.operation == b.operation) {
+        let mut covered_until: Option<i32> = None;
+        for (index, (register, start, end)) in group.iter().enumerate() {
+            if let Some(covered_until) = covered_until.filter(|until| *start > until + 1) {
+                report.gaps.push(RegisterGap {
+                    operation: register.operation.clone(),
+                    start: covered_until + 1,
+                    end: start - 1,
+                });
+            }
+            covered_until = Some(covered_until.map_or(*end, |until| until.max(*end)));
+
+            for (other, other_start, other_end) in group[index + 1..]
+                .iter()
+                .take_while(|(_, other_start, _)| other_start <= end)
+            {
+                report.overlaps.push(RegisterOverlap {
+                    kind: if other_start == start && other_end == end {
+                        OverlapKind::Duplicate
+                    } else {
+                        OverlapKind::Overlap
+                    },
+                    operation: register.operation.clone(),
+                    register_ids: (register.id, other.id),
+                    start: *other_start,
+                    end: (*end).min(*other_end),
+                });
+            }
+        }
+    }
+
+    report
+}
@@ -1,6 +1,27 @@
+pub mod bulk;
+pub mod catalogue;
+pub mod codec;
+pub mod csv_import;
 pub mod devices;
+pub mod exports;
+pub mod facets;
+pub mod filter_expression;
+pub mod history;
+pub mod imports;
 pub mod inputs;
+pub mod live;
+pub mod map_lint;
+pub mod pagination;
 pub mod product_device_mappings;
 pub mod queries;
+pub mod resolve;
+pub mod restore;
 pub mod routes;
+pub mod search;
 pub mod settings;
+pub mod simulator;
+pub mod templates;
+pub mod units;
+pub mod validation;
+pub mod value_labels;
+pub mod versions;
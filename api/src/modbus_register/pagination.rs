@@ -0,0 +1,226 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use sea_orm::{prelude::*, sea_query::SimpleExpr, Condition, Order};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use super::inputs::{ModbusRegisterColumns, OrderByDirection};
+use crate::{
+    entity::modbus_register,
+    error::{Error, Result},
+};
+
+/// The most items a list returns at once, larger limits are reduced to it.
+pub const MAX_PAGE_SIZE: u64 = 1000;
+
+/// A column a register list is sorted by, and the direction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub column: ModbusRegisterColumns,
+    pub order: Order,
+}
+
+/// Parses a comma separated sort, such as `device_id,-register_address`, where a leading
+/// `-` sorts a column in descending order. The id is always the last key, in the
+/// direction of the key before it, so every register has its own position.
+pub fn parse_sort(sort: &str) -> Result<Vec<SortKey>> {
+    let mut keys: Vec<SortKey> = vec![];
+    for name in sort
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let (name, order) = match name.strip_prefix('-') {
+            Some(name) => (name, Order::Desc),
+            None => (name.strip_prefix('+').unwrap_or(name), Order::Asc),
+        };
+        let column: ModbusRegisterColumns =
+            serde_json::from_value(JsonValue::String(name.to_string()))
+                .map_err(|_| Error::BadRequest(format!("Unknown sort column \"{}\"", name)))?;
+        if keys.iter().any(|key| key.column == column) {
+            return Err(Error::BadRequest(format!(
+                "Sort column \"{}\" is given twice",
+                name
+            )));
+        }
+        keys.push(SortKey { column, order });
+    }
+    Ok(with_id(keys))
+}
+
+/// The sort of the single column `order_by` and `order_dir` parameters.
+pub fn single_sort(
+    column: Option<ModbusRegisterColumns>,
+    direction: Option<OrderByDirection>,
+) -> Vec<SortKey> {
+    with_id(vec![SortKey {
+        column: column.unwrap_or(ModbusRegisterColumns::Id),
+        order: direction.unwrap_or(OrderByDirection::Desc).into(),
+    }])
+}
+
+fn with_id(mut keys: Vec<SortKey>) -> Vec<SortKey> {
+    if !keys
+        .iter()
+        .any(|key| key.column == ModbusRegisterColumns::Id)
+    {
+        let order = keys.last().map_or(Order::Desc, |key| key.order.clone());
+        keys.push(SortKey {
+            column: ModbusRegisterColumns::Id,
+            order,
+        });
+    }
+    keys
+}
+
+// The value of a sort column for a register, as stored in cursors.
+fn sort_value(column: ModbusRegisterColumns, register: &modbus_register::Model) -> JsonValue {
+    match column {
+        ModbusRegisterColumns::Id => register.id.into(),
+        ModbusRegisterColumns::RegisterAddress => register.register_address.into(),
+        ModbusRegisterColumns::Operation => register.operation.clone().into(),
+        ModbusRegisterColumns::RegisterLength => register.register_length.into(),
+        ModbusRegisterColumns::RegisterName => register.register_name.clone().into(),
+        ModbusRegisterColumns::DataFormat => register.data_format.clone().into(),
+        ModbusRegisterColumns::Description => register.description.clone().into(),
+        ModbusRegisterColumns::DeviceId => register.device_id.into(),
+        ModbusRegisterColumns::Status => register.status.clone().into(),
+        ModbusRegisterColumns::Unit => register.unit.clone().into(),
+        ModbusRegisterColumns::CreatedAt => timestamp_value(register.created_at),
+        ModbusRegisterColumns::UpdatedAt => timestamp_value(register.updated_at),
+    }
+}
+
+// Timestamps keep all their digits in cursors, so they compare equal to the stored ones.
+fn timestamp_value(timestamp: DateTime<Utc>) -> JsonValue {
+    timestamp
+        .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+        .into()
+}
+
+// Convert a value from a cursor back to a database value, None standing for NULL.
+fn column_value(column: ModbusRegisterColumns, value: &JsonValue) -> Result<Option<Value>> {
+    let invalid = || Error::BadRequest("Invalid cursor".to_string());
+    if value.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(match column {
+        ModbusRegisterColumns::Id
+        | ModbusRegisterColumns::RegisterAddress
+        | ModbusRegisterColumns::RegisterLength
+        | ModbusRegisterColumns::DeviceId => value
+            .as_i64()
+            .and_then(|value| i32::try_from(value).ok())
+            .ok_or_else(invalid)?
+            .into(),
+        ModbusRegisterColumns::CreatedAt | ModbusRegisterColumns::UpdatedAt => value
+            .as_str()
+            .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+            .ok_or_else(invalid)?
+            .with_timezone(&Utc)
+            .into(),
+        _ => value.as_str().ok_or_else(invalid)?.to_string().into(),
+    }))
+}
+
+/// Which side of the register a cursor was taken from a page is on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CursorDirection {
+    /// The page after the register.
+    After,
+    /// The page before the register.
+    Before,
+}
+
+/// Position of a register in a sorted list, handed to clients as an opaque string.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Cursor {
+    pub direction: CursorDirection,
+    /// The sort the cursor belongs to, as sort column names.
+    pub sort: Vec<String>,
+    /// The register's values of the sort columns.
+    pub values: Vec<JsonValue>,
+}
+
+fn sort_names(keys: &[SortKey]) -> Vec<String> {
+    keys.iter()
+        .map(|key| match key.order {
+            Order::Desc => format!("-{}", key.column),
+            _ => key.column.to_string(),
+        })
+        .collect()
+}
+
+impl Cursor {
+    /// The cursor of a register in a list with the given sort.
+    pub fn new(
+        direction: CursorDirection,
+        keys: &[SortKey],
+        register: &modbus_register::Model,
+    ) -> Self {
+        Self {
+            direction,
+            sort: sort_names(keys),
+            values: keys
+                .iter()
+                .map(|key| sort_value(key.column, register))
+                .collect(),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    /// Decodes a cursor, which has to belong to the given sort.
+    pub fn decode(cursor: &str, keys: &[SortKey]) -> Result<Self> {
+        let cursor: Cursor = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or(Error::BadRequest("Invalid cursor".to_string()))?;
+        if cursor.sort != sort_names(keys) || cursor.values.len() != keys.len() {
+            return Err(Error::BadRequest(
+                "The cursor belongs to a different sort order".to_string(),
+            ));
+        }
+        Ok(cursor)
+    }
+
+    /// Matches the registers past the cursor's register in its direction. SQLite sorts
+    /// NULLs first, so they come before every value in ascending order and after every
+    /// value in descending order.
+    pub fn condition(&self, keys: &[SortKey]) -> Result<Condition> {
+        let mut condition = Condition::any();
+        let mut equal = Condition::all();
+        for (key, value) in keys.iter().zip(&self.values) {
+            let column: modbus_register::Column = key.column.into();
+            let value = column_value(key.column, value)?;
+            // Going backwards is going forwards in the opposite order.
+            let ascending = (key.order == Order::Asc) == (self.direction == CursorDirection::After);
+            let past: SimpleExpr = match (&value, ascending) {
+                (Some(value), true) => column.gt(value.clone()),
+                (Some(value), false) => column.lt(value.clone()).or(column.is_null()),
+                (None, true) => column.is_not_null(),
+                (None, false) => Expr::value(false),
+            };
+            condition = condition.add(equal.clone().add(past));
+            equal = equal.add(match value {
+                Some(value) => column.eq(value),
+                None => column.is_null(),
+            });
+        }
+        Ok(condition)
+    }
+}
+
+/// The order to query a page in. Pages before a cursor are queried in the opposite
+/// order, then reversed.
+pub fn query_order(key: &SortKey, direction: CursorDirection) -> Order {
+    match (&key.order, direction) {
+        (Order::Asc, CursorDirection::Before) => Order::Desc,
+        (_, CursorDirection::Before) => Order::Asc,
+        (order, CursorDirection::After) => order.clone(),
+    }
+}
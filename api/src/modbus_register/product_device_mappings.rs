@@ -1,7 +1,9 @@
 use axum::extract::Path;
+use axum::extract::Query;
 use axum::extract::State;
 use axum::Json;
 use sea_orm::entity::prelude::*;
+use sea_orm::QueryOrder;
 use sea_orm::Set;
 use sea_orm::TryIntoModel;
 
@@ -10,7 +12,7 @@ use crate::entity::modbus_register_product_device_mapping as device_mappings;
 use crate::entity::prelude::*;
 use crate::error::{Error, Result};
 
-use super::inputs::CreateDeviceNameIdMappingInput;
+use super::inputs::{CreateDeviceNameIdMappingInput, ProductDeviceMappingQueryParams};
 
 pub async fn get_all(State(state): State<AppState>) -> Result<Json<Vec<device_mappings::Model>>> {
     let conn = state.conn.lock().await;
@@ -21,12 +23,15 @@ pub async fn get_all(State(state): State<AppState>) -> Result<Json<Vec<device_ma
     }
 }
 
+// A product may be mapped to several devices, the device with the lowest ID is returned.
 pub async fn get_by_id(
     State(state): State<AppState>,
     Path(id): Path<i32>,
 ) -> Result<Json<device_mappings::Model>> {
     let conn = state.conn.lock().await;
-    let result = ModbusRegisterProductDeviceMapping::find_by_id(id)
+    let result = ModbusRegisterProductDeviceMapping::find()
+        .filter(device_mappings::Column::ProductId.eq(id))
+        .order_by_asc(device_mappings::Column::DeviceId)
         .one(&*conn)
         .await
         .map_err(|error| Error::DbError(error.to_string()))
@@ -55,19 +60,28 @@ pub async fn create(
     Ok(Json(res.try_into_model().unwrap()))
 }
 
+// Deletes the mappings of a product, or only its mapping to `device_id` when given.
 pub async fn delete(
     State(state): State<AppState>,
     Path(id): Path<i32>,
+    Query(params): Query<ProductDeviceMappingQueryParams>,
 ) -> Result<Json<device_mappings::Model>> {
     let conn = state.conn.lock().await;
-    let setting = ModbusRegisterProductDeviceMapping::find_by_id(id)
+    let mut condition = device_mappings::Column::ProductId.eq(id);
+    if let Some(device_id) = params.device_id {
+        condition = condition.and(device_mappings::Column::DeviceId.eq(device_id));
+    }
+    let setting = ModbusRegisterProductDeviceMapping::find()
+        .filter(condition.clone())
+        .order_by_asc(device_mappings::Column::DeviceId)
         .one(&*conn)
         .await
         .map_err(|error| Error::DbError(error.to_string()))?
         .ok_or(Error::NotFound)
         .map(Into::into)?;
 
-    ModbusRegisterProductDeviceMapping::delete_by_id(id)
+    ModbusRegisterProductDeviceMapping::delete_many()
+        .filter(condition)
         .exec(&*conn)
         .await
         .map_err(|error| Error::DbError(error.to_string()))?;
@@ -1,90 +1,177 @@
 use axum::{
     extract::{Path, Query, State},
+    http::HeaderMap,
     Json,
 };
+use chrono::Utc;
 use sea_orm::{
-    prelude::*, sea_query::IntoCondition, QueryOrder, QuerySelect, SelectTwo, Set, TryIntoModel,
+    prelude::*, Condition, ConnectionTrait, Order, QueryOrder, QuerySelect, SelectTwo, Set,
+    TransactionTrait, TryIntoModel,
 };
 use serde_json::json;
 
+use super::filter_expression::parse_filter;
+use super::history::{self, HistoryAction, REGISTER_TABLE};
 use super::inputs::{
-    CreateModbusRegisterItemInput, ModbusRegisterColumns, ModbusRegisterModel,
-    ModbusRegisterQueryParams, ModbusRegisterResponse, OrderByDirection,
+    CreateModbusRegisterItemInput, CreateModbusRegisterParams, ModbusRegisterGetParams,
+    ModbusRegisterModel, ModbusRegisterQueryParams, ModbusRegisterResponse, SavedModbusRegister,
     UpdateModbusRegisterItemInput,
 };
+use super::map_lint::check_overlaps;
+use super::pagination::{
+    parse_sort, query_order, single_sort, Cursor, CursorDirection, MAX_PAGE_SIZE,
+};
+use super::search::{match_expression, search_condition, search_matches, search_rank};
+use super::units::convert_register;
+use super::validation::{
+    touched_fields, validate_register, validate_register_update, RegisterDefinition,
+};
+use super::value_labels::labels_by_register;
+use super::versions::{requested_versions, versions_condition};
 use crate::{
     app_state::AppState,
+    db_connection::commit_or_rollback,
     entity::modbus_register::{self, Entity as ModbusRegister},
-    entity::modbus_register_devices::Entity as ModbusRegisterDevices,
-    error::{Error, Result},
+    entity::modbus_register_devices::{self, Entity as ModbusRegisterDevices},
+    entity::modbus_register_value_labels,
+    error::{Error, FieldError, Result},
+    etag::{check_if_match, etag_header},
 };
 
-/// Generates a filter query based on optional filter criteria.
-pub fn generate_filter_query(
+/// Builds the condition of the register list's filter criteria.
+/// Given versions restrict the registers to the register maps of those versions.
+pub fn filter_condition(
     filter: &Option<String>,
     device_id: &Option<i32>,
     local_only: bool,
-) -> SelectTwo<ModbusRegister, ModbusRegisterDevices> {
-    let mut query = ModbusRegister::find().find_also_related(ModbusRegisterDevices);
+    version_ids: Option<Vec<i32>>,
+) -> Condition {
+    let mut condition = Condition::all();
 
     // Apply text-based filters if a filter string is provided.
     if let Some(filter) = filter {
-        let fields = vec![
+        let like_expr = format!("%{}%", filter);
+        let mut text_filters = Condition::any();
+        for field in [
             modbus_register::Column::RegisterName,
             modbus_register::Column::Operation,
             modbus_register::Column::Description,
             modbus_register::Column::DeviceId,
             modbus_register::Column::DataFormat,
             modbus_register::Column::Unit,
-        ];
-        let like_expr = format!("%{}%", filter);
-        let mut or_filters = vec![];
-        for field in fields {
-            or_filters.push(field.like(like_expr.clone()));
+        ] {
+            text_filters = text_filters.add(field.like(like_expr.clone()));
         }
 
         // Apply numeric filters if the filter can be parsed as an integer.
         if let Ok(filter_num) = filter.parse::<i32>() {
-            query = query.filter(
-                modbus_register::Column::RegisterLength
-                    .eq(filter_num)
-                    .or(modbus_register::Column::RegisterAddress.eq(filter_num))
-                    .or(modbus_register::Column::Id.eq(filter_num))
-                    .or(or_filters
-                        .drain(..)
-                        .reduce(|l, r| l.or(r))
-                        .map(|c| c)
-                        .unwrap()),
-            );
-        } else {
-            query = query.filter(
-                or_filters
-                    .drain(..)
-                    .reduce(|l, r| l.or(r))
-                    .map(|c| c.into_condition())
-                    .unwrap(),
-            );
+            text_filters = text_filters
+                .add(modbus_register::Column::RegisterLength.eq(filter_num))
+                .add(modbus_register::Column::RegisterAddress.eq(filter_num))
+                .add(modbus_register::Column::Id.eq(filter_num));
         }
+        condition = condition.add(text_filters);
     }
 
     // Exclude records with specific statuses.
-    query = query.filter(modbus_register::Column::Status.not_like("REJECTED"));
-    query = query.filter(modbus_register::Column::Status.not_like("APPROVED"));
+    condition = condition
+        .add(modbus_register::Column::Status.not_like("REJECTED"))
+        .add(modbus_register::Column::Status.not_like("APPROVED"));
 
     // Filter by device ID if provided.
-    if device_id.is_some() {
-        query = query.filter(modbus_register::Column::DeviceId.eq(device_id.clone().unwrap()));
+    if let Some(device_id) = device_id {
+        condition = condition.add(modbus_register::Column::DeviceId.eq(*device_id));
     }
+    condition = condition.add(versions_condition(version_ids));
 
     // Apply local-only filters if specified.
     if local_only {
-        query =
-            query.filter(modbus_register::Column::Status.is_in(vec!["NEW", "UPDATED", "DELETED"]));
+        condition =
+            condition.add(modbus_register::Column::Status.is_in(vec!["NEW", "UPDATED", "DELETED"]));
     } else {
-        query = query.filter(modbus_register::Column::Status.not_like("DELETED"));
+        condition = condition.add(modbus_register::Column::Status.not_like("DELETED"));
+    }
+
+    condition
+}
+
+/// Generates a filter query based on optional filter criteria.
+/// Given versions restrict the registers to the register maps of those versions.
+pub fn generate_filter_query(
+    filter: &Option<String>,
+    device_id: &Option<i32>,
+    local_only: bool,
+    version_ids: Option<Vec<i32>>,
+) -> SelectTwo<ModbusRegister, ModbusRegisterDevices> {
+    ModbusRegister::find()
+        .find_also_related(ModbusRegisterDevices)
+        .filter(filter_condition(filter, device_id, local_only, version_ids))
+}
+
+/// Builds the condition of all filters of the register list: the filter criteria, the
+/// requested firmware or version, the filter expression and the search. Also returns the
+/// search's match expression.
+pub async fn list_condition<C: ConnectionTrait>(
+    conn: &C,
+    params: &ModbusRegisterQueryParams,
+) -> Result<(Condition, Option<String>)> {
+    // Resolve the firmware or version whose register map is requested.
+    let version_ids = requested_versions(
+        conn,
+        params.device_id,
+        params.firmware.as_deref(),
+        params.version_id,
+    )
+    .await?;
+
+    let mut condition = filter_condition(
+        &params.filter,
+        &params.device_id,
+        params.local_only.unwrap_or(false),
+        version_ids,
+    );
+    if let Some(expression) = &params.r#where {
+        condition = condition.add(parse_filter(expression)?);
+    }
+    let search = params.q.as_deref().and_then(match_expression);
+    if let Some(search) = &search {
+        condition = condition.add(search_condition(search));
     }
 
-    query
+    Ok((condition, search))
+}
+
+/// Maps a register and its device and labels to the response model.
+pub fn register_model(
+    register: &modbus_register::Model,
+    device: Option<modbus_register_devices::Model>,
+    labels: Vec<modbus_register_value_labels::Model>,
+) -> ModbusRegisterModel {
+    ModbusRegisterModel {
+        id: register.id,
+        register_address: register.register_address,
+        operation: register.operation.clone(),
+        register_length: register.register_length,
+        register_name: register.register_name.clone(),
+        data_format: register.data_format.clone(),
+        description: register.description.clone(),
+        device_id: register.device_id,
+        device,
+        status: register.status.clone(),
+        unit: register.unit.clone(),
+        private: register.private,
+        scale: register.scale,
+        offset: register.offset,
+        min_value: register.min_value,
+        max_value: register.max_value,
+        precision: register.precision,
+        default_value: register.default_value,
+        version_id: register.version_id,
+        labels,
+        created_at: register.created_at,
+        updated_at: register.updated_at,
+        search: None,
+    }
 }
 
 /// Handler to list Modbus registers with optional query parameters.
@@ -94,62 +181,135 @@ pub async fn list(
 ) -> Result<Json<ModbusRegisterResponse>> {
     let conn = state.conn.lock().await;
     // Generate the base query with filters.
-    let mut query = generate_filter_query(
-        &params.filter,
-        &params.device_id,
-        params.local_only.unwrap_or(false),
-    );
-
-    // Apply ordering, limit, and offset to the query.
-    query = query.order_by(
-        Into::<modbus_register::Column>::into(params.order_by.unwrap_or(ModbusRegisterColumns::Id)),
-        params.order_dir.unwrap_or(OrderByDirection::Desc).into(),
-    );
-    query = query
-        .limit(params.limit.unwrap_or(100))
-        .offset(params.offset.unwrap_or(0));
+    let (condition, search) = list_condition(&*conn, &params).await?;
+    let mut query = ModbusRegister::find()
+        .find_also_related(ModbusRegisterDevices)
+        .filter(condition);
 
-    // Execute the query and fetch the results.
+    // Count every matching register, not just the page.
     let count = query
         .clone()
         .count(&*conn)
         .await
         .map_err(|error| Error::DbError(error.to_string()))?;
-    let items = query
+
+    // Apply ordering, limit, and offset to the query. Searches are ordered by relevance
+    // unless another order is requested, other lists can be paged with cursors.
+    let keys = match &params.sort {
+        Some(sort) => parse_sort(sort)?,
+        None => single_sort(params.order_by, params.order_dir),
+    };
+    let cursor = params
+        .cursor
+        .as_deref()
+        .map(|cursor| Cursor::decode(cursor, &keys))
+        .transpose()?;
+    let direction = cursor
+        .as_ref()
+        .map_or(CursorDirection::After, |cursor| cursor.direction);
+    let relevance = search
+        .as_ref()
+        .filter(|_| params.sort.is_none() && params.order_by.is_none());
+    if let Some(search) = relevance {
+        if cursor.is_some() {
+            return Err(Error::BadRequest(
+                "Searches ordered by relevance are paged with offset, not cursors".to_string(),
+            ));
+        }
+        query = query
+            .order_by(search_rank(search), Order::Asc)
+            .order_by_desc(modbus_register::Column::Id);
+    } else {
+        if let Some(cursor) = &cursor {
+            query = query.filter(cursor.condition(&keys)?);
+        }
+        for key in &keys {
+            query = query.order_by(
+                Into::<modbus_register::Column>::into(key.column),
+                query_order(key, direction),
+            );
+        }
+    }
+    // Cursors take the place of the offset. One more register than requested tells
+    // whether there is another page.
+    let limit = params.limit.unwrap_or(100).min(MAX_PAGE_SIZE);
+    let offset = match cursor {
+        Some(_) => 0,
+        None => params.offset.unwrap_or(0),
+    };
+    let mut items = query
+        .limit(limit + 1)
+        .offset(offset)
         .all(&*conn)
         .await
         .map_err(|error| Error::DbError(error.to_string()))?;
+    let more = items.len() as u64 > limit;
+    items.truncate(limit as usize);
+    if direction == CursorDirection::Before {
+        items.reverse();
+    }
+
+    // Point the cursors at the first and last registers of the page.
+    let (next_cursor, prev_cursor) = match relevance {
+        Some(_) => (None, None),
+        None => {
+            let (has_next, has_prev) = match direction {
+                CursorDirection::After => (more, cursor.is_some() || offset > 0),
+                CursorDirection::Before => (true, more),
+            };
+            let page_cursor = |direction, item: Option<&(modbus_register::Model, _)>| {
+                item.map(|item| Cursor::new(direction, &keys, &item.0).encode())
+            };
+            (
+                page_cursor(CursorDirection::After, items.last().filter(|_| has_next)),
+                page_cursor(CursorDirection::Before, items.first().filter(|_| has_prev)),
+            )
+        }
+    };
+
+    let mut labels =
+        labels_by_register(&*conn, items.iter().map(|item| item.0.id).collect()).await?;
 
     // Map the results to the response model.
-    let items = items
+    let mut items: Vec<ModbusRegisterModel> = items
         .iter()
-        .map(|item| ModbusRegisterModel {
-            id: item.0.id,
-            register_address: item.0.register_address,
-            operation: item.0.operation.clone(),
-            register_length: item.0.register_length,
-            register_name: item.0.register_name.clone(),
-            data_format: item.0.data_format.clone(),
-            description: item.0.description.clone(),
-            device_id: item.0.device_id,
-            device: item.1.clone(),
-            status: item.0.status.clone(),
-            unit: item.0.unit.clone(),
-            private: item.0.private,
-            created_at: item.0.created_at.clone(),
-            updated_at: item.0.updated_at.clone(),
+        .map(|item| {
+            let register_labels = labels.remove(&item.0.id).unwrap_or_default();
+            register_model(&item.0, item.1.clone(), register_labels)
         })
         .collect();
 
+    // Express the values in the requested unit system.
+    if let Some(unit_system) = params.unit_system {
+        for item in items.iter_mut() {
+            convert_register(item, unit_system);
+        }
+    }
+
+    // Add the relevance and highlighted snippets of searches.
+    if let Some(search) = &search {
+        let mut matches =
+            search_matches(&*conn, search, items.iter().map(|item| item.id).collect()).await?;
+        for item in items.iter_mut() {
+            item.search = matches.remove(&item.id);
+        }
+    }
+
     // Return the response with the items and count.
-    Ok(Json(ModbusRegisterResponse { data: items, count }))
+    Ok(Json(ModbusRegisterResponse {
+        data: items,
+        count,
+        next_cursor,
+        prev_cursor,
+    }))
 }
 
 /// Handler to get a single Modbus register by its ID.
 pub async fn get_one(
     State(state): State<AppState>,
     Path(id): Path<i32>,
-) -> Result<Json<Option<ModbusRegisterModel>>> {
+    Query(params): Query<ModbusRegisterGetParams>,
+) -> Result<(HeaderMap, Json<Option<ModbusRegisterModel>>)> {
     let conn = state.conn.lock().await;
     // Fetch the item by ID and related device.
     let item = ModbusRegister::find_by_id(id)
@@ -157,157 +317,264 @@ pub async fn get_one(
         .one(&*conn)
         .await
         .map_err(|error| Error::DbError(error.to_string()))?;
+    let mut labels =
+        labels_by_register(&*conn, item.iter().map(|item| item.0.id).collect()).await?;
 
     // Map the result to the response model.
-    let item = item.map(|item| ModbusRegisterModel {
-        id: item.0.id,
-        register_address: item.0.register_address,
-        operation: item.0.operation.clone(),
-        register_length: item.0.register_length,
-        register_name: item.0.register_name.clone(),
-        data_format: item.0.data_format.clone(),
-        description: item.0.description.clone(),
-        device_id: item.0.device_id,
-        device: item.1.clone(),
-        status: item.0.status.clone(),
-        unit: item.0.unit.clone(),
-        private: item.0.private,
-        created_at: item.0.created_at.clone(),
-        updated_at: item.0.updated_at.clone(),
+    let mut item = item.map(|item| {
+        let register_labels = labels.remove(&item.0.id).unwrap_or_default();
+        register_model(&item.0, item.1, register_labels)
     });
 
+    // Tag the response with the stored representation, so it changes with the device and
+    // labels as well, and so `If-Match` accepts it whichever unit system it was read in.
+    let headers = item.as_ref().map(etag_header).unwrap_or_default();
+
+    // Express the values in the requested unit system. The tag stays that of the stored
+    // units, the unit system being part of the URL.
+    if let (Some(item), Some(unit_system)) = (item.as_mut(), params.unit_system) {
+        convert_register(item, unit_system);
+    }
+
     // Return the item as a JSON response.
-    Ok(Json(item))
+    Ok((headers, Json(item)))
 }
 
-/// Handler to create a new Modbus register.
-pub async fn create(
-    State(state): State<AppState>,
-    Json(payload): Json<CreateModbusRegisterItemInput>,
-) -> Result<Json<modbus_register::Model>> {
-    let conn = state.conn.lock().await;
-    // Create an active model from the payload.
+/// Loads the device and labels of a register into the response model in stored units, the
+/// representation whose entity tag `If-Match` headers are checked against.
+async fn stored_register_model<C: ConnectionTrait>(
+    conn: &C,
+    register: &modbus_register::Model,
+) -> Result<ModbusRegisterModel> {
+    let device = match register.device_id {
+        Some(device_id) => ModbusRegisterDevices::find_by_id(device_id)
+            .one(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?,
+        None => None,
+    };
+    let mut labels = labels_by_register(conn, vec![register.id]).await?;
+    let labels = labels.remove(&register.id).unwrap_or_default();
+    Ok(register_model(register, device, labels))
+}
+
+/// Builds the active model for a new Modbus register from its create input. The timestamps
+/// of the input are only taken over when syncing, otherwise they're set on insert.
+pub fn new_register_model(
+    item: CreateModbusRegisterItemInput,
+    sync: bool,
+) -> modbus_register::ActiveModel {
     let mut model = modbus_register::ActiveModel {
-        register_address: Set(payload.register_address),
-        operation: Set(payload.operation),
-        register_length: Set(payload.register_length),
-        register_name: Set(payload.register_name),
-        data_format: Set(payload.data_format),
-        description: Set(payload.description),
-        device_id: Set(payload.device_id),
-        unit: Set(payload.unit),
+        register_address: Set(item.register_address),
+        operation: Set(item.operation),
+        register_length: Set(item.register_length),
+        register_name: Set(item.register_name),
+        data_format: Set(item.data_format),
+        description: Set(item.description),
+        device_id: Set(item.device_id),
+        unit: Set(item.unit),
+        scale: Set(item.scale),
+        offset: Set(item.offset),
+        min_value: Set(item.min_value),
+        max_value: Set(item.max_value),
+        precision: Set(item.precision),
+        default_value: Set(item.default_value),
+        version_id: Set(item.version_id),
         ..Default::default()
     };
 
     // Set optional fields if provided.
-    if payload.id.is_some() {
-        model.id = Set(payload.id.unwrap());
+    if let Some(id) = item.id {
+        model.id = Set(id);
     }
-
-    if payload.status.is_some() {
-        model.status = Set(payload.status.unwrap());
+    if let Some(status) = item.status {
+        model.status = Set(status);
     }
-    if payload.private.is_some() {
-        model.private = Set(payload.private);
+    if item.private.is_some() {
+        model.private = Set(item.private);
     }
-
-    if payload.created_at.is_some() {
-        model.created_at = Set(payload.created_at.unwrap());
+    if sync {
+        if let Some(created_at) = item.created_at {
+            model.created_at = Set(created_at);
+        }
+        if let Some(updated_at) = item.updated_at {
+            model.updated_at = Set(updated_at);
+        }
     }
 
-    if payload.updated_at.is_some() {
-        model.updated_at = Set(payload.updated_at.unwrap());
-    }
+    model
+}
+
+/// Handler to create a new Modbus register.
+pub async fn create(
+    State(state): State<AppState>,
+    Query(params): Query<CreateModbusRegisterParams>,
+    Json(payload): Json<CreateModbusRegisterItemInput>,
+) -> Result<Json<SavedModbusRegister>> {
+    let conn = state.conn.lock().await;
+    validate_register(&*conn, &RegisterDefinition::from(&payload)).await?;
 
-    // Insert the model into the database and return the created item.
-    let res = ModbusRegister::insert(model.clone())
-        .exec_with_returning(&*conn)
+    // Create an active model from the payload.
+    let model = new_register_model(payload, params.sync.unwrap_or(false));
+
+    // Insert the model into the database and record it in the history.
+    let txn = conn
+        .begin()
         .await
         .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = async {
+        let res = model
+            .insert(&txn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        let warnings = check_overlaps(&txn, &res).await?;
+        history::record(
+            &txn,
+            REGISTER_TABLE,
+            res.id,
+            HistoryAction::Create,
+            None,
+            Some(history::snapshot(&res)),
+        )
+        .await?;
+        Ok((res, warnings))
+    }
+    .await;
+    let (res, warnings) = commit_or_rollback(txn, result).await?;
 
-    Ok(Json(res.try_into_model().unwrap()))
+    Ok(Json(SavedModbusRegister {
+        register: res,
+        warnings,
+    }))
 }
 
 /// Handler to create multiple Modbus registers.
 pub async fn create_many(
     State(state): State<AppState>,
+    Query(params): Query<CreateModbusRegisterParams>,
     Json(payload): Json<Vec<CreateModbusRegisterItemInput>>,
 ) -> Result<Json<serde_json::Value>> {
     let conn = state.conn.lock().await;
-    let mut models = Vec::new();
-
-    // Create active models from the payload items.
-    for item in payload {
-        let mut model = modbus_register::ActiveModel {
-            register_address: Set(item.register_address),
-            operation: Set(item.operation),
-            register_length: Set(item.register_length),
-            register_name: Set(item.register_name),
-            data_format: Set(item.data_format),
-            description: Set(item.description),
-            device_id: Set(item.device_id),
-            unit: Set(item.unit),
-            ..Default::default()
-        };
-
-        // Set optional fields if provided.
-        if item.id.is_some() {
-            model.id = Set(item.id.unwrap());
-        }
 
-        if item.status.is_some() {
-            model.status = Set(item.status.unwrap());
+    // Validate every item up front, reporting errors with the index of the offending item.
+    let mut errors = vec![];
+    for (index, item) in payload.iter().enumerate() {
+        if let Err(Error::Validation(item_errors)) =
+            validate_register(&*conn, &RegisterDefinition::from(item)).await
+        {
+            errors.extend(item_errors.into_iter().map(|error| FieldError {
+                field: format!("[{}].{}", index, error.field),
+                message: error.message,
+            }));
         }
-        if item.private.is_some() {
-            model.private = Set(item.private);
-        }
-
-        if item.created_at.is_some() {
-            model.created_at = Set(item.created_at.unwrap());
-        }
-
-        if item.updated_at.is_some() {
-            model.updated_at = Set(item.updated_at.unwrap());
-        }
-
-        models.push(model);
     }
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    // Create active models from the payload items.
+    let sync = params.sync.unwrap_or(false);
+    let models: Vec<modbus_register::ActiveModel> = payload
+        .into_iter()
+        .map(|item| new_register_model(item, sync))
+        .collect();
     let count = models.len();
 
-    // Insert multiple models into the database.
-    ModbusRegister::insert_many(models)
-        .exec(&*conn)
+    // Insert the models one by one in a single transaction so each created row gets its
+    // own history revision. Overlaps are reported like validation errors, by item index.
+    let txn = conn
+        .begin()
         .await
         .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = async {
+        let mut warnings = vec![];
+        for (index, model) in models.into_iter().enumerate() {
+            let res = model
+                .insert(&txn)
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?;
+            let messages = check_overlaps(&txn, &res).await?;
+            warnings.extend(messages.into_iter().map(|message| FieldError {
+                field: format!("[{}].register_address", index),
+                message,
+            }));
+            history::record(
+                &txn,
+                REGISTER_TABLE,
+                res.id,
+                HistoryAction::Create,
+                None,
+                Some(history::snapshot(&res)),
+            )
+            .await?;
+        }
+        Ok(warnings)
+    }
+    .await;
+    let warnings = commit_or_rollback(txn, result).await?;
 
     // Return the count of created rows.
-    Ok(Json(json!({"created_rows_count": count})))
+    Ok(Json(
+        json!({"created_rows_count": count, "warnings": warnings}),
+    ))
 }
 
 /// Handler to update an existing Modbus register by its ID.
 pub async fn update(
     State(state): State<AppState>,
     Path(id): Path<i32>,
+    headers: HeaderMap,
     Json(payload): Json<UpdateModbusRegisterItemInput>,
-) -> Result<Json<modbus_register::Model>> {
+) -> Result<(HeaderMap, Json<SavedModbusRegister>)> {
     let conn = state.conn.lock().await;
-    // Fetch the existing model by ID and convert it to an active model.
-    let mut model = Into::<modbus_register::ActiveModel>::into(
-        ModbusRegister::find_by_id(id)
-            .one(&*conn)
-            .await
-            .map_err(|error| Error::DbError(error.to_string()))
-            .unwrap()
-            .ok_or(Error::NotFound)?,
-    );
+    // Fetch the existing model by ID.
+    let existing = ModbusRegister::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+    check_if_match(&headers, &stored_register_model(&*conn, &existing).await?)?;
+
+    // Save the updated model to the database and record the change in the history.
+    let txn = conn
+        .begin()
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = update_register(&txn, existing, payload).await;
+    let (updated_item, warnings) = commit_or_rollback(txn, result).await?;
+    let headers = etag_header(&stored_register_model(&*conn, &updated_item).await?);
+
+    Ok((
+        headers,
+        Json(SavedModbusRegister {
+            register: updated_item,
+            warnings,
+        }),
+    ))
+}
+
+/// Whether editing a register marks it as "UPDATED": public registers that are published, under
+/// review or in revision. Registers without a private flag count as private.
+pub fn marks_as_updated(register: &modbus_register::Model) -> bool {
+    register.private == Some(false)
+        && ["PUBLISHED", "UNDER_REVIEW", "REVISION"].contains(&register.status.as_str())
+}
+
+/// Applies the provided fields of an update to a register, validates and saves it, and
+/// records the change in the history. Returns the register with its overlap warnings.
+pub async fn update_register<C: ConnectionTrait>(
+    conn: &C,
+    existing: modbus_register::Model,
+    payload: UpdateModbusRegisterItemInput,
+) -> Result<(modbus_register::Model, Vec<String>)> {
+    let id = existing.id;
+    let before = history::snapshot(&existing);
+    let touched = touched_fields(&payload);
+    let mark_updated = payload.status.is_none() && marks_as_updated(&existing);
+    let mut model = Into::<modbus_register::ActiveModel>::into(existing);
 
     // Update the status if conditions are met.
-    if None == payload.status
-        && model.private.clone().unwrap().unwrap_or(true) == false
-        && (model.status.clone().unwrap() == "PUBLISHED".to_string()
-            || model.status.clone().unwrap() == "UNDER_REVIEW".to_string()
-            || model.status.clone().unwrap() == "REVISION".to_string())
-    {
+    if mark_updated {
         model.status = Set("UPDATED".to_string());
     }
 
@@ -343,44 +610,134 @@ pub async fn update(
     if let Some(private) = payload.private {
         model.private = Set(private);
     }
+    if let Some(scale) = payload.scale {
+        model.scale = Set(scale);
+    }
+    if let Some(offset) = payload.offset {
+        model.offset = Set(offset);
+    }
+    if let Some(min_value) = payload.min_value {
+        model.min_value = Set(min_value);
+    }
+    if let Some(max_value) = payload.max_value {
+        model.max_value = Set(max_value);
+    }
+    if let Some(precision) = payload.precision {
+        model.precision = Set(precision);
+    }
+    if let Some(default_value) = payload.default_value {
+        model.default_value = Set(default_value);
+    }
+    if let Some(version_id) = payload.version_id {
+        model.version_id = Set(version_id);
+    }
+
+    validate_register_update(
+        conn,
+        &model
+            .clone()
+            .try_into_model()
+            .map_err(|error| Error::ServerError(error.to_string()))?,
+        &touched,
+    )
+    .await?;
 
-    // Save the updated model to the database and return the updated item.
     let updated_item = model
-        .save(&*conn)
+        .save(conn)
         .await
-        .map_err(|error| Error::DbError(error.to_string()))?;
-
-    Ok(Json(updated_item.try_into_model().unwrap()))
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .try_into_model()
+        .map_err(|error| Error::ServerError(error.to_string()))?;
+    // Registers that already overlap stay editable as long as their range isn't touched.
+    let warnings = if touched.iter().any(|field| {
+        [
+            "register_address",
+            "register_length",
+            "operation",
+            "device_id",
+        ]
+        .contains(field)
+    }) {
+        check_overlaps(conn, &updated_item).await?
+    } else {
+        vec![]
+    };
+    history::record(
+        conn,
+        REGISTER_TABLE,
+        id,
+        HistoryAction::Update,
+        Some(before),
+        Some(history::snapshot(&updated_item)),
+    )
+    .await?;
+
+    Ok((updated_item, warnings))
 }
 
 /// Handler to delete a Modbus register by its ID.
-pub async fn delete(State(state): State<AppState>, Path(id): Path<i32>) -> Result<Json<String>> {
+pub async fn delete(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Json<String>> {
     let conn = state.conn.lock().await;
-    let item = ModbusRegister::find_by_id(id).one(&*conn).await;
-
-    match item {
-        // If the item is found and its status is "NEW" or "DELETED", delete it from the database.
-        Ok(Some(item)) => {
-            if item.status == "NEW" || item.status == "DELETED" {
-                ModbusRegister::delete_by_id(id)
-                    .exec(&*conn)
-                    .await
-                    .map_err(|error| Error::DbError(error.to_string()))?;
-                Ok(Json("Deleted successfully".to_string()))
-            } else {
-                // Otherwise, update its status to "DELETED".
-                let mut updated_item = modbus_register::ActiveModel::from(item);
-                updated_item.status = Set("DELETED".to_string());
-                updated_item
-                    .save(&*conn)
-                    .await
-                    .map_err(|error| Error::DbError(error.to_string()))?;
-                Ok(Json("Deleted successfully".to_string()))
-            }
-        }
-        // If the item is not found, return an error.
-        Ok(None) => Err(Error::NotFound),
-        // Handle any database errors.
-        Err(error) => Err(Error::DbError(error.to_string())),
-    }
+    let item = ModbusRegister::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+    check_if_match(&headers, &stored_register_model(&*conn, &item).await?)?;
+
+    let txn = conn
+        .begin()
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = delete_register(&txn, item).await;
+    commit_or_rollback(txn, result).await?;
+
+    Ok(Json("Deleted successfully".to_string()))
+}
+
+/// Deletes a register and records the deletion in the history. Registers that are "NEW" or
+/// already "DELETED" are removed, others are marked "DELETED" so the deletion can be synced.
+/// Returns the marked register, or None when it was removed.
+pub async fn delete_register<C: ConnectionTrait>(
+    conn: &C,
+    item: modbus_register::Model,
+) -> Result<Option<modbus_register::Model>> {
+    let id = item.id;
+    let before = history::snapshot(&item);
+
+    // If the item's status is "NEW" or "DELETED", delete it from the database.
+    let deleted = if item.status == "NEW" || item.status == "DELETED" {
+        ModbusRegister::delete_by_id(id)
+            .exec(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        None
+    } else {
+        // Otherwise, update its status to "DELETED", keeping the status to restore.
+        let mut updated_item = modbus_register::ActiveModel::from(item.clone());
+        updated_item.status = Set("DELETED".to_string());
+        updated_item.previous_status = Set(Some(item.status));
+        updated_item.deleted_at = Set(Some(Utc::now()));
+        let updated_item = updated_item
+            .update(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        Some(updated_item)
+    };
+
+    history::record(
+        conn,
+        REGISTER_TABLE,
+        id,
+        HistoryAction::Delete,
+        Some(before),
+        deleted.as_ref().map(history::snapshot),
+    )
+    .await?;
+
+    Ok(deleted)
 }
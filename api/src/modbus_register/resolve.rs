@@ -0,0 +1,227 @@
+use std::cmp::Reverse;
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use sea_orm::{prelude::*, ConnectionTrait, QueryOrder};
+
+use super::inputs::{
+    ProductCandidate, ProductResolution, ProductResolutionQueryParams, ProductResolutionSource,
+    ResolvedRegister,
+};
+use super::live::{function_codes, register_format};
+use super::queries::{generate_filter_query, register_model};
+use super::units::{convert_register, UnitSystem};
+use super::value_labels::labels_by_register;
+use super::versions::{includes_firmware, parse_firmware};
+use crate::{
+    app_state::AppState,
+    entity::{
+        modbus_register, modbus_register_devices as devices,
+        modbus_register_product_device_mapping as device_mappings,
+        modbus_register_value_labels as value_labels, prelude::*,
+    },
+    error::{Error, Result},
+    modbus_tcp::simulator::RegisterTable,
+};
+
+/// Name of the setting holding the ID of the device used for products that aren't mapped
+/// to any device. Without it, resolving an unmapped product fails with "not found".
+pub const FALLBACK_DEVICE_SETTING: &str = "product_fallback_device";
+
+// Read the fallback device, ignoring a setting that doesn't name an existing device.
+async fn fallback_device<C: ConnectionTrait>(conn: &C) -> Result<Option<devices::Model>> {
+    let setting = ModbusRegisterSettings::find_by_id(FALLBACK_DEVICE_SETTING)
+        .one(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let Some(device_id) = setting
+        .and_then(|setting| setting.value)
+        .and_then(|value| value.trim().parse::<i32>().ok())
+    else {
+        return Ok(None);
+    };
+
+    ModbusRegisterDevices::find_by_id(device_id)
+        .filter(devices::Column::Status.ne("DELETED"))
+        .one(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))
+}
+
+// Evaluate a candidate device for a firmware. The rank of its firmware match puts devices
+// with a version covering the firmware first, then devices without versions, whose maps
+// apply to any firmware, and then devices whose versions are all for other firmware.
+async fn evaluate_candidate<C: ConnectionTrait>(
+    conn: &C,
+    device: devices::Model,
+    firmware: Option<&[u64]>,
+) -> Result<(u8, ProductCandidate)> {
+    let versions = device
+        .find_related(ModbusRegisterDeviceVersions)
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let (rank, version) = match firmware {
+        None => (0, None),
+        Some(firmware) => match versions
+            .iter()
+            .find(|version| includes_firmware(version, firmware))
+        {
+            Some(version) => (0, Some(version.clone())),
+            None if versions.is_empty() => (1, None),
+            None => (2, None),
+        },
+    };
+
+    let version_ids = firmware.map(|_| version.iter().map(|version| version.id).collect());
+    let register_count = generate_filter_query(&None, &Some(device.id), false, version_ids)
+        .count(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok((
+        rank,
+        ProductCandidate {
+            device,
+            version,
+            register_count,
+        },
+    ))
+}
+
+// Describe how a register is read: its data table, function codes and data format, with
+// the scaling of the requested unit system.
+fn resolved_register(
+    register: &modbus_register::Model,
+    labels: Vec<value_labels::Model>,
+    unit_system: Option<UnitSystem>,
+) -> ResolvedRegister {
+    let mut model = register_model(register, None, labels);
+    if let Some(unit_system) = unit_system {
+        convert_register(&mut model, unit_system);
+    }
+
+    let codes = function_codes(register);
+    let format = register_format(&modbus_register::Model {
+        scale: model.scale,
+        offset: model.offset,
+        ..register.clone()
+    });
+    ResolvedRegister {
+        table: RegisterTable::from_function_codes(&codes),
+        function_codes: codes,
+        words: format
+            .as_ref()
+            .ok()
+            .map(|format| format.words(register.register_length)),
+        format_error: format.as_ref().err().cloned(),
+        format: format.ok(),
+        register: model,
+    }
+}
+
+/// Resolves the product ID reported by a T3 controller to a device and its register map.
+///
+/// Every device the product is mapped to is a candidate. Candidates are ranked by how well
+/// their firmware versions match the given firmware, then published devices come first,
+/// then devices with more registers, then the most recently updated ones. When the product
+/// isn't mapped to any device, the device of the fallback setting is used.
+pub async fn resolve_product<C: ConnectionTrait>(
+    conn: &C,
+    product_id: i32,
+    params: &ProductResolutionQueryParams,
+) -> Result<ProductResolution> {
+    let firmware = match params.firmware.as_deref() {
+        Some(firmware) => Some(parse_firmware(firmware).ok_or(Error::BadRequest(format!(
+            "\"{}\" is not a firmware version",
+            firmware
+        )))?),
+        None => None,
+    };
+
+    let mut resolved_by = ProductResolutionSource::Mapping;
+    let mut devices = ModbusRegisterDevices::find()
+        .inner_join(ModbusRegisterProductDeviceMapping)
+        .filter(device_mappings::Column::ProductId.eq(product_id))
+        .filter(devices::Column::Status.ne("DELETED"))
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    if devices.is_empty() {
+        resolved_by = ProductResolutionSource::Fallback;
+        devices = fallback_device(conn).await?.into_iter().collect::<Vec<_>>();
+    }
+
+    let mut candidates = vec![];
+    for device in devices {
+        candidates.push(evaluate_candidate(conn, device, firmware.as_deref()).await?);
+    }
+    candidates.sort_by_key(|(rank, candidate)| {
+        (
+            *rank,
+            candidate.device.status != "PUBLISHED",
+            Reverse(candidate.register_count),
+            Reverse(candidate.device.updated_at),
+            candidate.device.id,
+        )
+    });
+    let candidates: Vec<ProductCandidate> = candidates
+        .into_iter()
+        .map(|(_, candidate)| candidate)
+        .collect();
+    let best = candidates.first().ok_or(Error::NotFound)?;
+
+    // Load the register map of the best candidate, restricted to its matching version.
+    let version_ids = firmware
+        .as_ref()
+        .map(|_| best.version.iter().map(|version| version.id).collect());
+    let registers = generate_filter_query(&None, &Some(best.device.id), false, version_ids)
+        .order_by_asc(modbus_register::Column::Operation)
+        .order_by_asc(modbus_register::Column::RegisterAddress)
+        .order_by_asc(modbus_register::Column::Id)
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let mut labels = labels_by_register(
+        conn,
+        registers.iter().map(|(register, _)| register.id).collect(),
+    )
+    .await?;
+    let registers = registers
+        .iter()
+        .map(|(register, _)| {
+            let register_labels = labels.remove(&register.id).unwrap_or_default();
+            resolved_register(register, register_labels, params.unit_system)
+        })
+        .collect();
+
+    let image = match best.device.image_id {
+        Some(image_id) => Files::find_by_id(image_id)
+            .one(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?,
+        None => None,
+    };
+
+    Ok(ProductResolution {
+        product_id,
+        resolved_by,
+        device: best.device.clone(),
+        image,
+        version: best.version.clone(),
+        registers,
+        candidates,
+    })
+}
+
+/// Handler to resolve a T3 product ID to its device, image and decoded register map.
+pub async fn resolve(
+    State(state): State<AppState>,
+    Path(product_id): Path<i32>,
+    Query(params): Query<ProductResolutionQueryParams>,
+) -> Result<Json<ProductResolution>> {
+    let conn = state.conn.lock().await;
+    resolve_product(&*conn, product_id, &params).await.map(Json)
+}
@@ -0,0 +1,182 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use sea_orm::{prelude::*, QueryOrder, QuerySelect, Set, TransactionTrait};
+
+use super::history::{self, HistoryAction, DEVICE_TABLE, REGISTER_TABLE};
+use super::inputs::{DeletedItems, DeletedQueryParams};
+use super::pagination::MAX_PAGE_SIZE;
+use crate::{
+    app_state::AppState,
+    db_connection::commit_or_rollback,
+    entity::modbus_register::{self, Entity as ModbusRegister},
+    entity::modbus_register_devices::{self as devices, Entity as ModbusRegisterDevices},
+    error::{Error, Result},
+};
+
+// Items deleted before their previous status was kept are restored as updated, so the next
+// sync reviews them.
+const FALLBACK_STATUS: &str = "UPDATED";
+
+// Give a soft-deleted register its previous status back and record it in the history.
+async fn restore_register_model<C: ConnectionTrait>(
+    conn: &C,
+    register: modbus_register::Model,
+) -> Result<modbus_register::Model> {
+    let before = history::snapshot(&register);
+    let mut model = modbus_register::ActiveModel::from(register.clone());
+    model.status = Set(register
+        .previous_status
+        .unwrap_or(FALLBACK_STATUS.to_string()));
+    model.previous_status = Set(None);
+    model.deleted_at = Set(None);
+    let restored = model
+        .update(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    history::record(
+        conn,
+        REGISTER_TABLE,
+        restored.id,
+        HistoryAction::Restore,
+        Some(before),
+        Some(history::snapshot(&restored)),
+    )
+    .await?;
+
+    Ok(restored)
+}
+
+/// Handler to restore a soft-deleted Modbus register to the status it had before.
+pub async fn restore_register(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<modbus_register::Model>> {
+    let conn = state.conn.lock().await;
+    let register = ModbusRegister::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+    if register.status != "DELETED" {
+        return Err(Error::BadRequest(format!(
+            "Register {} isn't deleted",
+            register.id
+        )));
+    }
+    if let Some(device_id) = register.device_id {
+        let device = ModbusRegisterDevices::find_by_id(device_id)
+            .one(&*conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        if device.is_some_and(|device| device.status == "DELETED") {
+            return Err(Error::BadRequest(format!(
+                "Device {} of the register is deleted, restore the device instead",
+                device_id
+            )));
+        }
+    }
+
+    let txn = conn
+        .begin()
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = restore_register_model(&txn, register).await;
+    let restored = commit_or_rollback(txn, result).await?;
+
+    Ok(Json(restored))
+}
+
+/// Handler to restore a soft-deleted device to the status it had before, together with the
+/// registers that were deleted with it.
+pub async fn restore_device(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<devices::Model>> {
+    let conn = state.conn.lock().await;
+    let device = ModbusRegisterDevices::find_by_id(id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+    if device.status != "DELETED" {
+        return Err(Error::BadRequest(format!("Device {} isn't deleted", id)));
+    }
+
+    let txn = conn
+        .begin()
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = async {
+        // Registers deleted with the device share its deletion time. Registers deleted on
+        // their own stay deleted.
+        if let Some(deleted_at) = device.deleted_at {
+            let registers = ModbusRegister::find()
+                .filter(modbus_register::Column::DeviceId.eq(id))
+                .filter(modbus_register::Column::Status.eq("DELETED"))
+                .filter(modbus_register::Column::DeletedAt.eq(deleted_at))
+                .all(&txn)
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?;
+            for register in registers {
+                restore_register_model(&txn, register).await?;
+            }
+        }
+
+        let before = history::snapshot(&device);
+        let mut model = devices::ActiveModel::from(device.clone());
+        model.status = Set(device
+            .previous_status
+            .unwrap_or(FALLBACK_STATUS.to_string()));
+        model.previous_status = Set(None);
+        model.deleted_at = Set(None);
+        let restored = model
+            .update(&txn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        history::record(
+            &txn,
+            DEVICE_TABLE,
+            id,
+            HistoryAction::Restore,
+            Some(before),
+            Some(history::snapshot(&restored)),
+        )
+        .await?;
+        Ok(restored)
+    }
+    .await;
+    let restored = commit_or_rollback(txn, result).await?;
+
+    Ok(Json(restored))
+}
+
+/// Handler to list the soft-deleted registers and devices, most recently deleted first.
+/// Items deleted before deletion times were kept come last.
+pub async fn recently_deleted(
+    State(state): State<AppState>,
+    Query(params): Query<DeletedQueryParams>,
+) -> Result<Json<DeletedItems>> {
+    let conn = state.conn.lock().await;
+    let limit = params.limit.unwrap_or(100).min(MAX_PAGE_SIZE);
+
+    let registers = ModbusRegister::find()
+        .filter(modbus_register::Column::Status.eq("DELETED"))
+        .order_by_desc(modbus_register::Column::DeletedAt)
+        .order_by_desc(modbus_register::Column::Id)
+        .limit(limit)
+        .all(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let devices = ModbusRegisterDevices::find()
+        .filter(devices::Column::Status.eq("DELETED"))
+        .order_by_desc(devices::Column::DeletedAt)
+        .order_by_desc(devices::Column::Id)
+        .limit(limit)
+        .all(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(DeletedItems { registers, devices }))
+}
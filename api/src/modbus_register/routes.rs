@@ -1,31 +1,87 @@
 use axum::{
+    extract::DefaultBodyLimit,
     middleware,
     routing::{delete, get, patch, post},
     Router,
 };
 
 // Import the route handler modules
-use super::{devices, product_device_mappings, queries, settings};
-use crate::{app_state::AppState, auth::require_auth};
+use super::{
+    bulk, catalogue, codec, csv_import, devices, exports, facets, history, imports, live, map_lint,
+    product_device_mappings, queries, resolve, restore, settings, simulator, templates, units,
+    value_labels, versions,
+};
+use crate::{app_state::AppState, auth::require_auth, idempotency::idempotent};
 
 /// Configures the routes for Modbus register-related endpoints.
 /// Returns a `Router` with all the defined routes.
-pub fn modbus_register_routes() -> Router<AppState> {
+pub fn modbus_register_routes(app_state: AppState) -> Router<AppState> {
     // Define open routes that do not require authentication
     let open_routes = Router::new()
         .route("/modbus-registers", get(queries::list)) // List all Modbus registers
         .route("/modbus-registers/:id", get(queries::get_one)) // Get a single Modbus register by ID
+        .route("/modbus-registers/export", get(exports::export_registers)) // Export the filtered Modbus registers
+        .route("/modbus-registers/facets", get(facets::facets)) // Count the filtered Modbus registers per data format, unit, operation, status and device
+        .route("/modbus-registers/decode", post(codec::decode_value)) // Decode raw register words into a value
+        .route("/modbus-registers/encode", post(codec::encode_value)) // Encode a value into raw register words
+        .route(
+            "/modbus-registers/:id/history",
+            get(history::register_history),
+        ) // Get the change history of a Modbus register
+        .route("/modbus-registers/:id/labels", get(value_labels::list)) // List the enumeration values and bit fields of a Modbus register
+        .route("/modbus-register/units", get(units::list)) // List the unit catalogue
+        .route("/modbus-register/deleted", get(restore::recently_deleted)) // List the recently deleted Modbus registers and devices
         .route("/modbus-register/settings", get(settings::get_all)) // Get all settings
         .route(
             "/modbus-register/settings/:name",
             get(settings::get_by_name),
         ) // Get settings by name
         .route("/modbus-register/devices", get(devices::get_all)) // Get all devices
+        .route("/modbus-register/devices/facets", get(devices::facets)) // Count the devices per manufacturer and category
         .route("/modbus-register/devices/:id", get(devices::get_by_id)) // Get a device by ID
+        .route(
+            "/modbus-register/devices/:id/history",
+            get(history::device_history),
+        ) // Get the change history of a device
+        .route(
+            "/modbus-register/devices/:id/dependencies",
+            get(devices::dependencies),
+        ) // List what deleting a device would affect
+        .route(
+            "/modbus-register/devices/:id/export",
+            get(exports::export_device),
+        ) // Export the register map of a device
+        .route(
+            "/modbus-register/devices/:id/lint",
+            get(map_lint::lint_device),
+        ) // List overlaps, gaps and incomplete registers in the register map of a device
+        .route("/modbus-register/devices/:id/versions", get(versions::list)) // List the firmware versions of the register map of a device
+        .route(
+            "/modbus-register/devices/:id/versions/diff",
+            get(versions::diff),
+        ) // Compare the register maps of two versions of a device
         .route(
             "/modbus-register/devices/remote_id/:id",
             get(devices::get_by_remote_id),
         ) // Get a device by its remote ID
+        .route(
+            "/modbus-register/manufacturers",
+            get(catalogue::list_manufacturers),
+        ) // List the manufacturers
+        .route(
+            "/modbus-register/manufacturers/:id",
+            get(catalogue::get_manufacturer),
+        ) // Get a manufacturer by ID
+        .route(
+            "/modbus-register/categories",
+            get(catalogue::list_categories),
+        ) // List the device categories
+        .route(
+            "/modbus-register/categories/:id",
+            get(catalogue::get_category),
+        ) // Get a device category by ID
+        .route("/modbus-register/simulators", get(simulator::list)) // List the running device simulators
+        .route("/modbus-register/simulators/:id", get(simulator::get_by_id)) // Get a running device simulator with its current register values
         .route(
             "/modbus-register/product_device_mappings",
             get(product_device_mappings::get_all),
@@ -33,34 +89,125 @@ pub fn modbus_register_routes() -> Router<AppState> {
         .route(
             "/modbus-register/product_device_mappings/:id",
             get(product_device_mappings::get_by_id),
-        ); // Get a product-device mapping by ID
+        ) // Get the device mapping of a product
+        .route(
+            "/modbus-register/products/:product_id",
+            get(resolve::resolve),
+        ); // Resolve a T3 product ID to its device, image and register map
 
-    // Define protected routes that require authentication
-    let protected_routes = Router::new()
+    // Define the create routes, which can be retried safely with an `Idempotency-Key` header
+    let create_routes = Router::new()
         .route("/modbus-registers", post(queries::create)) // Create a new Modbus register
         .route("/modbus-registers/create_many", post(queries::create_many)) // Create many Modbus registers
+        .route("/modbus-registers/:id/labels", post(value_labels::create)) // Add an enumeration value or bit field to a Modbus register
+        .route("/modbus-register/settings", post(settings::create)) // Create new settings
+        .route("/modbus-register/devices", post(devices::create)) // Create a new device
+        .route(
+            "/modbus-register/devices/:id/versions",
+            post(versions::create),
+        ) // Add a firmware version to the register map of a device
+        .route(
+            "/modbus-register/devices/:id/clone",
+            post(templates::clone_device),
+        ) // Clone a device with its registers, labels and product mappings
+        .route(
+            "/modbus-register/manufacturers",
+            post(catalogue::create_manufacturer),
+        ) // Create a new manufacturer
+        .route(
+            "/modbus-register/categories",
+            post(catalogue::create_category),
+        ) // Create a new device category
+        .route(
+            "/modbus-register/product_device_mappings",
+            post(product_device_mappings::create),
+        ) // Create a new product-device mapping
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            idempotent,
+        ));
+
+    // Define protected routes that require authentication
+    let protected_routes = Router::new()
+        .merge(create_routes)
+        .route("/modbus-registers/bulk", post(imports::bulk_import)) // Import many Modbus registers with a per-row report
+        .route("/modbus-registers/bulk_update", post(bulk::bulk_update)) // Update many Modbus registers by ID or filter expression
+        .route("/modbus-registers/bulk_delete", post(bulk::bulk_delete)) // Delete many Modbus registers by ID or filter expression
         .route(
             "/modbus-registers/:id",
             patch(queries::update).delete(queries::delete),
         ) // Update or delete a Modbus register by ID
-        .route("/modbus-register/settings", post(settings::create)) // Create new settings
+        .route(
+            "/modbus-registers/:id/revert/:revision",
+            post(history::revert_register),
+        ) // Revert a Modbus register to a prior revision
+        .route(
+            "/modbus-registers/:id/restore",
+            post(restore::restore_register),
+        ) // Restore a deleted Modbus register
+        .route(
+            "/modbus-registers/:id/labels/:label_id",
+            patch(value_labels::update).delete(value_labels::delete),
+        ) // Update or delete an enumeration value or bit field of a Modbus register
         .route(
             "/modbus-register/settings/:name",
             patch(settings::update).delete(settings::delete),
         ) // Update or delete settings by name
-        .route("/modbus-register/devices", post(devices::create)) // Create a new device
         .route(
             "/modbus-register/devices/:id",
             patch(devices::update).delete(devices::delete),
         ) // Update or delete a device by ID
         .route(
-            "/modbus-register/product_device_mappings",
-            post(product_device_mappings::create),
-        ) // Create a new product-device mapping
+            "/modbus-register/devices/:id/revert/:revision",
+            post(history::revert_device),
+        ) // Revert a device to a prior revision
+        .route(
+            "/modbus-register/devices/:id/restore",
+            post(restore::restore_device),
+        ) // Restore a deleted device with the registers deleted along with it
+        .route(
+            "/modbus-register/devices/:id/versions/:version_id",
+            patch(versions::update).delete(versions::delete),
+        ) // Update or delete a firmware version of a device
+        .route(
+            "/modbus-register/devices/:id/resync",
+            post(templates::resync_device),
+        ) // Re-sync a cloned device with its template
+        .route(
+            "/modbus-register/devices/:id/import",
+            post(csv_import::import_register_map)
+                .layer(DefaultBodyLimit::max(1024 * 1000 * 20) /* 20 MB */),
+        ) // Import a CSV/TSV register map into a device
+        .route(
+            "/modbus-register/devices/:id/live/read",
+            post(live::read_device),
+        ) // Read the register map of a device from a live Modbus TCP server
+        .route(
+            "/modbus-register/devices/:id/live/write",
+            post(live::write_register),
+        ) // Write a register of a device on a live Modbus TCP server
+        .route(
+            "/modbus-register/manufacturers/:id",
+            patch(catalogue::update_manufacturer).delete(catalogue::delete_manufacturer),
+        ) // Update or delete a manufacturer by ID
+        .route(
+            "/modbus-register/categories/:id",
+            patch(catalogue::update_category).delete(catalogue::delete_category),
+        ) // Update or delete a device category by ID
+        .route("/modbus-register/simulators", post(simulator::start)) // Start a Modbus TCP simulator for a device
+        .route("/modbus-register/simulators/:id", delete(simulator::stop)) // Stop a device simulator
+        .route(
+            "/modbus-register/simulators/:id/registers/:register_id",
+            patch(simulator::update_register),
+        ) // Set the value or waveform of a simulated register
         .route(
             "/modbus-register/product_device_mappings/:id",
             delete(product_device_mappings::delete),
-        ) // Delete a product-device mapping by ID
+        ) // Delete the device mappings of a product
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            history::track_author,
+        )) // Record who makes the changes
         .route_layer(middleware::from_fn(require_auth)); // Apply authentication middleware to all protected routes
 
     // Combine open and protected routes into a single router
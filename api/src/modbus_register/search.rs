@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, HashMap};
+
+use sea_orm::{
+    prelude::*,
+    sea_query::{Expr, SimpleExpr},
+    ConnectionTrait, DbBackend, Statement,
+};
+
+use super::exports::escape_html;
+use super::inputs::SearchMatch;
+use crate::error::{Error, Result};
+
+// Columns of the full-text index, in index order, as named in the highlights of a match.
+const SEARCH_COLUMNS: [&str; 4] = [
+    "register_name",
+    "description",
+    "device_name",
+    "device_description",
+];
+
+// Relevance weights of the columns: matches in register names count the most.
+const RANK: &str = "bm25(modbus_register_search, 10.0, 2.0, 5.0, 1.0)";
+// Relevance weights of the device index: matches in device names count the most.
+const DEVICE_RANK: &str = "bm25(modbus_register_device_search, 5.0, 1.0)";
+
+// Matched terms are marked with control characters, so the text around them can be escaped
+// before the markers become <mark> tags.
+const MARK_START: char = '\u{2}';
+const MARK_END: char = '\u{3}';
+
+// Quote a term so FTS5 reads it as text rather than as query syntax.
+fn quote(term: &str) -> String {
+    format!("\"{}\"", term.replace('"', "\"\""))
+}
+
+/// Converts a search query to an FTS5 match expression.
+///
+/// Words match whole tokens, words ending in `*` match token prefixes and text in double
+/// quotes matches as a phrase, which may end in `*` too. Every term has to match. Query
+/// syntax such as `OR`, `NEAR` or column filters is searched for as text. Returns None
+/// when the query has nothing to search for.
+pub fn match_expression(query: &str) -> Option<String> {
+    let mut terms = vec![];
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let text: String = if c == '"' {
+            chars.next();
+            chars.by_ref().take_while(|c| *c != '"').collect()
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            word
+        };
+        let (text, prefix) = match text.strip_suffix('*') {
+            Some(text) => (text, true),
+            None => (text.as_str(), chars.next_if_eq(&'*').is_some()),
+        };
+
+        // Terms without letters or digits have no tokens to match.
+        if text.chars().any(char::is_alphanumeric) {
+            terms.push(format!("{}{}", quote(text), if prefix { "*" } else { "" }));
+        }
+    }
+
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Matches the registers found by a match expression.
+pub fn search_condition(expression: &str) -> SimpleExpr {
+    Expr::cust_with_values(
+        "modbus_register.id IN (SELECT rowid FROM modbus_register_search WHERE modbus_register_search MATCH ?)",
+        [expression],
+    )
+}
+
+/// Relevance of a register for a match expression, lower is more relevant.
+pub fn search_rank(expression: &str) -> SimpleExpr {
+    Expr::cust_with_values(
+        format!(
+            "(SELECT {} FROM modbus_register_search WHERE modbus_register_search MATCH ? AND rowid = modbus_register.id)",
+            RANK
+        ),
+        [expression],
+    )
+}
+
+/// Matches the devices found by a match expression in their name or description.
+pub fn device_search_condition(expression: &str) -> SimpleExpr {
+    Expr::cust_with_values(
+        "modbus_register_devices.id IN (SELECT rowid FROM modbus_register_device_search WHERE modbus_register_device_search MATCH ?)",
+        [expression],
+    )
+}
+
+/// Relevance of a device for a match expression, lower is more relevant.
+pub fn device_search_rank(expression: &str) -> SimpleExpr {
+    Expr::cust_with_values(
+        format!(
+            "(SELECT {} FROM modbus_register_device_search WHERE modbus_register_device_search MATCH ? AND rowid = modbus_register_devices.id)",
+            DEVICE_RANK
+        ),
+        [expression],
+    )
+}
+
+// Turn a snippet into HTML with the matched terms in <mark> tags.
+fn highlight(snippet: &str) -> String {
+    escape_html(snippet)
+        .replace(MARK_START, "<mark>")
+        .replace(MARK_END, "</mark>")
+}
+
+/// Loads the relevance and the highlighted snippets of the given registers for a match
+/// expression. Only the columns that matched are highlighted.
+pub async fn search_matches<C: ConnectionTrait>(
+    conn: &C,
+    expression: &str,
+    register_ids: Vec<i32>,
+) -> Result<HashMap<i32, SearchMatch>> {
+    let mut matches = HashMap::new();
+    let snippets = (0..SEARCH_COLUMNS.len())
+        .map(|column| {
+            format!(
+                "snippet(modbus_register_search, {}, '{}', '{}', '…', 12) AS snippet_{}",
+                column, MARK_START, MARK_END, column
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    // SQLite limits the number of bound parameters, so large pages are loaded in chunks.
+    for ids in register_ids.chunks(500) {
+        let sql = format!(
+            "SELECT rowid AS id, {} AS rank, {} FROM modbus_register_search \
+             WHERE modbus_register_search MATCH ? AND rowid IN ({})",
+            RANK,
+            snippets,
+            vec!["?"; ids.len()].join(", ")
+        );
+        let mut values: Vec<Value> = vec![expression.into()];
+        values.extend(ids.iter().map(|id| Value::from(*id)));
+
+        let rows = conn
+            .query_all(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                sql,
+                values,
+            ))
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        for row in rows {
+            let id: i32 = row
+                .try_get("", "id")
+                .map_err(|error| Error::DbError(error.to_string()))?;
+            let rank: f64 = row
+                .try_get("", "rank")
+                .map_err(|error| Error::DbError(error.to_string()))?;
+            let mut highlights = BTreeMap::new();
+            for (column, name) in SEARCH_COLUMNS.iter().enumerate() {
+                let snippet: Option<String> = row
+                    .try_get("", &format!("snippet_{}", column))
+                    .map_err(|error| Error::DbError(error.to_string()))?;
+                if let Some(snippet) = snippet.filter(|snippet| snippet.contains(MARK_START)) {
+                    highlights.insert(name.to_string(), highlight(&snippet));
+                }
+            }
+            matches.insert(id, SearchMatch { rank, highlights });
+        }
+    }
+
+    Ok(matches)
+}
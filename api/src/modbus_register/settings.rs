@@ -1,5 +1,6 @@
 use axum::extract::Path;
 use axum::extract::State;
+use axum::http::HeaderMap;
 use axum::Json;
 use sea_orm::entity::prelude::*;
 use sea_orm::ActiveValue::NotSet;
@@ -9,6 +10,7 @@ use crate::app_state::AppState;
 use crate::entity::modbus_register_settings as settings;
 use crate::entity::prelude::*;
 use crate::error::{Error, Result};
+use crate::etag::{check_if_match, etag_header};
 
 use super::inputs::UpdateSettingInput;
 
@@ -24,7 +26,7 @@ pub async fn get_all(State(state): State<AppState>) -> Result<Json<Vec<settings:
 pub async fn get_by_name(
     State(state): State<AppState>,
     Path(name): Path<String>,
-) -> Result<Json<settings::Model>> {
+) -> Result<(HeaderMap, Json<settings::Model>)> {
     let conn = state.conn.lock().await;
     let result = ModbusRegisterSettings::find_by_id(name)
         .one(&*conn)
@@ -32,7 +34,7 @@ pub async fn get_by_name(
         .map_err(|error| Error::DbError(error.to_string()))
         .unwrap();
     match result {
-        Some(item) => Ok(Json(item)),
+        Some(item) => Ok((etag_header(&item), Json(item))),
         None => Err(Error::NotFound),
     }
 }
@@ -53,15 +55,17 @@ pub async fn create(
 pub async fn update(
     State(state): State<AppState>,
     Path(name): Path<String>,
+    headers: HeaderMap,
     Json(item): Json<UpdateSettingInput>,
-) -> Result<Json<settings::Model>> {
+) -> Result<(HeaderMap, Json<settings::Model>)> {
     let conn = state.conn.lock().await;
-    let setting: settings::ActiveModel = ModbusRegisterSettings::find_by_id(name)
+    let existing = ModbusRegisterSettings::find_by_id(name)
         .one(&*conn)
         .await
         .map_err(|error| Error::DbError(error.to_string()))?
-        .ok_or(Error::NotFound)
-        .map(Into::into)?;
+        .ok_or(Error::NotFound)?;
+    check_if_match(&headers, &existing)?;
+    let setting: settings::ActiveModel = existing.into();
 
     let result = settings::ActiveModel {
         name: setting.name,
@@ -79,20 +83,21 @@ pub async fn update(
     .map_err(|error| Error::DbError(error.to_string()))
     .unwrap();
 
-    Ok(Json(result))
+    Ok((etag_header(&result), Json(result)))
 }
 
 pub async fn delete(
     State(state): State<AppState>,
     Path(name): Path<String>,
+    headers: HeaderMap,
 ) -> Result<Json<settings::Model>> {
     let conn = state.conn.lock().await;
-    let setting = ModbusRegisterSettings::find_by_id(&name)
+    let setting: settings::Model = ModbusRegisterSettings::find_by_id(&name)
         .one(&*conn)
         .await
         .map_err(|error| Error::DbError(error.to_string()))?
-        .ok_or(Error::NotFound)
-        .map(Into::into)?;
+        .ok_or(Error::NotFound)?;
+    check_if_match(&headers, &setting)?;
 
     ModbusRegisterSettings::delete_by_id(&name)
         .exec(&*conn)
@@ -0,0 +1,324 @@
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use lazy_static::lazy_static;
+use sea_orm::{prelude::*, QueryOrder};
+use tokio::sync::Mutex;
+
+use super::codec::RegisterValue;
+use super::inputs::{
+    SimulatorRegisterState, SimulatorSessionResponse, StartSimulatorInput,
+    UpdateSimulatorRegisterInput,
+};
+use super::live::{function_codes, register_format};
+use super::versions::{requested_versions, versions_condition};
+use crate::{
+    app_state::AppState,
+    entity::{modbus_register, prelude::*},
+    error::{Error, FieldError, Result},
+    modbus_tcp::simulator::{
+        RegisterTable, SimulatedRegister, SimulatedValue, Simulator, SimulatorServer, Waveform,
+    },
+};
+
+/// Port a simulator listens on when the request doesn't name one.
+pub const DEFAULT_SIMULATOR_PORT: u16 = 5020;
+const DEFAULT_BIND_ADDRESS: &str = "0.0.0.0";
+const DEFAULT_TICK_MS: u64 = 1000;
+
+// A running simulator and the device it was generated from.
+struct Session {
+    device_id: i32,
+    device_name: String,
+    register_names: BTreeMap<i32, Option<String>>,
+    tick_ms: u64,
+    started_at: chrono::DateTime<chrono::Utc>,
+    server: SimulatorServer,
+}
+
+lazy_static! {
+    // Simulators are kept in memory and stop when the API shuts down.
+    static ref SESSIONS: Mutex<BTreeMap<u32, Session>> = Mutex::new(BTreeMap::new());
+}
+
+static NEXT_SESSION_ID: AtomicU32 = AtomicU32::new(1);
+
+// Holding registers and coils are writable when the operation names a write function,
+// or when it names no function at all.
+fn is_writable(table: RegisterTable, codes: &[u8]) -> bool {
+    match table {
+        RegisterTable::Coils => codes.contains(&5) || codes.contains(&15),
+        RegisterTable::HoldingRegisters => {
+            codes.is_empty() || codes.contains(&6) || codes.contains(&16)
+        }
+        _ => false,
+    }
+}
+
+// Turn a register of the library into a simulated register, None when it has no
+// address the simulator can serve.
+fn simulated_register(
+    register: &modbus_register::Model,
+) -> core::result::Result<Option<SimulatedRegister>, String> {
+    let Some(address) = register
+        .register_address
+        .and_then(|address| u16::try_from(address).ok())
+    else {
+        return Ok(None);
+    };
+    let codes = function_codes(register);
+    let table = RegisterTable::from_function_codes(&codes);
+
+    Ok(Some(SimulatedRegister {
+        id: register.id,
+        table,
+        address,
+        length: register.register_length.clamp(1, u16::MAX as i32) as u16,
+        format: register_format(register)?,
+        writable: is_writable(table, &codes),
+        waveform: Waveform::Constant,
+    }))
+}
+
+fn session_response(id: u32, session: &Session) -> SimulatorSessionResponse {
+    let simulator = session.server.simulator();
+    let simulator = simulator.lock().unwrap_or_else(|error| error.into_inner());
+
+    let registers = simulator
+        .registers()
+        .iter()
+        .map(|register| SimulatorRegisterState {
+            register_id: register.id,
+            register_name: session.register_names.get(&register.id).cloned().flatten(),
+            table: register.table,
+            address: register.address,
+            length: register.length,
+            data_format: register.format,
+            writable: register.writable,
+            waveform: register.waveform.clone(),
+            value: simulator.value(register.id).unwrap_or(SimulatedValue {
+                words: None,
+                bits: None,
+                value: None,
+            }),
+        })
+        .collect();
+
+    SimulatorSessionResponse {
+        id,
+        device_id: session.device_id,
+        device_name: session.device_name.clone(),
+        address: session.server.local_addr.to_string(),
+        port: session.server.local_addr.port(),
+        tick_ms: session.tick_ms,
+        started_at: session.started_at,
+        registers,
+    }
+}
+
+/// Handler to start a Modbus TCP simulator for a device of the library.
+///
+/// Every register of the device that isn't deleted and has an address is served from the
+/// table its operation reads. Registers start at their default value, or cleared when they
+/// have none, unless `registers` gives them an initial value or a waveform. They can also be
+/// made writable or read-only one by one. With `firmware` or `version_id` the register map
+/// of that version is served.
+pub async fn start(
+    State(state): State<AppState>,
+    Json(payload): Json<StartSimulatorInput>,
+) -> Result<Json<SimulatorSessionResponse>> {
+    let (device, registers) = {
+        let conn = state.conn.lock().await;
+        let device = ModbusRegisterDevices::find_by_id(payload.device_id)
+            .one(&*conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?
+            .ok_or(Error::NotFound)?;
+        let version_ids = requested_versions(
+            &*conn,
+            Some(device.id),
+            payload.firmware.as_deref(),
+            payload.version_id,
+        )
+        .await?;
+        let registers = ModbusRegister::find()
+            .filter(modbus_register::Column::DeviceId.eq(device.id))
+            .filter(modbus_register::Column::Status.ne("DELETED"))
+            .filter(versions_condition(version_ids))
+            .order_by_asc(modbus_register::Column::RegisterAddress)
+            .order_by_asc(modbus_register::Column::Id)
+            .all(&*conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        (device, registers)
+    };
+
+    let mut simulated = Vec::with_capacity(registers.len());
+    let mut errors = vec![];
+    for register in &registers {
+        match simulated_register(register) {
+            Ok(Some(register)) => simulated.push(register),
+            Ok(None) => {}
+            Err(message) => errors.push(FieldError::new(
+                format!("register[{}].data_format", register.id),
+                message,
+            )),
+        }
+    }
+
+    let mut simulator = Simulator::new(simulated);
+    for (id, default_value) in registers
+        .iter()
+        .filter_map(|register| register.default_value.map(|value| (register.id, value)))
+    {
+        // Registers without a simulated address aren't in the simulator.
+        simulator
+            .set_value(id, &RegisterValue::Float(default_value))
+            .ok();
+    }
+    for (index, config) in payload.registers.iter().flatten().enumerate() {
+        let field = |name: &str| format!("registers[{}].{}", index, name);
+        let Some(register) = simulator
+            .registers()
+            .iter()
+            .find(|register| register.id == config.register_id)
+            .cloned()
+        else {
+            errors.push(FieldError::new(
+                field("register_id"),
+                "is not a simulated register of the device",
+            ));
+            continue;
+        };
+
+        if let Some(writable) = config.writable {
+            if writable
+                && !matches!(
+                    register.table,
+                    RegisterTable::Coils | RegisterTable::HoldingRegisters
+                )
+            {
+                errors.push(FieldError::new(
+                    field("writable"),
+                    "only coils and holding registers can be written",
+                ));
+            } else {
+                simulator.set_writable(register.id, writable).ok();
+            }
+        }
+        if let Some(value) = &config.value {
+            if let Err(message) = simulator.set_value(register.id, value) {
+                errors.push(FieldError::new(field("value"), message));
+            }
+        }
+        if let Some(waveform) = &config.waveform {
+            simulator.set_waveform(register.id, waveform.clone()).ok();
+        }
+    }
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    let bind = format!(
+        "{}:{}",
+        payload
+            .bind_address
+            .as_deref()
+            .unwrap_or(DEFAULT_BIND_ADDRESS)
+            .trim(),
+        payload.port.unwrap_or(DEFAULT_SIMULATOR_PORT)
+    );
+    let tick_ms = payload.tick_ms.unwrap_or(DEFAULT_TICK_MS);
+    let server = SimulatorServer::start(&bind, simulator, Duration::from_millis(tick_ms))
+        .await
+        .map_err(|error| Error::BadRequest(format!("Couldn't listen on {}: {}", bind, error)))?;
+    println!(
+        "->> {:<12} - device {} on {}",
+        "SIMULATOR", device.id, server.local_addr
+    );
+
+    let session = Session {
+        device_id: device.id,
+        device_name: device.name,
+        register_names: registers
+            .into_iter()
+            .map(|register| (register.id, register.register_name))
+            .collect(),
+        tick_ms,
+        started_at: chrono::Utc::now(),
+        server,
+    };
+    let id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+    let response = session_response(id, &session);
+    SESSIONS.lock().await.insert(id, session);
+
+    Ok(Json(response))
+}
+
+/// Handler to list the running simulators.
+pub async fn list() -> Result<Json<Vec<SimulatorSessionResponse>>> {
+    let sessions = SESSIONS.lock().await;
+    Ok(Json(
+        sessions
+            .iter()
+            .map(|(id, session)| session_response(*id, session))
+            .collect(),
+    ))
+}
+
+/// Handler to get a running simulator with the current values of its registers.
+pub async fn get_by_id(Path(id): Path<u32>) -> Result<Json<SimulatorSessionResponse>> {
+    let sessions = SESSIONS.lock().await;
+    let session = sessions.get(&id).ok_or(Error::NotFound)?;
+    Ok(Json(session_response(id, session)))
+}
+
+/// Handler to stop a running simulator, closing its port and every connection.
+pub async fn stop(Path(id): Path<u32>) -> Result<Json<SimulatorSessionResponse>> {
+    let session = SESSIONS.lock().await.remove(&id).ok_or(Error::NotFound)?;
+    session.server.stop();
+    println!(
+        "->> {:<12} - stopped device {} on {}",
+        "SIMULATOR", session.device_id, session.server.local_addr
+    );
+    Ok(Json(session_response(id, &session)))
+}
+
+/// Handler to set the value or the waveform of a register of a running simulator.
+/// A value without a waveform holds the register at that value.
+pub async fn update_register(
+    Path((id, register_id)): Path<(u32, i32)>,
+    Json(payload): Json<UpdateSimulatorRegisterInput>,
+) -> Result<Json<SimulatorSessionResponse>> {
+    let sessions = SESSIONS.lock().await;
+    let session = sessions.get(&id).ok_or(Error::NotFound)?;
+    {
+        let simulator = session.server.simulator();
+        let mut simulator = simulator.lock().unwrap_or_else(|error| error.into_inner());
+        if !simulator
+            .registers()
+            .iter()
+            .any(|register| register.id == register_id)
+        {
+            return Err(Error::NotFound);
+        }
+        if let Some(value) = &payload.value {
+            simulator
+                .set_value(register_id, value)
+                .map_err(|message| Error::Validation(vec![FieldError::new("value", message)]))?;
+        }
+        if let Some(waveform) = payload.waveform.clone() {
+            simulator.set_waveform(register_id, waveform).ok();
+        } else if payload.value.is_some() {
+            simulator.set_waveform(register_id, Waveform::Constant).ok();
+        }
+    }
+    Ok(Json(session_response(id, session)))
+}
@@ -0,0 +1,536 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::Utc;
+use sea_orm::{prelude::*, QueryOrder, Set, TransactionTrait};
+
+use super::history::{self, HistoryAction, DEVICE_TABLE, REGISTER_TABLE};
+use super::inputs::{
+    CloneDeviceInput, CloneDeviceResponse, ResyncDeviceInput, ResyncDeviceResponse,
+};
+use super::queries::{delete_register, marks_as_updated};
+use super::validation::{check_register, RegisterDefinition};
+use super::value_labels::labels_by_register;
+use crate::{
+    app_state::AppState,
+    db_connection::commit_or_rollback,
+    entity::{
+        modbus_register, modbus_register_device_versions as device_versions,
+        modbus_register_devices as devices,
+        modbus_register_product_device_mapping as device_mappings,
+        modbus_register_value_labels as value_labels, prelude::*,
+    },
+    error::{Error, FieldError, Result},
+};
+
+async fn find_device<C: ConnectionTrait>(conn: &C, id: i32) -> Result<devices::Model> {
+    ModbusRegisterDevices::find_by_id(id)
+        .one(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)
+}
+
+// Registers of a device in address order, optionally including deleted ones.
+async fn device_registers<C: ConnectionTrait>(
+    conn: &C,
+    device_id: i32,
+    include_deleted: bool,
+) -> Result<Vec<modbus_register::Model>> {
+    let mut query = ModbusRegister::find().filter(modbus_register::Column::DeviceId.eq(device_id));
+    if !include_deleted {
+        query = query.filter(modbus_register::Column::Status.ne("DELETED"));
+    }
+    query
+        .order_by_asc(modbus_register::Column::RegisterAddress)
+        .order_by_asc(modbus_register::Column::Id)
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))
+}
+
+fn shifted_address(address: Option<i32>, address_offset: i32) -> Option<i32> {
+    address.map(|address| address.saturating_add(address_offset))
+}
+
+// Check that shifting a register by the offset keeps it inside the address space.
+// Problems the register already had are left alone, so older registers can still be cloned.
+fn check_shift(register: &modbus_register::Model, address_offset: i32) -> Vec<FieldError> {
+    let original = RegisterDefinition::from(register);
+    let existing: Vec<String> = check_register(&original)
+        .into_iter()
+        .map(|error| error.field)
+        .collect();
+    let shifted = RegisterDefinition {
+        register_address: shifted_address(register.register_address, address_offset),
+        ..original
+    };
+
+    check_register(&shifted)
+        .into_iter()
+        .filter(|error| {
+            ["register_address", "register_length"].contains(&error.field.as_str())
+                && !existing.contains(&error.field)
+        })
+        .map(|error| {
+            FieldError::new(
+                format!("registers[{}].{}", register.id, error.field),
+                error.message,
+            )
+        })
+        .collect()
+}
+
+// Copy the definition of a register from its parent onto a register of a clone.
+// Versions of the parent are replaced with the clone's versions of the same name.
+fn apply_parent(
+    register: &mut modbus_register::Model,
+    parent: &modbus_register::Model,
+    address_offset: i32,
+    versions: &HashMap<i32, i32>,
+) {
+    register.version_id = parent.version_id.and_then(|id| versions.get(&id).copied());
+    register.register_address = shifted_address(parent.register_address, address_offset);
+    register.operation = parent.operation.clone();
+    register.register_length = parent.register_length;
+    register.register_name = parent.register_name.clone();
+    register.data_format = parent.data_format.clone();
+    register.description = parent.description.clone();
+    register.unit = parent.unit.clone();
+    register.scale = parent.scale;
+    register.offset = parent.offset;
+    register.min_value = parent.min_value;
+    register.max_value = parent.max_value;
+    register.precision = parent.precision;
+    register.default_value = parent.default_value;
+}
+
+// Insert a copy of a register into a device, linked to the register it was copied from.
+async fn copy_register<C: ConnectionTrait>(
+    conn: &C,
+    register: &modbus_register::Model,
+    device_id: i32,
+    address_offset: i32,
+    versions: &HashMap<i32, i32>,
+) -> Result<modbus_register::Model> {
+    let model = modbus_register::ActiveModel {
+        register_address: Set(shifted_address(register.register_address, address_offset)),
+        operation: Set(register.operation.clone()),
+        register_length: Set(register.register_length),
+        register_name: Set(register.register_name.clone()),
+        data_format: Set(register.data_format.clone()),
+        description: Set(register.description.clone()),
+        device_id: Set(Some(device_id)),
+        unit: Set(register.unit.clone()),
+        private: Set(register.private),
+        scale: Set(register.scale),
+        offset: Set(register.offset),
+        min_value: Set(register.min_value),
+        max_value: Set(register.max_value),
+        precision: Set(register.precision),
+        default_value: Set(register.default_value),
+        parent_register_id: Set(Some(register.id)),
+        version_id: Set(register
+            .version_id
+            .and_then(|id| versions.get(&id).copied())),
+        ..Default::default()
+    };
+
+    let res = model
+        .insert(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    history::record(
+        conn,
+        REGISTER_TABLE,
+        res.id,
+        HistoryAction::Create,
+        None,
+        Some(history::snapshot(&res)),
+    )
+    .await?;
+    Ok(res)
+}
+
+// Give a device the versions of another device it doesn't have yet, matched by name.
+// Returns the version of the device for each version of the other device.
+async fn copy_versions<C: ConnectionTrait>(
+    conn: &C,
+    from_device_id: i32,
+    to_device_id: i32,
+) -> Result<HashMap<i32, i32>> {
+    let existing = ModbusRegisterDeviceVersions::find()
+        .filter(device_versions::Column::DeviceId.eq(to_device_id))
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let mut versions = HashMap::new();
+    for version in ModbusRegisterDeviceVersions::find()
+        .filter(device_versions::Column::DeviceId.eq(from_device_id))
+        .order_by_asc(device_versions::Column::Id)
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+    {
+        let id = match existing.iter().find(|other| {
+            other
+                .version
+                .trim()
+                .eq_ignore_ascii_case(version.version.trim())
+        }) {
+            Some(other) => other.id,
+            None => {
+                device_versions::ActiveModel {
+                    device_id: Set(to_device_id),
+                    version: Set(version.version.clone()),
+                    firmware_min: Set(version.firmware_min.clone()),
+                    firmware_max: Set(version.firmware_max.clone()),
+                    release_notes: Set(version.release_notes.clone()),
+                    ..Default::default()
+                }
+                .insert(conn)
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?
+                .id
+            }
+        };
+        versions.insert(version.id, id);
+    }
+    Ok(versions)
+}
+
+// Insert copies of labels for a register, returning how many were copied.
+async fn copy_labels<C: ConnectionTrait>(
+    conn: &C,
+    labels: &[value_labels::Model],
+    register_id: i32,
+) -> Result<usize> {
+    if labels.is_empty() {
+        return Ok(0);
+    }
+    // Inserting many at once skips `before_save`, so the copies are stamped here.
+    let now = Utc::now();
+    ModbusRegisterValueLabels::insert_many(labels.iter().map(|label| value_labels::ActiveModel {
+        register_id: Set(register_id),
+        kind: Set(label.kind.clone()),
+        value: Set(label.value),
+        bit_length: Set(label.bit_length),
+        label: Set(label.label.clone()),
+        description: Set(label.description.clone()),
+        created_at: Set(now),
+        ..Default::default()
+    }))
+    .exec(conn)
+    .await
+    .map_err(|error| Error::DbError(error.to_string()))?;
+    Ok(labels.len())
+}
+
+// Whether two registers have the same labels, ignoring their IDs.
+fn same_labels(labels: &[value_labels::Model], others: &[value_labels::Model]) -> bool {
+    let key = |label: &value_labels::Model| {
+        (
+            label.kind.clone(),
+            label.value,
+            label.bit_length,
+            label.label.clone(),
+            label.description.clone(),
+        )
+    };
+    labels.len() == others.len() && labels.iter().map(key).eq(others.iter().map(key))
+}
+
+/// Handler to clone a device with its registers, their labels, its versions, its image and
+/// its product mappings into a new device.
+///
+/// Registers are shifted by `address_offset` when given, and only the registers in
+/// `register_ids` are copied when given. The clone remembers the device it was copied from,
+/// so it can be re-synced when that device is a template.
+pub async fn clone_device(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<CloneDeviceInput>,
+) -> Result<Json<CloneDeviceResponse>> {
+    let conn = state.conn.lock().await;
+    let source = find_device(&*conn, id).await?;
+    let mut registers = device_registers(&*conn, id, false).await?;
+    let address_offset = payload.address_offset.unwrap_or(0);
+
+    let mut errors = vec![];
+    if payload.name.trim().is_empty() {
+        errors.push(FieldError::new("name", "must not be empty"));
+    }
+    if let Some(register_ids) = &payload.register_ids {
+        for register_id in register_ids {
+            if !registers.iter().any(|register| register.id == *register_id) {
+                errors.push(FieldError::new(
+                    "register_ids",
+                    format!("{} is not a register of device {}", register_id, id),
+                ));
+            }
+        }
+        registers.retain(|register| register_ids.contains(&register.id));
+    }
+    if address_offset != 0 {
+        for register in &registers {
+            errors.extend(check_shift(register, address_offset));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    let labels = labels_by_register(&*conn, registers.iter().map(|r| r.id).collect()).await?;
+    let mappings = if payload.include_mappings.unwrap_or(true) {
+        ModbusRegisterProductDeviceMapping::find()
+            .filter(device_mappings::Column::DeviceId.eq(id))
+            .all(&*conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?
+    } else {
+        vec![]
+    };
+
+    let txn = conn
+        .begin()
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = async {
+        let device = devices::ActiveModel {
+            name: Set(payload.name.trim().to_string()),
+            description: Set(payload.description.or(source.description)),
+            private: Set(payload.private.unwrap_or(source.private)),
+            image_id: Set(source.image_id),
+            is_template: Set(payload.is_template.unwrap_or(false)),
+            parent_id: Set(Some(source.id)),
+            address_offset: Set(Some(address_offset)),
+            manufacturer_id: Set(source.manufacturer_id),
+            category_id: Set(source.category_id),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+        history::record(
+            &txn,
+            DEVICE_TABLE,
+            device.id,
+            HistoryAction::Create,
+            None,
+            Some(history::snapshot(&device)),
+        )
+        .await?;
+
+        let versions = copy_versions(&txn, source.id, device.id).await?;
+        let mut label_count = 0;
+        for register in &registers {
+            let copy = copy_register(&txn, register, device.id, address_offset, &versions).await?;
+            let labels = labels
+                .get(&register.id)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            label_count += copy_labels(&txn, labels, copy.id).await?;
+        }
+        for mapping in &mappings {
+            ModbusRegisterProductDeviceMapping::insert(device_mappings::ActiveModel {
+                product_id: Set(mapping.product_id),
+                device_id: Set(device.id),
+            })
+            .exec(&txn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        }
+        Ok((device, label_count))
+    }
+    .await;
+    let (device, label_count) = commit_or_rollback(txn, result).await?;
+
+    Ok(Json(CloneDeviceResponse {
+        device,
+        registers: registers.len(),
+        labels: label_count,
+        mappings: mappings.len(),
+    }))
+}
+
+/// Handler to re-sync a clone with the template it was cloned from.
+///
+/// Registers copied from the template take over its current definition and labels, shifted
+/// by the clone's address offset, and are deleted when the template no longer has them.
+/// Registers added to the template since are copied unless `include_new` is false, and
+/// registers added to the clone itself are left alone. Versions of the template the clone
+/// doesn't have yet are added. A dry run reports the changes without keeping them.
+pub async fn resync_device(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<ResyncDeviceInput>,
+) -> Result<Json<ResyncDeviceResponse>> {
+    let conn = state.conn.lock().await;
+    let device = find_device(&*conn, id).await?;
+    let parent_id = device.parent_id.ok_or(Error::BadRequest(format!(
+        "Device {} wasn't cloned from a template",
+        id
+    )))?;
+    let parent = match find_device(&*conn, parent_id).await {
+        Err(Error::NotFound) => {
+            return Err(Error::BadRequest(format!(
+                "Template {} of device {} no longer exists",
+                parent_id, id
+            )))
+        }
+        result => result?,
+    };
+    if !parent.is_template {
+        return Err(Error::BadRequest(format!(
+            "Device {} is not a template",
+            parent_id
+        )));
+    }
+    let address_offset = device.address_offset.unwrap_or(0);
+
+    let parent_registers = device_registers(&*conn, parent_id, false).await?;
+    // Registers deleted from the clone stay linked, so they aren't copied again.
+    let registers = device_registers(&*conn, id, true).await?;
+    let linked: HashSet<i32> = registers
+        .iter()
+        .filter_map(|register| register.parent_register_id)
+        .collect();
+
+    let include_new = payload.include_new.unwrap_or(true);
+    let errors: Vec<FieldError> = parent_registers
+        .iter()
+        .filter(|register| include_new || linked.contains(&register.id))
+        .flat_map(|register| check_shift(register, address_offset))
+        .collect();
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    let mut labels = labels_by_register(
+        &*conn,
+        parent_registers
+            .iter()
+            .chain(registers.iter())
+            .map(|register| register.id)
+            .collect(),
+    )
+    .await?;
+    let parents: HashMap<i32, &modbus_register::Model> = parent_registers
+        .iter()
+        .map(|register| (register.id, register))
+        .collect();
+
+    let mut response = ResyncDeviceResponse {
+        device_id: id,
+        parent_id,
+        dry_run: payload.dry_run.unwrap_or(false),
+        ..Default::default()
+    };
+    let txn = conn
+        .begin()
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let result = async {
+        let versions = copy_versions(&txn, parent_id, id).await?;
+
+        for register in &registers {
+            let Some(parent_register_id) = register
+                .parent_register_id
+                .filter(|_| register.status != "DELETED")
+            else {
+                continue;
+            };
+            let before = history::snapshot(register);
+
+            let Some(parent_register) = parents.get(&parent_register_id) else {
+                // The template no longer has the register, so it's deleted like any other.
+                delete_register(&txn, register.clone()).await?;
+                response.deleted.push(register.id);
+                continue;
+            };
+
+            let mut synced = register.clone();
+            apply_parent(&mut synced, parent_register, address_offset, &versions);
+            let parent_labels = labels.remove(&parent_register_id).unwrap_or_default();
+            let own_labels = labels.remove(&register.id).unwrap_or_default();
+            let labels_changed = !same_labels(&parent_labels, &own_labels);
+            if synced == *register && !labels_changed {
+                response.unchanged += 1;
+                continue;
+            }
+
+            if synced != *register {
+                let mut model = modbus_register::ActiveModel::from(register.clone());
+                model.register_address = Set(synced.register_address);
+                model.operation = Set(synced.operation);
+                model.register_length = Set(synced.register_length);
+                model.register_name = Set(synced.register_name);
+                model.data_format = Set(synced.data_format);
+                model.description = Set(synced.description);
+                model.unit = Set(synced.unit);
+                model.scale = Set(synced.scale);
+                model.offset = Set(synced.offset);
+                model.min_value = Set(synced.min_value);
+                model.max_value = Set(synced.max_value);
+                model.precision = Set(synced.precision);
+                model.default_value = Set(synced.default_value);
+                model.version_id = Set(synced.version_id);
+                // Published registers are marked as updated, as with any other edit.
+                if marks_as_updated(register) {
+                    model.status = Set("UPDATED".to_string());
+                }
+                let updated = model
+                    .update(&txn)
+                    .await
+                    .map_err(|error| Error::DbError(error.to_string()))?;
+                history::record(
+                    &txn,
+                    REGISTER_TABLE,
+                    register.id,
+                    HistoryAction::Update,
+                    Some(before),
+                    Some(history::snapshot(&updated)),
+                )
+                .await?;
+            }
+            if labels_changed {
+                ModbusRegisterValueLabels::delete_many()
+                    .filter(value_labels::Column::RegisterId.eq(register.id))
+                    .exec(&txn)
+                    .await
+                    .map_err(|error| Error::DbError(error.to_string()))?;
+                copy_labels(&txn, &parent_labels, register.id).await?;
+            }
+            response.updated.push(register.id);
+        }
+
+        if include_new {
+            for parent_register in parent_registers
+                .iter()
+                .filter(|register| !linked.contains(&register.id))
+            {
+                let copy =
+                    copy_register(&txn, parent_register, id, address_offset, &versions).await?;
+                let parent_labels = labels.remove(&parent_register.id).unwrap_or_default();
+                copy_labels(&txn, &parent_labels, copy.id).await?;
+                response.created.push(copy.id);
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    if response.dry_run {
+        txn.rollback()
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        result?;
+    } else {
+        commit_or_rollback(txn, result).await?;
+    }
+
+    Ok(Json(response))
+}
@@ -0,0 +1,281 @@
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use super::inputs::ModbusRegisterModel;
+use crate::error::Result;
+
+/// Physical quantities of the unit catalogue. Units only convert within a quantity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quantity {
+    Temperature,
+    Pressure,
+    VolumeFlow,
+    MassFlow,
+    Volume,
+    Mass,
+    Length,
+    Velocity,
+    Energy,
+    Power,
+    ApparentPower,
+    ReactivePower,
+    Voltage,
+    Current,
+    Resistance,
+    Frequency,
+    Time,
+    RotationalSpeed,
+    Illuminance,
+    Ratio,
+    RelativeHumidity,
+}
+
+/// Unit systems values can be requested in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    Metric,
+    Imperial,
+}
+
+/// A unit of the catalogue. A value `x` in this unit is `x * factor + offset` in the
+/// base unit of its quantity, e.g. kelvin for temperatures and pascal for pressures.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Unit {
+    pub symbol: &'static str,
+    pub name: &'static str,
+    pub quantity: Quantity,
+    /// None for units used the same way in both systems, such as volts or percent.
+    pub system: Option<UnitSystem>,
+    pub aliases: &'static [&'static str],
+    #[serde(skip)]
+    factor: f64,
+    #[serde(skip)]
+    offset: f64,
+}
+
+const fn unit(
+    symbol: &'static str,
+    name: &'static str,
+    quantity: Quantity,
+    system: Option<UnitSystem>,
+    factor: f64,
+    offset: f64,
+    aliases: &'static [&'static str],
+) -> Unit {
+    Unit {
+        symbol,
+        name,
+        quantity,
+        system,
+        aliases,
+        factor,
+        offset,
+    }
+}
+
+const METRIC: Option<UnitSystem> = Some(UnitSystem::Metric);
+const IMPERIAL: Option<UnitSystem> = Some(UnitSystem::Imperial);
+
+const INCH: f64 = 0.0254;
+const FOOT: f64 = 0.3048;
+const POUND: f64 = 0.45359237;
+const CUBIC_FOOT: f64 = FOOT * FOOT * FOOT;
+const US_GALLON: f64 = 0.003785411784;
+
+/// Every unit a register may be given.
+#[rustfmt::skip]
+pub const UNITS: &[Unit] = {
+    use Quantity::*;
+    &[
+        unit("°C", "degree Celsius", Temperature, METRIC, 1.0, 273.15, &["degC", "deg C", "celsius", "C°", "℃"]),
+        unit("°F", "degree Fahrenheit", Temperature, IMPERIAL, 5.0 / 9.0, 273.15 - 32.0 * 5.0 / 9.0, &["degF", "deg F", "fahrenheit", "F°", "℉"]),
+        unit("K", "kelvin", Temperature, METRIC, 1.0, 0.0, &["kelvin"]),
+        unit("Pa", "pascal", Pressure, METRIC, 1.0, 0.0, &["pascal"]),
+        unit("hPa", "hectopascal", Pressure, METRIC, 100.0, 0.0, &[]),
+        unit("kPa", "kilopascal", Pressure, METRIC, 1e3, 0.0, &[]),
+        unit("MPa", "megapascal", Pressure, METRIC, 1e6, 0.0, &[]),
+        unit("mbar", "millibar", Pressure, METRIC, 100.0, 0.0, &[]),
+        unit("bar", "bar", Pressure, METRIC, 1e5, 0.0, &[]),
+        unit("mmH2O", "millimetre of water", Pressure, METRIC, 9.80665, 0.0, &["mmH₂O", "mmWC", "mm H2O"]),
+        unit("mmHg", "millimetre of mercury", Pressure, METRIC, 133.322387415, 0.0, &["torr"]),
+        unit("psi", "pound per square inch", Pressure, IMPERIAL, 6894.757293168, 0.0, &["lbf/in²", "lbf/in2"]),
+        unit("inH2O", "inch of water", Pressure, IMPERIAL, 249.08891, 0.0, &["inH₂O", "inWC", "in H2O", "\"H2O", "in. w.c."]),
+        unit("inHg", "inch of mercury", Pressure, IMPERIAL, 3386.389, 0.0, &["in Hg"]),
+        unit("m³/h", "cubic metre per hour", VolumeFlow, METRIC, 1.0 / 3600.0, 0.0, &["m3/h", "m^3/h", "cmh"]),
+        unit("m³/s", "cubic metre per second", VolumeFlow, METRIC, 1.0, 0.0, &["m3/s", "m^3/s"]),
+        unit("l/s", "litre per second", VolumeFlow, METRIC, 1e-3, 0.0, &["L/s", "lps"]),
+        unit("l/min", "litre per minute", VolumeFlow, METRIC, 1e-3 / 60.0, 0.0, &["L/min", "lpm"]),
+        unit("l/h", "litre per hour", VolumeFlow, METRIC, 1e-3 / 3600.0, 0.0, &["L/h", "lph"]),
+        unit("CFM", "cubic foot per minute", VolumeFlow, IMPERIAL, CUBIC_FOOT / 60.0, 0.0, &["cfm", "ft³/min", "ft3/min"]),
+        unit("GPM", "US gallon per minute", VolumeFlow, IMPERIAL, US_GALLON / 60.0, 0.0, &["gpm", "gal/min"]),
+        unit("kg/h", "kilogram per hour", MassFlow, METRIC, 1.0 / 3600.0, 0.0, &[]),
+        unit("kg/s", "kilogram per second", MassFlow, METRIC, 1.0, 0.0, &[]),
+        unit("lb/h", "pound per hour", MassFlow, IMPERIAL, POUND / 3600.0, 0.0, &["lb/hr", "pph"]),
+        unit("m³", "cubic metre", Volume, METRIC, 1.0, 0.0, &["m3", "m^3"]),
+        unit("l", "litre", Volume, METRIC, 1e-3, 0.0, &["L", "litre", "liter"]),
+        unit("ft³", "cubic foot", Volume, IMPERIAL, CUBIC_FOOT, 0.0, &["ft3", "cu ft"]),
+        unit("gal", "US gallon", Volume, IMPERIAL, US_GALLON, 0.0, &["gallon"]),
+        unit("g", "gram", Mass, METRIC, 1e-3, 0.0, &[]),
+        unit("kg", "kilogram", Mass, METRIC, 1.0, 0.0, &[]),
+        unit("t", "tonne", Mass, METRIC, 1e3, 0.0, &["tonne"]),
+        unit("lb", "pound", Mass, IMPERIAL, POUND, 0.0, &["lbs"]),
+        unit("mm", "millimetre", Length, METRIC, 1e-3, 0.0, &[]),
+        unit("cm", "centimetre", Length, METRIC, 1e-2, 0.0, &[]),
+        unit("m", "metre", Length, METRIC, 1.0, 0.0, &["metre", "meter"]),
+        unit("km", "kilometre", Length, METRIC, 1e3, 0.0, &[]),
+        unit("in", "inch", Length, IMPERIAL, INCH, 0.0, &["inch", "\""]),
+        unit("ft", "foot", Length, IMPERIAL, FOOT, 0.0, &["feet", "'"]),
+        unit("m/s", "metre per second", Velocity, METRIC, 1.0, 0.0, &["mps"]),
+        unit("km/h", "kilometre per hour", Velocity, METRIC, 1.0 / 3.6, 0.0, &["kph"]),
+        unit("ft/min", "foot per minute", Velocity, IMPERIAL, FOOT / 60.0, 0.0, &["fpm"]),
+        unit("mph", "mile per hour", Velocity, IMPERIAL, 0.44704, 0.0, &[]),
+        unit("J", "joule", Energy, METRIC, 1.0, 0.0, &[]),
+        unit("kJ", "kilojoule", Energy, METRIC, 1e3, 0.0, &[]),
+        unit("MJ", "megajoule", Energy, METRIC, 1e6, 0.0, &[]),
+        unit("Wh", "watt hour", Energy, METRIC, 3600.0, 0.0, &[]),
+        unit("kWh", "kilowatt hour", Energy, METRIC, 3.6e6, 0.0, &[]),
+        unit("MWh", "megawatt hour", Energy, METRIC, 3.6e9, 0.0, &[]),
+        unit("BTU", "British thermal unit", Energy, IMPERIAL, 1055.05585262, 0.0, &["Btu"]),
+        unit("W", "watt", Power, METRIC, 1.0, 0.0, &[]),
+        unit("kW", "kilowatt", Power, METRIC, 1e3, 0.0, &[]),
+        unit("MW", "megawatt", Power, METRIC, 1e6, 0.0, &[]),
+        unit("BTU/h", "British thermal unit per hour", Power, IMPERIAL, 0.29307107, 0.0, &["Btu/h", "BTU/hr", "BTUH"]),
+        unit("hp", "horsepower", Power, IMPERIAL, 745.69987158, 0.0, &["HP"]),
+        unit("VA", "volt-ampere", ApparentPower, None, 1.0, 0.0, &[]),
+        unit("kVA", "kilovolt-ampere", ApparentPower, None, 1e3, 0.0, &[]),
+        unit("var", "volt-ampere reactive", ReactivePower, None, 1.0, 0.0, &["VAr", "VAR"]),
+        unit("kvar", "kilovolt-ampere reactive", ReactivePower, None, 1e3, 0.0, &["kVAr", "kVAR"]),
+        unit("mV", "millivolt", Voltage, None, 1e-3, 0.0, &[]),
+        unit("V", "volt", Voltage, None, 1.0, 0.0, &["volt"]),
+        unit("kV", "kilovolt", Voltage, None, 1e3, 0.0, &[]),
+        unit("mA", "milliampere", Current, None, 1e-3, 0.0, &[]),
+        unit("A", "ampere", Current, None, 1.0, 0.0, &["amp"]),
+        unit("Ω", "ohm", Resistance, None, 1.0, 0.0, &["ohm", "Ohm"]),
+        unit("kΩ", "kiloohm", Resistance, None, 1e3, 0.0, &["kohm", "kOhm"]),
+        unit("Hz", "hertz", Frequency, None, 1.0, 0.0, &[]),
+        unit("ms", "millisecond", Time, None, 1e-3, 0.0, &[]),
+        unit("s", "second", Time, None, 1.0, 0.0, &["sec"]),
+        unit("min", "minute", Time, None, 60.0, 0.0, &[]),
+        unit("h", "hour", Time, None, 3600.0, 0.0, &["hr", "hrs"]),
+        unit("d", "day", Time, None, 86400.0, 0.0, &["day", "days"]),
+        unit("rpm", "revolution per minute", RotationalSpeed, None, 1.0, 0.0, &["RPM", "1/min"]),
+        unit("lx", "lux", Illuminance, None, 1.0, 0.0, &["lux"]),
+        unit("%", "percent", Ratio, None, 1e-2, 0.0, &["percent"]),
+        unit("‰", "per mille", Ratio, None, 1e-3, 0.0, &[]),
+        unit("ppm", "parts per million", Ratio, None, 1e-6, 0.0, &[]),
+        unit("%RH", "percent relative humidity", RelativeHumidity, None, 1.0, 0.0, &["% RH", "%rH", "rH%"]),
+    ]
+};
+
+// Units values are shown in when a unit system is requested.
+const PREFERRED_UNITS: [(Quantity, &str, &str); 10] = [
+    (Quantity::Temperature, "°C", "°F"),
+    (Quantity::Pressure, "kPa", "psi"),
+    (Quantity::VolumeFlow, "m³/h", "CFM"),
+    (Quantity::MassFlow, "kg/h", "lb/h"),
+    (Quantity::Volume, "m³", "ft³"),
+    (Quantity::Mass, "kg", "lb"),
+    (Quantity::Length, "m", "ft"),
+    (Quantity::Velocity, "m/s", "ft/min"),
+    (Quantity::Energy, "kWh", "BTU"),
+    (Quantity::Power, "kW", "BTU/h"),
+];
+
+/// Looks up a unit by its symbol or one of its aliases, ignoring surrounding whitespace.
+/// Case is only ignored when that leaves a single match, so "MW" and "mW" stay apart.
+pub fn find_unit(name: &str) -> Option<&'static Unit> {
+    let name = name.trim();
+    let names =
+        |unit: &'static Unit| std::iter::once(unit.symbol).chain(unit.aliases.iter().copied());
+
+    UNITS
+        .iter()
+        .find(|unit| names(unit).any(|candidate| candidate == name))
+        .or_else(|| {
+            let mut matches = UNITS
+                .iter()
+                .filter(|unit| names(unit).any(|candidate| candidate.eq_ignore_ascii_case(name)));
+            match (matches.next(), matches.next()) {
+                (Some(unit), None) => Some(unit),
+                _ => None,
+            }
+        })
+}
+
+/// A linear conversion `y = x * factor + offset` between two units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Conversion {
+    pub factor: f64,
+    pub offset: f64,
+}
+
+impl Conversion {
+    pub fn apply(&self, value: f64) -> f64 {
+        value * self.factor + self.offset
+    }
+}
+
+/// Returns the conversion between two units of the same quantity.
+pub fn conversion(from: &Unit, to: &Unit) -> Option<Conversion> {
+    if from.quantity != to.quantity {
+        return None;
+    }
+    Some(Conversion {
+        factor: from.factor / to.factor,
+        offset: (from.offset - to.offset) / to.factor,
+    })
+}
+
+/// Converts a value between two units of the catalogue.
+pub fn convert(value: f64, from: &str, to: &str) -> Option<f64> {
+    conversion(find_unit(from)?, find_unit(to)?).map(|conversion| conversion.apply(value))
+}
+
+/// Returns the unit a value is shown in for a unit system, which is the unit itself
+/// when it already belongs to that system or is used by both.
+pub fn unit_for_system(unit: &'static Unit, system: UnitSystem) -> &'static Unit {
+    if unit.system.is_none_or(|unit_system| unit_system == system) {
+        return unit;
+    }
+    PREFERRED_UNITS
+        .iter()
+        .find(|(quantity, _, _)| *quantity == unit.quantity)
+        .and_then(|(_, metric, imperial)| {
+            find_unit(match system {
+                UnitSystem::Metric => metric,
+                UnitSystem::Imperial => imperial,
+            })
+        })
+        .unwrap_or(unit)
+}
+
+/// Converts a register to a unit system: its unit, scaling, valid range and default value
+/// are all expressed in the unit of that system. Registers without a known unit are kept.
+pub fn convert_register(register: &mut ModbusRegisterModel, system: UnitSystem) {
+    let Some(from) = register.unit.as_deref().and_then(find_unit) else {
+        return;
+    };
+    let to = unit_for_system(from, system);
+    if std::ptr::eq(from, to) {
+        return;
+    }
+    let Some(conversion) = conversion(from, to) else {
+        return;
+    };
+
+    // Engineering values are raw * scale + offset, converted they become
+    // raw * (scale * factor) + (offset * factor + conversion offset).
+    register.scale = Some(register.scale.unwrap_or(1.0) * conversion.factor);
+    register.offset = Some(conversion.apply(register.offset.unwrap_or(0.0)));
+    register.min_value = register.min_value.map(|value| conversion.apply(value));
+    register.max_value = register.max_value.map(|value| conversion.apply(value));
+    register.default_value = register.default_value.map(|value| conversion.apply(value));
+    register.unit = Some(to.symbol.to_string());
+}
+
+/// Handler to list the unit catalogue registers can use.
+pub async fn list() -> Result<Json<&'static [Unit]>> {
+    Ok(Json(UNITS))
+}
@@ -0,0 +1,332 @@
+use sea_orm::{ConnectionTrait, EntityTrait};
+
+use super::codec::DataFormat;
+use super::inputs::{CreateModbusRegisterItemInput, UpdateModbusRegisterItemInput};
+use super::units::find_unit;
+use crate::{
+    entity::{modbus_register, prelude::*},
+    error::{Error, FieldError, Result},
+};
+
+/// Highest address reachable with a 16-bit Modbus register address.
+pub const MAX_REGISTER_ADDRESS: i32 = 65535;
+/// Most registers a single Modbus read request may return.
+pub const MAX_REGISTER_LENGTH: i32 = 125;
+
+/// Most decimal places a register may be shown with.
+pub const MAX_PRECISION: i32 = 10;
+
+/// Modbus function codes an operation may refer to.
+pub const FUNCTION_CODES: [u8; 8] = [1, 2, 3, 4, 5, 6, 15, 16];
+
+/// The parts of a register definition that are checked before it is written.
+#[derive(Debug, Clone, Copy)]
+pub struct RegisterDefinition<'a> {
+    pub register_address: Option<i32>,
+    pub register_length: i32,
+    pub operation: Option<&'a str>,
+    pub data_format: Option<&'a str>,
+    pub device_id: Option<i32>,
+    pub unit: Option<&'a str>,
+    pub scale: Option<f64>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub precision: Option<i32>,
+    pub default_value: Option<f64>,
+    pub version_id: Option<i32>,
+}
+
+impl<'a> From<&'a CreateModbusRegisterItemInput> for RegisterDefinition<'a> {
+    fn from(item: &'a CreateModbusRegisterItemInput) -> Self {
+        Self {
+            register_address: item.register_address,
+            register_length: item.register_length,
+            operation: item.operation.as_deref(),
+            data_format: item.data_format.as_deref(),
+            device_id: item.device_id,
+            unit: item.unit.as_deref(),
+            scale: item.scale,
+            min_value: item.min_value,
+            max_value: item.max_value,
+            precision: item.precision,
+            default_value: item.default_value,
+            version_id: item.version_id,
+        }
+    }
+}
+
+impl<'a> From<&'a modbus_register::Model> for RegisterDefinition<'a> {
+    fn from(model: &'a modbus_register::Model) -> Self {
+        Self {
+            register_address: model.register_address,
+            register_length: model.register_length,
+            operation: model.operation.as_deref(),
+            data_format: model.data_format.as_deref(),
+            device_id: model.device_id,
+            unit: model.unit.as_deref(),
+            scale: model.scale,
+            min_value: model.min_value,
+            max_value: model.max_value,
+            precision: model.precision,
+            default_value: model.default_value,
+            version_id: model.version_id,
+        }
+    }
+}
+
+/// Returns how many registers one value of the given data format occupies.
+pub fn data_format_width(data_format: &str) -> Option<i32> {
+    DataFormat::parse(data_format).map(|format| format.data_type.words() as i32)
+}
+
+/// Extracts the function codes from an operation such as "03_06 Read Holding and Write Single".
+pub fn operation_function_codes(operation: &str) -> Option<Vec<u8>> {
+    let codes = operation.split_whitespace().next()?;
+    codes
+        .split('_')
+        .map(|code| code.parse::<u8>().ok())
+        .collect::<Option<Vec<u8>>>()
+        .filter(|codes| !codes.is_empty())
+}
+
+/// Checks a register definition without touching the database.
+/// Empty operations and data formats are treated as not set yet, so drafts still validate.
+pub fn check_register(register: &RegisterDefinition) -> Vec<FieldError> {
+    let mut errors = vec![];
+
+    if let Some(address) = register.register_address {
+        if !(0..=MAX_REGISTER_ADDRESS).contains(&address) {
+            errors.push(FieldError::new(
+                "register_address",
+                format!("must be between 0 and {}", MAX_REGISTER_ADDRESS),
+            ));
+        }
+    }
+
+    if !(1..=MAX_REGISTER_LENGTH).contains(&register.register_length) {
+        errors.push(FieldError::new(
+            "register_length",
+            format!("must be between 1 and {}", MAX_REGISTER_LENGTH),
+        ));
+    } else if let Some(address) = register
+        .register_address
+        .filter(|address| (0..=MAX_REGISTER_ADDRESS).contains(address))
+    {
+        if address + register.register_length - 1 > MAX_REGISTER_ADDRESS {
+            errors.push(FieldError::new(
+                "register_length",
+                format!(
+                    "runs past the last register address {}",
+                    MAX_REGISTER_ADDRESS
+                ),
+            ));
+        }
+    }
+
+    if let Some(operation) = register.operation.filter(|o| !o.trim().is_empty()) {
+        match operation_function_codes(operation) {
+            Some(codes) => {
+                for code in codes.iter().filter(|code| !FUNCTION_CODES.contains(code)) {
+                    errors.push(FieldError::new(
+                        "operation",
+                        format!("{:02} is not a supported Modbus function code", code),
+                    ));
+                }
+            }
+            None => errors.push(FieldError::new(
+                "operation",
+                "must start with a Modbus function code such as \"03\" or \"03_06\"",
+            )),
+        }
+    }
+
+    if let Some(data_format) = register.data_format.filter(|f| !f.trim().is_empty()) {
+        match data_format_width(data_format) {
+            Some(width) if register.register_length % width != 0 => {
+                errors.push(FieldError::new(
+                    "register_length",
+                    format!(
+                        "{} needs a multiple of {} registers",
+                        data_format.trim(),
+                        width
+                    ),
+                ));
+            }
+            Some(_) => {}
+            None => errors.push(FieldError::new(
+                "data_format",
+                format!("\"{}\" is not a known data format", data_format),
+            )),
+        }
+    }
+
+    if let Some(unit) = register.unit.filter(|u| !u.trim().is_empty()) {
+        if find_unit(unit).is_none() {
+            errors.push(FieldError::new(
+                "unit",
+                format!("\"{}\" is not in the unit catalogue", unit.trim()),
+            ));
+        }
+    }
+
+    if let Some(scale) = register.scale {
+        if scale == 0.0 || !scale.is_finite() {
+            errors.push(FieldError::new("scale", "must be a non-zero number"));
+        }
+    }
+
+    if let (Some(min_value), Some(max_value)) = (register.min_value, register.max_value) {
+        if min_value > max_value {
+            errors.push(FieldError::new(
+                "min_value",
+                format!("must not be greater than max_value {}", max_value),
+            ));
+        }
+    }
+
+    if let Some(precision) = register.precision {
+        if !(0..=MAX_PRECISION).contains(&precision) {
+            errors.push(FieldError::new(
+                "precision",
+                format!("must be between 0 and {}", MAX_PRECISION),
+            ));
+        }
+    }
+
+    if let Some(default_value) = register.default_value {
+        if register.min_value.is_some_and(|min| default_value < min)
+            || register.max_value.is_some_and(|max| default_value > max)
+        {
+            errors.push(FieldError::new(
+                "default_value",
+                "must be within min_value and max_value",
+            ));
+        }
+    }
+
+    errors
+}
+
+/// Validates a register definition, including that the referenced device exists and that
+/// the referenced version belongs to it.
+pub async fn validate_register<C: ConnectionTrait>(
+    conn: &C,
+    register: &RegisterDefinition<'_>,
+) -> Result<()> {
+    let mut errors = check_register(register);
+
+    if let Some(device_id) = register.device_id {
+        let device = ModbusRegisterDevices::find_by_id(device_id)
+            .one(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        if device.is_none() {
+            errors.push(FieldError::new(
+                "device_id",
+                format!("device {} does not exist", device_id),
+            ));
+        }
+    }
+
+    if let Some(version_id) = register.version_id {
+        let version = ModbusRegisterDeviceVersions::find_by_id(version_id)
+            .one(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?;
+        match version {
+            None => errors.push(FieldError::new(
+                "version_id",
+                format!("version {} does not exist", version_id),
+            )),
+            Some(version) if register.device_id != Some(version.device_id) => {
+                errors.push(FieldError::new(
+                    "version_id",
+                    format!(
+                        "version {} belongs to device {}",
+                        version_id, version.device_id
+                    ),
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+/// Lists the fields an update touches, as named in validation errors.
+/// Changing the data format re-checks the length, since the length must fit the format,
+/// changing the range re-checks the default value and moving the register to another
+/// device re-checks its version.
+pub fn touched_fields(payload: &UpdateModbusRegisterItemInput) -> Vec<&'static str> {
+    let mut touched = vec![];
+    if payload.register_address.is_some() {
+        touched.push("register_address");
+    }
+    if payload.register_length.is_some() || payload.data_format.is_some() {
+        touched.push("register_length");
+    }
+    if payload.operation.is_some() {
+        touched.push("operation");
+    }
+    if payload.data_format.is_some() {
+        touched.push("data_format");
+    }
+    if payload.device_id.is_some() {
+        touched.push("device_id");
+    }
+    if payload.version_id.is_some() || payload.device_id.is_some() {
+        touched.push("version_id");
+    }
+    if payload.unit.is_some() {
+        touched.push("unit");
+    }
+    if payload.scale.is_some() {
+        touched.push("scale");
+    }
+    if payload.min_value.is_some() || payload.max_value.is_some() {
+        touched.push("min_value");
+    }
+    if payload.precision.is_some() {
+        touched.push("precision");
+    }
+    if payload.default_value.is_some() || payload.min_value.is_some() || payload.max_value.is_some()
+    {
+        touched.push("default_value");
+    }
+    touched
+}
+
+/// Validates a register after an update has been applied to it.
+/// Only problems with the touched fields are reported, so registers that predate validation
+/// stay editable.
+pub async fn validate_register_update<C: ConnectionTrait>(
+    conn: &C,
+    updated: &modbus_register::Model,
+    touched: &[&str],
+) -> Result<()> {
+    let mut register = RegisterDefinition::from(updated);
+    // The version is checked against the register's device, even if only the version changed.
+    if !touched.contains(&"device_id") && !touched.contains(&"version_id") {
+        register.device_id = None;
+    }
+
+    match validate_register(conn, &register).await {
+        Err(Error::Validation(errors)) => {
+            let errors: Vec<FieldError> = errors
+                .into_iter()
+                .filter(|error| touched.contains(&error.field.as_str()))
+                .collect();
+            if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(Error::Validation(errors))
+            }
+        }
+        result => result,
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use sea_orm::{prelude::*, QueryOrder, Set, TryIntoModel};
+
+use super::codec::RegisterValue;
+use super::inputs::{BitFieldValue, CreateValueLabelInput, UpdateValueLabelInput, ValueLabelKind};
+use crate::{
+    app_state::AppState,
+    entity::{modbus_register, modbus_register_value_labels as value_labels, prelude::*},
+    error::{Error, FieldError, Result},
+};
+
+/// Highest bit a bit field may start at, the last bit of a 64-bit value.
+pub const MAX_BIT: i64 = 63;
+
+// Kind of a stored label, enumerations unless it says bits.
+fn label_kind(label: &value_labels::Model) -> ValueLabelKind {
+    if label.kind == ValueLabelKind::Bits.as_str() {
+        ValueLabelKind::Bits
+    } else {
+        ValueLabelKind::Enum
+    }
+}
+
+// Bits a bit field covers, as a half-open range.
+fn bit_range(label: &value_labels::Model) -> std::ops::Range<i64> {
+    label.value..label.value + label.bit_length.unwrap_or(1) as i64
+}
+
+/// Checks a label against the register it belongs to and the other labels of that register.
+/// Enumeration values must be unique and bit fields must fit the register without overlapping.
+pub fn check_label(
+    label: &value_labels::Model,
+    register: &modbus_register::Model,
+    others: &[value_labels::Model],
+) -> Vec<FieldError> {
+    let mut errors = vec![];
+    let mut others = others
+        .iter()
+        .filter(|other| other.id != label.id && label_kind(other) == label_kind(label));
+
+    if label.label.trim().is_empty() {
+        errors.push(FieldError::new("label", "must not be empty"));
+    }
+
+    match label_kind(label) {
+        ValueLabelKind::Enum => {
+            if label.bit_length.is_some() {
+                errors.push(FieldError::new("bit_length", "only applies to bit fields"));
+            }
+            if let Some(other) = others.find(|other| other.value == label.value) {
+                errors.push(FieldError::new(
+                    "value",
+                    format!("{} is already labelled \"{}\"", label.value, other.label),
+                ));
+            }
+        }
+        ValueLabelKind::Bits => {
+            let register_bits = (register.register_length.max(1) as i64 * 16).min(MAX_BIT + 1);
+            if !(0..register_bits).contains(&label.value) {
+                errors.push(FieldError::new(
+                    "value",
+                    format!("must be a bit between 0 and {}", register_bits - 1),
+                ));
+            } else if !(1..=register_bits - label.value)
+                .contains(&(label.bit_length.unwrap_or(1) as i64))
+            {
+                errors.push(FieldError::new(
+                    "bit_length",
+                    format!(
+                        "must be between 1 and {} for a field starting at bit {}",
+                        register_bits - label.value,
+                        label.value
+                    ),
+                ));
+            } else {
+                let bits = bit_range(label);
+                if let Some(other) = others.find(|other| {
+                    let other_bits = bit_range(other);
+                    bits.start < other_bits.end && other_bits.start < bits.end
+                }) {
+                    errors.push(FieldError::new(
+                        "value",
+                        format!("overlaps the bit field \"{}\"", other.label),
+                    ));
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+/// Loads the labels of the given registers, enumeration values before bit fields and
+/// each ordered by value.
+pub async fn labels_by_register<C: ConnectionTrait>(
+    conn: &C,
+    register_ids: Vec<i32>,
+) -> Result<HashMap<i32, Vec<value_labels::Model>>> {
+    let mut labels: HashMap<i32, Vec<value_labels::Model>> = HashMap::new();
+    if register_ids.is_empty() {
+        return Ok(labels);
+    }
+
+    // SQLite limits the number of bound parameters, so large pages are loaded in chunks.
+    for ids in register_ids.chunks(500) {
+        for label in ModbusRegisterValueLabels::find()
+            .filter(value_labels::Column::RegisterId.is_in(ids.to_vec()))
+            .order_by_desc(value_labels::Column::Kind)
+            .order_by_asc(value_labels::Column::Value)
+            .all(conn)
+            .await
+            .map_err(|error| Error::DbError(error.to_string()))?
+        {
+            labels.entry(label.register_id).or_default().push(label);
+        }
+    }
+    Ok(labels)
+}
+
+// Raw bits of a decoded value, if it is a whole number or a list of bits.
+fn raw_bits(value: &RegisterValue) -> Option<i64> {
+    match value {
+        RegisterValue::Integer(value) => Some(*value),
+        RegisterValue::Float(value) if value.fract() == 0.0 => Some(*value as i64),
+        RegisterValue::Bits(bits) => Some(
+            bits.iter()
+                .take(64)
+                .enumerate()
+                .fold(0, |raw, (bit, set)| raw | ((*set as i64) << bit)),
+        ),
+        _ => None,
+    }
+}
+
+/// Describes a decoded value with the labels of its register: the label of the matching
+/// enumeration value and the value of every bit field.
+pub fn describe(
+    value: &RegisterValue,
+    labels: &[value_labels::Model],
+) -> (Option<String>, Vec<BitFieldValue>) {
+    let Some(raw) = raw_bits(value) else {
+        return (None, vec![]);
+    };
+
+    let label = labels
+        .iter()
+        .find(|label| label_kind(label) == ValueLabelKind::Enum && label.value == raw)
+        .map(|label| label.label.clone());
+    let bit_fields = labels
+        .iter()
+        .filter(|label| label_kind(label) == ValueLabelKind::Bits)
+        .map(|label| {
+            let bit_length = label.bit_length.unwrap_or(1);
+            let mask = u64::MAX.checked_shr(64 - bit_length as u32).unwrap_or(0);
+            BitFieldValue {
+                label: label.label.clone(),
+                bit: label.value,
+                bit_length,
+                value: (raw as u64).checked_shr(label.value as u32).unwrap_or(0) & mask,
+            }
+        })
+        .collect();
+
+    (label, bit_fields)
+}
+
+/// Replaces the label of an enumeration value, e.g. "Cooling", with the value it stands for.
+/// Other values are returned unchanged.
+pub fn resolve_label(value: RegisterValue, labels: &[value_labels::Model]) -> RegisterValue {
+    match &value {
+        RegisterValue::Text(text) => labels
+            .iter()
+            .find(|label| {
+                label_kind(label) == ValueLabelKind::Enum
+                    && label.label.trim().eq_ignore_ascii_case(text.trim())
+            })
+            .map(|label| RegisterValue::Integer(label.value))
+            .unwrap_or(value),
+        _ => value,
+    }
+}
+
+async fn find_register<C: ConnectionTrait>(
+    conn: &C,
+    register_id: i32,
+) -> Result<modbus_register::Model> {
+    ModbusRegister::find_by_id(register_id)
+        .one(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)
+}
+
+async fn find_label<C: ConnectionTrait>(
+    conn: &C,
+    register_id: i32,
+    id: i32,
+) -> Result<value_labels::Model> {
+    ModbusRegisterValueLabels::find_by_id(id)
+        .filter(value_labels::Column::RegisterId.eq(register_id))
+        .one(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)
+}
+
+// Validate a label against its register and the labels it already has.
+async fn validate_label<C: ConnectionTrait>(
+    conn: &C,
+    register: &modbus_register::Model,
+    label: &value_labels::Model,
+) -> Result<()> {
+    let others = labels_by_register(conn, vec![register.id])
+        .await?
+        .remove(&register.id)
+        .unwrap_or_default();
+    let errors = check_label(label, register, &others);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+/// Handler to list the enumeration values and bit fields of a register.
+pub async fn list(
+    State(state): State<AppState>,
+    Path(register_id): Path<i32>,
+) -> Result<Json<Vec<value_labels::Model>>> {
+    let conn = state.conn.lock().await;
+    find_register(&*conn, register_id).await?;
+    let labels = labels_by_register(&*conn, vec![register_id])
+        .await?
+        .remove(&register_id)
+        .unwrap_or_default();
+    Ok(Json(labels))
+}
+
+/// Handler to add an enumeration value or a bit field to a register.
+pub async fn create(
+    State(state): State<AppState>,
+    Path(register_id): Path<i32>,
+    Json(payload): Json<CreateValueLabelInput>,
+) -> Result<Json<value_labels::Model>> {
+    let conn = state.conn.lock().await;
+    let register = find_register(&*conn, register_id).await?;
+
+    let label = value_labels::Model {
+        id: 0,
+        register_id,
+        kind: payload.kind.as_str().to_string(),
+        value: payload.value,
+        bit_length: payload.bit_length,
+        label: payload.label.trim().to_string(),
+        description: payload.description,
+        created_at: chrono::Utc::now(),
+    };
+    validate_label(&*conn, &register, &label).await?;
+
+    let model = value_labels::ActiveModel {
+        register_id: Set(label.register_id),
+        kind: Set(label.kind),
+        value: Set(label.value),
+        bit_length: Set(label.bit_length),
+        label: Set(label.label),
+        description: Set(label.description),
+        ..Default::default()
+    };
+    let res = model
+        .insert(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(res))
+}
+
+/// Handler to update an enumeration value or a bit field of a register.
+pub async fn update(
+    State(state): State<AppState>,
+    Path((register_id, id)): Path<(i32, i32)>,
+    Json(payload): Json<UpdateValueLabelInput>,
+) -> Result<Json<value_labels::Model>> {
+    let conn = state.conn.lock().await;
+    let register = find_register(&*conn, register_id).await?;
+    let mut model = value_labels::ActiveModel::from(find_label(&*conn, register_id, id).await?);
+
+    if let Some(kind) = payload.kind {
+        model.kind = Set(kind.as_str().to_string());
+    }
+    if let Some(value) = payload.value {
+        model.value = Set(value);
+    }
+    if let Some(bit_length) = payload.bit_length {
+        model.bit_length = Set(bit_length);
+    }
+    if let Some(label) = payload.label {
+        model.label = Set(label.trim().to_string());
+    }
+    if let Some(description) = payload.description {
+        model.description = Set(description);
+    }
+
+    validate_label(
+        &*conn,
+        &register,
+        &model
+            .clone()
+            .try_into_model()
+            .map_err(|error| Error::ServerError(error.to_string()))?,
+    )
+    .await?;
+
+    let updated = model
+        .update(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(updated))
+}
+
+/// Handler to delete an enumeration value or a bit field of a register.
+pub async fn delete(
+    State(state): State<AppState>,
+    Path((register_id, id)): Path<(i32, i32)>,
+) -> Result<Json<value_labels::Model>> {
+    let conn = state.conn.lock().await;
+    let label = find_label(&*conn, register_id, id).await?;
+
+    ModbusRegisterValueLabels::delete_by_id(id)
+        .exec(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(label))
+}
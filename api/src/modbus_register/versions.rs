@@ -0,0 +1,470 @@
+use std::cmp::Ordering;
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use sea_orm::{prelude::*, Condition, QueryOrder, Set, TryIntoModel};
+use serde_json::Value;
+
+use super::history;
+use super::inputs::{
+    CreateDeviceVersionInput, DeviceVersionDiff, DeviceVersionDiffParams, ExportValueLabel,
+    RegisterDiff, UpdateDeviceVersionInput,
+};
+use super::value_labels::labels_by_register;
+use crate::{
+    app_state::AppState,
+    entity::{modbus_register, modbus_register_device_versions as versions, prelude::*},
+    error::{Error, FieldError, Result},
+};
+
+// Register fields that don't describe the register itself and are left out of diffs.
+const IGNORED_DIFF_FIELDS: [&str; 8] = [
+    "id",
+    "device_id",
+    "version_id",
+    "parent_register_id",
+    "status",
+    "private",
+    "created_at",
+    "updated_at",
+];
+
+/// Parses a firmware version such as "2.10.1" or "v2.10" into its numeric parts.
+/// Trailing zeros are dropped, so "2.1" and "2.1.0" compare as equal.
+pub fn parse_firmware(firmware: &str) -> Option<Vec<u64>> {
+    let firmware = firmware.trim();
+    let firmware = firmware.strip_prefix(['v', 'V']).unwrap_or(firmware);
+    let mut parts = firmware
+        .split('.')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    while parts.last() == Some(&0) {
+        parts.pop();
+    }
+    Some(parts)
+}
+
+// Compare a firmware against an optional bound, an open bound compares as `open`.
+fn compare_bound(firmware: &[u64], bound: &Option<String>, open: Ordering) -> Ordering {
+    bound
+        .as_deref()
+        .and_then(parse_firmware)
+        .map_or(open, |bound| firmware.cmp(&bound))
+}
+
+/// Whether a version's firmware range includes the firmware. Ranges are inclusive and an
+/// open end matches any firmware.
+pub fn includes_firmware(version: &versions::Model, firmware: &[u64]) -> bool {
+    compare_bound(firmware, &version.firmware_min, Ordering::Greater) != Ordering::Less
+        && compare_bound(firmware, &version.firmware_max, Ordering::Less) != Ordering::Greater
+}
+
+/// Checks a version against the other versions of its device.
+/// Names must be unique and firmware ranges may not overlap, so every firmware maps to at
+/// most one version.
+pub fn check_version(version: &versions::Model, others: &[versions::Model]) -> Vec<FieldError> {
+    let mut errors = vec![];
+    let others = others.iter().filter(|other| other.id != version.id);
+
+    if version.version.trim().is_empty() {
+        errors.push(FieldError::new("version", "must not be empty"));
+    } else if let Some(other) = others.clone().find(|other| {
+        other
+            .version
+            .trim()
+            .eq_ignore_ascii_case(version.version.trim())
+    }) {
+        errors.push(FieldError::new(
+            "version",
+            format!("is already used by version {}", other.id),
+        ));
+    }
+
+    let mut bounds_valid = true;
+    for (field, bound) in [
+        ("firmware_min", &version.firmware_min),
+        ("firmware_max", &version.firmware_max),
+    ] {
+        if bound
+            .as_deref()
+            .is_some_and(|bound| parse_firmware(bound).is_none())
+        {
+            bounds_valid = false;
+            errors.push(FieldError::new(field, "must be a version such as 2.1.0"));
+        }
+    }
+    if !bounds_valid {
+        return errors;
+    }
+
+    let range = |version: &versions::Model| {
+        (
+            version.firmware_min.as_deref().and_then(parse_firmware),
+            version.firmware_max.as_deref().and_then(parse_firmware),
+        )
+    };
+    // Open ends start before and end after everything.
+    let not_after = |start: &Option<Vec<u64>>, end: &Option<Vec<u64>>| match (start, end) {
+        (Some(start), Some(end)) => start <= end,
+        _ => true,
+    };
+
+    let (min, max) = range(version);
+    if !not_after(&min, &max) {
+        errors.push(FieldError::new(
+            "firmware_min",
+            "must not be greater than firmware_max",
+        ));
+        return errors;
+    }
+
+    // Two ranges overlap when each starts before the other ends.
+    if let Some(other) = others.into_iter().find(|other| {
+        let (other_min, other_max) = range(other);
+        not_after(&min, &other_max) && not_after(&other_min, &max)
+    }) {
+        errors.push(FieldError::new(
+            "firmware_min",
+            format!(
+                "overlaps the firmware range of version \"{}\"",
+                other.version
+            ),
+        ));
+    }
+
+    errors
+}
+
+/// Resolves the versions whose register maps are requested, either by ID or by the firmware
+/// they cover, for one device or for every device. Returns None when neither is given.
+pub async fn requested_versions<C: ConnectionTrait>(
+    conn: &C,
+    device_id: Option<i32>,
+    firmware: Option<&str>,
+    version_id: Option<i32>,
+) -> Result<Option<Vec<i32>>> {
+    let mut query = ModbusRegisterDeviceVersions::find();
+    if let Some(device_id) = device_id {
+        query = query.filter(versions::Column::DeviceId.eq(device_id));
+    }
+
+    match (firmware, version_id) {
+        (None, None) => Ok(None),
+        (Some(_), Some(_)) => Err(Error::BadRequest(
+            "Use either firmware or version_id, not both".to_string(),
+        )),
+        (None, Some(version_id)) => {
+            query
+                .filter(versions::Column::Id.eq(version_id))
+                .one(conn)
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?
+                .ok_or(Error::BadRequest(format!(
+                    "Version {} does not exist",
+                    version_id
+                )))?;
+            Ok(Some(vec![version_id]))
+        }
+        (Some(firmware), None) => {
+            let firmware = parse_firmware(firmware).ok_or(Error::BadRequest(format!(
+                "\"{}\" is not a firmware version",
+                firmware
+            )))?;
+            let versions = query
+                .all(conn)
+                .await
+                .map_err(|error| Error::DbError(error.to_string()))?;
+            Ok(Some(
+                versions
+                    .iter()
+                    .filter(|version| includes_firmware(version, &firmware))
+                    .map(|version| version.id)
+                    .collect(),
+            ))
+        }
+    }
+}
+
+/// Matches the registers in the maps of the given versions, including the registers shared
+/// by every version. Matches every register when no versions are given.
+pub fn versions_condition(version_ids: Option<Vec<i32>>) -> Condition {
+    match version_ids {
+        Some(version_ids) => Condition::any()
+            .add(modbus_register::Column::VersionId.is_null())
+            .add(modbus_register::Column::VersionId.is_in(version_ids)),
+        None => Condition::all(),
+    }
+}
+
+async fn find_device_version<C: ConnectionTrait>(
+    conn: &C,
+    device_id: i32,
+    id: i32,
+) -> Result<versions::Model> {
+    ModbusRegisterDeviceVersions::find_by_id(id)
+        .filter(versions::Column::DeviceId.eq(device_id))
+        .one(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)
+}
+
+async fn device_versions<C: ConnectionTrait>(
+    conn: &C,
+    device_id: i32,
+) -> Result<Vec<versions::Model>> {
+    ModbusRegisterDeviceVersions::find()
+        .filter(versions::Column::DeviceId.eq(device_id))
+        .order_by_asc(versions::Column::Id)
+        .all(conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))
+}
+
+// Validate a version against the other versions of its device.
+async fn validate_version<C: ConnectionTrait>(conn: &C, version: &versions::Model) -> Result<()> {
+    let errors = check_version(version, &device_versions(conn, version.device_id).await?);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+/// Handler to list the versions of a device's register map.
+pub async fn list(
+    State(state): State<AppState>,
+    Path(device_id): Path<i32>,
+) -> Result<Json<Vec<versions::Model>>> {
+    let conn = state.conn.lock().await;
+    ModbusRegisterDevices::find_by_id(device_id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+    Ok(Json(device_versions(&*conn, device_id).await?))
+}
+
+/// Handler to add a version to a device's register map.
+pub async fn create(
+    State(state): State<AppState>,
+    Path(device_id): Path<i32>,
+    Json(payload): Json<CreateDeviceVersionInput>,
+) -> Result<Json<versions::Model>> {
+    let conn = state.conn.lock().await;
+    ModbusRegisterDevices::find_by_id(device_id)
+        .one(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?
+        .ok_or(Error::NotFound)?;
+
+    let version = versions::Model {
+        id: 0,
+        device_id,
+        version: payload.version.trim().to_string(),
+        firmware_min: payload.firmware_min,
+        firmware_max: payload.firmware_max,
+        release_notes: payload.release_notes,
+        created_at: chrono::Utc::now(),
+    };
+    validate_version(&*conn, &version).await?;
+
+    let model = versions::ActiveModel {
+        device_id: Set(version.device_id),
+        version: Set(version.version),
+        firmware_min: Set(version.firmware_min),
+        firmware_max: Set(version.firmware_max),
+        release_notes: Set(version.release_notes),
+        ..Default::default()
+    };
+    let res = model
+        .insert(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(res))
+}
+
+/// Handler to update a version of a device's register map.
+pub async fn update(
+    State(state): State<AppState>,
+    Path((device_id, id)): Path<(i32, i32)>,
+    Json(payload): Json<UpdateDeviceVersionInput>,
+) -> Result<Json<versions::Model>> {
+    let conn = state.conn.lock().await;
+    let mut model = versions::ActiveModel::from(find_device_version(&*conn, device_id, id).await?);
+
+    if let Some(version) = payload.version {
+        model.version = Set(version.trim().to_string());
+    }
+    if let Some(firmware_min) = payload.firmware_min {
+        model.firmware_min = Set(firmware_min);
+    }
+    if let Some(firmware_max) = payload.firmware_max {
+        model.firmware_max = Set(firmware_max);
+    }
+    if let Some(release_notes) = payload.release_notes {
+        model.release_notes = Set(release_notes);
+    }
+
+    validate_version(
+        &*conn,
+        &model
+            .clone()
+            .try_into_model()
+            .map_err(|error| Error::ServerError(error.to_string()))?,
+    )
+    .await?;
+
+    let updated = model
+        .update(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(updated))
+}
+
+/// Handler to delete a version of a device's register map.
+/// Versions that still have registers can't be deleted.
+pub async fn delete(
+    State(state): State<AppState>,
+    Path((device_id, id)): Path<(i32, i32)>,
+) -> Result<Json<versions::Model>> {
+    let conn = state.conn.lock().await;
+    let version = find_device_version(&*conn, device_id, id).await?;
+
+    let registers = ModbusRegister::find()
+        .filter(modbus_register::Column::VersionId.eq(id))
+        .count(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    if registers > 0 {
+        return Err(Error::BadRequest(format!(
+            "Version \"{}\" still has {} registers",
+            version.version, registers
+        )));
+    }
+
+    ModbusRegisterDeviceVersions::delete_by_id(id)
+        .exec(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+
+    Ok(Json(version))
+}
+
+// Key registers are matched on between versions: the operation and address, or the name of
+// registers without an address.
+fn register_key(
+    register: &modbus_register::Model,
+) -> (Option<String>, Option<i32>, Option<String>) {
+    match register.register_address {
+        Some(address) => (register.operation.clone(), Some(address), None),
+        None => (None, None, register.register_name.clone()),
+    }
+}
+
+// Snapshot of the fields of a register, and its labels, that are compared between versions.
+fn diff_snapshot(register: &modbus_register::Model, labels: Vec<ExportValueLabel>) -> Value {
+    let mut snapshot = history::snapshot(register);
+    if let Some(fields) = snapshot.as_object_mut() {
+        for field in IGNORED_DIFF_FIELDS {
+            fields.remove(field);
+        }
+        fields.insert("labels".to_string(), history::snapshot(&labels));
+    }
+    snapshot
+}
+
+/// Handler to compare the register maps of two versions of a device.
+///
+/// Each map holds the registers of its version and the registers shared by every version.
+/// Registers are matched on their operation and address, or on their name when they have
+/// no address, and reported as added, removed or changed from `from` to `to`.
+pub async fn diff(
+    State(state): State<AppState>,
+    Path(device_id): Path<i32>,
+    Query(params): Query<DeviceVersionDiffParams>,
+) -> Result<Json<DeviceVersionDiff>> {
+    let conn = state.conn.lock().await;
+    let from = find_device_version(&*conn, device_id, params.from).await?;
+    let to = find_device_version(&*conn, device_id, params.to).await?;
+
+    let registers = ModbusRegister::find()
+        .filter(modbus_register::Column::DeviceId.eq(device_id))
+        .filter(modbus_register::Column::Status.ne("DELETED"))
+        .filter(versions_condition(Some(vec![from.id, to.id])))
+        .order_by_asc(modbus_register::Column::RegisterAddress)
+        .order_by_asc(modbus_register::Column::Id)
+        .all(&*conn)
+        .await
+        .map_err(|error| Error::DbError(error.to_string()))?;
+    let mut labels = labels_by_register(
+        &*conn,
+        registers.iter().map(|register| register.id).collect(),
+    )
+    .await?;
+    let mut snapshot = |register: &modbus_register::Model| {
+        let labels = labels
+            .remove(&register.id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|label| ExportValueLabel {
+                kind: label.kind,
+                value: label.value,
+                bit_length: label.bit_length,
+                label: label.label,
+                description: label.description,
+            })
+            .collect();
+        diff_snapshot(register, labels)
+    };
+
+    let in_map = |register: &modbus_register::Model, version: &versions::Model| {
+        register.version_id.is_none_or(|id| id == version.id)
+    };
+    let mut removed: Vec<modbus_register::Model> = registers
+        .iter()
+        .filter(|register| in_map(register, &from))
+        .cloned()
+        .collect();
+    let mut added = vec![];
+    let mut changed = vec![];
+    let mut unchanged = 0;
+
+    for register in registers.iter().filter(|register| in_map(register, &to)) {
+        let key = register_key(register);
+        let Some(index) = removed.iter().position(|other| register_key(other) == key) else {
+            added.push(register.clone());
+            continue;
+        };
+        let previous = removed.remove(index);
+        if previous.id == register.id {
+            // Registers shared by both versions can't differ.
+            unchanged += 1;
+            continue;
+        }
+        let changes = history::diff(&Some(snapshot(&previous)), &Some(snapshot(register)));
+        if changes.is_empty() {
+            unchanged += 1;
+        } else {
+            changed.push(RegisterDiff {
+                from: previous,
+                to: register.clone(),
+                changes,
+            });
+        }
+    }
+
+    Ok(Json(DeviceVersionDiff {
+        device_id,
+        from,
+        to,
+        added,
+        removed,
+        changed,
+        unchanged,
+    }))
+}
@@ -0,0 +1,270 @@
+use std::{io, time::Duration};
+
+use tokio::{net::TcpStream, time::timeout};
+
+use super::frame::{pack_bits, unpack_bits, Frame};
+use super::{exception_name, FunctionCode, MAX_WRITE_COILS, MAX_WRITE_REGISTERS};
+
+/// Errors of a Modbus TCP request.
+#[derive(Debug)]
+pub enum ModbusError {
+    Io(io::Error),
+    Timeout,
+    /// The device answered with a Modbus exception.
+    Exception {
+        function: u8,
+        code: u8,
+    },
+    InvalidResponse(String),
+    /// The request can't be sent, e.g. it writes more values than one request holds.
+    InvalidRequest(String),
+}
+
+impl core::fmt::Display for ModbusError {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        match self {
+            Self::Io(error) => write!(fmt, "{}", error),
+            Self::Timeout => write!(fmt, "The device did not respond in time"),
+            Self::Exception { function, code } => write!(
+                fmt,
+                "Function {:02} failed with exception {:02} ({})",
+                function,
+                code,
+                exception_name(*code)
+            ),
+            Self::InvalidResponse(reason) => write!(fmt, "Invalid response: {}", reason),
+            Self::InvalidRequest(reason) => write!(fmt, "Invalid request: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ModbusError {}
+
+impl From<io::Error> for ModbusError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+pub type ModbusResult<T> = core::result::Result<T, ModbusError>;
+
+/// A Modbus TCP client talking to a single unit over one connection.
+pub struct ModbusTcpClient {
+    stream: TcpStream,
+    unit_id: u8,
+    transaction_id: u16,
+    timeout: Duration,
+}
+
+impl ModbusTcpClient {
+    /// Connects to a Modbus TCP server, e.g. `ModbusTcpClient::connect("192.168.0.10:502", 1, ..)`.
+    /// The timeout applies to the connection and to every request.
+    pub async fn connect(
+        address: &str,
+        unit_id: u8,
+        timeout_after: Duration,
+    ) -> ModbusResult<Self> {
+        let stream = timeout(timeout_after, TcpStream::connect(address))
+            .await
+            .map_err(|_| ModbusError::Timeout)??;
+        stream.set_nodelay(true)?;
+
+        Ok(Self {
+            stream,
+            unit_id,
+            transaction_id: 0,
+            timeout: timeout_after,
+        })
+    }
+
+    // Send a request PDU and wait for the matching response PDU.
+    async fn request(&mut self, pdu: Vec<u8>) -> ModbusResult<Vec<u8>> {
+        let function = pdu[0];
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        let request = Frame {
+            transaction_id: self.transaction_id,
+            unit_id: self.unit_id,
+            pdu,
+        };
+
+        let response = timeout(self.timeout, async {
+            request.write(&mut self.stream).await?;
+            loop {
+                let response = Frame::read(&mut self.stream).await?;
+                // Late answers to earlier requests that timed out are dropped.
+                if response.transaction_id == request.transaction_id {
+                    return Ok::<Frame, io::Error>(response);
+                }
+            }
+        })
+        .await
+        .map_err(|_| ModbusError::Timeout)??;
+
+        match response.pdu.first() {
+            Some(code) if *code == function | 0x80 => Err(ModbusError::Exception {
+                function,
+                code: response.pdu.get(1).copied().unwrap_or_default(),
+            }),
+            Some(code) if *code == function => Ok(response.pdu),
+            _ => Err(ModbusError::InvalidResponse(format!(
+                "Expected function {:02}",
+                function
+            ))),
+        }
+    }
+
+    // Read request shared by the four read functions, returning the data bytes.
+    async fn read(
+        &mut self,
+        function: FunctionCode,
+        address: u16,
+        count: u16,
+    ) -> ModbusResult<Vec<u8>> {
+        let mut pdu = vec![function as u8];
+        pdu.extend_from_slice(&address.to_be_bytes());
+        pdu.extend_from_slice(&count.to_be_bytes());
+
+        let response = self.request(pdu).await?;
+        let length = *response.get(1).ok_or(ModbusError::InvalidResponse(
+            "Missing byte count".to_string(),
+        ))? as usize;
+        let data = response
+            .get(2..2 + length)
+            .ok_or(ModbusError::InvalidResponse(
+                "Truncated response".to_string(),
+            ))?;
+        Ok(data.to_vec())
+    }
+
+    async fn read_bits(
+        &mut self,
+        function: FunctionCode,
+        address: u16,
+        count: u16,
+    ) -> ModbusResult<Vec<bool>> {
+        let data = self.read(function, address, count).await?;
+        if data.len() < (count as usize).div_ceil(8) {
+            return Err(ModbusError::InvalidResponse(format!(
+                "Expected {} coils",
+                count
+            )));
+        }
+        Ok(unpack_bits(&data, count as usize))
+    }
+
+    async fn read_words(
+        &mut self,
+        function: FunctionCode,
+        address: u16,
+        count: u16,
+    ) -> ModbusResult<Vec<u16>> {
+        let data = self.read(function, address, count).await?;
+        if data.len() != count as usize * 2 {
+            return Err(ModbusError::InvalidResponse(format!(
+                "Expected {} registers",
+                count
+            )));
+        }
+        Ok(data
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect())
+    }
+
+    /// Reads coils with function 01.
+    pub async fn read_coils(&mut self, address: u16, count: u16) -> ModbusResult<Vec<bool>> {
+        self.read_bits(FunctionCode::ReadCoils, address, count)
+            .await
+    }
+
+    /// Reads discrete inputs with function 02.
+    pub async fn read_discrete_inputs(
+        &mut self,
+        address: u16,
+        count: u16,
+    ) -> ModbusResult<Vec<bool>> {
+        self.read_bits(FunctionCode::ReadDiscreteInputs, address, count)
+            .await
+    }
+
+    /// Reads holding registers with function 03.
+    pub async fn read_holding_registers(
+        &mut self,
+        address: u16,
+        count: u16,
+    ) -> ModbusResult<Vec<u16>> {
+        self.read_words(FunctionCode::ReadHoldingRegisters, address, count)
+            .await
+    }
+
+    /// Reads input registers with function 04.
+    pub async fn read_input_registers(
+        &mut self,
+        address: u16,
+        count: u16,
+    ) -> ModbusResult<Vec<u16>> {
+        self.read_words(FunctionCode::ReadInputRegisters, address, count)
+            .await
+    }
+
+    /// Writes a single coil with function 05.
+    pub async fn write_single_coil(&mut self, address: u16, value: bool) -> ModbusResult<()> {
+        let mut pdu = vec![FunctionCode::WriteSingleCoil as u8];
+        pdu.extend_from_slice(&address.to_be_bytes());
+        pdu.extend_from_slice(&if value { 0xFF00u16 } else { 0 }.to_be_bytes());
+        self.request(pdu).await.map(|_| ())
+    }
+
+    /// Writes a single holding register with function 06.
+    pub async fn write_single_register(&mut self, address: u16, value: u16) -> ModbusResult<()> {
+        let mut pdu = vec![FunctionCode::WriteSingleRegister as u8];
+        pdu.extend_from_slice(&address.to_be_bytes());
+        pdu.extend_from_slice(&value.to_be_bytes());
+        self.request(pdu).await.map(|_| ())
+    }
+
+    /// Writes consecutive coils with function 15.
+    pub async fn write_multiple_coils(
+        &mut self,
+        address: u16,
+        values: &[bool],
+    ) -> ModbusResult<()> {
+        if values.is_empty() || values.len() > MAX_WRITE_COILS {
+            return Err(ModbusError::InvalidRequest(format!(
+                "Function 15 writes 1 to {} coils, not {}",
+                MAX_WRITE_COILS,
+                values.len()
+            )));
+        }
+        let data = pack_bits(values);
+        let mut pdu = vec![FunctionCode::WriteMultipleCoils as u8];
+        pdu.extend_from_slice(&address.to_be_bytes());
+        pdu.extend_from_slice(&(values.len() as u16).to_be_bytes());
+        pdu.push(data.len() as u8);
+        pdu.extend_from_slice(&data);
+        self.request(pdu).await.map(|_| ())
+    }
+
+    /// Writes consecutive holding registers with function 16.
+    pub async fn write_multiple_registers(
+        &mut self,
+        address: u16,
+        values: &[u16],
+    ) -> ModbusResult<()> {
+        if values.is_empty() || values.len() > MAX_WRITE_REGISTERS {
+            return Err(ModbusError::InvalidRequest(format!(
+                "Function 16 writes 1 to {} registers, not {}",
+                MAX_WRITE_REGISTERS,
+                values.len()
+            )));
+        }
+        let mut pdu = vec![FunctionCode::WriteMultipleRegisters as u8];
+        pdu.extend_from_slice(&address.to_be_bytes());
+        pdu.extend_from_slice(&(values.len() as u16).to_be_bytes());
+        pdu.push((values.len() * 2) as u8);
+        for value in values {
+            pdu.extend_from_slice(&value.to_be_bytes());
+        }
+        self.request(pdu).await.map(|_| ())
+    }
+}
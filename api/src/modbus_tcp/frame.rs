@@ -0,0 +1,86 @@
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// Largest PDU allowed by the Modbus specification.
+const MAX_PDU_LENGTH: usize = 253;
+
+/// A Modbus TCP application data unit: the MBAP header fields and the PDU,
+/// which starts with the function code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub transaction_id: u16,
+    pub unit_id: u8,
+    pub pdu: Vec<u8>,
+}
+
+impl Frame {
+    /// Serialises the frame with its MBAP header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(7 + self.pdu.len());
+        bytes.extend_from_slice(&self.transaction_id.to_be_bytes());
+        // Protocol identifier, always 0 for Modbus.
+        bytes.extend_from_slice(&0u16.to_be_bytes());
+        bytes.extend_from_slice(&(self.pdu.len() as u16 + 1).to_be_bytes());
+        bytes.push(self.unit_id);
+        bytes.extend_from_slice(&self.pdu);
+        bytes
+    }
+
+    /// Reads the next frame from a stream.
+    pub async fn read<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Self> {
+        let mut header = [0u8; 7];
+        reader.read_exact(&mut header).await?;
+
+        let transaction_id = u16::from_be_bytes([header[0], header[1]]);
+        let protocol_id = u16::from_be_bytes([header[2], header[3]]);
+        let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if protocol_id != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown protocol identifier {}", protocol_id),
+            ));
+        }
+        if length < 2 || length - 1 > MAX_PDU_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid frame length {}", length),
+            ));
+        }
+
+        let mut pdu = vec![0u8; length - 1];
+        reader.read_exact(&mut pdu).await?;
+
+        Ok(Self {
+            transaction_id,
+            unit_id: header[6],
+            pdu,
+        })
+    }
+
+    /// Writes the frame to a stream.
+    pub async fn write<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes()).await?;
+        writer.flush().await
+    }
+}
+
+/// Packs coil states into bytes, the first coil in the least significant bit.
+pub fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    let mut bytes = vec![0u8; bits.len().div_ceil(8)];
+    for (index, _) in bits.iter().enumerate().filter(|(_, bit)| **bit) {
+        bytes[index / 8] |= 1 << (index % 8);
+    }
+    bytes
+}
+
+/// Unpacks `count` coil states from bytes packed by `pack_bits`.
+pub fn unpack_bits(bytes: &[u8], count: usize) -> Vec<bool> {
+    (0..count)
+        .map(|index| {
+            bytes
+                .get(index / 8)
+                .is_some_and(|byte| byte & (1 << (index % 8)) != 0)
+        })
+        .collect()
+}
@@ -0,0 +1,60 @@
+pub mod client;
+pub mod frame;
+pub mod simulator;
+
+/// Modbus function codes supported by the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FunctionCode {
+    ReadCoils = 1,
+    ReadDiscreteInputs = 2,
+    ReadHoldingRegisters = 3,
+    ReadInputRegisters = 4,
+    WriteSingleCoil = 5,
+    WriteSingleRegister = 6,
+    WriteMultipleCoils = 15,
+    WriteMultipleRegisters = 16,
+}
+
+impl FunctionCode {
+    pub fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Self::ReadCoils),
+            2 => Some(Self::ReadDiscreteInputs),
+            3 => Some(Self::ReadHoldingRegisters),
+            4 => Some(Self::ReadInputRegisters),
+            5 => Some(Self::WriteSingleCoil),
+            6 => Some(Self::WriteSingleRegister),
+            15 => Some(Self::WriteMultipleCoils),
+            16 => Some(Self::WriteMultipleRegisters),
+            _ => None,
+        }
+    }
+}
+
+/// Most coils a single function 15 request may write.
+pub const MAX_WRITE_COILS: usize = 1968;
+/// Most holding registers a single function 16 request may write.
+pub const MAX_WRITE_REGISTERS: usize = 123;
+
+/// Modbus exception codes.
+pub const ILLEGAL_FUNCTION: u8 = 1;
+pub const ILLEGAL_DATA_ADDRESS: u8 = 2;
+pub const ILLEGAL_DATA_VALUE: u8 = 3;
+pub const SERVER_DEVICE_FAILURE: u8 = 4;
+
+/// Returns the name of a Modbus exception code.
+pub fn exception_name(code: u8) -> &'static str {
+    match code {
+        ILLEGAL_FUNCTION => "illegal function",
+        ILLEGAL_DATA_ADDRESS => "illegal data address",
+        ILLEGAL_DATA_VALUE => "illegal data value",
+        SERVER_DEVICE_FAILURE => "server device failure",
+        5 => "acknowledge",
+        6 => "server device busy",
+        8 => "memory parity error",
+        10 => "gateway path unavailable",
+        11 => "gateway target device failed to respond",
+        _ => "unknown exception",
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::BTreeMap,
+    f64::consts::PI,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpListener, sync::watch, task::JoinHandle};
+
+use super::frame::{pack_bits, unpack_bits, Frame};
+use super::{FunctionCode, ILLEGAL_DATA_ADDRESS, ILLEGAL_DATA_VALUE, ILLEGAL_FUNCTION};
+use crate::{
+    error::Error,
+    modbus_register::codec::{decode, encode, DataFormat, RegisterValue},
+};
+
+/// The four Modbus data tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegisterTable {
+    Coils,
+    DiscreteInputs,
+    HoldingRegisters,
+    InputRegisters,
+}
+
+impl RegisterTable {
+    /// Picks the table a register lives in from the function codes of its operation,
+    /// defaulting to holding registers.
+    pub fn from_function_codes(codes: &[u8]) -> Self {
+        if codes.iter().any(|code| [1, 5, 15].contains(code)) {
+            Self::Coils
+        } else if codes.contains(&2) {
+            Self::DiscreteInputs
+        } else if codes.contains(&4) {
+            Self::InputRegisters
+        } else {
+            Self::HoldingRegisters
+        }
+    }
+
+    pub fn is_bits(self) -> bool {
+        matches!(self, Self::Coils | Self::DiscreteInputs)
+    }
+}
+
+/// Drives the value of a simulated register over time.
+/// Values are in engineering units and encoded with the register's data format.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Waveform {
+    /// Keeps the last value that was set.
+    #[default]
+    Constant,
+    /// Rises linearly from `min` to `max` over `period_ms`, then starts over.
+    Ramp { min: f64, max: f64, period_ms: u64 },
+    /// Swings between `min` and `max` once per `period_ms`.
+    Sine { min: f64, max: f64, period_ms: u64 },
+    /// Picks a new value between `min` and `max` on every tick.
+    Random { min: f64, max: f64 },
+}
+
+impl Waveform {
+    // Value of the waveform after `elapsed`, or None for constants.
+    fn value_at(&self, elapsed: Duration, seed: &mut u64) -> Option<f64> {
+        let phase = |period_ms: u64| {
+            (elapsed.as_millis() % period_ms.max(1) as u128) as f64 / period_ms.max(1) as f64
+        };
+        match *self {
+            Self::Constant => None,
+            Self::Ramp {
+                min,
+                max,
+                period_ms,
+            } => Some(min + (max - min) * phase(period_ms)),
+            Self::Sine {
+                min,
+                max,
+                period_ms,
+            } => Some(min + (max - min) * (1.0 + (2.0 * PI * phase(period_ms)).sin()) / 2.0),
+            Self::Random { min, max } => {
+                // xorshift64, plenty for simulated noise.
+                *seed ^= *seed << 13;
+                *seed ^= *seed >> 7;
+                *seed ^= *seed << 17;
+                Some(min + (max - min) * (*seed % 1_000_001) as f64 / 1_000_000.0)
+            }
+        }
+    }
+}
+
+/// A register exposed by the simulator.
+#[derive(Debug, Clone)]
+pub struct SimulatedRegister {
+    pub id: i32,
+    pub table: RegisterTable,
+    pub address: u16,
+    pub length: u16,
+    pub format: DataFormat,
+    pub writable: bool,
+    pub waveform: Waveform,
+}
+
+impl SimulatedRegister {
+    fn contains(&self, table: RegisterTable, address: u16) -> bool {
+        self.table == table
+            && address >= self.address
+            && (address as u32) < self.address as u32 + self.length as u32
+    }
+}
+
+// Addresses a register covers, cut off at the end of the address space.
+fn addresses(register: &SimulatedRegister) -> impl Iterator<Item = u16> + '_ {
+    (0..register.length).filter_map(|offset| register.address.checked_add(offset))
+}
+
+/// The current contents of a simulated register.
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulatedValue {
+    pub words: Option<Vec<u16>>,
+    pub bits: Option<Vec<bool>>,
+    pub value: Option<RegisterValue>,
+}
+
+/// The memory of a simulated Modbus device. Only addresses covered by one of its
+/// registers exist, every other address answers with an illegal data address exception.
+#[derive(Debug)]
+pub struct Simulator {
+    registers: Vec<SimulatedRegister>,
+    bits: BTreeMap<(RegisterTable, u16), bool>,
+    words: BTreeMap<(RegisterTable, u16), u16>,
+    started: Instant,
+    seed: u64,
+}
+
+impl Simulator {
+    /// Creates a simulator with every register cleared.
+    pub fn new(registers: Vec<SimulatedRegister>) -> Self {
+        let mut bits = BTreeMap::new();
+        let mut words = BTreeMap::new();
+        for register in &registers {
+            for address in addresses(register) {
+                if register.table.is_bits() {
+                    bits.insert((register.table, address), false);
+                } else {
+                    words.insert((register.table, address), 0);
+                }
+            }
+        }
+
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or_default()
+            | 1;
+
+        Self {
+            registers,
+            bits,
+            words,
+            started: Instant::now(),
+            seed,
+        }
+    }
+
+    pub fn registers(&self) -> &[SimulatedRegister] {
+        &self.registers
+    }
+
+    fn register_index(&self, id: i32) -> Result<usize, String> {
+        self.registers
+            .iter()
+            .position(|register| register.id == id)
+            .ok_or(format!("Register {} isn't simulated", id))
+    }
+
+    /// Sets the value of a register, encoded with its data format.
+    pub fn set_value(&mut self, id: i32, value: &RegisterValue) -> Result<(), String> {
+        let register = self.registers[self.register_index(id)?].clone();
+
+        if register.table.is_bits() {
+            let bits = match value {
+                RegisterValue::Integer(value) => vec![*value != 0],
+                RegisterValue::Float(value) => vec![value.round() != 0.0],
+                RegisterValue::Bits(bits) => bits.clone(),
+                RegisterValue::Text(_) => {
+                    return Err("Coils can't hold text".to_string());
+                }
+            };
+            for (address, bit) in addresses(&register).zip(bits) {
+                self.bits.insert((register.table, address), bit);
+            }
+            return Ok(());
+        }
+
+        let words =
+            encode(&register.format, value, register.length as i32).map_err(
+                |error| match error {
+                    Error::BadRequest(message) => message,
+                    error => error.to_string(),
+                },
+            )?;
+        for (address, word) in addresses(&register).zip(words) {
+            self.words.insert((register.table, address), word);
+        }
+        Ok(())
+    }
+
+    /// Replaces the waveform of a register.
+    pub fn set_waveform(&mut self, id: i32, waveform: Waveform) -> Result<(), String> {
+        let index = self.register_index(id)?;
+        self.registers[index].waveform = waveform;
+        Ok(())
+    }
+
+    /// Allows or refuses writes to a register from Modbus clients.
+    pub fn set_writable(&mut self, id: i32, writable: bool) -> Result<(), String> {
+        let index = self.register_index(id)?;
+        self.registers[index].writable = writable;
+        Ok(())
+    }
+
+    /// Returns the raw and decoded contents of a register.
+    pub fn value(&self, id: i32) -> Result<SimulatedValue, String> {
+        let register = &self.registers[self.register_index(id)?];
+
+        if register.table.is_bits() {
+            let bits: Vec<bool> = addresses(register)
+                .filter_map(|address| self.bits.get(&(register.table, address)).copied())
+                .collect();
+            let value = if bits.len() == 1 {
+                RegisterValue::Integer(bits[0] as i64)
+            } else {
+                RegisterValue::Bits(bits.clone())
+            };
+            return Ok(SimulatedValue {
+                words: None,
+                bits: Some(bits),
+                value: Some(value),
+            });
+        }
+
+        let words: Vec<u16> = addresses(register)
+            .filter_map(|address| self.words.get(&(register.table, address)).copied())
+            .collect();
+        let length = register
+            .format
+            .words(register.length as i32)
+            .min(words.len());
+        Ok(SimulatedValue {
+            value: decode(&register.format, &words[..length]).ok(),
+            words: Some(words),
+            bits: None,
+        })
+    }
+
+    /// Advances every waveform to the current time.
+    pub fn tick(&mut self) {
+        let elapsed = self.started.elapsed();
+        let updates: Vec<(i32, f64)> = self
+            .registers
+            .iter()
+            .filter_map(|register| {
+                register
+                    .waveform
+                    .value_at(elapsed, &mut self.seed)
+                    .map(|value| (register.id, value))
+            })
+            .collect();
+        for (id, value) in updates {
+            // Values a format can't hold, e.g. numbers for text registers, are skipped.
+            self.set_value(id, &RegisterValue::Float(value)).ok();
+        }
+    }
+
+    /// Answers a request PDU, with an exception response when it can't be served.
+    pub fn handle(&mut self, pdu: &[u8]) -> Vec<u8> {
+        let function = pdu.first().copied().unwrap_or_default();
+        match self.process(pdu) {
+            Ok(response) => response,
+            Err(code) => vec![function | 0x80, code],
+        }
+    }
+
+    fn process(&mut self, pdu: &[u8]) -> Result<Vec<u8>, u8> {
+        let function = pdu
+            .first()
+            .copied()
+            .and_then(FunctionCode::from_u8)
+            .ok_or(ILLEGAL_FUNCTION)?;
+        if pdu.len() < 5 {
+            return Err(ILLEGAL_DATA_VALUE);
+        }
+        let address = u16::from_be_bytes([pdu[1], pdu[2]]);
+        let value = u16::from_be_bytes([pdu[3], pdu[4]]);
+        let addresses = |count: u16| -> Result<Vec<u16>, u8> {
+            (0..count)
+                .map(|i| address.checked_add(i).ok_or(ILLEGAL_DATA_ADDRESS))
+                .collect()
+        };
+
+        match function {
+            FunctionCode::ReadCoils | FunctionCode::ReadDiscreteInputs => {
+                if value == 0 || value > 2000 {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                let table = if function == FunctionCode::ReadCoils {
+                    RegisterTable::Coils
+                } else {
+                    RegisterTable::DiscreteInputs
+                };
+                let bits = addresses(value)?
+                    .into_iter()
+                    .map(|address| self.bits.get(&(table, address)).copied())
+                    .collect::<Option<Vec<bool>>>()
+                    .ok_or(ILLEGAL_DATA_ADDRESS)?;
+                let data = pack_bits(&bits);
+                let mut response = vec![function as u8, data.len() as u8];
+                response.extend_from_slice(&data);
+                Ok(response)
+            }
+            FunctionCode::ReadHoldingRegisters | FunctionCode::ReadInputRegisters => {
+                if value == 0 || value > 125 {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                let table = if function == FunctionCode::ReadHoldingRegisters {
+                    RegisterTable::HoldingRegisters
+                } else {
+                    RegisterTable::InputRegisters
+                };
+                let words = addresses(value)?
+                    .into_iter()
+                    .map(|address| self.words.get(&(table, address)).copied())
+                    .collect::<Option<Vec<u16>>>()
+                    .ok_or(ILLEGAL_DATA_ADDRESS)?;
+                let mut response = vec![function as u8, (words.len() * 2) as u8];
+                for word in words {
+                    response.extend_from_slice(&word.to_be_bytes());
+                }
+                Ok(response)
+            }
+            FunctionCode::WriteSingleCoil => {
+                let bit = match value {
+                    0xFF00 => true,
+                    0x0000 => false,
+                    _ => return Err(ILLEGAL_DATA_VALUE),
+                };
+                self.check_writable(RegisterTable::Coils, &[address])?;
+                self.bits.insert((RegisterTable::Coils, address), bit);
+                Ok(pdu[..5].to_vec())
+            }
+            FunctionCode::WriteSingleRegister => {
+                self.check_writable(RegisterTable::HoldingRegisters, &[address])?;
+                self.words
+                    .insert((RegisterTable::HoldingRegisters, address), value);
+                Ok(pdu[..5].to_vec())
+            }
+            FunctionCode::WriteMultipleCoils => {
+                let data = pdu.get(6..).unwrap_or_default();
+                if value == 0
+                    || value > 1968
+                    || pdu.get(5).copied() != Some((value as usize).div_ceil(8) as u8)
+                    || data.len() != (value as usize).div_ceil(8)
+                {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                let targets = addresses(value)?;
+                self.check_writable(RegisterTable::Coils, &targets)?;
+                for (address, bit) in targets.into_iter().zip(unpack_bits(data, value as usize)) {
+                    self.bits.insert((RegisterTable::Coils, address), bit);
+                }
+                Ok(pdu[..5].to_vec())
+            }
+            FunctionCode::WriteMultipleRegisters => {
+                let data = pdu.get(6..).unwrap_or_default();
+                if value == 0
+                    || value > 123
+                    || pdu.get(5).copied() != Some((value * 2) as u8)
+                    || data.len() != value as usize * 2
+                {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                let targets = addresses(value)?;
+                self.check_writable(RegisterTable::HoldingRegisters, &targets)?;
+                for (address, pair) in targets.into_iter().zip(data.chunks(2)) {
+                    self.words.insert(
+                        (RegisterTable::HoldingRegisters, address),
+                        u16::from_be_bytes([pair[0], pair[1]]),
+                    );
+                }
+                Ok(pdu[..5].to_vec())
+            }
+        }
+    }
+
+    // Every address must belong to a writable register. Writes take over from the
+    // waveform of the registers they touch.
+    fn check_writable(&mut self, table: RegisterTable, addresses: &[u16]) -> Result<(), u8> {
+        let mut touched = vec![];
+        for address in addresses {
+            let index = self
+                .registers
+                .iter()
+                .position(|register| register.contains(table, *address))
+                .ok_or(ILLEGAL_DATA_ADDRESS)?;
+            if !self.registers[index].writable {
+                return Err(ILLEGAL_DATA_ADDRESS);
+            }
+            touched.push(index);
+        }
+        for index in touched {
+            self.registers[index].waveform = Waveform::Constant;
+        }
+        Ok(())
+    }
+}
+
+/// A running Modbus TCP server answering from a `Simulator`.
+/// The server stops when it is dropped.
+pub struct SimulatorServer {
+    pub local_addr: SocketAddr,
+    simulator: Arc<Mutex<Simulator>>,
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl SimulatorServer {
+    /// Binds the server, e.g. to "0.0.0.0:5020" or to "127.0.0.1:0" for any free port,
+    /// and advances the waveforms every `tick`. Requests for any unit ID are answered.
+    pub async fn start(bind: &str, simulator: Simulator, tick: Duration) -> io::Result<Self> {
+        let listener = TcpListener::bind(bind).await?;
+        let local_addr = listener.local_addr()?;
+        let simulator = Arc::new(Mutex::new(simulator));
+        let (shutdown, shutdown_rx) = watch::channel(false);
+
+        let shared = simulator.clone();
+        let server = tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let simulator = shared.clone();
+                let mut shutdown = shutdown_rx.clone();
+                tokio::spawn(async move {
+                    loop {
+                        let request = tokio::select! {
+                            request = Frame::read(&mut stream) => match request {
+                                Ok(request) => request,
+                                Err(_) => break,
+                            },
+                            // Connections are closed when the server stops.
+                            _ = shutdown.changed() => break,
+                        };
+                        let pdu = match simulator.lock() {
+                            Ok(mut simulator) => simulator.handle(&request.pdu),
+                            Err(_) => break,
+                        };
+                        let response = Frame { pdu, ..request };
+                        if response.write(&mut stream).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        let shared = simulator.clone();
+        let ticker = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tick.max(Duration::from_millis(10)));
+            loop {
+                interval.tick().await;
+                match shared.lock() {
+                    Ok(mut simulator) => simulator.tick(),
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Ok(Self {
+            local_addr,
+            simulator,
+            shutdown,
+            tasks: vec![server, ticker],
+        })
+    }
+
+    /// The simulator behind the server, shared with the connections.
+    pub fn simulator(&self) -> Arc<Mutex<Simulator>> {
+        self.simulator.clone()
+    }
+
+    /// Closes the listener and every connection and stops advancing the waveforms.
+    pub fn stop(&self) {
+        self.shutdown.send(true).ok();
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for SimulatorServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
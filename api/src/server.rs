@@ -1,7 +1,8 @@
 use std::{env, error::Error};
 
 use axum::{
-    http::StatusCode,
+    http::{header::ETAG, StatusCode},
+    middleware,
     routing::{get, get_service},
     Router,
 };
@@ -15,7 +16,9 @@ use tower_http::{
 
 use crate::{
     app_state::{self, AppState},
+    etag::conditional_get,
     file::routes::file_routes,
+    idempotency::IDEMPOTENT_REPLAYED,
     utils::{run_migrations, SHUTDOWN_CHANNEL, SPA_DIR},
 };
 
@@ -54,15 +57,17 @@ pub async fn create_app(app_state: AppState) -> Result<Router, Box<dyn Error>> {
     let cors = CorsLayer::new()
         .allow_methods(Any)
         .allow_headers(Any)
-        .allow_origin(Any);
+        .allow_origin(Any)
+        .expose_headers([ETAG, IDEMPOTENT_REPLAYED]);
 
     Ok(Router::new()
         .nest(
             "/api",
-            modbus_register_routes()
-                .merge(user_routes())
-                .merge(file_routes())
-                .route("/health", get(health_check_handler)),
+            modbus_register_routes(app_state.clone())
+                .merge(user_routes(app_state.clone()))
+                .merge(file_routes(app_state.clone()))
+                .route("/health", get(health_check_handler))
+                .layer(middleware::from_fn(conditional_get)),
         )
         .with_state(app_state)
         .fallback_service(routes_static())
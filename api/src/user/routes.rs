@@ -8,7 +8,7 @@ use sea_orm::{entity::prelude::*, IntoActiveModel, Set, TryIntoModel};
 use serde::Deserialize;
 
 use crate::entity::user;
-use crate::{auth::require_auth, entity::prelude::*};
+use crate::{auth::require_auth, entity::prelude::*, idempotency::idempotent};
 
 use crate::{
     app_state::AppState,
@@ -16,9 +16,15 @@ use crate::{
 };
 
 // Defines the routes related to user operations and applies authentication middleware.
-pub fn user_routes() -> Router<AppState> {
+pub fn user_routes(app_state: AppState) -> Router<AppState> {
+    // Saving the user may be retried with an Idempotency-Key.
+    let create_routes = Router::new()
+        .route("/user", post(save_user))
+        .route_layer(middleware::from_fn_with_state(app_state, idempotent));
+
     Router::new()
-        .route("/user", get(get_user).post(save_user).delete(delete_user)) // User CRUD routes.
+        .route("/user", get(get_user).delete(delete_user)) // User CRUD routes.
+        .merge(create_routes)
         .route("/login", post(login)) // Login route.
         .route("/logout", post(logout)) // Logout route.
         .route(
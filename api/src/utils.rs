@@ -16,6 +16,13 @@ lazy_static! {
     // SPA_DIR is set from environment variable or defaults to a local directory.
     pub static ref SPA_DIR: String =
         env::var("SPA_DIR").unwrap_or_else(|_| "./ResourceFile/webview/www".to_string());
+    // IDEMPOTENCY_RETENTION_HOURS is how long responses to idempotent requests are kept, 24 hours by default.
+    // Negative values are ignored.
+    pub static ref IDEMPOTENCY_RETENTION_HOURS: i64 = env::var("IDEMPOTENCY_RETENTION_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse().ok())
+        .filter(|hours| *hours >= 0)
+        .unwrap_or(24);
 
     pub static ref SHUTDOWN_CHANNEL: Arc<Mutex<mpsc::Sender<()>>> = Arc::new(Mutex::new(mpsc::channel(1).0));
 }
@@ -0,0 +1,561 @@
+use t3_webview_api::modbus_register::codec::{
+    decode, encode, ByteOrder, DataFormat, DataType, RegisterValue,
+};
+
+const ORDERS: [ByteOrder; 4] = [
+    ByteOrder::Abcd,
+    ByteOrder::Cdab,
+    ByteOrder::Badc,
+    ByteOrder::Dcba,
+];
+
+fn format(data_type: DataType, byte_order: ByteOrder) -> DataFormat {
+    DataFormat {
+        byte_order,
+        ..DataFormat::new(data_type)
+    }
+}
+
+fn parse(name: &str) -> DataFormat {
+    DataFormat::parse(name).unwrap_or_else(|| panic!("{} should parse", name))
+}
+
+fn assert_float(value: RegisterValue, expected: f64) {
+    match value {
+        RegisterValue::Float(value) => assert!(
+            (value - expected).abs() < 1e-6,
+            "{} is not {}",
+            value,
+            expected
+        ),
+        value => panic!("expected a float, got {:?}", value),
+    }
+}
+
+#[test]
+fn test_parse_editor_formats() {
+    let cases = [
+        (
+            "8 Bit Unsigned Integer",
+            DataType::Uint8,
+            ByteOrder::Abcd,
+            1.0,
+        ),
+        ("8 Bit Signed Integer", DataType::Int8, ByteOrder::Abcd, 1.0),
+        (
+            "16 Bit Unsigned Integer",
+            DataType::Uint16,
+            ByteOrder::Abcd,
+            1.0,
+        ),
+        (
+            "16 Bit Signed Integer/10",
+            DataType::Int16,
+            ByteOrder::Abcd,
+            0.1,
+        ),
+        (
+            "16 Bit Unsigned Integer/100",
+            DataType::Uint16,
+            ByteOrder::Abcd,
+            0.01,
+        ),
+        (
+            "32 Bit Unsigned Integer HI_LO",
+            DataType::Uint32,
+            ByteOrder::Abcd,
+            1.0,
+        ),
+        (
+            "32 Bit Unsigned Integer LO_HI",
+            DataType::Uint32,
+            ByteOrder::Cdab,
+            1.0,
+        ),
+        (
+            "32 Bit Signed Integer LO_HI",
+            DataType::Int32,
+            ByteOrder::Cdab,
+            1.0,
+        ),
+        ("Floating HI_LO/10", DataType::Float32, ByteOrder::Abcd, 0.1),
+        (
+            "Floating LO_HI/1000",
+            DataType::Float32,
+            ByteOrder::Cdab,
+            0.001,
+        ),
+        (
+            "Character String HI_LO",
+            DataType::Ascii,
+            ByteOrder::Abcd,
+            1.0,
+        ),
+        (
+            "Character String LO_HI",
+            DataType::Ascii,
+            ByteOrder::Badc,
+            1.0,
+        ),
+        ("32 Bit Float_ABCD", DataType::Float32, ByteOrder::Abcd, 1.0),
+        ("32 Bit Float_CDAB", DataType::Float32, ByteOrder::Cdab, 1.0),
+        ("32 Bit Float_BADC", DataType::Float32, ByteOrder::Badc, 1.0),
+        ("32 Bit Float_DCBA", DataType::Float32, ByteOrder::Dcba, 1.0),
+    ];
+    for (name, data_type, byte_order, scale) in cases {
+        let format = parse(name);
+        assert_eq!(format.data_type, data_type, "{}", name);
+        assert_eq!(format.byte_order, byte_order, "{}", name);
+        assert!((format.scale - scale).abs() < 1e-12, "{}", name);
+        assert_eq!(format.offset, 0.0, "{}", name);
+    }
+}
+
+#[test]
+fn test_parse_short_formats() {
+    let cases = [
+        ("int16", DataType::Int16, ByteOrder::Abcd),
+        ("UINT16", DataType::Uint16, ByteOrder::Abcd),
+        ("int32_cdab", DataType::Int32, ByteOrder::Cdab),
+        ("uint32_badc", DataType::Uint32, ByteOrder::Badc),
+        ("int64_dcba", DataType::Int64, ByteOrder::Dcba),
+        ("float32", DataType::Float32, ByteOrder::Abcd),
+        ("float64_cdab", DataType::Float64, ByteOrder::Cdab),
+        ("ascii", DataType::Ascii, ByteOrder::Abcd),
+        ("bitfield", DataType::Bitfield, ByteOrder::Abcd),
+    ];
+    for (name, data_type, byte_order) in cases {
+        let format = parse(name);
+        assert_eq!(format.data_type, data_type, "{}", name);
+        assert_eq!(format.byte_order, byte_order, "{}", name);
+    }
+
+    assert!((parse("float64_dcba/100").scale - 0.01).abs() < 1e-12);
+    assert!(DataFormat::parse("").is_none());
+    assert!(DataFormat::parse("int128").is_none());
+    assert!(DataFormat::parse("int16/0").is_none());
+    assert!(DataFormat::parse("int16/ten").is_none());
+}
+
+#[test]
+fn test_decode_16_bit() {
+    let uint16 = format(DataType::Uint16, ByteOrder::Abcd);
+    assert_eq!(
+        decode(&uint16, &[0x1234]).unwrap(),
+        RegisterValue::Integer(0x1234)
+    );
+    assert_eq!(
+        decode(&uint16, &[0xFFFF]).unwrap(),
+        RegisterValue::Integer(65535)
+    );
+
+    let int16 = format(DataType::Int16, ByteOrder::Abcd);
+    assert_eq!(
+        decode(&int16, &[0xFFFE]).unwrap(),
+        RegisterValue::Integer(-2)
+    );
+    assert_eq!(
+        decode(&int16, &[0x8000]).unwrap(),
+        RegisterValue::Integer(-32768)
+    );
+
+    // Word swaps don't affect single registers, byte swaps do.
+    for byte_order in [ByteOrder::Abcd, ByteOrder::Cdab] {
+        let uint16 = format(DataType::Uint16, byte_order);
+        assert_eq!(
+            decode(&uint16, &[0x1234]).unwrap(),
+            RegisterValue::Integer(0x1234)
+        );
+    }
+    for byte_order in [ByteOrder::Badc, ByteOrder::Dcba] {
+        let uint16 = format(DataType::Uint16, byte_order);
+        assert_eq!(
+            decode(&uint16, &[0x3412]).unwrap(),
+            RegisterValue::Integer(0x1234)
+        );
+    }
+}
+
+#[test]
+fn test_decode_8_bit() {
+    let uint8 = format(DataType::Uint8, ByteOrder::Abcd);
+    assert_eq!(
+        decode(&uint8, &[0x00FF]).unwrap(),
+        RegisterValue::Integer(255)
+    );
+    // Only the low byte counts.
+    assert_eq!(
+        decode(&uint8, &[0xAB12]).unwrap(),
+        RegisterValue::Integer(0x12)
+    );
+
+    let int8 = format(DataType::Int8, ByteOrder::Abcd);
+    assert_eq!(
+        decode(&int8, &[0x00FF]).unwrap(),
+        RegisterValue::Integer(-1)
+    );
+    assert_eq!(
+        decode(&int8, &[0x0080]).unwrap(),
+        RegisterValue::Integer(-128)
+    );
+}
+
+#[test]
+fn test_decode_32_bit_integer_orders() {
+    let cases = [
+        (ByteOrder::Abcd, [0x1234, 0x5678]),
+        (ByteOrder::Cdab, [0x5678, 0x1234]),
+        (ByteOrder::Badc, [0x3412, 0x7856]),
+        (ByteOrder::Dcba, [0x7856, 0x3412]),
+    ];
+    for (byte_order, words) in cases {
+        let uint32 = format(DataType::Uint32, byte_order);
+        assert_eq!(
+            decode(&uint32, &words).unwrap(),
+            RegisterValue::Integer(0x12345678),
+            "{:?}",
+            byte_order
+        );
+    }
+
+    let int32 = format(DataType::Int32, ByteOrder::Abcd);
+    assert_eq!(
+        decode(&int32, &[0xFFFF, 0xFFFE]).unwrap(),
+        RegisterValue::Integer(-2)
+    );
+    let int32 = format(DataType::Int32, ByteOrder::Cdab);
+    assert_eq!(
+        decode(&int32, &[0xFFFE, 0xFFFF]).unwrap(),
+        RegisterValue::Integer(-2)
+    );
+
+    let uint32 = format(DataType::Uint32, ByteOrder::Abcd);
+    assert_eq!(
+        decode(&uint32, &[0xFFFF, 0xFFFF]).unwrap(),
+        RegisterValue::Integer(u32::MAX as i64)
+    );
+}
+
+#[test]
+fn test_decode_64_bit_integer_orders() {
+    let cases = [
+        (ByteOrder::Abcd, [0x0102, 0x0304, 0x0506, 0x0708]),
+        (ByteOrder::Cdab, [0x0708, 0x0506, 0x0304, 0x0102]),
+        (ByteOrder::Badc, [0x0201, 0x0403, 0x0605, 0x0807]),
+        (ByteOrder::Dcba, [0x0807, 0x0605, 0x0403, 0x0201]),
+    ];
+    for (byte_order, words) in cases {
+        let int64 = format(DataType::Int64, byte_order);
+        assert_eq!(
+            decode(&int64, &words).unwrap(),
+            RegisterValue::Integer(0x0102030405060708),
+            "{:?}",
+            byte_order
+        );
+    }
+
+    let int64 = format(DataType::Int64, ByteOrder::Abcd);
+    assert_eq!(
+        decode(&int64, &[0xFFFF, 0xFFFF, 0xFFFF, 0xFFFF]).unwrap(),
+        RegisterValue::Integer(-1)
+    );
+}
+
+#[test]
+fn test_decode_floats() {
+    // 123.456f32 is 0x42F6E979.
+    let cases = [
+        (ByteOrder::Abcd, [0x42F6, 0xE979]),
+        (ByteOrder::Cdab, [0xE979, 0x42F6]),
+        (ByteOrder::Badc, [0xF642, 0x79E9]),
+        (ByteOrder::Dcba, [0x79E9, 0xF642]),
+    ];
+    for (byte_order, words) in cases {
+        let float32 = format(DataType::Float32, byte_order);
+        assert_float(decode(&float32, &words).unwrap(), 123.456f32 as f64);
+    }
+
+    // 1.0f64 is 0x3FF0000000000000.
+    let cases = [
+        (ByteOrder::Abcd, [0x3FF0, 0, 0, 0]),
+        (ByteOrder::Cdab, [0, 0, 0, 0x3FF0]),
+        (ByteOrder::Badc, [0xF03F, 0, 0, 0]),
+        (ByteOrder::Dcba, [0, 0, 0, 0xF03F]),
+    ];
+    for (byte_order, words) in cases {
+        let float64 = format(DataType::Float64, byte_order);
+        assert_float(decode(&float64, &words).unwrap(), 1.0);
+    }
+}
+
+#[test]
+fn test_decode_ascii() {
+    let hi_lo = parse("Character String HI_LO");
+    assert_eq!(
+        decode(&hi_lo, &[0x4845, 0x4C4C, 0x4F00]).unwrap(),
+        RegisterValue::Text("HELLO".to_string())
+    );
+
+    let lo_hi = parse("Character String LO_HI");
+    assert_eq!(
+        decode(&lo_hi, &[0x4548, 0x4C4C, 0x004F]).unwrap(),
+        RegisterValue::Text("HELLO".to_string())
+    );
+
+    // Text stops at the first NUL byte.
+    assert_eq!(
+        decode(&hi_lo, &[0x4849, 0x0000, 0x4142]).unwrap(),
+        RegisterValue::Text("HI".to_string())
+    );
+}
+
+#[test]
+fn test_decode_bitfield() {
+    let bitfield = format(DataType::Bitfield, ByteOrder::Abcd);
+    let RegisterValue::Bits(bits) = decode(&bitfield, &[0b101, 0x8000]).unwrap() else {
+        panic!("expected bits");
+    };
+    assert_eq!(bits.len(), 32);
+    assert!(bits[0] && !bits[1] && bits[2]);
+    assert!(bits[31]);
+    assert_eq!(bits.iter().filter(|bit| **bit).count(), 3);
+
+    // With swapped bytes the low byte comes first on the wire.
+    let bitfield = format(DataType::Bitfield, ByteOrder::Badc);
+    let RegisterValue::Bits(bits) = decode(&bitfield, &[0x0100]).unwrap() else {
+        panic!("expected bits");
+    };
+    assert!(bits[0]);
+    assert_eq!(bits.iter().filter(|bit| **bit).count(), 1);
+}
+
+#[test]
+fn test_decode_scale_and_offset() {
+    let scaled = parse("16 Bit Signed Integer/10");
+    assert_float(decode(&scaled, &[0xFF9C]).unwrap(), -10.0);
+
+    let scaled = parse("32 Bit Unsigned Integer LO_HI");
+    let scaled = DataFormat {
+        scale: 0.5,
+        offset: -40.0,
+        ..scaled
+    };
+    assert_float(decode(&scaled, &[100, 0]).unwrap(), 10.0);
+
+    let float = DataFormat {
+        scale: 2.0,
+        offset: 1.0,
+        ..parse("float32")
+    };
+    assert_float(decode(&float, &[0x3FC0, 0x0000]).unwrap(), 4.0);
+}
+
+#[test]
+fn test_decode_register_count_mismatch() {
+    assert!(decode(&parse("uint16"), &[]).is_err());
+    assert!(decode(&parse("uint16"), &[1, 2]).is_err());
+    assert!(decode(&parse("float32"), &[1]).is_err());
+    assert!(decode(&parse("int64"), &[1, 2, 3]).is_err());
+}
+
+#[test]
+fn test_encode_integers() {
+    assert_eq!(
+        encode(&parse("uint16"), &RegisterValue::Integer(0x1234), 1).unwrap(),
+        [0x1234]
+    );
+    assert_eq!(
+        encode(&parse("int16"), &RegisterValue::Integer(-2), 1).unwrap(),
+        [0xFFFE]
+    );
+    assert_eq!(
+        encode(&parse("int8"), &RegisterValue::Integer(-1), 1).unwrap(),
+        [0x00FF]
+    );
+    assert_eq!(
+        encode(
+            &parse("uint32_cdab"),
+            &RegisterValue::Integer(0x12345678),
+            2
+        )
+        .unwrap(),
+        [0x5678, 0x1234]
+    );
+    assert_eq!(
+        encode(
+            &parse("int64_dcba"),
+            &RegisterValue::Integer(0x0102030405060708),
+            4
+        )
+        .unwrap(),
+        [0x0807, 0x0605, 0x0403, 0x0201]
+    );
+    // Floats are rounded to the nearest raw value.
+    assert_eq!(
+        encode(&parse("uint16"), &RegisterValue::Float(41.6), 1).unwrap(),
+        [42]
+    );
+}
+
+#[test]
+fn test_encode_scaled() {
+    assert_eq!(
+        encode(
+            &parse("16 Bit Signed Integer/10"),
+            &RegisterValue::Float(12.3),
+            1
+        )
+        .unwrap(),
+        [123]
+    );
+    assert_eq!(
+        encode(
+            &parse("16 Bit Signed Integer/10"),
+            &RegisterValue::Float(-10.0),
+            1
+        )
+        .unwrap(),
+        [0xFF9C]
+    );
+
+    let scaled = DataFormat {
+        scale: 0.5,
+        offset: -40.0,
+        ..parse("uint16")
+    };
+    assert_eq!(
+        encode(&scaled, &RegisterValue::Integer(10), 1).unwrap(),
+        [100]
+    );
+}
+
+#[test]
+fn test_encode_text_and_bits() {
+    assert_eq!(
+        encode(
+            &parse("Character String HI_LO"),
+            &RegisterValue::Text("HELLO".to_string()),
+            3
+        )
+        .unwrap(),
+        [0x4845, 0x4C4C, 0x4F00]
+    );
+    assert_eq!(
+        encode(
+            &parse("Character String LO_HI"),
+            &RegisterValue::Text("HI".to_string()),
+            2
+        )
+        .unwrap(),
+        [0x4948, 0x0000]
+    );
+
+    let mut bits = vec![false; 17];
+    bits[0] = true;
+    bits[16] = true;
+    assert_eq!(
+        encode(&parse("bitfield"), &RegisterValue::Bits(bits), 2).unwrap(),
+        [0x0001, 0x0001]
+    );
+    assert_eq!(
+        encode(&parse("bitfield"), &RegisterValue::Integer(0b101), 1).unwrap(),
+        [0b101]
+    );
+}
+
+#[test]
+fn test_encode_errors() {
+    let out_of_range = [
+        ("uint16", RegisterValue::Integer(65536)),
+        ("uint16", RegisterValue::Integer(-1)),
+        ("int16", RegisterValue::Integer(32768)),
+        ("int8", RegisterValue::Integer(-129)),
+        ("uint8", RegisterValue::Integer(256)),
+        ("int32", RegisterValue::Float(3e9)),
+        ("uint32", RegisterValue::Integer(-1)),
+        ("int64", RegisterValue::Float(f64::INFINITY)),
+        ("16 Bit Unsigned Integer/100", RegisterValue::Float(655.36)),
+    ];
+    for (name, value) in out_of_range {
+        assert!(
+            encode(&parse(name), &value, 1).is_err(),
+            "{} {:?}",
+            name,
+            value
+        );
+    }
+
+    // Values of the wrong kind.
+    assert!(encode(&parse("uint16"), &RegisterValue::Text("1".to_string()), 1).is_err());
+    assert!(encode(&parse("float32"), &RegisterValue::Bits(vec![true]), 2).is_err());
+    assert!(encode(&parse("ascii"), &RegisterValue::Integer(1), 1).is_err());
+    assert!(encode(&parse("bitfield"), &RegisterValue::Float(1.5), 1).is_err());
+
+    // Values that don't fit the register length.
+    assert!(encode(&parse("ascii"), &RegisterValue::Text("ABC".to_string()), 1).is_err());
+    assert!(encode(&parse("ascii"), &RegisterValue::Text("é".to_string()), 1).is_err());
+    assert!(encode(&parse("bitfield"), &RegisterValue::Bits(vec![true; 17]), 1).is_err());
+    assert!(encode(&parse("bitfield"), &RegisterValue::Integer(0x10000), 1).is_err());
+}
+
+#[test]
+fn test_round_trip_every_type_and_order() {
+    let values = [
+        (DataType::Int8, RegisterValue::Integer(-100)),
+        (DataType::Uint8, RegisterValue::Integer(200)),
+        (DataType::Int16, RegisterValue::Integer(-12345)),
+        (DataType::Uint16, RegisterValue::Integer(54321)),
+        (DataType::Int32, RegisterValue::Integer(-123456789)),
+        (DataType::Uint32, RegisterValue::Integer(3000000000)),
+        (
+            DataType::Int64,
+            RegisterValue::Integer(-1234567890123456789),
+        ),
+        (DataType::Float32, RegisterValue::Float(-0.15625)),
+        (DataType::Float64, RegisterValue::Float(1234.5678)),
+        (DataType::Ascii, RegisterValue::Text("T3-BB".to_string())),
+        (
+            DataType::Bitfield,
+            RegisterValue::Bits((0..32).map(|bit| bit % 3 == 0).collect()),
+        ),
+    ];
+
+    for byte_order in ORDERS {
+        for (data_type, value) in &values {
+            let format = format(*data_type, byte_order);
+            let register_length = match data_type {
+                DataType::Ascii => 3,
+                DataType::Bitfield => 2,
+                data_type => data_type.words() as i32,
+            };
+            let words = encode(&format, value, register_length).unwrap();
+            assert_eq!(words.len(), format.words(register_length));
+            assert_eq!(
+                &decode(&format, &words).unwrap(),
+                value,
+                "{:?} {:?}",
+                data_type,
+                byte_order
+            );
+        }
+    }
+}
+
+#[test]
+fn test_round_trip_scaled() {
+    for name in [
+        "16 Bit Unsigned Integer/10",
+        "16 Bit Signed Integer/100",
+        "32 Bit Signed Integer LO_HI/2",
+        "Floating HI_LO/1000",
+        "Floating LO_HI/10",
+        "float64_badc/100",
+    ] {
+        let format = DataFormat {
+            offset: 5.0,
+            ..parse(name)
+        };
+        let words = encode(&format, &RegisterValue::Float(25.5), 4).unwrap();
+        assert_float(decode(&format, &words).unwrap(), 25.5);
+    }
+}
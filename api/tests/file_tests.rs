@@ -0,0 +1,156 @@
+use std::env;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{self, Request, StatusCode},
+    Router,
+};
+use serde_json::Value;
+use t3_webview_api::{app_state, server::create_app, utils::run_migrations};
+use tower::ServiceExt;
+
+// Sends an authorized request to the app, returning the status and JSON body of the response.
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+fn request(method: &str, uri: &str) -> http::request::Builder {
+    Request::builder().method(method).uri(uri).header(
+        http::header::AUTHORIZATION,
+        env::var("API_SECRET_KEY").unwrap(),
+    )
+}
+
+// A multipart upload of a small text file.
+fn upload(name: &str) -> Request<Body> {
+    upload_content(name, &format!("content of {}", name))
+}
+
+fn upload_content(name: &str, content: &str) -> Request<Body> {
+    let body = format!(
+        "--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: text/plain\r\n\r\n{}\r\n--boundary--\r\n",
+        name, content
+    );
+    request("POST", "/api/file?path=file_tests")
+        .header(
+            http::header::CONTENT_TYPE,
+            "multipart/form-data; boundary=boundary",
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_delete_file_by_id() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let (status, first) = send(&app, upload("first.txt")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, second) = send(&app, upload("second.txt")).await;
+    assert_eq!(status, StatusCode::OK);
+    let file_uri = |file: &Value| format!("/api/files/{}", file["id"]);
+
+    // Only the file named by the path is deleted, from the database and the disk.
+    let (status, deleted) = send(
+        &app,
+        request("DELETE", &file_uri(&second))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(deleted["id"], second["id"]);
+    let (status, _) = send(
+        &app,
+        request("GET", &file_uri(&second))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, kept) = send(
+        &app,
+        request("GET", &file_uri(&first))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(kept["id"], first["id"]);
+
+    let (status, _) = send(
+        &app,
+        request("DELETE", &file_uri(&first))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_idempotent_upload() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    // Uploads larger than the limit of the other create routes are covered too.
+    let content = "x".repeat(3 * 1024 * 1024);
+    let key = uuid::Uuid::new_v4().to_string();
+    let upload_once = || {
+        let mut request = upload_content("retried.txt", &content);
+        request.headers_mut().insert(
+            "Idempotency-Key",
+            http::HeaderValue::from_str(&key).unwrap(),
+        );
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let replayed = response.headers().contains_key("Idempotent-Replayed");
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let file: Value = serde_json::from_slice(&body).unwrap_or_default();
+            (status, replayed, file)
+        }
+    };
+
+    // A retried upload returns the file stored the first time.
+    let (status, replayed, first) = upload_once().await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!replayed);
+    let (status, replayed, retried) = upload_once().await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(replayed);
+    assert_eq!(retried["id"], first["id"]);
+
+    let (_, files) = send(
+        &app,
+        request("GET", "/api/files").body(Body::empty()).unwrap(),
+    )
+    .await;
+    let uploads = files
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|file| file["name"] == "retried.txt")
+        .count();
+    assert_eq!(uploads, 1);
+
+    let (status, _) = send(
+        &app,
+        request("DELETE", &format!("/api/files/{}", first["id"]))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
@@ -0,0 +1,94 @@
+use sea_orm::{DbBackend, EntityTrait, QueryFilter, QueryTrait};
+use t3_webview_api::{
+    entity::modbus_register, error::Error, modbus_register::filter_expression::parse_filter,
+};
+
+// The WHERE clause a filter expression produces.
+fn sql(expression: &str) -> String {
+    let sql = modbus_register::Entity::find()
+        .filter(parse_filter(expression).unwrap())
+        .build(DbBackend::Sqlite)
+        .to_string();
+    sql.split_once(" WHERE ")
+        .map_or(String::new(), |(_, clause)| clause.to_string())
+}
+
+fn error(expression: &str) -> String {
+    match parse_filter(expression) {
+        Err(Error::BadRequest(message)) => message,
+        result => panic!("expected a bad request, got {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn comparisons_become_conditions() {
+    assert_eq!(
+        sql("register_address >= 40001"),
+        r#""modbus_register"."register_address" >= 40001"#
+    );
+    assert_eq!(
+        sql("status IN (NEW, 'UPDATED') AND private = false"),
+        r#""modbus_register"."status" IN ('NEW', 'UPDATED') AND "modbus_register"."private" = FALSE"#
+    );
+    assert_eq!(
+        sql("unit IS NULL OR NOT (register_name ~ \"fan's\")"),
+        r#""modbus_register"."unit" IS NULL OR (NOT "modbus_register"."register_name" LIKE '%fan''s%')"#
+    );
+    assert_eq!(
+        sql("device_id NOT IN (1, 2) and scale < 0.5"),
+        r#""modbus_register"."device_id" NOT IN (1, 2) AND "modbus_register"."scale" < 0.5"#
+    );
+    assert_eq!(sql("  "), "TRUE");
+}
+
+#[test]
+fn timestamps_are_normalized() {
+    assert_eq!(
+        sql("created_at >= 2024-05-01 AND updated_at < '2024-05-02T10:30:00+02:00'"),
+        r#""modbus_register"."created_at" >= '2024-05-01 00:00:00 +00:00' AND "modbus_register"."updated_at" < '2024-05-02 08:30:00 +00:00'"#
+    );
+}
+
+#[test]
+fn invalid_expressions_are_explained() {
+    assert_eq!(
+        error("colour = red"),
+        "Invalid filter at position 1: unknown field \"colour\""
+    );
+    assert_eq!(
+        error("register_address >= forty"),
+        "Invalid filter at position 21: \"forty\" is not an integer"
+    );
+    assert_eq!(
+        error("unit = null"),
+        "Invalid filter at position 8: use IS NULL or IS NOT NULL to match null"
+    );
+    assert_eq!(
+        error("scale ~ 1"),
+        "Invalid filter at position 7: \"~\" only applies to text fields, not \"scale\""
+    );
+    assert_eq!(
+        error("(status = NEW"),
+        "Invalid filter at position 14: expected \")\""
+    );
+    assert_eq!(
+        error(&format!("{}status = NEW", "(".repeat(5000))),
+        "Invalid filter at position 65: nested deeper than 64 levels"
+    );
+    assert_eq!(
+        error(&"NOT ".repeat(100)),
+        "Invalid filter at position 257: nested deeper than 64 levels"
+    );
+    assert_eq!(
+        error("status = NEW unit = V"),
+        "Invalid filter at position 14: expected AND or OR, found \"unit\""
+    );
+    assert_eq!(
+        error("created_at > yesterday"),
+        "Invalid filter at position 14: \"yesterday\" is not a date or timestamp"
+    );
+    assert_eq!(
+        error("unit = 'V"),
+        "Invalid filter at position 8: unterminated quoted value"
+    );
+}
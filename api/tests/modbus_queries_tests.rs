@@ -1,18 +1,28 @@
 use axum::{
     extract::{Path, Query, State},
+    http::HeaderMap,
     Json,
 };
+use chrono::{DateTime, Utc};
+use sea_orm::{ConnectionTrait, DbBackend, Statement};
 use serde_json::Value;
 use t3_webview_api::{
     app_state::app_state,
     entity::modbus_register_settings,
+    error::Error,
     modbus_register::{
+        csv_import::guess_mapping,
+        history,
+        imports::bulk_import,
         inputs::{
-            CreateModbusRegisterItemInput, ModbusRegisterQueryParams,
+            BulkImportInput, CreateModbusRegisterItemInput, CreateModbusRegisterParams, ImportMode,
+            ImportRowStatus, ModbusRegisterGetParams, ModbusRegisterQueryParams, RegisterMapField,
             UpdateModbusRegisterItemInput, UpdateSettingInput,
         },
-        queries::{create, delete, list, update},
+        queries::{create, delete, get_one, list, update},
         settings,
+        units::UnitSystem,
+        validation::{check_register, RegisterDefinition},
     },
     utils::run_migrations,
 };
@@ -25,21 +35,33 @@ async fn test_modbus_register_crud() {
         id: None,
         register_name: Some("test".to_string()),
         register_address: Some(1),
-        operation: Some("test".to_string()),
+        operation: Some("03 Read Holding Registers (4x)".to_string()),
         description: Some("test".to_string()),
         device_id: None,
-        data_format: Some("test".to_string()),
-        unit: Some("test".to_string()),
+        data_format: Some("16 Bit Unsigned Integer".to_string()),
+        unit: Some("°C".to_string()),
         status: None,
         private: None,
         register_length: 1,
         created_at: None,
         updated_at: None,
+        scale: None,
+        offset: None,
+        min_value: None,
+        max_value: None,
+        precision: None,
+        default_value: None,
+        version_id: None,
     };
     let conn = app_state().await.unwrap();
-    let item = create(State(conn.clone()), Json(payload)).await;
+    let item = create(
+        State(conn.clone()),
+        Query(CreateModbusRegisterParams::default()),
+        Json(payload),
+    )
+    .await;
     assert!(item.is_ok());
-    let item = item.unwrap();
+    let item = item.unwrap().0.register;
 
     let params = ModbusRegisterQueryParams {
         local_only: None,
@@ -49,6 +71,13 @@ async fn test_modbus_register_crud() {
         offset: None,
         device_id: None,
         order_dir: None,
+        unit_system: None,
+        firmware: None,
+        version_id: None,
+        q: None,
+        r#where: None,
+        sort: None,
+        cursor: None,
     };
     let result = list(State(conn.clone()), Query(params)).await;
     assert!(result.is_ok());
@@ -58,22 +87,29 @@ async fn test_modbus_register_crud() {
     let id = Path(item.id);
     let payload = UpdateModbusRegisterItemInput {
         register_address: Some(2),
-        operation: Some(Some("updated".to_string())),
+        operation: Some(Some("04 Read Input Registers (3x)".to_string())),
         register_length: Some(2),
         register_name: Some(Some("updated".to_string())),
-        data_format: Some(Some("updated".to_string())),
+        data_format: Some(Some("32 Bit Float_ABCD".to_string())),
         description: Some(Some("updated".to_string())),
         device_id: None,
-        unit: Some(Some("updated".to_string())),
+        unit: Some(Some("degF".to_string())),
         status: None,
         private: None,
+        scale: None,
+        offset: None,
+        min_value: None,
+        max_value: None,
+        precision: None,
+        default_value: None,
+        version_id: None,
     };
-    let result = update(State(conn.clone()), id, Json(payload)).await;
+    let result = update(State(conn.clone()), id, HeaderMap::new(), Json(payload)).await;
     assert!(result.is_ok());
-    assert_ne!(result.unwrap().data_format, item.data_format);
+    assert_ne!(result.unwrap().1.register.data_format, item.data_format);
 
     let id = Path(item.id);
-    let result = delete(State(conn.clone()), id).await;
+    let result = delete(State(conn.clone()), id, HeaderMap::new()).await;
     assert!(result.is_ok());
 }
 
@@ -106,11 +142,602 @@ async fn test_modbus_register_settings_crud() {
         value: Some(Some("updated".to_string())),
         json_value: Some(Some(Value::String("updated".to_string()))),
     };
-    let result = settings::update(State(conn.clone()), name, Json(payload)).await;
+    let result = settings::update(State(conn.clone()), name, HeaderMap::new(), Json(payload)).await;
     assert!(result.is_ok());
-    assert_eq!(result.unwrap().value, Some("updated".to_string()));
+    assert_eq!(result.unwrap().1.value, Some("updated".to_string()));
 
     let name = Path("test".to_string());
-    let result = settings::delete(State(conn.clone()), name).await;
+    let result = settings::delete(State(conn.clone()), name, HeaderMap::new()).await;
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn test_modbus_register_history_and_revert() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+    run_migrations().await.unwrap();
+    let conn = app_state().await.unwrap();
+
+    let payload = CreateModbusRegisterItemInput {
+        id: None,
+        register_name: Some("history".to_string()),
+        register_address: Some(10),
+        operation: None,
+        description: None,
+        device_id: None,
+        data_format: None,
+        unit: None,
+        status: None,
+        private: None,
+        register_length: 1,
+        created_at: None,
+        updated_at: None,
+        scale: None,
+        offset: None,
+        min_value: None,
+        max_value: None,
+        precision: None,
+        default_value: None,
+        version_id: None,
+    };
+    let item = create(
+        State(conn.clone()),
+        Query(CreateModbusRegisterParams::default()),
+        Json(payload),
+    )
+    .await
+    .unwrap()
+    .0
+    .register;
+
+    let payload = UpdateModbusRegisterItemInput {
+        register_address: Some(20),
+        operation: None,
+        register_length: None,
+        register_name: None,
+        data_format: None,
+        description: None,
+        device_id: None,
+        unit: None,
+        status: None,
+        private: None,
+        scale: None,
+        offset: None,
+        min_value: None,
+        max_value: None,
+        precision: None,
+        default_value: None,
+        version_id: None,
+    };
+    let result = update(
+        State(conn.clone()),
+        Path(item.id),
+        HeaderMap::new(),
+        Json(payload),
+    )
+    .await;
+    assert!(result.is_ok());
+    let result = delete(State(conn.clone()), Path(item.id), HeaderMap::new()).await;
+    assert!(result.is_ok());
+
+    let entries = history::register_history(State(conn.clone()), Path(item.id))
+        .await
+        .unwrap()
+        .0;
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].action, "DELETE");
+    assert!(entries[1]
+        .changes
+        .iter()
+        .any(|change| change.field == "register_address"));
+
+    // Reverting to the update revision brings the hard-deleted register back.
+    let restored = history::revert_register(State(conn.clone()), Path((item.id, 2)))
+        .await
+        .unwrap();
+    assert_eq!(restored.register_address, Some(20));
+
+    // The deletion revision has no state to revert to.
+    let result = history::revert_register(State(conn.clone()), Path((item.id, 3))).await;
+    assert!(result.is_err());
+
+    let result = delete(State(conn.clone()), Path(item.id), HeaderMap::new()).await;
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn test_revert_snapshot_without_newer_fields() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+    run_migrations().await.unwrap();
+    let conn = app_state().await.unwrap();
+
+    let payload = CreateModbusRegisterItemInput {
+        id: None,
+        register_name: Some("before scaling".to_string()),
+        register_address: Some(12),
+        operation: None,
+        description: None,
+        device_id: None,
+        data_format: None,
+        unit: None,
+        status: None,
+        private: None,
+        register_length: 1,
+        created_at: None,
+        updated_at: None,
+        scale: Some(2.0),
+        offset: None,
+        min_value: None,
+        max_value: None,
+        precision: None,
+        default_value: None,
+        version_id: None,
+    };
+    let item = create(
+        State(conn.clone()),
+        Query(CreateModbusRegisterParams::default()),
+        Json(payload),
+    )
+    .await
+    .unwrap()
+    .0
+    .register;
+    let payload = UpdateModbusRegisterItemInput {
+        register_address: None,
+        operation: None,
+        register_length: None,
+        register_name: Some(Some("renamed".to_string())),
+        data_format: None,
+        description: None,
+        device_id: None,
+        unit: None,
+        status: None,
+        private: None,
+        scale: None,
+        offset: None,
+        min_value: None,
+        max_value: None,
+        precision: None,
+        default_value: None,
+        version_id: None,
+    };
+    let result = update(
+        State(conn.clone()),
+        Path(item.id),
+        HeaderMap::new(),
+        Json(payload),
+    )
+    .await;
+    assert!(result.is_ok());
+
+    // Make the first revision look like it was recorded before registers had these columns.
+    conn.conn
+        .lock()
+        .await
+        .execute(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            "UPDATE modbus_register_history SET after = json_remove(after, '$.scale', '$.register_length', '$.previous_status') WHERE table_name = 'modbus_register' AND record_id = ? AND revision = 1",
+            [item.id.into()],
+        ))
+        .await
+        .unwrap();
+
+    // The snapshot is laid over the current register, which keeps its newer fields.
+    let reverted = history::revert_register(State(conn.clone()), Path((item.id, 1)))
+        .await
+        .unwrap()
+        .0;
+    assert_eq!(reverted.register_name.as_deref(), Some("before scaling"));
+    assert_eq!(reverted.scale, Some(2.0));
+    assert_eq!(reverted.register_length, 1);
+
+    let result = delete(State(conn.clone()), Path(item.id), HeaderMap::new()).await;
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn test_modbus_register_timestamps() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+    run_migrations().await.unwrap();
+    let conn = app_state().await.unwrap();
+
+    let started = Utc::now();
+    let synced_at = "2020-01-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+    let payload = |address: i32| CreateModbusRegisterItemInput {
+        id: None,
+        register_name: Some("timestamps".to_string()),
+        register_address: Some(address),
+        operation: None,
+        description: None,
+        device_id: None,
+        data_format: None,
+        unit: None,
+        status: None,
+        private: None,
+        register_length: 1,
+        created_at: Some(synced_at),
+        updated_at: Some(synced_at),
+        scale: None,
+        offset: None,
+        min_value: None,
+        max_value: None,
+        precision: None,
+        default_value: None,
+        version_id: None,
+    };
+
+    // Timestamps sent by clients are ignored, unless they sync registers.
+    let item = create(
+        State(conn.clone()),
+        Query(CreateModbusRegisterParams::default()),
+        Json(payload(30)),
+    )
+    .await
+    .unwrap()
+    .0
+    .register;
+    assert!(item.created_at >= started);
+    assert_eq!(item.created_at, item.updated_at);
+    let synced = create(
+        State(conn.clone()),
+        Query(CreateModbusRegisterParams { sync: Some(true) }),
+        Json(payload(31)),
+    )
+    .await
+    .unwrap()
+    .0
+    .register;
+    assert_eq!(synced.created_at, synced_at);
+    assert_eq!(synced.updated_at, synced_at);
+
+    // Updates move updated_at forward and leave created_at alone.
+    let payload = UpdateModbusRegisterItemInput {
+        register_address: None,
+        operation: None,
+        register_length: None,
+        register_name: None,
+        data_format: None,
+        description: Some(Some("changed".to_string())),
+        device_id: None,
+        unit: None,
+        status: None,
+        private: None,
+        scale: None,
+        offset: None,
+        min_value: None,
+        max_value: None,
+        precision: None,
+        default_value: None,
+        version_id: None,
+    };
+    let updated = update(
+        State(conn.clone()),
+        Path(synced.id),
+        HeaderMap::new(),
+        Json(payload),
+    )
+    .await
+    .unwrap()
+    .1
+     .0
+    .register;
+    assert_eq!(updated.created_at, synced_at);
+    assert!(updated.updated_at >= started);
+
+    // The history is stamped by the application as well, not with the database default.
+    let entries = conn
+        .conn
+        .lock()
+        .await
+        .query_all(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            "SELECT created_at FROM modbus_register_history WHERE table_name = 'modbus_register' AND record_id = ?",
+            [synced.id.into()],
+        ))
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 2);
+    for entry in entries {
+        let created_at: String = entry.try_get("", "created_at").unwrap();
+        assert!(created_at.parse::<DateTime<Utc>>().unwrap() >= started);
+    }
+
+    for id in [item.id, synced.id] {
+        let result = delete(State(conn.clone()), Path(id), HeaderMap::new()).await;
+        assert!(result.is_ok());
+    }
+}
+
+#[tokio::test]
+async fn test_modbus_register_bulk_import() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+    run_migrations().await.unwrap();
+    let conn = app_state().await.unwrap();
+
+    let row = |register_address: i32, device_id: Option<i32>| CreateModbusRegisterItemInput {
+        id: None,
+        register_name: Some("bulk".to_string()),
+        register_address: Some(register_address),
+        operation: Some("02 Read Discrete Inputs (1x)".to_string()),
+        description: None,
+        device_id,
+        data_format: None,
+        unit: None,
+        status: None,
+        private: None,
+        register_length: 1,
+        created_at: None,
+        updated_at: None,
+        scale: None,
+        offset: None,
+        min_value: None,
+        max_value: None,
+        precision: None,
+        default_value: None,
+        version_id: None,
+    };
+
+    // A row pointing at a missing device makes the whole import roll back.
+    let payload = BulkImportInput {
+        mode: None,
+        dry_run: None,
+        items: vec![row(100, None), row(101, Some(-1))],
+    };
+    let report = bulk_import(State(conn.clone()), Json(payload))
+        .await
+        .unwrap();
+    assert!(!report.committed);
+    assert_eq!(report.rows[0].status, ImportRowStatus::Created);
+    assert_eq!(report.rows[1].status, ImportRowStatus::Failed);
+
+    // Best effort keeps the valid row, and the in-batch duplicate is skipped.
+    let payload = BulkImportInput {
+        mode: Some(ImportMode::BestEffort),
+        dry_run: None,
+        items: vec![row(100, None), row(100, None), row(101, Some(-1))],
+    };
+    let report = bulk_import(State(conn.clone()), Json(payload))
+        .await
+        .unwrap();
+    assert!(report.committed);
+    assert_eq!((report.created, report.skipped, report.failed), (1, 1, 1));
+    let id = report.rows[0].id.unwrap();
+
+    // Upserting the same key updates the existing register.
+    let mut changed = row(100, None);
+    changed.register_length = 2;
+    let payload = BulkImportInput {
+        mode: Some(ImportMode::Upsert),
+        dry_run: None,
+        items: vec![changed],
+    };
+    let report = bulk_import(State(conn.clone()), Json(payload))
+        .await
+        .unwrap();
+    assert_eq!(report.rows[0].status, ImportRowStatus::Updated);
+    assert_eq!(report.rows[0].id, Some(id));
+
+    let result = delete(State(conn.clone()), Path(id), HeaderMap::new()).await;
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn test_modbus_register_validation() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+    run_migrations().await.unwrap();
+    let conn = app_state().await.unwrap();
+
+    let payload = CreateModbusRegisterItemInput {
+        id: None,
+        register_name: Some("invalid".to_string()),
+        register_address: Some(70000),
+        operation: Some("07 Read Exception Status".to_string()),
+        description: None,
+        device_id: Some(-1),
+        data_format: Some("32 Bit Float_ABCD".to_string()),
+        unit: None,
+        status: None,
+        private: None,
+        register_length: 1,
+        created_at: None,
+        updated_at: None,
+        scale: None,
+        offset: None,
+        min_value: None,
+        max_value: None,
+        precision: None,
+        default_value: None,
+        version_id: None,
+    };
+    match create(
+        State(conn.clone()),
+        Query(CreateModbusRegisterParams::default()),
+        Json(payload),
+    )
+    .await
+    {
+        Err(Error::Validation(errors)) => {
+            let mut fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+            fields.sort();
+            assert_eq!(
+                fields,
+                [
+                    "device_id",
+                    "operation",
+                    "register_address",
+                    "register_length"
+                ]
+            );
+        }
+        _ => panic!("expected a validation error"),
+    }
+
+    // Drafts without an operation or data format are still accepted.
+    let draft = RegisterDefinition {
+        register_address: Some(65534),
+        register_length: 2,
+        operation: Some(""),
+        data_format: None,
+        device_id: None,
+        unit: Some(""),
+        scale: None,
+        min_value: None,
+        max_value: None,
+        precision: None,
+        default_value: None,
+        version_id: None,
+    };
+    assert!(check_register(&draft).is_empty());
+
+    let past_the_end = RegisterDefinition {
+        register_length: 3,
+        ..draft
+    };
+    assert_eq!(check_register(&past_the_end)[0].field, "register_length");
+
+    let unscalable = RegisterDefinition {
+        unit: Some("furlong"),
+        scale: Some(0.0),
+        min_value: Some(10.0),
+        max_value: Some(0.0),
+        precision: Some(20),
+        default_value: Some(20.0),
+        ..draft
+    };
+    let fields: Vec<String> = check_register(&unscalable)
+        .into_iter()
+        .map(|error| error.field)
+        .collect();
+    assert_eq!(
+        fields,
+        ["unit", "scale", "min_value", "precision", "default_value"]
+    );
+}
+
+#[tokio::test]
+async fn test_modbus_register_units() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+    run_migrations().await.unwrap();
+    let conn = app_state().await.unwrap();
+
+    let payload = CreateModbusRegisterItemInput {
+        id: None,
+        register_name: Some("supply temperature".to_string()),
+        register_address: Some(30),
+        operation: Some("04 Read Input Registers (3x)".to_string()),
+        description: None,
+        device_id: None,
+        data_format: Some("16 Bit Signed Integer".to_string()),
+        unit: Some("°C".to_string()),
+        status: None,
+        private: None,
+        register_length: 1,
+        created_at: None,
+        updated_at: None,
+        scale: Some(0.1),
+        offset: None,
+        min_value: Some(-40.0),
+        max_value: Some(120.0),
+        precision: Some(1),
+        default_value: Some(20.0),
+        version_id: None,
+    };
+    let item = create(
+        State(conn.clone()),
+        Query(CreateModbusRegisterParams::default()),
+        Json(payload),
+    )
+    .await
+    .unwrap()
+    .0
+    .register;
+
+    let params = ModbusRegisterGetParams {
+        unit_system: Some(UnitSystem::Imperial),
+    };
+    let register = get_one(State(conn.clone()), Path(item.id), Query(params))
+        .await
+        .unwrap()
+        .1
+         .0
+        .unwrap();
+    assert_eq!(register.unit.as_deref(), Some("°F"));
+    assert!((register.scale.unwrap() - 0.18).abs() < 1e-9);
+    assert!((register.offset.unwrap() - 32.0).abs() < 1e-9);
+    assert!((register.min_value.unwrap() + 40.0).abs() < 1e-9);
+    assert!((register.max_value.unwrap() - 248.0).abs() < 1e-9);
+    assert!((register.default_value.unwrap() - 68.0).abs() < 1e-9);
+    assert_eq!(register.precision, Some(1));
+
+    // Metric registers are returned as stored in the metric system.
+    let params = ModbusRegisterGetParams {
+        unit_system: Some(UnitSystem::Metric),
+    };
+    let register = get_one(State(conn.clone()), Path(item.id), Query(params))
+        .await
+        .unwrap()
+        .1
+         .0
+        .unwrap();
+    assert_eq!(register.unit.as_deref(), Some("°C"));
+    assert_eq!(register.scale, Some(0.1));
+
+    // Narrowing the range below the default value is rejected.
+    let payload = UpdateModbusRegisterItemInput {
+        register_address: None,
+        operation: None,
+        register_length: None,
+        register_name: None,
+        data_format: None,
+        description: None,
+        device_id: None,
+        unit: None,
+        status: None,
+        private: None,
+        scale: None,
+        offset: None,
+        min_value: None,
+        max_value: Some(Some(10.0)),
+        precision: None,
+        default_value: None,
+        version_id: None,
+    };
+    match update(
+        State(conn.clone()),
+        Path(item.id),
+        HeaderMap::new(),
+        Json(payload),
+    )
+    .await
+    {
+        Err(Error::Validation(errors)) => assert_eq!(errors[0].field, "default_value"),
+        _ => panic!("expected a validation error"),
+    }
+
+    let result = delete(State(conn.clone()), Path(item.id), HeaderMap::new()).await;
     assert!(result.is_ok());
 }
+
+#[test]
+fn test_register_map_header_guessing() {
+    let headers: Vec<String> = [
+        "Register Address",
+        "Point Name",
+        "Data Type",
+        "Words",
+        "Units",
+        "R/W",
+        "Notes",
+    ]
+    .iter()
+    .map(|h| h.to_string())
+    .collect();
+    let mapping = guess_mapping(&headers);
+
+    assert_eq!(mapping[&RegisterMapField::Address], "Register Address");
+    assert_eq!(mapping[&RegisterMapField::Name], "Point Name");
+    assert_eq!(mapping[&RegisterMapField::Format], "Data Type");
+    assert_eq!(mapping[&RegisterMapField::Length], "Words");
+    assert_eq!(mapping[&RegisterMapField::Unit], "Units");
+    assert_eq!(mapping[&RegisterMapField::Operation], "R/W");
+    assert_eq!(mapping[&RegisterMapField::Description], "Notes");
+}
@@ -0,0 +1,618 @@
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{self, Request, StatusCode},
+};
+use serde_json::Value;
+use t3_webview_api::{
+    app_state,
+    modbus_register::codec::{DataFormat, RegisterValue},
+    modbus_tcp::{
+        client::{ModbusError, ModbusTcpClient},
+        frame::{pack_bits, unpack_bits, Frame},
+        simulator::{RegisterTable, SimulatedRegister, Simulator, Waveform},
+        ILLEGAL_DATA_ADDRESS, MAX_WRITE_COILS, MAX_WRITE_REGISTERS,
+    },
+    server::create_app,
+    utils::run_migrations,
+};
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+
+// Start a minimal Modbus TCP server with 200 holding registers and 2000 coils.
+async fn start_test_server() -> (u16, Arc<Mutex<(Vec<u16>, Vec<bool>)>>) {
+    let memory = Arc::new(Mutex::new((vec![0u16; 200], vec![false; 2000])));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let shared = memory.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let memory = shared.clone();
+            tokio::spawn(async move {
+                while let Ok(request) = Frame::read(&mut stream).await {
+                    let pdu = &request.pdu;
+                    let address = u16::from_be_bytes([pdu[1], pdu[2]]) as usize;
+                    let count = u16::from_be_bytes([pdu[3], pdu[4]]) as usize;
+                    let response = {
+                        let (registers, coils) = &mut *memory.lock().unwrap();
+                        match pdu[0] {
+                            3 | 4 if address + count <= registers.len() => {
+                                let mut response = vec![pdu[0], (count * 2) as u8];
+                                for value in &registers[address..address + count] {
+                                    response.extend_from_slice(&value.to_be_bytes());
+                                }
+                                response
+                            }
+                            1 if address + count <= coils.len() => {
+                                let data = pack_bits(&coils[address..address + count]);
+                                let mut response = vec![pdu[0], data.len() as u8];
+                                response.extend_from_slice(&data);
+                                response
+                            }
+                            5 if address < coils.len() => {
+                                coils[address] = count == 0xFF00;
+                                pdu.to_vec()
+                            }
+                            6 if address < registers.len() => {
+                                registers[address] = count as u16;
+                                pdu.to_vec()
+                            }
+                            16 if address + count <= registers.len() => {
+                                for index in 0..count {
+                                    registers[address + index] = u16::from_be_bytes([
+                                        pdu[6 + index * 2],
+                                        pdu[7 + index * 2],
+                                    ]);
+                                }
+                                pdu[..5].to_vec()
+                            }
+                            15 if address + count <= coils.len() => {
+                                let bits = unpack_bits(&pdu[6..], count);
+                                coils[address..address + count].copy_from_slice(&bits);
+                                pdu[..5].to_vec()
+                            }
+                            function => vec![function | 0x80, ILLEGAL_DATA_ADDRESS],
+                        }
+                    };
+                    let response = Frame {
+                        pdu: response,
+                        ..request
+                    };
+                    if response.write(&mut stream).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    (port, memory)
+}
+
+#[test]
+fn test_frame_bytes() {
+    let frame = Frame {
+        transaction_id: 0x0102,
+        unit_id: 7,
+        pdu: vec![3, 0, 10, 0, 2],
+    };
+    assert_eq!(
+        frame.to_bytes(),
+        [0x01, 0x02, 0, 0, 0, 6, 7, 3, 0, 10, 0, 2]
+    );
+
+    let bits = [true, false, true, true, false, false, false, false, true];
+    assert_eq!(pack_bits(&bits), [0b0000_1101, 0b0000_0001]);
+    assert_eq!(unpack_bits(&pack_bits(&bits), bits.len()), bits);
+}
+
+#[tokio::test]
+async fn test_modbus_tcp_client() {
+    let (port, memory) = start_test_server().await;
+    memory.lock().unwrap().0[10] = 1234;
+
+    let mut client =
+        ModbusTcpClient::connect(&format!("127.0.0.1:{}", port), 1, Duration::from_secs(2))
+            .await
+            .unwrap();
+
+    assert_eq!(
+        client.read_holding_registers(10, 2).await.unwrap(),
+        [1234, 0]
+    );
+    client.write_single_register(11, 42).await.unwrap();
+    client
+        .write_multiple_registers(20, &[1, 2, 3])
+        .await
+        .unwrap();
+    assert_eq!(
+        client.read_input_registers(10, 2).await.unwrap(),
+        [1234, 42]
+    );
+    assert_eq!(
+        client.read_holding_registers(20, 3).await.unwrap(),
+        [1, 2, 3]
+    );
+
+    client.write_single_coil(3, true).await.unwrap();
+    client
+        .write_multiple_coils(5, &[true, false, true])
+        .await
+        .unwrap();
+    assert_eq!(
+        client.read_coils(3, 5).await.unwrap(),
+        [true, false, true, false, true]
+    );
+
+    match client.read_holding_registers(199, 2).await {
+        Err(ModbusError::Exception { function, code }) => {
+            assert_eq!((function, code), (3, ILLEGAL_DATA_ADDRESS))
+        }
+        result => panic!("expected an exception, got {:?}", result),
+    }
+
+    // The connection stays usable after an exception.
+    assert_eq!(client.read_holding_registers(11, 1).await.unwrap(), [42]);
+
+    // Multiple writes are limited to what fits in one request.
+    client
+        .write_multiple_registers(0, &[7; MAX_WRITE_REGISTERS])
+        .await
+        .unwrap();
+    assert_eq!(memory.lock().unwrap().0[MAX_WRITE_REGISTERS - 1], 7);
+    assert!(matches!(
+        client
+            .write_multiple_registers(0, &[7; MAX_WRITE_REGISTERS + 1])
+            .await,
+        Err(ModbusError::InvalidRequest(_))
+    ));
+    client
+        .write_multiple_coils(0, &[true; MAX_WRITE_COILS])
+        .await
+        .unwrap();
+    assert!(memory.lock().unwrap().1[MAX_WRITE_COILS - 1]);
+    assert!(matches!(
+        client
+            .write_multiple_coils(0, &[true; MAX_WRITE_COILS + 1])
+            .await,
+        Err(ModbusError::InvalidRequest(_))
+    ));
+    assert!(matches!(
+        client.write_multiple_registers(0, &[]).await,
+        Err(ModbusError::InvalidRequest(_))
+    ));
+}
+
+fn authorized_request(method: &str, uri: &str, body: String) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(
+            http::header::AUTHORIZATION,
+            env::var("API_SECRET_KEY").unwrap(),
+        )
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn json_body(response: axum::response::Response) -> Value {
+    serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn test_live_read_and_write() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+    run_migrations().await.unwrap();
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let (port, memory) = start_test_server().await;
+    {
+        let (registers, coils) = &mut *memory.lock().unwrap();
+        // 123.456 as a CDAB float at 0, -2 as a signed integer at 2.
+        registers[0] = 0xE979;
+        registers[1] = 0x42F6;
+        registers[2] = 0xFFFE;
+        coils[4] = true;
+    }
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            "/api/modbus-register/devices",
+            r#"{"name":"Live Test"}"#.to_string(),
+        ))
+        .await
+        .unwrap();
+    let device_id = json_body(response).await["id"].as_i64().unwrap();
+
+    let registers = [
+        (
+            0,
+            2,
+            "03_16 Read Holding and Write Multiple",
+            "32 Bit Float_CDAB",
+        ),
+        (
+            2,
+            1,
+            "03_06 Read Holding and Write Single",
+            "16 Bit Signed Integer",
+        ),
+        (4, 1, "01_05 Read Coil and Write Single", ""),
+        (
+            200,
+            1,
+            "04 Read Input Registers (3x)",
+            "16 Bit Unsigned Integer",
+        ),
+        (10, 123, "03_16 Read Holding and Write Multiple", "ASCII"),
+        (300, 124, "03_16 Read Holding and Write Multiple", "ASCII"),
+    ];
+    let mut ids = vec![];
+    for (address, length, operation, data_format) in registers {
+        let response = app
+            .clone()
+            .oneshot(authorized_request(
+                "POST",
+                "/api/modbus-registers",
+                format!(
+                    r#"{{"register_address":{},"register_length":{},"operation":"{}","data_format":"{}","device_id":{}}}"#,
+                    address, length, operation, data_format, device_id
+                ),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        ids.push(json_body(response).await["id"].as_i64().unwrap());
+    }
+
+    let connection = format!(r#""host":"127.0.0.1","port":{},"unit_id":1"#, port);
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            &format!("/api/modbus-register/devices/{}/live/read", device_id),
+            format!("{{{}}}", connection),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let read = json_body(response).await;
+    let values = read["registers"].as_array().unwrap();
+    assert_eq!(values.len(), 6);
+    assert!((values[0]["value"].as_f64().unwrap() - 123.456).abs() < 1e-4);
+    assert_eq!(values[1]["value"], -2);
+    assert_eq!(values[2]["value"], 1);
+    assert_eq!(values[2]["function_code"], 1);
+    // Addresses the device doesn't have are reported per register.
+    assert!(values[4]["error"]
+        .as_str()
+        .unwrap()
+        .contains("exception 02"));
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            &format!("/api/modbus-register/devices/{}/live/write", device_id),
+            format!(
+                r#"{{{},"register_id":{},"value":-1.5}}"#,
+                connection, ids[0]
+            ),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json_body(response).await["function_code"], 16);
+    // -1.5 is 0xBFC00000, written low word first.
+    assert_eq!(memory.lock().unwrap().0[..2], [0x0000, 0xBFC0]);
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            &format!("/api/modbus-register/devices/{}/live/write", device_id),
+            format!(r#"{{{},"register_id":{},"value":0}}"#, connection, ids[2]),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!memory.lock().unwrap().1[4]);
+
+    // Read-only registers can't be written.
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            &format!("/api/modbus-register/devices/{}/live/write", device_id),
+            format!(r#"{{{},"register_id":{},"value":1}}"#, connection, ids[3]),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Function 16 writes at most 123 registers.
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            &format!("/api/modbus-register/devices/{}/live/write", device_id),
+            format!(
+                r#"{{{},"register_id":{},"value":"AB"}}"#,
+                connection, ids[4]
+            ),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(memory.lock().unwrap().0[10], 0x4142);
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            &format!("/api/modbus-register/devices/{}/live/write", device_id),
+            format!(
+                r#"{{{},"register_id":{},"value":"AB"}}"#,
+                connection, ids[5]
+            ),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "DELETE",
+            &format!("/api/modbus-register/devices/{}?cascade=true", device_id),
+            String::new(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn test_simulator_memory() {
+    let register = |id, table, address, length, format: &str| SimulatedRegister {
+        id,
+        table,
+        address,
+        length,
+        format: DataFormat::parse(format).unwrap(),
+        writable: true,
+        waveform: Waveform::Constant,
+    };
+    let mut simulator = Simulator::new(vec![
+        register(
+            1,
+            RegisterTable::HoldingRegisters,
+            10,
+            2,
+            "32 Bit Float_CDAB",
+        ),
+        register(
+            2,
+            RegisterTable::InputRegisters,
+            10,
+            1,
+            "16 Bit Signed Integer",
+        ),
+        register(3, RegisterTable::Coils, 0, 1, "16 Bit Unsigned Integer"),
+    ]);
+
+    simulator
+        .set_value(1, &RegisterValue::Float(123.456))
+        .unwrap();
+    simulator.set_value(2, &RegisterValue::Integer(-2)).unwrap();
+    assert_eq!(
+        simulator.handle(&[3, 0, 10, 0, 2]),
+        [3, 4, 0xE9, 0x79, 0x42, 0xF6]
+    );
+    // Holding and input registers are separate tables.
+    assert_eq!(simulator.handle(&[4, 0, 10, 0, 1]), [4, 2, 0xFF, 0xFE]);
+    // Addresses outside the register map don't exist.
+    assert_eq!(
+        simulator.handle(&[3, 0, 11, 0, 2]),
+        [0x83, ILLEGAL_DATA_ADDRESS]
+    );
+    assert_eq!(simulator.handle(&[5, 0, 0, 0xFF, 0]), [5, 0, 0, 0xFF, 0]);
+    assert_eq!(
+        simulator.value(3).unwrap().value,
+        Some(RegisterValue::Integer(1))
+    );
+
+    simulator
+        .set_waveform(
+            2,
+            Waveform::Ramp {
+                min: 100.0,
+                max: 200.0,
+                period_ms: 60_000,
+            },
+        )
+        .unwrap();
+    simulator.tick();
+    match simulator.value(2).unwrap().value {
+        Some(RegisterValue::Integer(value)) => assert!((100..=200).contains(&value)),
+        value => panic!("expected an integer, got {:?}", value),
+    }
+
+    simulator.set_writable(1, false).unwrap();
+    assert_eq!(
+        simulator.handle(&[6, 0, 10, 0, 1]),
+        [0x86, ILLEGAL_DATA_ADDRESS]
+    );
+}
+
+#[tokio::test]
+async fn test_device_simulator() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+    run_migrations().await.unwrap();
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            "/api/modbus-register/devices",
+            r#"{"name":"Simulator Test"}"#.to_string(),
+        ))
+        .await
+        .unwrap();
+    let device_id = json_body(response).await["id"].as_i64().unwrap();
+
+    let registers = [
+        (
+            0,
+            2,
+            "03_16 Read Holding and Write Multiple",
+            "32 Bit Float_CDAB",
+        ),
+        (
+            2,
+            1,
+            "04 Read Input Registers (3x)",
+            "16 Bit Unsigned Integer",
+        ),
+        (4, 1, "01_05 Read Coil and Write Single", ""),
+    ];
+    let mut ids = vec![];
+    for (address, length, operation, data_format) in registers {
+        let response = app
+            .clone()
+            .oneshot(authorized_request(
+                "POST",
+                "/api/modbus-registers",
+                format!(
+                    r#"{{"register_address":{},"register_length":{},"operation":"{}","data_format":"{}","device_id":{}}}"#,
+                    address, length, operation, data_format, device_id
+                ),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        ids.push(json_body(response).await["id"].as_i64().unwrap());
+    }
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            "/api/modbus-register/simulators",
+            format!(
+                r#"{{"device_id":{},"bind_address":"127.0.0.1","port":0,"tick_ms":50,"registers":[{{"register_id":{},"value":21.5}},{{"register_id":{},"waveform":{{"type":"random","min":10,"max":20}}}}]}}"#,
+                device_id, ids[0], ids[1]
+            ),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let session = json_body(response).await;
+    let session_id = session["id"].as_u64().unwrap();
+    let port = session["port"].as_u64().unwrap();
+    assert_eq!(session["registers"].as_array().unwrap().len(), 3);
+    assert_eq!(session["registers"][0]["value"], 21.5);
+
+    // The simulator can be read with the live endpoints like a real device.
+    let connection = format!(r#""host":"127.0.0.1","port":{}"#, port);
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            &format!("/api/modbus-register/devices/{}/live/read", device_id),
+            format!("{{{}}}", connection),
+        ))
+        .await
+        .unwrap();
+    let read = json_body(response).await;
+    assert_eq!(read["registers"][0]["value"], 21.5);
+    let random = read["registers"][1]["value"].as_i64().unwrap();
+    assert!((10..=20).contains(&random));
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            &format!("/api/modbus-register/devices/{}/live/write", device_id),
+            format!(r#"{{{},"register_id":{},"value":1}}"#, connection, ids[2]),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "PATCH",
+            &format!(
+                "/api/modbus-register/simulators/{}/registers/{}",
+                session_id, ids[0]
+            ),
+            r#"{"value":-7.25}"#.to_string(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut client =
+        ModbusTcpClient::connect(&format!("127.0.0.1:{}", port), 1, Duration::from_secs(2))
+            .await
+            .unwrap();
+    // -7.25 is 0xC0E80000, low word first.
+    assert_eq!(
+        client.read_holding_registers(0, 2).await.unwrap(),
+        [0x0000, 0xC0E8]
+    );
+    assert_eq!(client.read_coils(4, 1).await.unwrap(), [true]);
+    // Input registers are read-only.
+    assert!(client.write_single_register(2, 1).await.is_err());
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/modbus-register/simulators/{}", session_id))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let session = json_body(response).await;
+    assert_eq!(session["registers"][2]["value"], 1);
+    assert_eq!(session["registers"][1]["waveform"]["type"], "random");
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "DELETE",
+            &format!("/api/modbus-register/simulators/{}", session_id),
+            String::new(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // Stopping the simulator closes its connections.
+    assert!(client.read_coils(4, 1).await.is_err());
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "DELETE",
+            &format!("/api/modbus-register/devices/{}?cascade=true", device_id),
+            String::new(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
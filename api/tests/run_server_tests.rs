@@ -1,8 +1,19 @@
+use std::env;
+
 use axum::{
-    body::Body,
-    http::{Request, StatusCode},
+    body::{to_bytes, Body},
+    http::{self, HeaderMap, Request, StatusCode},
+    Router,
 };
-use t3_webview_api::{app_state, server::create_app, utils::run_migrations}; // Assuming you've modified server_start to create_app
+use chrono::{Duration, Utc};
+use sea_orm::{ActiveModelTrait, EntityTrait, Set};
+use serde_json::Value;
+use t3_webview_api::{
+    app_state,
+    entity::{idempotency_keys, user},
+    server::create_app,
+    utils::run_migrations,
+}; // Assuming you've modified server_start to create_app
 use tower::ServiceExt; // for `call`, `oneshot`, and `ready`
 
 #[tokio::test]
@@ -29,3 +40,2326 @@ async fn test_server_start() {
     assert_eq!(response.status(), StatusCode::OK);
     // Add more assertions here based on what you expect the response to be
 }
+
+#[tokio::test]
+async fn test_register_map_export() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/modbus-register/devices")
+        .header(
+            http::header::AUTHORIZATION,
+            env::var("API_SECRET_KEY").unwrap(),
+        )
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"name":"Export <Test>"}"#))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let device: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    let device_id = device["id"].as_i64().unwrap();
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/modbus-registers")
+        .header(
+            http::header::AUTHORIZATION,
+            env::var("API_SECRET_KEY").unwrap(),
+        )
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(format!(
+            r#"{{"register_address":7,"register_length":1,"register_name":"Setpoint","device_id":{}}}"#,
+            device_id
+        )))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let register: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/modbus-registers",
+        format!(
+            r#"{{"register_address":12,"register_length":1,"register_name":"=SUM(A1:A2)","offset":-5,"device_id":{}}}"#,
+            device_id
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let export = |format: &str| {
+        Request::builder()
+            .uri(format!(
+                "/api/modbus-register/devices/{}/export?format={}",
+                device_id, format
+            ))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(export("json")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let document: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(document["version"], 1);
+    assert_eq!(
+        document["devices"][0]["registers"][0]["register_name"],
+        "Setpoint"
+    );
+
+    let response = app.clone().oneshot(export("csv")).await.unwrap();
+    let csv = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let csv = String::from_utf8_lossy(&csv);
+    assert!(csv.starts_with("Id,Device,Register Address"));
+    assert!(csv.contains("Setpoint"));
+    // Text that looks like a formula is quoted, negative numbers stay numbers.
+    assert!(csv.contains(",'=SUM(A1:A2),"));
+    assert!(csv.contains(",-5,"));
+
+    // Filter expressions apply to device exports too.
+    let response = app
+        .clone()
+        .oneshot(export("csv&where=register_address%20%3E%2010"))
+        .await
+        .unwrap();
+    let csv = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(!String::from_utf8_lossy(&csv).contains("Setpoint"));
+
+    let response = app.clone().oneshot(export("html")).await.unwrap();
+    let html = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8_lossy(&html).contains("Export &lt;Test&gt;"));
+
+    // Deleting a device with registers needs cascade, then it cascades to its registers.
+    for (query, status) in [
+        ("", StatusCode::CONFLICT),
+        ("?cascade=true", StatusCode::OK),
+    ] {
+        let request = Request::builder()
+            .method("DELETE")
+            .uri(format!(
+                "/api/modbus-register/devices/{}{}",
+                device_id, query
+            ))
+            .header(
+                http::header::AUTHORIZATION,
+                env::var("API_SECRET_KEY").unwrap(),
+            )
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), status);
+    }
+
+    // The cascaded register deletion is in the register's history.
+    let (status, entries) = send(
+        &app,
+        "GET",
+        &format!("/api/modbus-registers/{}/history", register["id"]),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(entries[0]["action"], "DELETE");
+}
+
+fn authorized_request(method: &str, uri: &str, body: String) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(
+            http::header::AUTHORIZATION,
+            env::var("API_SECRET_KEY").unwrap(),
+        )
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+// Sends a request to the app, returning the status, headers and JSON body of the response.
+async fn send_request(app: &Router, request: Request<Body>) -> (StatusCode, HeaderMap, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (
+        status,
+        headers,
+        serde_json::from_slice::<Value>(&body).unwrap_or_default(),
+    )
+}
+
+// Sends an authorized JSON request to the app, returning the status and JSON body of the
+// response.
+async fn send(app: &Router, method: &str, uri: &str, body: String) -> (StatusCode, Value) {
+    let (status, _, body) = send_request(app, authorized_request(method, uri, body)).await;
+    (status, body)
+}
+
+#[tokio::test]
+async fn test_register_map_lint() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let request = authorized_request(
+        "POST",
+        "/api/modbus-register/devices",
+        r#"{"name":"Lint Test"}"#.to_string(),
+    );
+    let response = app.clone().oneshot(request).await.unwrap();
+    let device: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    let device_id = device["id"].as_i64().unwrap();
+
+    let register = |address: i32, length: i32| {
+        authorized_request(
+            "POST",
+            "/api/modbus-registers",
+            format!(
+                r#"{{"register_address":{},"register_length":{},"operation":"03 Read Holding Registers (4x)","device_id":{}}}"#,
+                address, length, device_id
+            ),
+        )
+    };
+
+    // Overlaps are only reported while the policy is "warn".
+    for (address, length, warnings) in [(10, 2, 0), (11, 1, 1), (20, 1, 0)] {
+        let response = app
+            .clone()
+            .oneshot(register(address, length))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let created: Value =
+            serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap())
+                .unwrap();
+        assert_eq!(created["warnings"].as_array().map_or(0, Vec::len), warnings);
+    }
+
+    let request = Request::builder()
+        .uri(format!("/api/modbus-register/devices/{}/lint", device_id))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(report["register_count"], 3);
+    assert_eq!(report["overlaps"].as_array().unwrap().len(), 1);
+    assert_eq!(report["overlaps"][0]["kind"], "overlap");
+    assert_eq!(report["gaps"][0]["start"], 12);
+    assert_eq!(report["gaps"][0]["end"], 19);
+    assert_eq!(report["unnamed"].as_array().unwrap().len(), 3);
+    assert_eq!(report["missing_units"].as_array().unwrap().len(), 3);
+
+    let request = authorized_request(
+        "POST",
+        "/api/modbus-register/settings",
+        r#"{"name":"overlap_policy","value":"reject","json_value":null}"#.to_string(),
+    );
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(register(20, 1)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let response = app.clone().oneshot(register(21, 1)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = authorized_request(
+        "DELETE",
+        "/api/modbus-register/settings/overlap_policy",
+        String::new(),
+    );
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = authorized_request(
+        "DELETE",
+        &format!("/api/modbus-register/devices/{}?cascade=true", device_id),
+        String::new(),
+    );
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_register_value_codec() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/modbus-registers/decode")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"data_format":"32 Bit Float_CDAB","words":[59769,17142]}"#,
+        ))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let decoded: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert!((decoded["value"].as_f64().unwrap() - 123.456).abs() < 1e-4);
+    assert_eq!(decoded["data_format"]["byte_order"], "CDAB");
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/modbus-registers/encode")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"data_format":"16 Bit Signed Integer/10","value":-10.0}"#,
+        ))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let encoded: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(encoded["words"], serde_json::json!([65436]));
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/modbus-registers/decode")
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"data_format":"nonsense","words":[1]}"#))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_register_value_labels() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let body = |response: axum::response::Response| async move {
+        serde_json::from_slice::<Value>(&to_bytes(response.into_body(), usize::MAX).await.unwrap())
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            "/api/modbus-registers",
+            r#"{"register_name":"Mode and status","register_address":700,"register_length":1,"operation":"03_06 Read Holding and Write Single","data_format":"16 Bit Unsigned Integer"}"#.to_string(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let register_id = body(response).await["id"].as_i64().unwrap();
+    let labels_uri = format!("/api/modbus-registers/{}/labels", register_id);
+
+    for label in [
+        r#"{"kind":"enum","value":0,"label":"Off"}"#,
+        r#"{"kind":"enum","value":2,"label":"Cooling"}"#,
+        r#"{"kind":"bits","value":8,"bit_length":1,"label":"Alarm"}"#,
+        r#"{"kind":"bits","value":12,"bit_length":4,"label":"Fan speed"}"#,
+    ] {
+        let response = app
+            .clone()
+            .oneshot(authorized_request("POST", &labels_uri, label.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // Duplicate values and overlapping or oversized bit fields are rejected.
+    for label in [
+        r#"{"kind":"enum","value":2,"label":"Heating"}"#,
+        r#"{"kind":"bits","value":14,"bit_length":1,"label":"Filter"}"#,
+        r#"{"kind":"bits","value":15,"bit_length":4,"label":"Too wide"}"#,
+    ] {
+        let response = app
+            .clone()
+            .oneshot(authorized_request("POST", &labels_uri, label.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/modbus-registers/{}", register_id))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let register = body(response).await;
+    let labels = register["labels"].as_array().unwrap();
+    assert_eq!(labels.len(), 4);
+    let off_id = labels.iter().find(|label| label["label"] == "Off").unwrap()["id"]
+        .as_i64()
+        .unwrap();
+
+    // Enumerations match the whole value, bit fields pick their bits out of it.
+    for (word, label) in [(2, Some("Cooling")), (0x3100, None)] {
+        let response = app
+            .clone()
+            .oneshot(authorized_request(
+                "POST",
+                "/api/modbus-registers/decode",
+                format!(r#"{{"register_id":{},"words":[{}]}}"#, register_id, word),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let decoded = body(response).await;
+        assert_eq!(decoded["label"].as_str(), label);
+        assert_eq!(decoded["bit_fields"][0]["label"], "Alarm");
+        assert_eq!(decoded["bit_fields"][0]["value"], (word >> 8) & 1);
+        assert_eq!(decoded["bit_fields"][1]["value"], word >> 12);
+    }
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "POST",
+            "/api/modbus-registers/encode",
+            format!(r#"{{"register_id":{},"value":"cooling"}}"#, register_id),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(body(response).await["words"], serde_json::json!([2]));
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "PATCH",
+            &format!("{}/{}", labels_uri, off_id),
+            r#"{"label":"Standby","description":"Fans stopped"}"#.to_string(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body(response).await["label"], "Standby");
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/modbus-registers/export?format=csv&filter=Mode%20and%20status")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let csv = String::from_utf8(
+        to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec(),
+    )
+    .unwrap();
+    assert!(csv.contains("0=Standby; 2=Cooling; bit 8=Alarm; bits 12-15=Fan speed"));
+
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "DELETE",
+            &format!("{}/{}", labels_uri, off_id),
+            String::new(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Deleting a new register deletes its labels with it.
+    let response = app
+        .clone()
+        .oneshot(authorized_request(
+            "DELETE",
+            &format!("/api/modbus-registers/{}", register_id),
+            String::new(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(&labels_uri)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_device_templates() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let (status, template) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/devices",
+        r#"{"name":"Template controller","is_template":true}"#.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(template["is_template"], true);
+    let template_id = template["id"].as_i64().unwrap();
+
+    let mut register_ids = vec![];
+    for (name, address) in [("Supply temperature", 10), ("Fan speed", 11)] {
+        let (status, register) = send(
+            &app,
+            "POST",
+            "/api/modbus-registers",
+            format!(
+                r#"{{"register_name":"{}","register_address":{},"register_length":1,"device_id":{}}}"#,
+                name, address, template_id
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        register_ids.push(register["id"].as_i64().unwrap());
+    }
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("/api/modbus-registers/{}/labels", register_ids[1]),
+        r#"{"kind":"enum","value":0,"label":"Off"}"#.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let product_id = 900_000 + template_id;
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/product_device_mappings",
+        format!(
+            r#"{{"product_id":{},"device_id":{}}}"#,
+            product_id, template_id
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Offsets that push registers out of the address space are rejected.
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("/api/modbus-register/devices/{}/clone", template_id),
+        r#"{"name":"Out of range","address_offset":65530}"#.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, clone) = send(
+        &app,
+        "POST",
+        &format!("/api/modbus-register/devices/{}/clone", template_id),
+        r#"{"name":"Controller variant","address_offset":1000}"#.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(clone["registers"], 2);
+    assert_eq!(clone["labels"], 1);
+    assert_eq!(clone["mappings"], 1);
+    assert_eq!(clone["device"]["parent_id"], template_id);
+    let clone_id = clone["device"]["id"].as_i64().unwrap();
+
+    let (_, registers) = send(
+        &app,
+        "GET",
+        &format!("/api/modbus-registers?device_id={}", clone_id),
+        String::new(),
+    )
+    .await;
+    let mut addresses: Vec<i64> = registers["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|register| register["register_address"].as_i64().unwrap())
+        .collect();
+    addresses.sort();
+    assert_eq!(addresses, vec![1010, 1011]);
+
+    // The product is now mapped to both devices.
+    let (_, mappings) = send(
+        &app,
+        "GET",
+        "/api/modbus-register/product_device_mappings",
+        String::new(),
+    )
+    .await;
+    let mapped = mappings
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|mapping| mapping["product_id"] == product_id)
+        .count();
+    assert_eq!(mapped, 2);
+
+    let (status, subset) = send(
+        &app,
+        "POST",
+        &format!("/api/modbus-register/devices/{}/clone", template_id),
+        format!(
+            r#"{{"name":"Fan only","register_ids":[{}],"include_mappings":false}}"#,
+            register_ids[1]
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(subset["registers"], 1);
+    assert_eq!(subset["mappings"], 0);
+
+    // Changes to the template are picked up by a re-sync.
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        &format!("/api/modbus-registers/{}", register_ids[0]),
+        r#"{"register_name":"Supply air temperature"}"#.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/modbus-registers",
+        format!(
+            r#"{{"register_name":"Filter alarm","register_address":12,"register_length":1,"device_id":{}}}"#,
+            template_id
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let resync_uri = format!("/api/modbus-register/devices/{}/resync", clone_id);
+    let (status, preview) =
+        send(&app, "POST", &resync_uri, r#"{"dry_run":true}"#.to_string()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(preview["updated"].as_array().unwrap().len(), 1);
+    assert_eq!(preview["created"].as_array().unwrap().len(), 1);
+
+    let (status, resync) = send(&app, "POST", &resync_uri, "{}".to_string()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(resync["updated"].as_array().unwrap().len(), 1);
+    assert_eq!(resync["unchanged"], 1);
+    let created_id = resync["created"][0].as_i64().unwrap();
+
+    let (_, created) = send(
+        &app,
+        "GET",
+        &format!("/api/modbus-registers/{}", created_id),
+        String::new(),
+    )
+    .await;
+    assert_eq!(created["register_address"], 1012);
+    assert_eq!(created["device_id"], clone_id);
+
+    let (_, resync) = send(&app, "POST", &resync_uri, "{}".to_string()).await;
+    assert_eq!(resync["unchanged"], 3);
+
+    // Only clones of a template can be re-synced.
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("/api/modbus-register/devices/{}/resync", template_id),
+        "{}".to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, mapping) = send(
+        &app,
+        "DELETE",
+        &format!(
+            "/api/modbus-register/product_device_mappings/{}?device_id={}",
+            product_id, clone_id
+        ),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(mapping["device_id"], clone_id);
+    let (_, mapping) = send(
+        &app,
+        "GET",
+        &format!(
+            "/api/modbus-register/product_device_mappings/{}",
+            product_id
+        ),
+        String::new(),
+    )
+    .await;
+    assert_eq!(mapping["device_id"], template_id);
+}
+
+#[tokio::test]
+async fn test_firmware_versions() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let (_, device) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/devices",
+        r#"{"name":"Versioned controller"}"#.to_string(),
+    )
+    .await;
+    let device_id = device["id"].as_i64().unwrap();
+    let versions_uri = format!("/api/modbus-register/devices/{}/versions", device_id);
+
+    let mut version_ids = vec![];
+    for version in [
+        r#"{"version":"1.x","firmware_min":"1.0","firmware_max":"1.9"}"#,
+        r#"{"version":"2.x","firmware_min":"2.0","release_notes":"Adds the filter alarm"}"#,
+    ] {
+        let (status, version) = send(&app, "POST", &versions_uri, version.to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+        version_ids.push(version["id"].as_i64().unwrap());
+    }
+    let (status, _) = send(
+        &app,
+        "POST",
+        &versions_uri,
+        r#"{"version":"1.5","firmware_min":"1.5","firmware_max":"1.6"}"#.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // A shared register, one moved between versions, one removed and one added.
+    for (name, address, data_format, version_id) in [
+        ("Supply temperature", 0, "16 Bit Signed Integer", None),
+        (
+            "Setpoint",
+            10,
+            "16 Bit Unsigned Integer",
+            Some(version_ids[0]),
+        ),
+        (
+            "Setpoint",
+            10,
+            "16 Bit Signed Integer",
+            Some(version_ids[1]),
+        ),
+        (
+            "Legacy mode",
+            30,
+            "16 Bit Unsigned Integer",
+            Some(version_ids[0]),
+        ),
+        (
+            "Filter alarm",
+            20,
+            "16 Bit Unsigned Integer",
+            Some(version_ids[1]),
+        ),
+    ] {
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/modbus-registers",
+            serde_json::json!({
+                "register_name": name,
+                "register_address": address,
+                "register_length": 1,
+                "data_format": data_format,
+                "device_id": device_id,
+                "version_id": version_id,
+            })
+            .to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    // Versions of other devices are rejected.
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/modbus-registers",
+        format!(
+            r#"{{"register_name":"Stray","register_length":1,"version_id":{}}}"#,
+            version_ids[0]
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    for (firmware, expected) in [
+        ("1.5", ["Legacy mode", "Setpoint", "Supply temperature"]),
+        ("2.3.1", ["Filter alarm", "Setpoint", "Supply temperature"]),
+    ] {
+        let (status, registers) = send(
+            &app,
+            "GET",
+            &format!(
+                "/api/modbus-registers?device_id={}&firmware={}",
+                device_id, firmware
+            ),
+            String::new(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let mut names: Vec<&str> = registers["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|register| register["register_name"].as_str().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, expected);
+    }
+    let (status, _) = send(
+        &app,
+        "GET",
+        "/api/modbus-registers?firmware=latest",
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, diff) = send(
+        &app,
+        "GET",
+        &format!(
+            "/api/modbus-register/devices/{}/versions/diff?from={}&to={}",
+            device_id, version_ids[0], version_ids[1]
+        ),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(diff["added"][0]["register_name"], "Filter alarm");
+    assert_eq!(diff["removed"][0]["register_name"], "Legacy mode");
+    assert_eq!(diff["changed"][0]["changes"][0]["field"], "data_format");
+    assert_eq!(diff["unchanged"], 1);
+
+    // Versions with registers can't be deleted.
+    let (status, _) = send(
+        &app,
+        "DELETE",
+        &format!("{}/{}", versions_uri, version_ids[0]),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Clones get their own copies of the versions.
+    let (status, clone) = send(
+        &app,
+        "POST",
+        &format!("/api/modbus-register/devices/{}/clone", device_id),
+        r#"{"name":"Versioned controller variant"}"#.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let clone_id = clone["device"]["id"].as_i64().unwrap();
+    let (_, clone_versions) = send(
+        &app,
+        "GET",
+        &format!("/api/modbus-register/devices/{}/versions", clone_id),
+        String::new(),
+    )
+    .await;
+    let clone_versions = clone_versions.as_array().unwrap();
+    assert_eq!(clone_versions.len(), 2);
+    let (_, registers) = send(
+        &app,
+        "GET",
+        &format!(
+            "/api/modbus-registers?device_id={}&version_id={}",
+            clone_id, clone_versions[1]["id"]
+        ),
+        String::new(),
+    )
+    .await;
+    assert_eq!(registers["count"], 3);
+}
+
+#[tokio::test]
+async fn test_product_resolution() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    // A versioned device with two registers and an unversioned one with a single register.
+    let mut device_ids = vec![];
+    for name in ["Resolved controller", "Resolved sensor"] {
+        let (_, device) = send(
+            &app,
+            "POST",
+            "/api/modbus-register/devices",
+            format!(r#"{{"name":"{}"}}"#, name),
+        )
+        .await;
+        device_ids.push(device["id"].as_i64().unwrap());
+    }
+    let (_, version) = send(
+        &app,
+        "POST",
+        &format!("/api/modbus-register/devices/{}/versions", device_ids[0]),
+        r#"{"version":"1.x","firmware_min":"1.0","firmware_max":"1.9"}"#.to_string(),
+    )
+    .await;
+    for (device_id, name, version_id) in [
+        (device_ids[0], "Supply temperature", Value::Null),
+        (device_ids[0], "Setpoint", version["id"].clone()),
+        (device_ids[1], "Humidity", Value::Null),
+    ] {
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/modbus-registers",
+            serde_json::json!({
+                "register_address": 0,
+                "operation": if version_id.is_null() {
+                    "03 Read Holding Registers (4x)"
+                } else {
+                    "06 Write Single Register"
+                },
+                "register_length": 2,
+                "register_name": name,
+                "data_format": "32 Bit Float",
+                "device_id": device_id,
+                "version_id": version_id,
+            })
+            .to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let product_id = 903_901;
+    for device_id in &device_ids {
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/modbus-register/product_device_mappings",
+            format!(
+                r#"{{"product_id":{},"device_id":{}}}"#,
+                product_id, device_id
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let product_uri = format!("/api/modbus-register/products/{}", product_id);
+
+    // Without a firmware, the device with the most registers wins.
+    let (status, resolution) = send(&app, "GET", &product_uri, String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(resolution["resolved_by"], "mapping");
+    assert_eq!(resolution["device"]["id"].as_i64(), Some(device_ids[0]));
+    assert_eq!(resolution["candidates"].as_array().unwrap().len(), 2);
+    assert_eq!(
+        resolution["candidates"][1]["device"]["id"].as_i64(),
+        Some(device_ids[1])
+    );
+    let registers = resolution["registers"].as_array().unwrap();
+    assert_eq!(registers.len(), 2);
+    assert_eq!(registers[0]["register_name"], "Supply temperature");
+    assert_eq!(registers[0]["table"], "holding_registers");
+    assert_eq!(registers[0]["function_codes"], serde_json::json!([3]));
+    assert_eq!(registers[0]["words"], 2);
+    assert_eq!(registers[0]["format"]["data_type"], "float32");
+
+    // A firmware covered by a version selects that version's map.
+    let (_, resolution) = send(
+        &app,
+        "GET",
+        &format!("{}?firmware=1.2", product_uri),
+        String::new(),
+    )
+    .await;
+    assert_eq!(resolution["device"]["id"].as_i64(), Some(device_ids[0]));
+    assert_eq!(resolution["version"]["version"], "1.x");
+    assert_eq!(resolution["registers"].as_array().unwrap().len(), 2);
+
+    // A firmware no version covers prefers the device without versions.
+    let (_, resolution) = send(
+        &app,
+        "GET",
+        &format!("{}?firmware=3.0", product_uri),
+        String::new(),
+    )
+    .await;
+    assert_eq!(resolution["device"]["id"].as_i64(), Some(device_ids[1]));
+    assert_eq!(resolution["candidates"][1]["register_count"], 1);
+
+    let (status, _) = send(
+        &app,
+        "GET",
+        &format!("{}?firmware=abc", product_uri),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Unmapped products use the fallback device, if one is configured.
+    let unmapped_uri = format!("/api/modbus-register/products/{}", product_id + 1);
+    let (status, _) = send(&app, "GET", &unmapped_uri, String::new()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/settings",
+        format!(
+            r#"{{"name":"product_fallback_device","value":"{}"}}"#,
+            device_ids[1]
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, resolution) = send(&app, "GET", &unmapped_uri, String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(resolution["resolved_by"], "fallback");
+    assert_eq!(resolution["device"]["id"].as_i64(), Some(device_ids[1]));
+    send(
+        &app,
+        "DELETE",
+        "/api/modbus-register/settings/product_fallback_device",
+        String::new(),
+    )
+    .await;
+
+    // The same resolution is available in-process.
+    let json = tokio::task::spawn_blocking(move || unsafe {
+        let firmware = std::ffi::CString::new("1.2").unwrap();
+        let result = t3_webview_api::resolve_product(product_id, firmware.as_ptr());
+        let json = std::ffi::CStr::from_ptr(result)
+            .to_str()
+            .unwrap()
+            .to_string();
+        t3_webview_api::free_string(result);
+        json
+    })
+    .await
+    .unwrap();
+    let resolution = serde_json::from_str::<Value>(&json).unwrap();
+    assert_eq!(resolution["device"]["id"].as_i64(), Some(device_ids[0]));
+    assert_eq!(resolution["version"]["version"], "1.x");
+    let json = tokio::task::spawn_blocking(move || unsafe {
+        let result = t3_webview_api::resolve_product(product_id + 1, std::ptr::null());
+        let json = std::ffi::CStr::from_ptr(result)
+            .to_str()
+            .unwrap()
+            .to_string();
+        t3_webview_api::free_string(result);
+        json
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(&json).unwrap()["error"]["type"],
+        "NotFound"
+    );
+}
+
+#[tokio::test]
+async fn test_device_catalogue() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let (status, manufacturer) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/manufacturers",
+        r#"{"name":"Temco Controls","website":"https://temcocontrols.com"}"#.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let manufacturer_id = manufacturer["id"].as_i64().unwrap();
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/manufacturers",
+        r#"{"name":"temco controls"}"#.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (_, category) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/categories",
+        r#"{"name":"VAV","description":"Variable air volume boxes"}"#.to_string(),
+    )
+    .await;
+    let category_id = category["id"].as_i64().unwrap();
+
+    // Devices link by ID, or by name when synced from another library.
+    let (status, vav) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/devices",
+        format!(
+            r#"{{"name":"Catalogue VAV","manufacturer_id":{},"category_id":{}}}"#,
+            manufacturer_id, category_id
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, meter) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/devices",
+        r#"{"name":"Catalogue meter","manufacturer_name":"Acme Meters","category_name":"Meter"}"#
+            .to_string(),
+    )
+    .await;
+    let (_, device) = send(
+        &app,
+        "GET",
+        &format!("/api/modbus-register/devices/{}", meter["id"]),
+        String::new(),
+    )
+    .await;
+    assert_eq!(device["manufacturer"]["name"], "Acme Meters");
+    assert_eq!(device["category"]["name"], "Meter");
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        &format!("/api/modbus-register/devices/{}", meter["id"]),
+        r#"{"category_id":999999}"#.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (_, devices) = send(
+        &app,
+        "GET",
+        &format!(
+            "/api/modbus-register/devices?manufacturer_id={}",
+            manufacturer_id
+        ),
+        String::new(),
+    )
+    .await;
+    let devices = devices.as_array().unwrap();
+    assert!(devices
+        .iter()
+        .all(|device| device["manufacturer_id"] == manufacturer_id));
+    assert!(devices.iter().any(|device| device["id"] == vav["id"]));
+
+    let (_, devices) = send(
+        &app,
+        "GET",
+        "/api/modbus-register/devices?order_by=manufacturer&order_dir=desc",
+        String::new(),
+    )
+    .await;
+    let position = |id: &Value| {
+        devices
+            .as_array()
+            .unwrap()
+            .iter()
+            .position(|device| device["id"] == *id)
+            .unwrap()
+    };
+    assert!(position(&vav["id"]) < position(&meter["id"]));
+
+    // Facets apply the other filters only.
+    let (_, facets) = send(
+        &app,
+        "GET",
+        &format!(
+            "/api/modbus-register/devices/facets?category_id={}",
+            category_id
+        ),
+        String::new(),
+    )
+    .await;
+    assert_eq!(facets["total"], 1);
+    assert_eq!(facets["manufacturers"][0]["name"], "Temco Controls");
+    assert_eq!(facets["manufacturers"][0]["count"], 1);
+    let categories = facets["categories"].as_array().unwrap();
+    assert!(categories
+        .iter()
+        .any(|facet| facet["name"] == "Meter" && facet["count"] == 1));
+
+    // Devices without a manufacturer or category have their own bucket and filter.
+    let (_, loose) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/devices",
+        r#"{"name":"Catalogue loose"}"#.to_string(),
+    )
+    .await;
+    let (_, facets) = send(
+        &app,
+        "GET",
+        "/api/modbus-register/devices/facets?category_id=none",
+        String::new(),
+    )
+    .await;
+    let unlinked = facets["manufacturers"]
+        .as_array()
+        .unwrap()
+        .last()
+        .unwrap()
+        .clone();
+    assert_eq!(unlinked["id"], Value::Null);
+    let (status, devices) = send(
+        &app,
+        "GET",
+        "/api/modbus-register/devices?manufacturer_id=none&category_id=none",
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let devices = devices.as_array().unwrap();
+    assert_eq!(unlinked["count"], devices.len());
+    assert!(devices.iter().any(|device| device["id"] == loose["id"]));
+    assert!(devices
+        .iter()
+        .all(|device| device["manufacturer_id"].is_null() && device["category_id"].is_null()));
+    let (status, _) = send(
+        &app,
+        "GET",
+        "/api/modbus-register/devices?manufacturer_id=nobody",
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, document) = send(
+        &app,
+        "GET",
+        &format!(
+            "/api/modbus-register/devices/{}/export?format=json",
+            vav["id"]
+        ),
+        String::new(),
+    )
+    .await;
+    assert_eq!(document["devices"][0]["manufacturer"], "Temco Controls");
+    assert_eq!(document["devices"][0]["category"], "VAV");
+
+    // Manufacturers in use can't be deleted until their devices are unlinked.
+    let manufacturer_uri = format!("/api/modbus-register/manufacturers/{}", manufacturer_id);
+    let (status, _) = send(&app, "DELETE", &manufacturer_uri, String::new()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, device) = send(
+        &app,
+        "PATCH",
+        &format!("/api/modbus-register/devices/{}", vav["id"]),
+        r#"{"manufacturer_id":null}"#.to_string(),
+    )
+    .await;
+    assert_eq!(device["manufacturer_id"], Value::Null);
+    let (status, _) = send(&app, "DELETE", &manufacturer_uri, String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_register_search() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let (_, device) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/devices",
+        r#"{"name":"Searchable rooftop unit","description":"Packaged <RTU>"}"#.to_string(),
+    )
+    .await;
+    let device_id = device["id"].as_i64().unwrap();
+    for (address, name, description) in [
+        (
+            0,
+            "Supply air temperature",
+            "Measured after the cooling coil",
+        ),
+        (1, "Return fan speed", "Follows the supply fan"),
+    ] {
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/modbus-registers",
+            serde_json::json!({
+                "register_address": address,
+                "register_length": 1,
+                "register_name": name,
+                "description": description,
+                "device_id": device_id,
+            })
+            .to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let search = |q: &str| {
+        format!(
+            "/api/modbus-registers?device_id={}&q={}",
+            device_id,
+            q.replace(' ', "%20").replace('"', "%22")
+        )
+    };
+
+    // Matches in register names rank above matches in descriptions.
+    let (status, result) = send(&app, "GET", &search("supply"), String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["count"], 2);
+    assert_eq!(result["data"][0]["register_name"], "Supply air temperature");
+    assert_eq!(
+        result["data"][0]["search"]["highlights"]["register_name"],
+        "<mark>Supply</mark> air temperature"
+    );
+    assert_eq!(
+        result["data"][1]["search"]["highlights"]["description"],
+        "Follows the <mark>supply</mark> fan"
+    );
+
+    let (_, result) = send(&app, "GET", &search("temp* coil"), String::new()).await;
+    assert_eq!(result["count"], 1);
+    let (_, result) = send(&app, "GET", &search(r#""air temperature""#), String::new()).await;
+    assert_eq!(result["count"], 1);
+    let (_, result) = send(&app, "GET", &search(r#""temperature air""#), String::new()).await;
+    assert_eq!(result["count"], 0);
+
+    // Registers are found by their device, and follow its renames.
+    let (_, result) = send(&app, "GET", &search("rooftop"), String::new()).await;
+    assert_eq!(result["count"], 2);
+    send(
+        &app,
+        "PATCH",
+        &format!("/api/modbus-register/devices/{}", device_id),
+        r#"{"name":"Searchable air handler"}"#.to_string(),
+    )
+    .await;
+    let (_, result) = send(&app, "GET", &search("rooftop"), String::new()).await;
+    assert_eq!(result["count"], 0);
+    let (_, result) = send(&app, "GET", &search("handler rtu"), String::new()).await;
+    assert_eq!(result["count"], 2);
+    assert_eq!(
+        result["data"][0]["search"]["highlights"]["device_description"],
+        "Packaged &lt;<mark>RTU</mark>&gt;"
+    );
+
+    // Devices are found by their own name and description, with or without registers.
+    let (_, bridge) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/devices",
+        r#"{"name":"Searchable gateway","description":"Unwired quokka bridge"}"#.to_string(),
+    )
+    .await;
+    let device_search =
+        |q: &str| format!("/api/modbus-register/devices?q={}", q.replace(' ', "%20"));
+    let (status, devices) = send(&app, "GET", &device_search("quokka bridge"), String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(devices.as_array().unwrap().len(), 1);
+    assert_eq!(devices[0]["id"], bridge["id"]);
+    let (_, devices) = send(&app, "GET", &device_search("searchab*"), String::new()).await;
+    let ids: Vec<&Value> = devices
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|device| &device["id"])
+        .collect();
+    assert_eq!(ids, [&device["id"], &bridge["id"]]);
+    let (status, body) = send(
+        &app,
+        "PATCH",
+        &format!("/api/modbus-register/devices/{}", bridge["id"]),
+        r#"{"description":"Modbus bridge"}"#.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (_, devices) = send(&app, "GET", &device_search("quokka"), String::new()).await;
+    assert_eq!(devices.as_array().unwrap().len(), 0);
+
+    // Filter expressions narrow the list down field by field.
+    let (status, result) = send(
+        &app,
+        "GET",
+        &format!(
+            "/api/modbus-registers?device_id={}&where=register_address%3E%3D1%20AND%20unit%20IS%20NULL",
+            device_id
+        ),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["count"], 1);
+    assert_eq!(result["data"][0]["register_name"], "Return fan speed");
+    let (status, _) = send(
+        &app,
+        "GET",
+        "/api/modbus-registers?where=register_address%3E%3Dhigh",
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_register_keyset_pagination() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let (_, device) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/devices",
+        r#"{"name":"Paged device"}"#.to_string(),
+    )
+    .await;
+    let device_id = device["id"].as_i64().unwrap();
+    for (name, address) in [
+        ("a", None),
+        ("b", Some(5)),
+        ("c", None),
+        ("d", Some(1)),
+        ("e", Some(3)),
+    ] {
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/modbus-registers",
+            serde_json::json!({
+                "register_address": address,
+                "register_length": 1,
+                "register_name": name,
+                "device_id": device_id,
+            })
+            .to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let page = |sort: &str, cursor: &str| {
+        let uri = format!(
+            "/api/modbus-registers?device_id={}&limit=2&sort={}",
+            device_id, sort
+        );
+        match cursor {
+            "" => uri,
+            cursor => format!("{}&cursor={}", uri, cursor),
+        }
+    };
+    let names = |result: &Value| {
+        result["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|register| register["register_name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+            .join("")
+    };
+
+    // Limits too large to add one to are capped instead of overflowing.
+    let (status, result) = send(
+        &app,
+        "GET",
+        &format!(
+            "/api/modbus-registers?device_id={}&limit={}",
+            device_id,
+            u64::MAX
+        ),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["data"].as_array().unwrap().len(), 5);
+
+    // Walk forwards and back again. Registers without an address sort first, ties are
+    // broken by id in the direction of the last column.
+    for (sort, expected) in [
+        ("register_address,-id", ["ca", "de", "b"]),
+        ("-register_address", ["be", "dc", "a"]),
+    ] {
+        let mut cursor = String::new();
+        for (index, expected) in expected.iter().enumerate() {
+            let (status, result) = send(&app, "GET", &page(sort, &cursor), String::new()).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(result["count"], 5);
+            assert_eq!(names(&result), *expected);
+            assert_eq!(result["prev_cursor"].is_null(), index == 0);
+            assert_eq!(result["next_cursor"].is_null(), index == 2);
+            cursor = result["next_cursor"]
+                .as_str()
+                .or(result["prev_cursor"].as_str())
+                .unwrap()
+                .to_string();
+        }
+        for expected in expected[..2].iter().rev() {
+            let (_, result) = send(&app, "GET", &page(sort, &cursor), String::new()).await;
+            assert_eq!(names(&result), *expected);
+            assert!(!result["next_cursor"].is_null());
+            cursor = result["prev_cursor"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+        }
+        assert!(cursor.is_empty());
+    }
+
+    // Cursors only work with the sort they were made for.
+    let (_, result) = send(&app, "GET", &page("-register_address", ""), String::new()).await;
+    let cursor = result["next_cursor"].as_str().unwrap();
+    let (status, _) = send(
+        &app,
+        "GET",
+        &page("register_address", cursor),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, "GET", &page("id", "not-a-cursor"), String::new()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, "GET", &page("colour", ""), String::new()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_register_facets() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let (_, device) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/devices",
+        r#"{"name":"Faceted meter"}"#.to_string(),
+    )
+    .await;
+    let device_id = device["id"].as_i64().unwrap();
+    for (address, length, data_format, unit) in [
+        (0, 1, "16 Bit Unsigned Integer", Some("V")),
+        (1, 1, "16 Bit Unsigned Integer", Some("A")),
+        (2, 2, "32 Bit Float", Some("V")),
+        (4, 1, "16 Bit Signed Integer", None),
+    ] {
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/modbus-registers",
+            serde_json::json!({
+                "register_address": address,
+                "register_length": length,
+                "data_format": data_format,
+                "unit": unit,
+                "device_id": device_id,
+            })
+            .to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    // Values are counted most common first, registers without a value last.
+    let (status, facets) = send(
+        &app,
+        "GET",
+        &format!("/api/modbus-registers/facets?device_id={}", device_id),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(facets["total"], 4);
+    assert_eq!(
+        facets["units"],
+        serde_json::json!([
+            {"value": "V", "count": 2},
+            {"value": "A", "count": 1},
+            {"value": null, "count": 1},
+        ])
+    );
+    assert_eq!(
+        facets["data_formats"][0]["value"],
+        "16 Bit Unsigned Integer"
+    );
+    assert_eq!(facets["data_formats"][0]["count"], 2);
+    assert_eq!(facets["data_formats"].as_array().unwrap().len(), 3);
+    assert_eq!(
+        facets["devices"],
+        serde_json::json!([{"id": device_id, "name": "Faceted meter", "count": 4}])
+    );
+
+    // The counts follow the list's filters.
+    let (_, facets) = send(
+        &app,
+        "GET",
+        &format!(
+            "/api/modbus-registers/facets?device_id={}&where=unit%3D'V'",
+            device_id
+        ),
+        String::new(),
+    )
+    .await;
+    assert_eq!(facets["total"], 2);
+    assert_eq!(
+        facets["data_formats"],
+        serde_json::json!([
+            {"value": "16 Bit Unsigned Integer", "count": 1},
+            {"value": "32 Bit Float", "count": 1},
+        ])
+    );
+    assert_eq!(facets["statuses"][0]["count"], 2);
+}
+
+#[tokio::test]
+async fn test_register_bulk_changes() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let (_, device) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/devices",
+        r#"{"name":"Bulk edited device"}"#.to_string(),
+    )
+    .await;
+    let device_id = device["id"].as_i64().unwrap();
+    let mut ids = vec![];
+    for (address, unit, status) in [
+        (0, "degC", "NEW"),
+        (1, "degC", "PUBLISHED"),
+        (2, "V", "NEW"),
+    ] {
+        let (status_code, register) = send(
+            &app,
+            "POST",
+            "/api/modbus-registers",
+            serde_json::json!({
+                "register_address": address,
+                "register_length": 1,
+                "unit": unit,
+                "status": status,
+                "device_id": device_id,
+            })
+            .to_string(),
+        )
+        .await;
+        assert_eq!(status_code, StatusCode::OK);
+        ids.push(register["id"].as_i64().unwrap());
+    }
+    let units = || {
+        let app = app.clone();
+        async move {
+            let (_, result) = send(
+                &app,
+                "GET",
+                &format!(
+                    "/api/modbus-registers?device_id={}&local_only=false&sort=register_address",
+                    device_id
+                ),
+                String::new(),
+            )
+            .await;
+            result["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|register| register["unit"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+    let fix_units = |dry_run: bool| {
+        serde_json::json!({
+            "where": format!("device_id = {} AND unit = 'degC'", device_id),
+            "changes": {"unit": "°C"},
+            "dry_run": dry_run,
+        })
+        .to_string()
+    };
+
+    // A dry run previews the changes without keeping them.
+    let (status, report) = send(
+        &app,
+        "POST",
+        "/api/modbus-registers/bulk_update",
+        fix_units(true),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["dry_run"], true);
+    assert_eq!(report["count"], 2);
+    assert_eq!(report["changes"][0]["before"]["unit"], "degC");
+    assert_eq!(report["changes"][0]["after"]["unit"], "°C");
+    // Published registers become updated, like with a single update.
+    assert_eq!(report["changes"][1]["after"]["status"], "UPDATED");
+    assert_eq!(units().await, ["degC", "degC", "V"]);
+
+    let (status, report) = send(
+        &app,
+        "POST",
+        "/api/modbus-registers/bulk_update",
+        fix_units(false),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["count"], 2);
+    assert_eq!(units().await, ["°C", "°C", "V"]);
+
+    // One invalid register rolls back the whole update.
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/modbus-registers/bulk_update",
+        serde_json::json!({
+            "ids": ids,
+            "changes": {"unit": "A", "data_format": "32 Bit Float"},
+        })
+        .to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(units().await, ["°C", "°C", "V"]);
+
+    for payload in [
+        serde_json::json!({"ids": [ids[0], -1], "changes": {}}),
+        serde_json::json!({"changes": {"unit": "V"}}),
+        serde_json::json!({"ids": [ids[0]], "where": "id > 0", "changes": {}}),
+    ] {
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/modbus-registers/bulk_update",
+            payload.to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    // New registers are removed, published ones are marked deleted.
+    let (status, report) = send(
+        &app,
+        "POST",
+        "/api/modbus-registers/bulk_delete",
+        serde_json::json!({"ids": [ids[0], ids[1]]}).to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["changes"][0]["action"], "removed");
+    assert_eq!(report["changes"][1]["action"], "marked_deleted");
+    assert_eq!(report["changes"][1]["after"]["status"], "DELETED");
+    assert_eq!(units().await, ["V"]);
+}
+
+#[tokio::test]
+async fn test_restore_deleted_items() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let (_, device) = send(
+        &app,
+        "POST",
+        "/api/modbus-register/devices",
+        r#"{"name":"Restorable device","status":"PUBLISHED"}"#.to_string(),
+    )
+    .await;
+    let device_id = device["id"].as_i64().unwrap();
+    let mut ids = vec![];
+    for (address, status) in [(0, "PUBLISHED"), (1, "NEW"), (2, "UNDER_REVIEW")] {
+        let (_, register) = send(
+            &app,
+            "POST",
+            "/api/modbus-registers",
+            serde_json::json!({
+                "register_address": address,
+                "register_length": 1,
+                "status": status,
+                "device_id": device_id,
+            })
+            .to_string(),
+        )
+        .await;
+        ids.push(register["id"].as_i64().unwrap());
+    }
+    let register = |id: i64| {
+        let app = app.clone();
+        async move {
+            send(
+                &app,
+                "GET",
+                &format!("/api/modbus-registers/{}", id),
+                String::new(),
+            )
+            .await
+            .1
+        }
+    };
+
+    // Soft-deleted registers get their status back.
+    send(
+        &app,
+        "DELETE",
+        &format!("/api/modbus-registers/{}", ids[2]),
+        String::new(),
+    )
+    .await;
+    assert_eq!(register(ids[2]).await["status"], "DELETED");
+    let (status, restored) = send(
+        &app,
+        "POST",
+        &format!("/api/modbus-registers/{}/restore", ids[2]),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(restored["status"], "UNDER_REVIEW");
+    assert!(restored["deleted_at"].is_null());
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("/api/modbus-registers/{}/restore", ids[2]),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Deleting the device deletes its synced registers along with it.
+    send(
+        &app,
+        "DELETE",
+        &format!("/api/modbus-registers/{}", ids[2]),
+        String::new(),
+    )
+    .await;
+    send(
+        &app,
+        "DELETE",
+        &format!("/api/modbus-register/devices/{}?cascade=true", device_id),
+        String::new(),
+    )
+    .await;
+    assert_eq!(register(ids[0]).await["status"], "DELETED");
+    assert_eq!(register(ids[1]).await["status"], "NEW");
+    let (status, deleted) = send(&app, "GET", "/api/modbus-register/deleted", String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(deleted["devices"][0]["id"], device_id);
+    assert_eq!(deleted["devices"][0]["previous_status"], "PUBLISHED");
+    assert_eq!(deleted["registers"][0]["id"], ids[0]);
+    assert_eq!(deleted["registers"][1]["id"], ids[2]);
+
+    // Its registers come back with the device, unless they were deleted on their own.
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("/api/modbus-registers/{}/restore", ids[0]),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, restored) = send(
+        &app,
+        "POST",
+        &format!("/api/modbus-register/devices/{}/restore", device_id),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(restored["status"], "PUBLISHED");
+    assert_eq!(register(ids[0]).await["status"], "PUBLISHED");
+    assert_eq!(register(ids[2]).await["status"], "DELETED");
+}
+
+#[tokio::test]
+async fn test_safe_device_deletion() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let mut device_ids = vec![];
+    for name in ["Replaced controller", "Replacement controller"] {
+        let (_, device) = send(
+            &app,
+            "POST",
+            "/api/modbus-register/devices",
+            serde_json::json!({ "name": name }).to_string(),
+        )
+        .await;
+        device_ids.push(device["id"].as_i64().unwrap());
+    }
+    let (old_id, new_id) = (device_ids[0], device_ids[1]);
+    for address in [0, 1] {
+        send(
+            &app,
+            "POST",
+            "/api/modbus-registers",
+            serde_json::json!({
+                "register_address": address,
+                "register_length": 1,
+                "device_id": old_id,
+            })
+            .to_string(),
+        )
+        .await;
+    }
+    send(
+        &app,
+        "POST",
+        "/api/modbus-register/product_device_mappings",
+        serde_json::json!({ "product_id": 947, "device_id": old_id }).to_string(),
+    )
+    .await;
+    let device_uri = |query: &str| format!("/api/modbus-register/devices/{}{}", old_id, query);
+
+    let (status, dependencies) =
+        send(&app, "GET", &device_uri("/dependencies"), String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(dependencies["registers"], 2);
+    assert_eq!(dependencies["product_ids"], serde_json::json!([947]));
+    assert_eq!(dependencies["versions"], 0);
+
+    // Nothing is deleted or moved until everything depending on the device is accounted for.
+    let (status, _) = send(&app, "DELETE", &device_uri(""), String::new()).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send(
+        &app,
+        "DELETE",
+        &device_uri(&format!("?reassign_to={}", new_id)),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (_, dependencies) = send(&app, "GET", &device_uri("/dependencies"), String::new()).await;
+    assert_eq!(dependencies["registers"], 2);
+    let (status, _) = send(
+        &app,
+        "DELETE",
+        &device_uri(&format!("?force=true&reassign_to={}", old_id)),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // The registers move to the other device, the mapping goes with the deleted device.
+    let (status, _) = send(
+        &app,
+        "DELETE",
+        &device_uri(&format!("?force=true&reassign_to={}", new_id)),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "GET", &device_uri(""), String::new()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, registers) = send(
+        &app,
+        "GET",
+        &format!("/api/modbus-registers?device_id={}", new_id),
+        String::new(),
+    )
+    .await;
+    assert_eq!(registers["count"], 2);
+    let (status, _) = send(
+        &app,
+        "GET",
+        "/api/modbus-register/product_device_mappings/947",
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(
+        &app,
+        "DELETE",
+        &format!("/api/modbus-register/devices/{}?cascade=true", new_id),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_conditional_requests() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state).await.unwrap();
+
+    let send = |method: &'static str,
+                uri: String,
+                payload: String,
+                condition: Option<(http::HeaderName, String)>| {
+        let mut request = authorized_request(method, &uri, payload);
+        if let Some((name, value)) = condition {
+            request
+                .headers_mut()
+                .insert(name, http::HeaderValue::from_str(&value).unwrap());
+        }
+        let app = app.clone();
+        async move {
+            let (status, headers, body) = send_request(&app, request).await;
+            let etag = headers
+                .get(http::header::ETAG)
+                .map(|etag| etag.to_str().unwrap().to_string());
+            (status, etag, body)
+        }
+    };
+    let if_match = |etag: &str| Some((http::header::IF_MATCH, etag.to_string()));
+
+    let (_, _, register) = send(
+        "POST",
+        "/api/modbus-registers".to_string(),
+        r#"{"register_address":4100,"register_length":1,"register_name":"Tagged","unit":"°C"}"#
+            .to_string(),
+        None,
+    )
+    .await;
+    let register_uri = format!("/api/modbus-registers/{}", register["id"]);
+
+    // Unchanged registers are answered with an empty 304.
+    let (status, etag, _) = send("GET", register_uri.clone(), String::new(), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let etag = etag.unwrap();
+    let (status, _, body) = send(
+        "GET",
+        register_uri.clone(),
+        String::new(),
+        Some((http::header::IF_NONE_MATCH, etag.clone())),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert_eq!(body, Value::Null);
+
+    // The first update wins, the second one is based on the old version.
+    let (status, updated_etag, updated) = send(
+        "PATCH",
+        register_uri.clone(),
+        r#"{"register_name":"First"}"#.to_string(),
+        if_match(&etag),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["register_name"], "First");
+    let updated_etag = updated_etag.unwrap();
+    assert_ne!(updated_etag, etag);
+    let (status, _, _) = send(
+        "PATCH",
+        register_uri.clone(),
+        r#"{"register_name":"Second"}"#.to_string(),
+        if_match(&etag),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let (status, current_etag, current) =
+        send("GET", register_uri.clone(), String::new(), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(current["register_name"], "First");
+    assert_eq!(current_etag.unwrap(), updated_etag);
+
+    // Labels are part of the response, so adding one changes the tag as well.
+    let (status, _, _) = send(
+        "POST",
+        format!("{}/labels", register_uri),
+        r#"{"kind":"enum","value":1,"label":"On"}"#.to_string(),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send(
+        "GET",
+        register_uri.clone(),
+        String::new(),
+        Some((http::header::IF_NONE_MATCH, updated_etag.clone())),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send(
+        "PATCH",
+        register_uri.clone(),
+        r#"{"register_name":"Third"}"#.to_string(),
+        if_match(&updated_etag),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+
+    // Registers are tagged in stored units, so the tag of any unit system can be sent back.
+    let (status, imperial_etag, imperial) = send(
+        "GET",
+        format!("{}?unit_system=imperial", register_uri),
+        String::new(),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(imperial["unit"], "°F");
+    let (status, _, _) = send(
+        "PATCH",
+        register_uri.clone(),
+        r#"{"register_name":"Third"}"#.to_string(),
+        if_match(&imperial_etag.unwrap()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, _) = send(
+        "DELETE",
+        register_uri.clone(),
+        String::new(),
+        if_match(&etag),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let (status, _, _) = send("DELETE", register_uri.clone(), String::new(), if_match("*")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Settings are tagged the same way.
+    send(
+        "POST",
+        "/api/modbus-register/settings".to_string(),
+        r#"{"name":"etag_test","value":"1","json_value":null}"#.to_string(),
+        None,
+    )
+    .await;
+    let setting_uri = "/api/modbus-register/settings/etag_test".to_string();
+    let (_, etag, _) = send("GET", setting_uri.clone(), String::new(), None).await;
+    let etag = etag.unwrap();
+    let (status, _, _) = send(
+        "PATCH",
+        setting_uri.clone(),
+        r#"{"value":"2"}"#.to_string(),
+        if_match(&etag),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send(
+        "DELETE",
+        setting_uri.clone(),
+        String::new(),
+        if_match(&etag),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let (status, _, _) = send("DELETE", setting_uri, String::new(), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Lists are tagged by their content.
+    let list_uri = "/api/modbus-register/units".to_string();
+    let (status, etag, _) = send("GET", list_uri.clone(), String::new(), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send(
+        "GET",
+        list_uri,
+        String::new(),
+        Some((http::header::IF_NONE_MATCH, format!("W/{}", etag.unwrap()))),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn test_idempotent_creates() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state.clone()).await.unwrap();
+
+    let send = |uri: &'static str, payload: &'static str, key: String| {
+        let mut request = authorized_request("POST", uri, payload.to_string());
+        request.headers_mut().insert(
+            "Idempotency-Key",
+            http::HeaderValue::from_str(&key).unwrap(),
+        );
+        let app = app.clone();
+        async move {
+            let (status, headers, body) = send_request(&app, request).await;
+            (status, headers.contains_key("Idempotent-Replayed"), body)
+        }
+    };
+
+    // A retried create returns the register created the first time.
+    let key = uuid::Uuid::new_v4().to_string();
+    let payload = r#"{"register_address":4200,"register_length":1,"register_name":"Once"}"#;
+    let (status, replayed, first) = send("/api/modbus-registers", payload, key.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!replayed);
+    let (status, replayed, retried) = send("/api/modbus-registers", payload, key.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(replayed);
+    assert_eq!(retried["id"], first["id"]);
+
+    // The key can't be reused for another request.
+    let (status, _, _) = send(
+        "/api/modbus-registers",
+        r#"{"register_address":4201,"register_length":1}"#,
+        key.clone(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _, _) = send("/api/modbus-register/devices", payload, key).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Failed requests aren't remembered, so they can be fixed and sent again.
+    let key = uuid::Uuid::new_v4().to_string();
+    let (status, _, _) = send("/api/modbus-register/devices", r#"{"name":1}"#, key.clone()).await;
+    assert!(status.is_client_error());
+    let payload = r#"{"name":"Idempotent device"}"#;
+    let (status, replayed, device) =
+        send("/api/modbus-register/devices", payload, key.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!replayed);
+    let (_, replayed, retried) = send("/api/modbus-register/devices", payload, key).await;
+    assert!(replayed);
+    assert_eq!(retried["id"], device["id"]);
+
+    // Other POST requests don't store their responses.
+    let key = uuid::Uuid::new_v4().to_string();
+    let payload = r#"{"data_format":"16 Bit Unsigned Integer","words":[7]}"#;
+    for _ in 0..2 {
+        let (status, replayed, _) =
+            send("/api/modbus-registers/decode", payload, key.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!replayed);
+    }
+
+    // A claim whose request never finished is given up after a while.
+    let key = uuid::Uuid::new_v4().to_string();
+    idempotency_keys::ActiveModel {
+        key: Set(key.clone()),
+        method: Set("POST".to_string()),
+        path: Set("/api/modbus-register/devices".to_string()),
+        request_hash: Set(String::new()),
+        status_code: Set(None),
+        content_type: Set(None),
+        response_body: Set(None),
+        created_at: Set(Utc::now() - Duration::minutes(10)),
+    }
+    .insert(&*state.conn.lock().await)
+    .await
+    .unwrap();
+    let (status, replayed, stalled) = send(
+        "/api/modbus-register/devices",
+        r#"{"name":"Stalled device"}"#,
+        key,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!replayed);
+
+    for uri in [
+        format!("/api/modbus-registers/{}", first["id"]),
+        format!("/api/modbus-register/devices/{}", device["id"]),
+        format!("/api/modbus-register/devices/{}", stalled["id"]),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(authorized_request("DELETE", &uri, String::new()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn test_history_author() {
+    dotenvy::from_filename("./tests/.test.env").ok();
+
+    run_migrations().await.unwrap();
+
+    let state = app_state::app_state().await.unwrap();
+    let app = create_app(state.clone()).await.unwrap();
+
+    user::ActiveModel {
+        id: Set(4242),
+        name: Set("History author".to_string()),
+        token: Set(Some("author-token".to_string())),
+        last_modbus_register_pull: Set(None),
+    }
+    .insert(&*state.conn.lock().await)
+    .await
+    .unwrap();
+
+    let (_, register) = send(
+        &app,
+        "POST",
+        "/api/modbus-registers",
+        r#"{"register_address":4300,"register_length":1,"register_name":"Authored"}"#.to_string(),
+    )
+    .await;
+    let register_uri = format!("/api/modbus-registers/{}", register["id"]);
+
+    // Changes are credited to the user whose token the request carries.
+    let mut request = authorized_request(
+        "PATCH",
+        &register_uri,
+        r#"{"register_name":"Edited"}"#.to_string(),
+    );
+    request
+        .headers_mut()
+        .insert("auth", http::HeaderValue::from_static("author-token"));
+    let (status, _, _) = send_request(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, history) = send(
+        &app,
+        "GET",
+        &format!("{}/history", register_uri),
+        String::new(),
+    )
+    .await;
+    assert_eq!(history[0]["changed_by"], "History author");
+    // Requests without a token don't name anyone.
+    assert_eq!(history[1]["changed_by"], Value::Null);
+
+    let (status, _) = send(&app, "DELETE", &register_uri, String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    user::Entity::delete_by_id(4242)
+        .exec(&*state.conn.lock().await)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,35 @@
+use t3_webview_api::modbus_register::search::match_expression;
+
+#[test]
+fn words_and_phrases_become_quoted_terms() {
+    assert_eq!(
+        match_expression("supply temp*"),
+        Some(r#""supply" "temp"*"#.to_string())
+    );
+    assert_eq!(
+        match_expression(r#""supply air" fan"#),
+        Some(r#""supply air" "fan""#.to_string())
+    );
+    assert_eq!(
+        match_expression(r#""supply te"*"#),
+        Some(r#""supply te"*"#.to_string())
+    );
+}
+
+#[test]
+fn query_syntax_is_searched_as_text() {
+    assert_eq!(
+        match_expression("fan OR NEAR(pump) name:x"),
+        Some(r#""fan" "OR" "NEAR(pump)" "name:x""#.to_string())
+    );
+    assert_eq!(
+        match_expression(r#"unclosed "quote"#),
+        Some(r#""unclosed" "quote""#.to_string())
+    );
+}
+
+#[test]
+fn queries_without_terms_search_nothing() {
+    assert_eq!(match_expression(""), None);
+    assert_eq!(match_expression("  * \"\" -- "), None);
+}
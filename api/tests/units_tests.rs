@@ -0,0 +1,73 @@
+use t3_webview_api::modbus_register::units::{
+    convert, find_unit, unit_for_system, Quantity, UnitSystem, UNITS,
+};
+
+fn assert_close(value: Option<f64>, expected: f64) {
+    let value = value.unwrap();
+    assert!(
+        (value - expected).abs() < 1e-6 * expected.abs().max(1.0),
+        "{} != {}",
+        value,
+        expected
+    );
+}
+
+#[test]
+fn find_units_by_symbol_and_alias() {
+    assert_eq!(find_unit("°C").unwrap().quantity, Quantity::Temperature);
+    assert_eq!(find_unit(" degF ").unwrap().symbol, "°F");
+    assert_eq!(find_unit("m3/h").unwrap().symbol, "m³/h");
+    assert_eq!(find_unit("cfm").unwrap().symbol, "CFM");
+    // Case only matters where it tells units apart.
+    assert_eq!(find_unit("KWH").unwrap().symbol, "kWh");
+    assert_eq!(find_unit("MW").unwrap().symbol, "MW");
+    assert!(find_unit("furlong").is_none());
+}
+
+#[test]
+fn unit_symbols_are_unique() {
+    for (index, unit) in UNITS.iter().enumerate() {
+        for other in &UNITS[index + 1..] {
+            assert_ne!(unit.symbol, other.symbol);
+            assert!(!other.aliases.contains(&unit.symbol), "{}", unit.symbol);
+        }
+    }
+}
+
+#[test]
+fn convert_temperature() {
+    assert_close(convert(100.0, "°C", "°F"), 212.0);
+    assert_close(convert(-40.0, "°F", "°C"), -40.0);
+    assert_close(convert(0.0, "°C", "K"), 273.15);
+}
+
+#[test]
+fn convert_pressure_and_flow() {
+    assert_close(convert(1.0, "inH2O", "Pa"), 249.08891);
+    assert_close(convert(1.0, "psi", "kPa"), 6.894757293168);
+    assert_close(convert(1.0, "bar", "psi"), 14.503773773);
+    assert_close(convert(1.0, "m³/h", "CFM"), 0.588577779);
+    assert_close(convert(1000.0, "CFM", "m³/h"), 1699.010796);
+}
+
+#[test]
+fn convert_between_quantities_fails() {
+    assert!(convert(1.0, "°C", "Pa").is_none());
+    assert!(convert(1.0, "°C", "furlong").is_none());
+}
+
+#[test]
+fn units_for_system() {
+    let celsius = find_unit("°C").unwrap();
+    assert_eq!(unit_for_system(celsius, UnitSystem::Imperial).symbol, "°F");
+    assert_eq!(unit_for_system(celsius, UnitSystem::Metric).symbol, "°C");
+    assert_eq!(
+        unit_for_system(find_unit("inH2O").unwrap(), UnitSystem::Metric).symbol,
+        "kPa"
+    );
+    // Units shared by both systems are kept.
+    assert_eq!(
+        unit_for_system(find_unit("V").unwrap(), UnitSystem::Imperial).symbol,
+        "V"
+    );
+}
@@ -0,0 +1,81 @@
+use t3_webview_api::entity::modbus_register_device_versions as versions;
+use t3_webview_api::modbus_register::versions::{check_version, includes_firmware, parse_firmware};
+
+fn version(id: i32, name: &str, min: Option<&str>, max: Option<&str>) -> versions::Model {
+    versions::Model {
+        id,
+        device_id: 1,
+        version: name.to_string(),
+        firmware_min: min.map(str::to_string),
+        firmware_max: max.map(str::to_string),
+        release_notes: None,
+        created_at: chrono::Utc::now(),
+    }
+}
+
+#[test]
+fn parse_firmware_versions() {
+    assert_eq!(parse_firmware("2.10.1"), Some(vec![2, 10, 1]));
+    assert_eq!(parse_firmware(" v2.1 "), Some(vec![2, 1]));
+    assert_eq!(parse_firmware("2.1.0"), parse_firmware("2.1"));
+    assert!(parse_firmware("2.10").unwrap() > parse_firmware("2.9").unwrap());
+    assert_eq!(parse_firmware("2.x"), None);
+    assert_eq!(parse_firmware(""), None);
+}
+
+#[test]
+fn firmware_ranges_are_inclusive_and_open_ended() {
+    let bounded = version(1, "1.x", Some("1.0"), Some("1.9"));
+    assert!(includes_firmware(&bounded, &parse_firmware("1.0").unwrap()));
+    assert!(includes_firmware(
+        &bounded,
+        &parse_firmware("1.9.0").unwrap()
+    ));
+    assert!(!includes_firmware(
+        &bounded,
+        &parse_firmware("1.10").unwrap()
+    ));
+    assert!(!includes_firmware(
+        &bounded,
+        &parse_firmware("0.9").unwrap()
+    ));
+
+    let open = version(2, "2.x", Some("2.0"), None);
+    assert!(includes_firmware(&open, &parse_firmware("14.2").unwrap()));
+    assert!(!includes_firmware(&open, &parse_firmware("1.9").unwrap()));
+}
+
+#[test]
+fn check_versions_of_a_device() {
+    let others = vec![
+        version(1, "1.x", Some("1.0"), Some("1.9")),
+        version(2, "2.x", Some("2.0"), None),
+    ];
+    assert!(check_version(&version(3, "0.x", None, Some("0.9")), &others).is_empty());
+    // A version may keep its own range when it is updated.
+    assert!(check_version(&others[0], &others).is_empty());
+
+    let fields = |version: versions::Model| -> Vec<String> {
+        check_version(&version, &others)
+            .into_iter()
+            .map(|error| error.field)
+            .collect()
+    };
+    assert_eq!(fields(version(3, " 2.X ", None, Some("0.9"))), ["version"]);
+    assert_eq!(
+        fields(version(3, "1.5", Some("1.5"), Some("1.6"))),
+        ["firmware_min"]
+    );
+    assert_eq!(
+        fields(version(3, "3.x", Some("3.0"), None)),
+        ["firmware_min"]
+    );
+    assert_eq!(
+        fields(version(3, "0.x", Some("0.9"), Some("0.1"))),
+        ["firmware_min"]
+    );
+    assert_eq!(
+        fields(version(3, "beta", Some("beta"), None)),
+        ["firmware_min"]
+    );
+}
@@ -1,6 +1,29 @@
 import { Cookies } from "quasar";
 import ky from "ky";
 
+// The create endpoints of the API, which remember POST requests by their idempotency key
+const createPaths =
+  /\/(file|user|modbus-registers(\/create_many|\/\d+\/labels)?|modbus-register\/(settings|devices|manufacturers|categories|product_device_mappings)|modbus-register\/devices\/\d+\/(versions|clone))$/;
+
+// Give create requests a key the API remembers them by, so retries don't create the same item twice
+function setIdempotencyKey(request) {
+  if (
+    request.method === "POST" &&
+    createPaths.test(new URL(request.url).pathname) &&
+    !request.headers.has("Idempotency-Key") &&
+    globalThis.crypto?.randomUUID
+  ) {
+    request.headers.set("Idempotency-Key", globalThis.crypto.randomUUID());
+  }
+}
+
+// Only retry POST requests that carry an idempotency key, others could run twice
+function stopUnsafeRetry({ request }) {
+  if (request.method === "POST" && !request.headers.has("Idempotency-Key")) {
+    return ky.stop;
+  }
+}
+
 // Create a live API client with authentication headers
 export const liveApi = ky.create({
   prefixUrl: process.env.API_URL, // Set the API URL from environment variables
@@ -25,6 +48,10 @@ export const liveApi = ky.create({
 export const localApi = ky.create({
   prefixUrl: process.env.LOCAL_API_URL, // Set the local API URL from environment variables
   headers: { Authorization: process.env.LOCAL_API_SECRET_KEY || "secret" }, // Set the authorization header with the secret key from environment variables or a default value
+  retry: {
+    limit: 2,
+    methods: ["get", "put", "head", "delete", "options", "trace", "post"], // Create requests are safe to retry with their idempotency key
+  },
   hooks: {
     beforeRequest: [
       (request) => {
@@ -32,8 +59,14 @@ export const localApi = ky.create({
           "Authorization",
           process.env.LOCAL_API_SECRET_KEY || "secret" // Set the authorization header again before each request with the secret key from environment variables or a default value
         );
+        const token = Cookies.get("token");
+        if (token) {
+          request.headers.set("auth", token); // Name the signed-in user as the author of changes in the history
+        }
       },
+      setIdempotencyKey,
     ],
+    beforeRetry: [stopUnsafeRetry],
     afterResponse: [
       (request) => {
         if (request.status === 401) {